@@ -0,0 +1,85 @@
+use kvs::{AnyKvs, Error, Measurable, Result, Scannable, Scrubbable};
+
+use crate::args::Command;
+
+pub(crate) trait Administrable:
+    Measurable + Scannable + Scrubbable
+{
+    fn execute_verify(&self) -> Result<()> {
+        let stats = self.stats()?;
+        let report = self.scrub()?;
+        if !report.is_clean() {
+            for problem in &report.problems {
+                eprintln!("problem: {}", problem);
+            }
+            return Err(Error::corrupt_database(format!(
+                "{} problems found",
+                report.problems.len()
+            )));
+        }
+        println!("ok: {} keys, {} records", stats.keys, report.records);
+        Ok(())
+    }
+
+    fn execute_stats(&self) -> Result<()> {
+        let stats = self.stats()?;
+        println!("keys: {}", stats.keys);
+        println!("stale records: {}", stats.stale_records);
+        println!("stale bytes: {}", stats.stale_bytes);
+        println!("disk bytes: {}", stats.disk_bytes);
+        Ok(())
+    }
+
+    fn execute_compact(&mut self) -> Result<()>;
+
+    fn execute_compact_dry_run(&self) -> Result<()> {
+        let stats = self.stats()?;
+        println!(
+            "would drop {} stale records, reclaiming {} of {} bytes",
+            stats.stale_records, stats.stale_bytes, stats.disk_bytes
+        );
+        Ok(())
+    }
+
+    fn execute_rm_prefix(&mut self, prefix: &str) -> Result<()>;
+
+    fn execute_rm_prefix_dry_run(&self, prefix: &str) -> Result<()> {
+        let keys = self.keys()?;
+        let matching = keys.iter().filter(|key| key.starts_with(prefix));
+        println!("would remove {} keys", matching.count());
+        Ok(())
+    }
+
+    fn execute(&mut self, command: Command) -> Result<()> {
+        match command {
+            Command::Verify => self.execute_verify(),
+            Command::Stats => self.execute_stats(),
+            Command::Compact { dry_run: true } => {
+                self.execute_compact_dry_run()
+            }
+            Command::Compact { dry_run: false } => self.execute_compact(),
+            Command::RmPrefix {
+                prefix,
+                dry_run: true,
+            } => self.execute_rm_prefix_dry_run(&prefix),
+            Command::RmPrefix {
+                prefix,
+                dry_run: false,
+            } => self.execute_rm_prefix(&prefix),
+            Command::PruneBackups { .. } => {
+                unreachable!("backups are pruned without opening the store")
+            }
+        }
+    }
+}
+
+impl Administrable for AnyKvs {
+    fn execute_compact(&mut self) -> Result<()> {
+        self.compact()
+    }
+
+    fn execute_rm_prefix(&mut self, prefix: &str) -> Result<()> {
+        println!("removed {} keys", self.drop_prefix(prefix)?);
+        Ok(())
+    }
+}
@@ -0,0 +1,136 @@
+use std::path::PathBuf;
+
+use kvs::{Capability, Engine};
+use structopt::StructOpt;
+use strum_macros::{Display, EnumString};
+
+#[derive(Debug, StructOpt)]
+pub(crate) struct Opt {
+    /// Which type of backing store to use.
+    #[structopt(
+        short,
+        long,
+        env = "KVS_STORE",
+        default_value = "hashmap",
+        possible_values = Store::VARIANTS
+    )]
+    pub(crate) store: Store,
+    /// The location of the backing store.
+    #[structopt(short, long, env = "KVS_LOCATION", parse(from_os_str))]
+    pub(crate) location: PathBuf,
+    /// Allow commands that modify the store. Without this, only commands
+    /// that read the store can be run.
+    #[structopt(long)]
+    pub(crate) allow_writes: bool,
+    #[structopt(subcommand)]
+    pub(crate) command: Command,
+}
+
+#[derive(Clone, Copy, Debug, Display, EnumString, StructOpt)]
+pub(crate) enum Store {
+    /// Use a hashmap backed to the given file location.
+    #[strum(serialize = "hashmap")]
+    HashMap,
+    /// Use an append-only log store backed in the given directory location.
+    #[strum(serialize = "log")]
+    Log,
+}
+
+impl Store {
+    /// The names each variant is parsed from.
+    pub(crate) const VARIANTS: &'static [&'static str] = &["hashmap", "log"];
+}
+
+impl From<Store> for Engine {
+    fn from(store: Store) -> Engine {
+        match store {
+            Store::HashMap => Engine::HashMap,
+            Store::Log => Engine::Log,
+        }
+    }
+}
+
+#[derive(Debug, Display, StructOpt)]
+pub(crate) enum Command {
+    #[structopt(name = "verify")]
+    /// Read the whole store, checking that it can be decoded and agrees
+    /// with its index.
+    Verify,
+    #[structopt(name = "stats")]
+    /// Print statistics about the store.
+    Stats,
+    #[structopt(name = "compact")]
+    /// Compact the key-value store's storage. Requires --allow-writes, and
+    /// is only supported by the log store.
+    Compact {
+        /// Report what compaction would reclaim without changing the store.
+        /// Doesn't need --allow-writes.
+        #[structopt(long)]
+        dry_run: bool,
+    },
+    #[structopt(name = "rm-prefix")]
+    /// Remove every key starting with a prefix. Requires --allow-writes.
+    /// The log store writes a single record for them, and reclaims their
+    /// space when it's next compacted.
+    RmPrefix {
+        /// The prefix of the keys to remove.
+        prefix: String,
+        /// Print how many keys would be removed without removing them.
+        /// Doesn't need --allow-writes.
+        #[structopt(long)]
+        dry_run: bool,
+    },
+    #[structopt(name = "prune-backups")]
+    /// Remove old backups from a directory holding one per subdirectory,
+    /// always keeping the newest. Requires --allow-writes, and ignores
+    /// --location.
+    PruneBackups {
+        /// The directory holding the backups.
+        #[structopt(parse(from_os_str))]
+        dir: PathBuf,
+        /// Keep at most this many backups.
+        #[structopt(long)]
+        keep: Option<usize>,
+        /// Remove backups last written more than this many days ago.
+        #[structopt(long)]
+        max_age_days: Option<u64>,
+        /// Print the backups that would be removed without removing them.
+        /// Doesn't need --allow-writes.
+        #[structopt(long)]
+        dry_run: bool,
+    },
+}
+
+impl Command {
+    /// Whether running the command can modify the store.
+    pub(crate) fn writes(&self) -> bool {
+        match self {
+            Command::Verify | Command::Stats => false,
+            Command::Compact { dry_run }
+            | Command::RmPrefix { dry_run, .. }
+            | Command::PruneBackups { dry_run, .. } => !dry_run,
+        }
+    }
+
+    /// The operation to record in the store's audit log after running the
+    /// command, if it changes the store.
+    pub(crate) fn audited_as(&self) -> Option<&'static str> {
+        match self {
+            Command::Compact { dry_run: false } => Some("compact"),
+            Command::RmPrefix { dry_run: false, .. } => Some("rm-prefix"),
+            _ => None,
+        }
+    }
+
+    /// The optional operation the store needs to support to run the
+    /// command, if any.
+    pub(crate) fn requires(&self) -> Option<Capability> {
+        match self {
+            Command::Verify
+            | Command::Stats
+            | Command::RmPrefix { .. }
+            | Command::PruneBackups { .. } => None,
+            Command::Compact { .. } => Some(Capability::Compaction),
+        }
+    }
+}
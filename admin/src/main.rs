@@ -0,0 +1,363 @@
+use std::fmt::Display;
+use std::path::Path;
+use std::time::Duration;
+
+use kvs::{AuditLog, Engine, Kvs, Result, RetentionPolicy};
+use structopt::StructOpt;
+
+mod args;
+use args::{Command, Opt};
+mod administrable;
+use administrable::Administrable;
+
+fn main() {
+    let opt = Opt::from_args();
+    let engine = Engine::from(opt.store);
+    if let Some(capability) = opt.command.requires() {
+        if !engine.supports(capability) {
+            exit_with_error(format!(
+                "`{}` needs {}, which the {} store doesn't support",
+                opt.command, capability, engine
+            ));
+        }
+    }
+    if opt.command.writes() && !opt.allow_writes {
+        exit_with_error(format!(
+            "`{}` modifies the store, rerun with --allow-writes",
+            opt.command
+        ));
+    }
+    if let Command::PruneBackups {
+        dir,
+        keep,
+        max_age_days,
+        dry_run,
+    } = &opt.command
+    {
+        let policy = RetentionPolicy {
+            keep: *keep,
+            max_age: max_age_days
+                .map(|days| Duration::from_secs(days * 24 * 60 * 60)),
+        };
+        if let Err(err) = prune_backups(dir, &policy, *dry_run) {
+            exit_with_error(err);
+        }
+        return;
+    }
+    // opening a store creates it if it's missing, which an admin command
+    // shouldn't do
+    if !opt.location.exists() {
+        exit_with_error(format!("no store at {}", opt.location.display()));
+    }
+
+    if let Err(err) = run(opt) {
+        exit_with_error(err);
+    }
+}
+
+fn exit_with_error<T: Display>(err: T) -> ! {
+    eprintln!("error: {}", err);
+    std::process::exit(1)
+}
+
+fn prune_backups(
+    dir: &Path,
+    policy: &RetentionPolicy,
+    dry_run: bool,
+) -> Result<()> {
+    for path in kvs::prune_backups(dir, policy, dry_run)? {
+        if dry_run {
+            println!("would remove {}", path.display());
+        } else {
+            println!("removed {}", path.display());
+        }
+    }
+    Ok(())
+}
+
+fn run(opt: Opt) -> Result<()> {
+    let audited_as = opt.command.audited_as();
+    let mut builder =
+        Kvs::builder().engine(opt.store.into()).path(&opt.location);
+    // so commands that only read can run while another process writes
+    if !opt.command.writes() {
+        builder = builder.read_only();
+    }
+    let mut store = builder.open_any()?;
+    store.execute(opt.command)?;
+
+    if let Some(operation) = audited_as {
+        AuditLog::for_store(&opt.location).record(
+            &AuditLog::local_principal(),
+            operation,
+            "",
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_cmd::prelude::*;
+    use predicates::ord::eq;
+    use predicates::prelude::*;
+    use predicates::str::{contains, is_empty};
+    use std::process::Command;
+    use tempfile::TempDir;
+
+    use kvs::{HashMapKvs, KvStore, LogKvs, Measurable, Persistent, Scannable};
+
+    // `kvs-admin` with no args should exit with a non-zero code.
+    #[test]
+    fn admin_no_args() {
+        Command::cargo_bin("kvs-admin").unwrap().assert().failure();
+    }
+
+    // `kvs-admin stats` should report the number of keys and stale records.
+    #[test]
+    fn admin_stats() -> Result<()> {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+
+        let mut store = LogKvs::open(temp_dir.path().join("log_dir"))?;
+        store.set("key1".to_owned(), "value1".to_owned())?;
+        store.set("key1".to_owned(), "value2".to_owned())?;
+
+        // reading the stats doesn't need the store closed
+        Command::cargo_bin("kvs-admin")
+            .unwrap()
+            .args(&["-s", "log", "-l", "log_dir", "stats"])
+            .current_dir(&temp_dir)
+            .assert()
+            .success()
+            .stdout(contains("keys: 1").and(contains("stale records: 1")));
+
+        Ok(())
+    }
+
+    // `kvs-admin verify` should succeed on a readable store.
+    #[test]
+    fn admin_verify() -> Result<()> {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+
+        let mut store = HashMapKvs::open(temp_dir.path().join("kvs_file"))?;
+        store.set("key1".to_owned(), "value1".to_owned())?;
+        drop(store);
+
+        Command::cargo_bin("kvs-admin")
+            .unwrap()
+            .args(&["-l", "kvs_file", "verify"])
+            .current_dir(&temp_dir)
+            .assert()
+            .success()
+            .stdout(contains("ok: 1 keys"));
+
+        Ok(())
+    }
+
+    // `kvs-admin verify` should fail on a store that can't be decoded.
+    #[test]
+    fn admin_verify_corrupt() -> Result<()> {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+        std::fs::write(temp_dir.path().join("kvs_file"), "not json")?;
+
+        Command::cargo_bin("kvs-admin")
+            .unwrap()
+            .args(&["-l", "kvs_file", "verify"])
+            .current_dir(&temp_dir)
+            .assert()
+            .failure();
+
+        Ok(())
+    }
+
+    // admin commands shouldn't create a store that doesn't exist.
+    #[test]
+    fn admin_missing_store() {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+
+        Command::cargo_bin("kvs-admin")
+            .unwrap()
+            .args(&["-s", "log", "-l", "log_dir", "stats"])
+            .current_dir(&temp_dir)
+            .assert()
+            .failure()
+            .stderr(contains("no store"));
+        assert!(!temp_dir.path().join("log_dir").exists());
+    }
+
+    // `kvs-admin compact` should refuse to run without --allow-writes.
+    #[test]
+    fn admin_compact_read_only() -> Result<()> {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+
+        let mut store = LogKvs::open(temp_dir.path().join("log_dir"))?;
+        store.set("key1".to_owned(), "value1".to_owned())?;
+        drop(store);
+
+        Command::cargo_bin("kvs-admin")
+            .unwrap()
+            .args(&["-s", "log", "-l", "log_dir", "compact"])
+            .current_dir(&temp_dir)
+            .assert()
+            .failure()
+            .stderr(contains("--allow-writes"));
+
+        Command::cargo_bin("kvs-admin")
+            .unwrap()
+            .args(&["-s", "log", "-l", "log_dir", "--allow-writes", "compact"])
+            .current_dir(&temp_dir)
+            .assert()
+            .success()
+            .stdout(is_empty());
+        let audit = AuditLog::for_store(temp_dir.path().join("log_dir"));
+        assert_eq!(audit.entries()?.len(), 1);
+        assert_eq!(audit.entries()?[0].operation, "compact");
+
+        Ok(())
+    }
+
+    // `kvs-admin compact` should be refused up front on a store that can't
+    // be compacted, even with --allow-writes.
+    #[test]
+    fn admin_compact_unsupported() -> Result<()> {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+
+        let mut store = HashMapKvs::open(temp_dir.path().join("kvs_file"))?;
+        store.set("key1".to_owned(), "value1".to_owned())?;
+        drop(store);
+
+        Command::cargo_bin("kvs-admin")
+            .unwrap()
+            .args(&["-l", "kvs_file", "--allow-writes", "compact"])
+            .current_dir(&temp_dir)
+            .assert()
+            .failure()
+            .stderr(contains("doesn't support"));
+
+        Ok(())
+    }
+
+    // `kvs-admin compact --dry-run` should report what compaction would
+    // reclaim, without needing --allow-writes or changing the store.
+    #[test]
+    fn admin_compact_dry_run() -> Result<()> {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+
+        let mut store = LogKvs::open(temp_dir.path().join("log_dir"))?;
+        store.set("key1".to_owned(), "value1".to_owned())?;
+        store.set("key1".to_owned(), "value2".to_owned())?;
+        let before = store.stats()?;
+        drop(store);
+
+        Command::cargo_bin("kvs-admin")
+            .unwrap()
+            .args(&["-s", "log", "-l", "log_dir", "compact", "--dry-run"])
+            .current_dir(&temp_dir)
+            .assert()
+            .success()
+            .stdout(contains(format!(
+                "would drop 1 stale records, reclaiming {} of {} bytes",
+                before.stale_bytes, before.disk_bytes
+            )));
+
+        let store = LogKvs::open(temp_dir.path().join("log_dir"))?;
+        assert_eq!(store.stats()?, before);
+
+        Ok(())
+    }
+
+    // `kvs-admin rm-prefix` should remove every key with the prefix, and
+    // only count them with --dry-run, which doesn't need --allow-writes.
+    #[test]
+    fn admin_rm_prefix() -> Result<()> {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+
+        for store in &["hashmap", "log"] {
+            let mut kvs = Kvs::builder()
+                .engine(store.parse()?)
+                .path(temp_dir.path().join(store))
+                .open_any()?;
+            kvs.set("session:1".to_owned(), "value1".to_owned())?;
+            kvs.set("session:2".to_owned(), "value2".to_owned())?;
+            kvs.set("user:1".to_owned(), "value3".to_owned())?;
+            drop(kvs);
+            let admin = || {
+                let mut cmd = Command::cargo_bin("kvs-admin").unwrap();
+                cmd.args(&["-s", store, "-l", store]).current_dir(&temp_dir);
+                cmd
+            };
+
+            admin()
+                .args(&["rm-prefix", "session:", "--dry-run"])
+                .assert()
+                .success()
+                .stdout(eq("would remove 2 keys\n"));
+            admin()
+                .args(&["rm-prefix", "session:"])
+                .assert()
+                .failure()
+                .stderr(contains("--allow-writes"));
+            admin()
+                .args(&["--allow-writes", "rm-prefix", "session:"])
+                .assert()
+                .success()
+                .stdout(eq("removed 2 keys\n"));
+
+            let kvs = Kvs::builder()
+                .engine(store.parse()?)
+                .path(temp_dir.path().join(store))
+                .open_any()?;
+            assert_eq!(kvs.keys()?, vec!["user:1".to_owned()]);
+            let audit = AuditLog::for_store(temp_dir.path().join(store));
+            assert_eq!(audit.entries()?[0].operation, "rm-prefix");
+        }
+
+        Ok(())
+    }
+
+    // `kvs-admin prune-backups` should remove all but the newest backups,
+    // and only with --allow-writes.
+    #[test]
+    fn admin_prune_backups() -> Result<()> {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+        let backups = temp_dir.path().join("backups");
+        std::fs::create_dir(&backups)?;
+        let store = Kvs::builder()
+            .engine(Engine::Log)
+            .path(temp_dir.path().join("log_dir"))
+            .open_any()?;
+        kvs::backup(&store, backups.join("old"))?;
+        std::thread::sleep(Duration::from_millis(20));
+        kvs::backup(&store, backups.join("new"))?;
+
+        Command::cargo_bin("kvs-admin")
+            .unwrap()
+            .args(&["-l", "log_dir", "prune-backups", "backups", "--keep", "1"])
+            .current_dir(&temp_dir)
+            .assert()
+            .failure()
+            .stderr(contains("--allow-writes"));
+        Command::cargo_bin("kvs-admin")
+            .unwrap()
+            .args(&["-l", "log_dir", "--allow-writes", "prune-backups"])
+            .args(&["backups", "--keep", "1"])
+            .current_dir(&temp_dir)
+            .assert()
+            .success()
+            .stdout(contains("removed").and(contains("old")));
+        assert!(!backups.join("old").exists());
+        assert!(backups.join("new").exists());
+
+        Ok(())
+    }
+}
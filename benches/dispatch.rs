@@ -0,0 +1,55 @@
+//! Compares calling a store through `Box<dyn KvStore>` with calling it
+//! through the `AnyKvs` enum.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use tempfile::TempDir;
+
+use kvs::{Engine, KvStore, Kvs};
+
+const KEYS: usize = 1000;
+
+fn keys() -> Vec<String> {
+    (0..KEYS).map(|i| format!("key{}", i)).collect()
+}
+
+fn get_all<S: KvStore + ?Sized>(store: &S, keys: &[String]) {
+    for key in keys {
+        store.get(key.clone()).unwrap();
+    }
+}
+
+fn dispatch(c: &mut Criterion) {
+    let mut group = c.benchmark_group("get");
+    let keys = keys();
+
+    for name in Engine::VARIANTS {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+        let builder = Kvs::builder()
+            .engine(name.parse().unwrap())
+            .path(temp_dir.path().join(name));
+
+        let mut store = builder.clone().open_any().unwrap();
+        for key in &keys {
+            store.set(key.clone(), key.clone()).unwrap();
+        }
+        group.bench_with_input(
+            BenchmarkId::new("AnyKvs", name),
+            &keys,
+            |b, keys| b.iter(|| get_all(&store, keys)),
+        );
+        drop(store);
+
+        let store = builder.open().unwrap();
+        group.bench_with_input(
+            BenchmarkId::new("Box<dyn KvStore>", name),
+            &keys,
+            |b, keys| b.iter(|| get_all(&store, keys)),
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, dispatch);
+criterion_main!(benches);
@@ -0,0 +1,75 @@
+//! Compares reading values with `get`, which copies each one, with
+//! `get_ref`, which can lend them out instead.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use tempfile::TempDir;
+
+use kvs::{AnyKvs, Capability, Engine, KvStore, Kvs};
+
+const KEYS: usize = 1000;
+
+fn keys() -> Vec<String> {
+    (0..KEYS).map(|i| format!("key{}", i)).collect()
+}
+
+fn get_all(store: &AnyKvs, keys: &[String]) {
+    for key in keys {
+        store.get(key.clone()).unwrap();
+    }
+}
+
+fn get_ref_all(store: &AnyKvs, keys: &[String]) {
+    for key in keys {
+        store.get_ref(key).unwrap();
+    }
+}
+
+fn get_ref(c: &mut Criterion) {
+    let mut group = c.benchmark_group("get_ref");
+    let keys = keys();
+    // long enough that copying it is a noticeable part of a read
+    let value = "value".repeat(200);
+
+    let mut stores = Vec::new();
+    for name in Engine::VARIANTS {
+        stores.push((
+            name.to_string(),
+            Kvs::builder().engine(name.parse().unwrap()),
+        ));
+    }
+    // reads go to the disk instead of the page cache
+    if Engine::Log.supports(Capability::DirectIo) {
+        stores.push((
+            "log-direct".to_owned(),
+            Kvs::builder().engine(Engine::Log).direct_io(),
+        ));
+    }
+
+    for (name, builder) in stores {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+        let mut store = builder
+            .path(temp_dir.path().join(&name))
+            .open_any()
+            .unwrap();
+        for key in &keys {
+            store.set(key.clone(), value.clone()).unwrap();
+        }
+
+        group.bench_with_input(
+            BenchmarkId::new("get", &name),
+            &keys,
+            |b, keys| b.iter(|| get_all(&store, keys)),
+        );
+        group.bench_with_input(
+            BenchmarkId::new("get_ref", &name),
+            &keys,
+            |b, keys| b.iter(|| get_ref_all(&store, keys)),
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, get_ref);
+criterion_main!(benches);
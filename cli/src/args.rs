@@ -1,44 +1,413 @@
 use std::path::PathBuf;
 
+use kvs::{ConflictPolicy, Engine, SyncPolicy};
+use serde::Deserialize;
+use structopt::clap::Shell;
 use structopt::StructOpt;
 use strum_macros::{Display, EnumString};
 
 #[derive(Debug, StructOpt)]
+#[structopt(after_help = "EXIT CODES:
+    0     Success. Also used for missing keys unless --strict is given.
+    1     The key was not found (with --strict, or by `exists`).
+    2     The script given to `run` was invalid or an assertion failed.
+    3     `merge --on-conflict fail` found conflicting values.
+    64    The command line arguments were invalid, or a write was rejected.
+    65    The store could not be decoded or is corrupt.
+    74    The store, or a file given to the command, could not be read from
+          or written to.
+    75    The store is already open for writing by another process.
+    78    The config file could not be loaded.")]
 pub(crate) struct Opt {
-    /// Which type of backing store to use.
-    #[structopt(short, long, default_value = "hashmap")]
-    pub(crate) store: Store,
-    /// The location to load and save the backing store.
+    /// Which type of backing store to use [default: hashmap].
     #[structopt(
         short,
         long,
-        parse(from_os_str),
-        default_value = "../target/store"
+        env = "KVS_STORE",
+        possible_values = Store::VARIANTS
     )]
-    pub(crate) location: PathBuf,
+    pub(crate) store: Option<Store>,
+    /// The location to load and save the backing store [default:
+    /// ../target/store].
+    #[structopt(short, long, env = "KVS_LOCATION", parse(from_os_str))]
+    pub(crate) location: Option<PathBuf>,
+    /// When writes are synced to disk [default: never].
+    #[structopt(long, env = "KVS_SYNC", possible_values = SyncMode::VARIANTS)]
+    pub(crate) sync: Option<SyncMode>,
+    /// A TOML config file to read settings from. Flags and environment
+    /// variables take precedence over the values it contains.
+    #[structopt(short, long, env = "KVS_CONFIG", parse(from_os_str))]
+    pub(crate) config: Option<PathBuf>,
+    /// Report missing keys on stderr and exit with a non-zero code instead
+    /// of treating them as a success.
+    #[structopt(long)]
+    pub(crate) strict: bool,
+    /// Keys and values given as arguments are hex encoded, and values are
+    /// printed hex encoded.
+    #[structopt(long, conflicts_with = "base64")]
+    pub(crate) hex: bool,
+    /// Keys and values given as arguments are base64 encoded, and values
+    /// are printed base64 encoded.
+    #[structopt(long)]
+    pub(crate) base64: bool,
+    /// Count how often each key is read and written, in a `.keystats` file
+    /// next to the store, for `hotkeys` to report.
+    #[structopt(long)]
+    pub(crate) track_keys: bool,
+    /// Open the log store read-only, so it can be read while another
+    /// process has it open for writing. Commands that write fail.
+    #[structopt(long)]
+    pub(crate) read_only: bool,
+    /// Record each get, set and remove made to the store, with the time it
+    /// was made, by appending it to this file, for `replay` to run again.
+    #[structopt(long, env = "KVS_CAPTURE", parse(from_os_str))]
+    pub(crate) capture: Option<PathBuf>,
     #[structopt(subcommand)]
     pub(crate) command: Command,
 }
 
 // TODO: update strum to version 0.16 when it is released and derive
 // EnumVariantNames
-#[derive(Debug, Display, EnumString, StructOpt)]
+#[derive(Clone, Copy, Debug, Display, EnumString, StructOpt, Deserialize)]
 pub(crate) enum Store {
     /// Use a hashmap backed to the given file location.
     #[strum(serialize = "hashmap")]
+    #[serde(rename = "hashmap")]
     HashMap,
     /// Use an append-only log store backed in the given directory location.
     #[strum(serialize = "log")]
+    #[serde(rename = "log")]
     Log,
 }
 
+impl Store {
+    /// The names each variant is parsed from.
+    pub(crate) const VARIANTS: &'static [&'static str] = &["hashmap", "log"];
+}
+
+impl From<Store> for Engine {
+    fn from(store: Store) -> Engine {
+        match store {
+            Store::HashMap => Engine::HashMap,
+            Store::Log => Engine::Log,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Display, EnumString, Deserialize)]
+pub(crate) enum SyncMode {
+    /// Sync every write to disk before the command finishes.
+    #[strum(serialize = "always")]
+    #[serde(rename = "always")]
+    Always,
+    /// Let the store decide when writes reach disk.
+    #[strum(serialize = "never")]
+    #[serde(rename = "never")]
+    Never,
+}
+
+impl SyncMode {
+    /// The names each variant is parsed from.
+    pub(crate) const VARIANTS: &'static [&'static str] = &["always", "never"];
+}
+
+impl From<SyncMode> for SyncPolicy {
+    fn from(sync: SyncMode) -> SyncPolicy {
+        match sync {
+            SyncMode::Always => SyncPolicy::Always,
+            SyncMode::Never => SyncPolicy::Never,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Display, EnumString)]
+pub(crate) enum OnConflict {
+    /// Take the value from the last source that has the key.
+    #[strum(serialize = "prefer-source")]
+    PreferSource,
+    /// Write nothing if any key has different values.
+    #[strum(serialize = "fail")]
+    Fail,
+}
+
+impl OnConflict {
+    /// The names each variant is parsed from.
+    pub(crate) const VARIANTS: &'static [&'static str] =
+        &["prefer-source", "fail"];
+}
+
+impl From<OnConflict> for ConflictPolicy {
+    fn from(on_conflict: OnConflict) -> ConflictPolicy {
+        match on_conflict {
+            OnConflict::PreferSource => ConflictPolicy::PreferSource,
+            OnConflict::Fail => ConflictPolicy::Fail,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Display, EnumString)]
+pub(crate) enum ImportFormat {
+    /// A Redis append-only file of `SET`, `MSET` and `DEL` commands.
+    #[strum(serialize = "aof")]
+    Aof,
+    /// A Redis RDB dump holding only strings.
+    #[strum(serialize = "rdb")]
+    Rdb,
+    /// Comma separated values, a key and value from each row.
+    #[strum(serialize = "csv")]
+    Csv,
+    /// A JSON object on each line, holding a key and value.
+    #[strum(serialize = "jsonl")]
+    Jsonl,
+}
+
+impl ImportFormat {
+    /// The names each variant is parsed from.
+    pub(crate) const VARIANTS: &'static [&'static str] =
+        &["aof", "rdb", "csv", "jsonl"];
+}
+
+/// Where to find the key and value in each CSV row or JSON object.
+#[derive(Debug, StructOpt)]
+pub(crate) struct ImportFields {
+    /// The CSV column holding each key, counting from 0.
+    #[structopt(long, default_value = "0")]
+    pub(crate) key_col: usize,
+    /// The CSV column holding each value, counting from 0.
+    #[structopt(long, default_value = "1")]
+    pub(crate) value_col: usize,
+    /// Skip the first row of a CSV file.
+    #[structopt(long)]
+    pub(crate) headers: bool,
+    /// The JSON field holding each key.
+    #[structopt(long, default_value = "key")]
+    pub(crate) key_field: String,
+    /// The JSON field holding each value. Values that aren't strings are
+    /// stored as JSON.
+    #[structopt(long, default_value = "value")]
+    pub(crate) value_field: String,
+}
+
+#[derive(Clone, Copy, Debug, Display, EnumString)]
+pub(crate) enum ExportFormat {
+    /// A Redis append-only file with a `SET` command for each key.
+    #[strum(serialize = "aof")]
+    Aof,
+}
+
+impl ExportFormat {
+    /// The names each variant is parsed from.
+    pub(crate) const VARIANTS: &'static [&'static str] = &["aof"];
+}
+
+/// The subcommands. The ones that only need the store at --location to be
+/// opened for them are kept apart, in a `StoreCommand`.
 #[derive(Debug, Display, StructOpt)]
 pub(crate) enum Command {
+    #[structopt(flatten)]
+    Store(StoreCommand),
+    #[structopt(name = "run")]
+    /// Run each command in a script against the key-value store.
+    Run {
+        /// The script to run. Supports `set`, `rm`, `assert-get`, `begin`
+        /// and `commit`, one per line.
+        #[structopt(parse(from_os_str))]
+        script: PathBuf,
+    },
+    #[structopt(name = "diff")]
+    /// Compare two existing stores, printing `- <key>` for keys only in the
+    /// first, `+ <key>` for keys only in the second and `~ <key>` for keys
+    /// whose values differ. Ignores --location.
+    Diff {
+        /// The first store.
+        #[structopt(parse(from_os_str))]
+        a: PathBuf,
+        /// The second store.
+        #[structopt(parse(from_os_str))]
+        b: PathBuf,
+        /// The type of the second store, if it isn't the same as --store.
+        #[structopt(long, possible_values = Store::VARIANTS)]
+        b_store: Option<Store>,
+        /// Print the differences as a JSON object instead.
+        #[structopt(long)]
+        json: bool,
+    },
+    #[structopt(name = "merge")]
+    /// Copy every key from existing stores into another, in order, printing
+    /// `~ <key>` for keys with conflicting values. Ignores --location.
+    Merge {
+        /// The stores to copy from, followed by the store to copy into.
+        #[structopt(parse(from_os_str), required = true, min_values = 2)]
+        stores: Vec<PathBuf>,
+        /// What to do when a key has different values in different stores.
+        #[structopt(
+            long,
+            default_value = "prefer-source",
+            possible_values = OnConflict::VARIANTS
+        )]
+        on_conflict: OnConflict,
+    },
+    #[structopt(name = "restore")]
+    /// Check a backup against its manifest, then copy it to --location,
+    /// which mustn't exist yet.
+    Restore {
+        /// The directory the backup was written to.
+        #[structopt(parse(from_os_str))]
+        dir: PathBuf,
+        /// Check the backup and print what would be copied, without copying
+        /// it.
+        #[structopt(long)]
+        dry_run: bool,
+    },
+    #[structopt(name = "verify-backup")]
+    /// Check a backup directory, or an exported file, against its manifest,
+    /// printing each problem found. Ignores --location.
+    VerifyBackup {
+        /// The backup directory or exported file.
+        #[structopt(parse(from_os_str))]
+        path: PathBuf,
+    },
+    #[structopt(name = "repair")]
+    /// Rebuild a damaged log store at --location from whatever records in
+    /// its log can still be read, ignoring its index files. Prints what was
+    /// salvaged and the byte ranges skipped, and writes the same report to
+    /// REPAIR in the store's directory.
+    Repair,
+    #[structopt(name = "hotkeys")]
+    /// Print the keys read and written most while --track-keys was given,
+    /// most used first.
+    Hotkeys {
+        /// How many keys to print.
+        #[structopt(short, default_value = "10")]
+        n: usize,
+    },
+    #[structopt(name = "audit")]
+    /// Print the administrative operations recorded for the key-value
+    /// store, such as imports, merges and restores, oldest first. Each is
+    /// printed as its Unix time, principal, operation and detail.
+    Audit,
+    #[structopt(name = "completions")]
+    /// Print a completion script for the given shell.
+    Completions {
+        /// The shell to generate completions for.
+        #[structopt(possible_values = &Shell::variants())]
+        shell: Shell,
+    },
+}
+
+/// The subcommands run against the store at --location once it's open.
+#[derive(Debug, Display, StructOpt)]
+pub(crate) enum StoreCommand {
+    #[structopt(flatten)]
+    Key(KeyCommand),
+    #[structopt(name = "import")]
+    /// Add every key in a file to the key-value store. If there's a
+    /// `<file>.manifest` next to it, the file is checked against it first.
+    Import {
+        /// The file to read.
+        #[structopt(parse(from_os_str))]
+        file: PathBuf,
+        /// The format of the file.
+        #[structopt(long, possible_values = ImportFormat::VARIANTS)]
+        format: ImportFormat,
+        #[structopt(flatten)]
+        fields: ImportFields,
+    },
+    #[structopt(name = "export")]
+    /// Write every key in the key-value store to a file, along with a
+    /// `<file>.manifest` holding its size and digest.
+    Export {
+        /// The file to write, which is replaced if it exists.
+        #[structopt(parse(from_os_str))]
+        file: PathBuf,
+        /// The format of the file.
+        #[structopt(
+            long,
+            default_value = "aof",
+            possible_values = ExportFormat::VARIANTS
+        )]
+        format: ExportFormat,
+    },
+    #[structopt(name = "digest")]
+    /// Print a Merkle tree digest of the keys and values in a range, and how
+    /// many keys it covers. Stores holding the same keys and values have the
+    /// same digest, whatever their engine.
+    Digest {
+        /// The first key in the range.
+        #[structopt(long, default_value = "")]
+        start: String,
+        /// The key the range stops before. Goes to the last key if not given.
+        #[structopt(long)]
+        end: Option<String>,
+    },
+    #[structopt(name = "scan")]
+    /// Print the keys in a range in order, one per line. Needs a store that
+    /// keeps its keys in order, or can sort them.
+    Scan {
+        /// The first key in the range.
+        #[structopt(long, default_value = "")]
+        start: String,
+        /// The key the range stops before. Goes to the last key if not given.
+        #[structopt(long)]
+        end: Option<String>,
+        /// Start from the last key in the range and go backwards.
+        #[structopt(long)]
+        reverse: bool,
+        /// How many keys to skip before printing any.
+        #[structopt(long, default_value = "0")]
+        offset: usize,
+        /// The most keys to print.
+        #[structopt(long)]
+        limit: Option<usize>,
+    },
+    #[structopt(name = "query")]
+    /// Print the fields each matching key selects, one tab separated row of
+    /// JSON per key, such as `SELECT key WHERE value.age > 30`. See
+    /// `kvs::Query` for the syntax.
+    Query {
+        /// The query to run.
+        query: String,
+    },
+    #[structopt(name = "backup")]
+    /// Copy the key-value store into a new directory, along with a manifest
+    /// of the size and digest of each file.
+    Backup {
+        /// The directory to create.
+        #[structopt(parse(from_os_str))]
+        dir: PathBuf,
+        /// Add to a chain of backups in the directory instead, holding only
+        /// what was written since the last one. Needs the log store.
+        #[structopt(long)]
+        incremental: bool,
+    },
+    #[structopt(name = "replay")]
+    /// Run the operations recorded with --capture against a new store at
+    /// --location, which mustn't exist yet, paced as they were captured.
+    Replay {
+        /// The capture file to run.
+        #[structopt(parse(from_os_str))]
+        file: PathBuf,
+        /// How many times faster than they were captured to run the
+        /// operations.
+        #[structopt(long, default_value = "1.0")]
+        speed: f64,
+        /// Run the operations as fast as possible, ignoring --speed.
+        #[structopt(long)]
+        unpaced: bool,
+    },
+}
+
+/// The subcommands that read or write a single key.
+#[derive(Debug, Display, StructOpt)]
+pub(crate) enum KeyCommand {
     #[structopt(name = "get")]
     /// Retrieve a value from the key-value store.
     Get {
         /// The item to retreive the value of.
         key: String,
+        /// Write the value to this file as is, instead of printing it.
+        #[structopt(long, parse(from_os_str))]
+        output_file: Option<PathBuf>,
     },
     #[structopt(name = "set")]
     /// Add a value to the key-value store.
@@ -46,7 +415,26 @@ pub(crate) enum Command {
         /// The name to store the value under.
         key: String,
         /// The value to store.
-        value: String,
+        #[structopt(required_unless_one = &["value-file", "stdin"])]
+        value: Option<String>,
+        /// Read the value to store from this file, as is.
+        #[structopt(
+            long,
+            parse(from_os_str),
+            conflicts_with_all = &["value", "stdin"]
+        )]
+        value_file: Option<PathBuf>,
+        /// Read the value to store from standard input, as is. The log store
+        /// writes it out as it's read instead of holding it in memory.
+        #[structopt(long, conflicts_with = "value")]
+        stdin: bool,
+    },
+    #[structopt(name = "exists")]
+    /// Check whether a key has a value, printing nothing. Exits with 0 if it
+    /// does and 1 if it doesn't.
+    Exists {
+        /// The item to look for.
+        key: String,
     },
     #[structopt(name = "rm")]
     /// Remove a value from the key-value store.
@@ -54,7 +442,4 @@ pub(crate) enum Command {
         /// The item to delete.
         key: String,
     },
-    #[structopt(name = "compact")]
-    /// Compact the key-value store's storage.
-    Compact,
 }
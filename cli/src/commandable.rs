@@ -1,49 +1,79 @@
-use core::{Compactable, KvStore, Result};
-use hashmap_kvs::HashMapKvs;
-use log_kvs::LogKvs;
+use kvs::{KvStore, Result};
 
-use crate::args::Command;
+use crate::args::KeyCommand;
+
+/// The result of a command that completed without an error.
+#[derive(Debug, Eq, PartialEq)]
+pub(crate) enum Outcome {
+    /// The command did what it was asked to.
+    Success,
+    /// The command found a value to show the user.
+    Found(String),
+    /// The command was given a key that doesn't exist. In strict mode this
+    /// is a `KeyDoesNotExist` error instead.
+    KeyNotFound,
+    /// The command checked for a key that doesn't exist.
+    Absent,
+}
 
 pub(crate) trait Commandable: KvStore {
-    fn execute_get(&self, key: String) -> Result<()> {
-        let value = self.get(key)?;
-        match value {
-            Some(value) => println!("{}", value),
-            None => println!("Key not found"),
-        };
-        Ok(())
+    fn execute_get(&self, key: String, strict: bool) -> Result<Outcome> {
+        if strict {
+            return self.get_strict(key).map(Outcome::Found);
+        }
+        match self.get(key)? {
+            Some(value) => Ok(Outcome::Found(value)),
+            None => Ok(Outcome::KeyNotFound),
+        }
     }
 
-    fn execute_set(&mut self, key: String, value: String) -> Result<()> {
-        self.set(key, value)
+    fn execute_set(&mut self, key: String, value: String) -> Result<Outcome> {
+        self.set(key, value)?;
+        Ok(Outcome::Success)
     }
 
-    fn execute_rm(&mut self, key: String) -> Result<()> {
-        if self.remove(key.clone())?.is_none() {
-            println!("Key not found");
+    fn execute_rm(&mut self, key: String, strict: bool) -> Result<Outcome> {
+        if strict {
+            self.remove_strict(key)?;
+            return Ok(Outcome::Success);
+        }
+        match self.remove(key)? {
+            Some(_) => Ok(Outcome::Success),
+            None => Ok(Outcome::KeyNotFound),
         }
-        Ok(())
-    }
-
-    fn execute_compact(&mut self) -> Result<()> {
-        println!("Compaction not supported on this type of store.");
-        Ok(())
     }
 
-    fn execute(&mut self, command: Command) -> Result<()> {
+    /// Run the command once `Encoding::decode_command` has read any value
+    /// file into the value, so a `set` without a value reads it from
+    /// standard input.
+    fn execute(
+        &mut self,
+        command: KeyCommand,
+        strict: bool,
+    ) -> Result<Outcome> {
         match command {
-            Command::Get { key } => self.execute_get(key),
-            Command::Set { key, value } => self.execute_set(key, value),
-            Command::Remove { key } => self.execute_rm(key),
-            Command::Compact => self.execute_compact(),
+            KeyCommand::Get { key, .. } => self.execute_get(key, strict),
+            KeyCommand::Set {
+                key,
+                value: Some(value),
+                ..
+            } => self.execute_set(key, value),
+            KeyCommand::Set {
+                key, value: None, ..
+            } => {
+                self.set_from_reader(key, &mut std::io::stdin().lock())?;
+                Ok(Outcome::Success)
+            }
+            KeyCommand::Exists { key } => {
+                if self.contains_key(&key)? {
+                    Ok(Outcome::Success)
+                } else {
+                    Ok(Outcome::Absent)
+                }
+            }
+            KeyCommand::Remove { key } => self.execute_rm(key, strict),
         }
     }
 }
 
-impl Commandable for HashMapKvs {}
-
-impl Commandable for LogKvs {
-    fn execute_compact(&mut self) -> Result<()> {
-        self.compact()
-    }
-}
+impl<S: KvStore + ?Sized> Commandable for S {}
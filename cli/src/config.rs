@@ -0,0 +1,78 @@
+/*!
+ * Settings resolved from the config file, environment variables, and
+ * command line flags.
+ */
+
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use kvs::{Error, Result, SyncPolicy};
+
+use crate::args::{Opt, Store, SyncMode};
+
+/// The settings that can be given in a config file. Every field is optional
+/// so a file only needs to contain what it wants to change.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Config {
+    pub(crate) store: Option<Store>,
+    pub(crate) location: Option<PathBuf>,
+    pub(crate) sync: Option<SyncMode>,
+    pub(crate) capture: Option<PathBuf>,
+}
+
+impl Config {
+    /// Read a config from the given TOML file.
+    pub(crate) fn load<P: AsRef<Path>>(path: P) -> Result<Config> {
+        let contents = std::fs::read_to_string(path)?;
+        toml::from_str(&contents).map_err(|err| {
+            Error::serialization(format!("invalid config file: {}", err))
+        })
+    }
+}
+
+/// The settings used to open the store, after layering flags over environment
+/// variables over the config file over the defaults.
+#[derive(Debug)]
+pub(crate) struct Settings {
+    pub(crate) store: Store,
+    pub(crate) location: PathBuf,
+    pub(crate) sync: SyncPolicy,
+    pub(crate) capture: Option<PathBuf>,
+}
+
+impl Settings {
+    const DEFAULT_STORE: Store = Store::HashMap;
+    const DEFAULT_LOCATION: &'static str = "../target/store";
+
+    /// Resolve the settings to use. Environment variables have already been
+    /// folded into the flags by structopt, so only the config file and
+    /// defaults are left to layer underneath them.
+    pub(crate) fn resolve(opt: &mut Opt) -> Result<Settings> {
+        let config = match opt.config {
+            Some(ref path) => Config::load(path)?,
+            None => Config::default(),
+        };
+
+        Ok(Settings {
+            store: opt
+                .store
+                .take()
+                .or(config.store)
+                .unwrap_or(Self::DEFAULT_STORE),
+            location: opt
+                .location
+                .take()
+                .or(config.location)
+                .unwrap_or_else(|| PathBuf::from(Self::DEFAULT_LOCATION)),
+            sync: opt
+                .sync
+                .take()
+                .or(config.sync)
+                .map(SyncPolicy::from)
+                .unwrap_or_default(),
+            capture: opt.capture.take().or(config.capture),
+        })
+    }
+}
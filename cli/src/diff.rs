@@ -0,0 +1,69 @@
+/*!
+ * Comparing two stores for `cli diff`.
+ */
+
+use std::path::Path;
+
+use serde::Serialize;
+
+use kvs::{AnyKvs, Kvs, StoreDiff};
+
+use crate::args::Store;
+use crate::encoding::Encoding;
+use crate::errors::CliError;
+
+/// Open a store to compare, without creating it if it's missing.
+pub(crate) fn open_existing(
+    store: Store,
+    path: &Path,
+) -> Result<AnyKvs, CliError> {
+    if !path.exists() {
+        return Err(CliError::File(format!("no store at {}", path.display())));
+    }
+    Kvs::builder()
+        .engine(store.into())
+        .path(path)
+        .open_any()
+        .map_err(CliError::Store)
+}
+
+/// The differences between two stores, with the keys encoded for printing.
+#[derive(Debug, Serialize)]
+pub(crate) struct DiffReport {
+    only_in_a: Vec<String>,
+    only_in_b: Vec<String>,
+    changed: Vec<String>,
+}
+
+impl DiffReport {
+    pub(crate) fn new(diff: StoreDiff, encoding: Encoding) -> DiffReport {
+        let encode = |keys: Vec<String>| {
+            keys.iter().map(|key| encoding.encode(key)).collect()
+        };
+        DiffReport {
+            only_in_a: encode(diff.only_in_a),
+            only_in_b: encode(diff.only_in_b),
+            changed: encode(diff.changed),
+        }
+    }
+
+    pub(crate) fn print(&self, json: bool) {
+        if json {
+            println!(
+                "{}",
+                serde_json::to_string(self)
+                    .expect("keys are always valid JSON")
+            );
+            return;
+        }
+        for key in &self.only_in_a {
+            println!("- {}", key);
+        }
+        for key in &self.only_in_b {
+            println!("+ {}", key);
+        }
+        for key in &self.changed {
+            println!("~ {}", key);
+        }
+    }
+}
@@ -0,0 +1,112 @@
+/*!
+ * Reading and writing the whole store as a file, for `import` and `export`,
+ * and checking those files and backups against their manifests.
+ */
+
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::Path;
+
+use kvs::{AnyKvs, Error, ErrorKind, Manifest};
+
+use crate::args::{ExportFormat, ImportFields, ImportFormat};
+use crate::errors::CliError;
+
+/// Add every key in the file to the store, returning how many commands or
+/// keys were applied.
+pub(crate) fn import(
+    store: &mut AnyKvs,
+    path: &Path,
+    format: ImportFormat,
+    fields: &ImportFields,
+) -> Result<u64, CliError> {
+    let manifest_path = Manifest::path_for_file(path);
+    if manifest_path.exists() {
+        let problems = verify_file(path)?;
+        if !problems.is_empty() {
+            return Err(CliError::Store(Error::corrupt_database(format!(
+                "{} failed verification: {}",
+                path.display(),
+                problems.join(", ")
+            ))));
+        }
+    }
+
+    let file = File::open(path).map_err(|err| {
+        CliError::File(format!("unable to read {}: {}", path.display(), err))
+    })?;
+    let mut reader = BufReader::new(file);
+    match format {
+        ImportFormat::Aof => kvs::import_aof(&mut reader, store),
+        ImportFormat::Rdb => kvs::import_rdb(&mut reader, store),
+        ImportFormat::Csv => kvs::import_csv(
+            &mut reader,
+            store,
+            fields.key_col,
+            fields.value_col,
+            fields.headers,
+        ),
+        ImportFormat::Jsonl => kvs::import_jsonl(
+            &mut reader,
+            store,
+            &fields.key_field,
+            &fields.value_field,
+        ),
+    }
+    .map_err(|err| match err.kind() {
+        ErrorKind::Serde { message } => CliError::File(format!(
+            "unable to import {}: {}",
+            path.display(),
+            message
+        )),
+        _ => CliError::Store(err),
+    })
+}
+
+/// Write every key in the store to the file, followed by its manifest,
+/// returning how many keys were written.
+pub(crate) fn export(
+    store: &AnyKvs,
+    path: &Path,
+    format: ExportFormat,
+) -> Result<u64, CliError> {
+    let file = File::create(path).map_err(|err| {
+        CliError::File(format!("unable to write {}: {}", path.display(), err))
+    })?;
+    let mut writer = BufWriter::new(file);
+    let count = match format {
+        ExportFormat::Aof => {
+            kvs::export_aof(store, &mut writer).map_err(CliError::Store)?
+        }
+    };
+    writer.flush().map_err(|err| CliError::Store(err.into()))?;
+    drop(writer);
+
+    Manifest::of_file(path)
+        .and_then(|manifest| manifest.save(Manifest::path_for_file(path)))
+        .map_err(CliError::Store)?;
+    Ok(count)
+}
+
+/// Check a backup directory, or an exported file, against its manifest,
+/// returning a description of each problem found.
+pub(crate) fn verify(path: &Path) -> Result<Vec<String>, CliError> {
+    if path.is_dir() {
+        kvs::verify_backup(path).map_err(CliError::Store)
+    } else {
+        verify_file(path)
+    }
+}
+
+fn verify_file(path: &Path) -> Result<Vec<String>, CliError> {
+    let manifest_path = Manifest::path_for_file(path);
+    let manifest = Manifest::load(&manifest_path).map_err(|err| {
+        CliError::File(format!(
+            "unable to read {}: {}",
+            manifest_path.display(),
+            err
+        ))
+    })?;
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    manifest.verify(dir).map_err(CliError::Store)
+}
@@ -0,0 +1,140 @@
+/*!
+ * How keys and values are written on the command line and printed, so
+ * values with newlines or control characters can be passed around safely.
+ */
+
+use std::fs;
+use std::path::Path;
+
+use crate::args::{KeyCommand, StoreCommand};
+use crate::errors::CliError;
+
+/// The encoding used for keys and values in arguments and output.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum Encoding {
+    /// Used as is.
+    Raw,
+    /// Hex encoded bytes.
+    Hex,
+    /// Base64 encoded bytes, using the standard alphabet.
+    Base64,
+}
+
+impl Encoding {
+    /// Pick the encoding from the `--hex` and `--base64` flags.
+    pub(crate) fn from_flags(hex: bool, base64: bool) -> Encoding {
+        match (hex, base64) {
+            (true, _) => Encoding::Hex,
+            (false, true) => Encoding::Base64,
+            (false, false) => Encoding::Raw,
+        }
+    }
+
+    /// Decode an argument. Stores only hold UTF-8, so the decoded bytes must
+    /// be valid UTF-8.
+    pub(crate) fn decode(self, arg: String) -> Result<String, CliError> {
+        let bytes = match self {
+            Encoding::Raw => return Ok(arg),
+            Encoding::Hex => hex::decode(&arg).map_err(|err| {
+                CliError::Input(format!("`{}` isn't valid hex: {}", arg, err))
+            })?,
+            Encoding::Base64 => base64::decode(&arg).map_err(|err| {
+                CliError::Input(format!(
+                    "`{}` isn't valid base64: {}",
+                    arg, err
+                ))
+            })?,
+        };
+        String::from_utf8(bytes).map_err(|_| {
+            CliError::Input(format!("`{}` doesn't decode to UTF-8", arg))
+        })
+    }
+
+    /// Encode a value to be printed.
+    pub(crate) fn encode(self, value: &str) -> String {
+        match self {
+            Encoding::Raw => value.to_owned(),
+            Encoding::Hex => hex::encode(value),
+            Encoding::Base64 => base64::encode(value),
+        }
+    }
+
+    /// Decode the keys and values in a command, and read any value file, so
+    /// the command can be run as is. A `set` is left with either its value
+    /// or, if it's to be read from standard input, none.
+    pub(crate) fn decode_command(
+        self,
+        command: StoreCommand,
+    ) -> Result<StoreCommand, CliError> {
+        Ok(match command {
+            StoreCommand::Key(command) => {
+                StoreCommand::Key(self.decode_key_command(command)?)
+            }
+            StoreCommand::Digest { start, end } => StoreCommand::Digest {
+                start: self.decode(start)?,
+                end: end.map(|end| self.decode(end)).transpose()?,
+            },
+            StoreCommand::Scan {
+                start,
+                end,
+                reverse,
+                offset,
+                limit,
+            } => StoreCommand::Scan {
+                start: self.decode(start)?,
+                end: end.map(|end| self.decode(end)).transpose()?,
+                reverse,
+                offset,
+                limit,
+            },
+            command => command,
+        })
+    }
+
+    fn decode_key_command(
+        self,
+        command: KeyCommand,
+    ) -> Result<KeyCommand, CliError> {
+        Ok(match command {
+            KeyCommand::Get { key, output_file } => KeyCommand::Get {
+                key: self.decode(key)?,
+                output_file,
+            },
+            KeyCommand::Set {
+                key,
+                value,
+                value_file,
+                stdin,
+            } => {
+                // clap requires exactly one of a value, --value-file or
+                // --stdin, so with neither of the first two it's --stdin
+                let value = match (value, value_file) {
+                    (Some(value), _) => Some(self.decode(value)?),
+                    (None, Some(path)) => Some(read_value_file(&path)?),
+                    (None, None) => None,
+                };
+                KeyCommand::Set {
+                    key: self.decode(key)?,
+                    value,
+                    value_file: None,
+                    stdin,
+                }
+            }
+            KeyCommand::Exists { key } => KeyCommand::Exists {
+                key: self.decode(key)?,
+            },
+            KeyCommand::Remove { key } => KeyCommand::Remove {
+                key: self.decode(key)?,
+            },
+        })
+    }
+}
+
+/// Read a value file as is, since the encoding only applies to arguments.
+fn read_value_file(path: &Path) -> Result<String, CliError> {
+    let value = fs::read(path).map_err(|err| {
+        CliError::File(format!("unable to read {}: {}", path.display(), err))
+    })?;
+    String::from_utf8(value)
+        .map_err(|_| CliError::Input(format!("{} isn't UTF-8", path.display())))
+}
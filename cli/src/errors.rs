@@ -0,0 +1,172 @@
+/*!
+ * Rendering of errors into messages and exit codes for the user.
+ */
+
+use std::fmt;
+
+use kvs::{Error, ErrorKind};
+
+use crate::script::ScriptError;
+
+/// The exit codes used by the cli. Failures follow the BSD `sysexits.h`
+/// convention so scripts can tell them apart.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum ExitCode {
+    /// The command completed successfully.
+    Success = 0,
+    /// The key given to the command does not exist. Only used in strict
+    /// mode, and by `exists`.
+    KeyNotFound = 1,
+    /// The script given to `run` was invalid or one of its assertions
+    /// failed.
+    ScriptFailed = 2,
+    /// `merge` found conflicting values and was told to fail on them.
+    Conflict = 3,
+    /// The command line arguments were invalid, or a write was rejected
+    /// by a check the store was set up with.
+    Usage = 64,
+    /// The store's contents could not be understood.
+    CorruptStore = 65,
+    /// The store, or a file given to the command, could not be read from or
+    /// written to.
+    Io = 74,
+    /// The store is already open for writing by another process.
+    Locked = 75,
+    /// The config file could not be read or is invalid.
+    Config = 78,
+}
+
+impl ExitCode {
+    /// Exit the process with this code.
+    pub(crate) fn exit(self) -> ! {
+        std::process::exit(self as i32)
+    }
+}
+
+/// An error that stopped the cli, tagged with the stage it happened in.
+#[derive(Debug)]
+pub(crate) enum CliError {
+    /// Failed while loading the configuration.
+    Config(Error),
+    /// Failed while opening or operating on the store.
+    Store(Error),
+    /// Failed while loading or checking a script.
+    Script(ScriptError),
+    /// A key or value given to the command couldn't be decoded.
+    Input(String),
+    /// A file given to the command couldn't be read or written.
+    File(String),
+}
+
+impl CliError {
+    /// The exit code the process should finish with.
+    pub(crate) fn exit_code(&self) -> ExitCode {
+        match self {
+            CliError::Config(_) => ExitCode::Config,
+            CliError::Script(_) => ExitCode::ScriptFailed,
+            CliError::Input(_) => ExitCode::Usage,
+            CliError::File(_) => ExitCode::Io,
+            CliError::Store(err) => match err.kind() {
+                ErrorKind::Io { .. } => ExitCode::Io,
+                ErrorKind::Serde { .. } | ErrorKind::CorruptDatabase { .. } => {
+                    ExitCode::CorruptStore
+                }
+                ErrorKind::Config { .. } => ExitCode::Config,
+                ErrorKind::Locked { .. } => ExitCode::Locked,
+                ErrorKind::Unsupported { .. } | ErrorKind::Rejected { .. } => {
+                    ExitCode::Usage
+                }
+                ErrorKind::KeyDoesNotExist { .. } => ExitCode::KeyNotFound,
+                _ => ExitCode::Io,
+            },
+        }
+    }
+
+    /// A hint telling the user what they can do about the error, if there is
+    /// one.
+    fn hint(&self) -> Option<&'static str> {
+        match self {
+            CliError::Config(_) => Some(
+                "check the file given by --config or KVS_CONFIG; only \
+                 `store`, `location`, `sync` and `capture` are supported",
+            ),
+            CliError::Store(err) => match err.kind() {
+                ErrorKind::Io { .. } => Some(
+                    "check that --location points somewhere you can read and \
+                     write",
+                ),
+                ErrorKind::Serde { .. } => Some(
+                    "check that --store matches the type of store saved at \
+                     --location",
+                ),
+                ErrorKind::Locked { .. } => Some(
+                    "wait for the other process to close the store, or pass \
+                     --read-only",
+                ),
+                _ => None,
+            },
+            CliError::Script(_) | CliError::File(_) => None,
+            CliError::Input(_) => Some(
+                "values are stored as UTF-8 text, so only UTF-8 can be stored \
+                 even with --hex or --base64",
+            ),
+        }
+    }
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CliError::Config(err) => {
+                write!(f, "error: unable to load config: {}", err)?
+            }
+            CliError::Script(err) => {
+                write!(f, "error: script failed: {}", err)?
+            }
+            CliError::Input(msg) => write!(f, "error: invalid input: {}", msg)?,
+            CliError::File(msg) => write!(f, "error: {}", msg)?,
+            CliError::Store(err) => match err.kind() {
+                ErrorKind::Io {
+                    path: Some(path),
+                    message,
+                } => write!(
+                    f,
+                    "error: unable to access {}: {}",
+                    path.display(),
+                    message
+                )?,
+                ErrorKind::Io {
+                    path: None,
+                    message,
+                } => {
+                    write!(f, "error: unable to access the store: {}", message)?
+                }
+                ErrorKind::Serde { message } => {
+                    write!(f, "error: unable to decode the store: {}", message)?
+                }
+                ErrorKind::CorruptDatabase { message, .. } => {
+                    write!(f, "error: the store is corrupt: {}", message)?
+                }
+                ErrorKind::Config { message } => {
+                    write!(f, "error: invalid store settings: {}", message)?
+                }
+                ErrorKind::Locked { message } => {
+                    write!(f, "error: the store is locked: {}", message)?
+                }
+                ErrorKind::Unsupported { capability } => write!(
+                    f,
+                    "error: the store does not support {}",
+                    capability
+                )?,
+                ErrorKind::KeyDoesNotExist { key } => {
+                    write!(f, "error: key not found: {}", key)?
+                }
+                kind => write!(f, "error: {}", kind)?,
+            },
+        }
+        if let Some(hint) = self.hint() {
+            write!(f, "\nhint: {}", hint)?;
+        }
+        Ok(())
+    }
+}
@@ -1,20 +1,471 @@
-use core::{Persistent, Result};
-use hashmap_kvs::HashMapKvs;
-use log_kvs::LogKvs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use kvs::{
+    AnyKvs, AuditLog, CaptureKvs, KeyStats, KvStore, Kvs, LogKvs, Query,
+    ScanOptions, Scannable,
+};
+use structopt::clap::ErrorKind as ClapErrorKind;
 use structopt::StructOpt;
 
 mod args;
-use args::{Opt, Store};
+use args::{Command, KeyCommand, OnConflict, Opt, Store, StoreCommand};
 mod commandable;
-use commandable::Commandable;
+use commandable::{Commandable, Outcome};
+mod config;
+use config::Settings;
+mod diff;
+use diff::DiffReport;
+mod dump;
+mod encoding;
+use encoding::Encoding;
+mod errors;
+use errors::{CliError, ExitCode};
+mod script;
+use script::Script;
+
+fn main() {
+    let opt = match Opt::from_iter_safe(std::env::args_os()) {
+        Ok(opt) => opt,
+        Err(err) => match err.kind {
+            ClapErrorKind::HelpDisplayed | ClapErrorKind::VersionDisplayed => {
+                err.exit()
+            }
+            _ => {
+                eprintln!("{}", err.message);
+                ExitCode::Usage.exit()
+            }
+        },
+    };
+
+    match run(opt) {
+        Ok(code) => code.exit(),
+        Err(err) => {
+            eprintln!("{}", err);
+            err.exit_code().exit()
+        }
+    }
+}
+
+fn run(mut opt: Opt) -> Result<ExitCode, CliError> {
+    let settings = Settings::resolve(&mut opt).map_err(CliError::Config)?;
+    let encoding = Encoding::from_flags(opt.hex, opt.base64);
+    let open = OpenFlags {
+        read_only: opt.read_only,
+        track_keys: opt.track_keys,
+    };
+    let strict = opt.strict;
+
+    match opt.command {
+        Command::Store(command) => {
+            let command = encoding.decode_command(command)?;
+            if let StoreCommand::Replay { .. } = command {
+                if settings.location.exists() {
+                    return Err(CliError::Input(format!(
+                        "{} already exists, replays need a new store",
+                        settings.location.display()
+                    )));
+                }
+            }
+            with_store(&settings, open, |store| {
+                run_with_store(store, command, &settings, encoding, strict)
+            })
+        }
+        Command::Run { script } => {
+            // parse the script first, so a bad one doesn't create the store
+            let script = Script::load(&script).map_err(CliError::Script)?;
+            with_store(&settings, open, |store| {
+                script.run(&mut capturing(store, &settings)?)?;
+                Ok(ExitCode::Success)
+            })
+        }
+        Command::Diff {
+            a,
+            b,
+            b_store,
+            json,
+        } => {
+            let b_store = b_store.unwrap_or(settings.store);
+            let a = diff::open_existing(settings.store, &a)?;
+            let b = diff::open_existing(b_store, &b)?;
+            let diff = kvs::diff(&a, &b).map_err(CliError::Store)?;
+            DiffReport::new(diff, encoding).print(json);
+            Ok(ExitCode::Success)
+        }
+        Command::Merge {
+            mut stores,
+            on_conflict,
+        } => {
+            let dest_path =
+                stores.pop().expect("clap requires at least two stores");
+            let sources = stores
+                .iter()
+                .map(|path| diff::open_existing(settings.store, path))
+                .collect::<Result<Vec<_>, _>>()?;
+            let sources: Vec<&dyn Scannable> = sources
+                .iter()
+                .map(|source| source as &dyn Scannable)
+                .collect();
+            let existed = dest_path.exists();
+            let mut dest = Kvs::builder()
+                .engine(settings.store.into())
+                .path(&dest_path)
+                .sync(settings.sync)
+                .open_any()
+                .map_err(CliError::Store)?;
+
+            let merged = kvs::merge(&sources, &mut dest, on_conflict.into());
+            let aborted = match (&merged, on_conflict) {
+                (Ok(report), OnConflict::Fail) => !report.conflicts.is_empty(),
+                (Ok(_), OnConflict::PreferSource) => false,
+                (Err(_), _) => true,
+            };
+            if aborted && !existed {
+                // don't leave behind the empty store opening it created
+                drop(dest);
+                remove_store(&dest_path)?;
+            }
+            let report = merged.map_err(CliError::Store)?;
+            for key in &report.conflicts {
+                println!("~ {}", encoding.encode(key));
+            }
+            match on_conflict {
+                OnConflict::Fail if !report.conflicts.is_empty() => {
+                    eprintln!("error: conflicting values, nothing was merged");
+                    Ok(ExitCode::Conflict)
+                }
+                _ => {
+                    audit(
+                        &dest_path,
+                        "merge",
+                        &format!(
+                            "{} keys from {} stores",
+                            report.written,
+                            sources.len()
+                        ),
+                    )?;
+                    Ok(ExitCode::Success)
+                }
+            }
+        }
+        Command::Restore { dir, dry_run: true } => {
+            let report = kvs::restore(&dir, &settings.location, true)
+                .map_err(CliError::Store)?;
+            println!(
+                "would restore {} files, {} bytes, from {} backups",
+                report.files, report.bytes, report.backups
+            );
+            Ok(ExitCode::Success)
+        }
+        Command::Restore {
+            dir,
+            dry_run: false,
+        } => {
+            kvs::restore(&dir, &settings.location, false)
+                .map_err(CliError::Store)?;
+            audit(
+                &settings.location,
+                "restore",
+                &format!("from {}", dir.display()),
+            )?;
+            println!("restored {}", dir.display());
+            Ok(ExitCode::Success)
+        }
+        Command::VerifyBackup { path } => {
+            let problems = dump::verify(&path)?;
+            for problem in &problems {
+                println!("{}", problem);
+            }
+            if problems.is_empty() {
+                Ok(ExitCode::Success)
+            } else {
+                eprintln!("error: {} failed verification", path.display());
+                Ok(ExitCode::CorruptStore)
+            }
+        }
+        Command::Repair => {
+            if let Store::HashMap = settings.store {
+                return Err(CliError::Input(
+                    "only log stores can be repaired".to_owned(),
+                ));
+            }
+            let report =
+                LogKvs::repair(&settings.location).map_err(CliError::Store)?;
+            print!("{}", report);
+            audit(
+                &settings.location,
+                "repair",
+                &format!(
+                    "salvaged {} records, skipped {} bytes",
+                    report.records,
+                    report.skipped_bytes()
+                ),
+            )?;
+            Ok(ExitCode::Success)
+        }
+        Command::Hotkeys { n } => {
+            let stats = KeyStats::load(key_stats_path(&settings.location))
+                .map_err(CliError::Store)?;
+            for access in stats.top_keys(n) {
+                println!(
+                    "{}: {} reads, {} writes",
+                    encoding.encode(&access.key),
+                    access.reads,
+                    access.writes
+                );
+            }
+            Ok(ExitCode::Success)
+        }
+        Command::Audit => {
+            let entries = AuditLog::for_store(&settings.location)
+                .entries()
+                .map_err(CliError::Store)?;
+            for entry in entries {
+                println!(
+                    "{} {} {} {}",
+                    entry.time, entry.principal, entry.operation, entry.detail
+                );
+            }
+            Ok(ExitCode::Success)
+        }
+        Command::Completions { shell } => {
+            Opt::clap().gen_completions_to(
+                env!("CARGO_PKG_NAME"),
+                shell,
+                &mut std::io::stdout(),
+            );
+            Ok(ExitCode::Success)
+        }
+    }
+}
+
+/// The flags that change how the store at --location is opened.
+#[derive(Clone, Copy, Debug)]
+struct OpenFlags {
+    read_only: bool,
+    track_keys: bool,
+}
+
+/// Open the store at --location and run `f` against it, saving the key
+/// stats afterwards if --track-keys was given.
+fn with_store<F>(
+    settings: &Settings,
+    open: OpenFlags,
+    f: F,
+) -> Result<ExitCode, CliError>
+where
+    F: FnOnce(&mut AnyKvs) -> Result<ExitCode, CliError>,
+{
+    let mut builder = Kvs::builder()
+        .engine(settings.store.into())
+        .path(&settings.location)
+        .sync(settings.sync);
+    if open.read_only {
+        builder = builder.read_only();
+    }
+    let key_stats = if open.track_keys {
+        let stats = KeyStats::load(key_stats_path(&settings.location))
+            .map_err(CliError::Store)?;
+        let stats = Arc::new(stats);
+        builder = builder.observer(stats.clone());
+        Some(stats)
+    } else {
+        None
+    };
+    let mut store = builder.open_any().map_err(CliError::Store)?;
+    let code = f(&mut store)?;
+    if let Some(stats) = key_stats {
+        stats
+            .save(key_stats_path(&settings.location))
+            .map_err(CliError::Store)?;
+    }
+    Ok(code)
+}
+
+/// Run a command against the store at --location.
+fn run_with_store(
+    store: &mut AnyKvs,
+    command: StoreCommand,
+    settings: &Settings,
+    encoding: Encoding,
+    strict: bool,
+) -> Result<ExitCode, CliError> {
+    match command {
+        StoreCommand::Key(command) => {
+            run_key_command(store, command, settings, encoding, strict)
+        }
+        StoreCommand::Import {
+            file,
+            format,
+            fields,
+        } => {
+            let count = dump::import(store, &file, format, &fields)?;
+            audit(
+                &settings.location,
+                "import",
+                &format!("{} from {}", count, file.display()),
+            )?;
+            println!("imported {}", count);
+            Ok(ExitCode::Success)
+        }
+        StoreCommand::Export { file, format } => {
+            let count = dump::export(store, &file, format)?;
+            println!("exported {}", count);
+            Ok(ExitCode::Success)
+        }
+        StoreCommand::Digest { start, end } => {
+            let digest = kvs::digest(store, &start, end.as_deref())
+                .map_err(CliError::Store)?;
+            println!("{} {}", digest.root, digest.keys);
+            Ok(ExitCode::Success)
+        }
+        StoreCommand::Scan {
+            start,
+            end,
+            reverse,
+            offset,
+            limit,
+        } => {
+            let options = ScanOptions {
+                reverse,
+                offset,
+                limit,
+            };
+            let keys = store
+                .scan_with(&start, end.as_deref(), options)
+                .map_err(CliError::Store)?;
+            for key in keys {
+                println!("{}", encoding.encode(&key));
+            }
+            Ok(ExitCode::Success)
+        }
+        StoreCommand::Query { query } => {
+            let rows = Query::parse(&query)
+                .and_then(|query| query.run(store))
+                .map_err(CliError::Store)?;
+            for row in rows {
+                let row: Vec<String> =
+                    row.iter().map(|field| field.to_string()).collect();
+                println!("{}", row.join("\t"));
+            }
+            Ok(ExitCode::Success)
+        }
+        StoreCommand::Backup {
+            dir,
+            incremental: false,
+        } => {
+            let manifest = kvs::backup(store, &dir).map_err(CliError::Store)?;
+            println!("backed up {} files", manifest.entries.len());
+            Ok(ExitCode::Success)
+        }
+        StoreCommand::Backup {
+            dir,
+            incremental: true,
+        } => {
+            let link = kvs::backup_incremental(store, &dir)
+                .map_err(CliError::Store)?;
+            println!(
+                "backed up to sequence {} in {}",
+                link.sequence, link.name
+            );
+            Ok(ExitCode::Success)
+        }
+        StoreCommand::Replay {
+            file,
+            speed,
+            unpaced,
+        } => {
+            let speed = if unpaced { None } else { Some(speed) };
+            let report =
+                kvs::replay(&file, store, speed).map_err(CliError::Store)?;
+            println!(
+                "replayed {} operations in {}ms",
+                report.operations,
+                report.elapsed.as_millis()
+            );
+            Ok(ExitCode::Success)
+        }
+    }
+}
+
+/// Read or write a single key in the store at --location.
+fn run_key_command(
+    store: &mut AnyKvs,
+    command: KeyCommand,
+    settings: &Settings,
+    encoding: Encoding,
+    strict: bool,
+) -> Result<ExitCode, CliError> {
+    let output_file = match &command {
+        KeyCommand::Get { output_file, .. } => output_file.clone(),
+        _ => None,
+    };
+    let outcome = capturing(store, settings)?
+        .execute(command, strict)
+        .map_err(CliError::Store)?;
+    match outcome {
+        Outcome::Success => Ok(ExitCode::Success),
+        Outcome::Found(value) => {
+            match output_file {
+                Some(path) => std::fs::write(&path, value).map_err(|err| {
+                    CliError::File(format!(
+                        "unable to write {}: {}",
+                        path.display(),
+                        err
+                    ))
+                })?,
+                None => println!("{}", encoding.encode(&value)),
+            }
+            Ok(ExitCode::Success)
+        }
+        Outcome::KeyNotFound => {
+            println!("Key not found");
+            Ok(ExitCode::Success)
+        }
+        Outcome::Absent => Ok(ExitCode::KeyNotFound),
+    }
+}
+
+/// The store, recording what's done to it in the file given with --capture,
+/// if there is one.
+fn capturing<'a>(
+    store: &'a mut AnyKvs,
+    settings: &Settings,
+) -> Result<Box<dyn KvStore + 'a>, CliError> {
+    Ok(match &settings.capture {
+        Some(path) => {
+            Box::new(CaptureKvs::append(store, path).map_err(CliError::Store)?)
+        }
+        None => Box::new(store),
+    })
+}
+
+/// Where the key stats collected with --track-keys are kept.
+fn key_stats_path(store: &Path) -> PathBuf {
+    let mut path = store.as_os_str().to_owned();
+    path.push(".keystats");
+    PathBuf::from(path)
+}
+
+/// Record an administrative operation in the store's audit log.
+fn audit(store: &Path, operation: &str, detail: &str) -> Result<(), CliError> {
+    AuditLog::for_store(store)
+        .record(&AuditLog::local_principal(), operation, detail)
+        .map(|_| ())
+        .map_err(CliError::Store)
+}
 
-fn main() -> Result<()> {
-    let opt = Opt::from_args();
-    let mut store: Box<dyn Commandable> = match opt.store {
-        Store::HashMap => Box::new(HashMapKvs::open(opt.location).unwrap()),
-        Store::Log => Box::new(LogKvs::open(opt.location).unwrap()),
+/// Remove the store at the path, whether it's kept in a file or a
+/// directory.
+fn remove_store(path: &Path) -> Result<(), CliError> {
+    let removed = if path.is_dir() {
+        std::fs::remove_dir_all(path)
+    } else {
+        std::fs::remove_file(path)
     };
-    store.execute(opt.command)
+    removed.map_err(|err| {
+        CliError::File(format!("unable to remove {}: {}", path.display(), err))
+    })
 }
 
 #[cfg(test)]
@@ -22,11 +473,11 @@ mod tests {
     use super::*;
     use assert_cmd::prelude::*;
     use predicates::ord::eq;
-    use predicates::str::{contains, is_empty, PredicateStrExt};
+    use predicates::str::{contains, is_empty, starts_with, PredicateStrExt};
     use std::process::Command;
     use tempfile::TempDir;
 
-    use core::KvStore;
+    use kvs::{HashMapKvs, KvStore, LogKvs, Persistent, Result};
 
     // `kvs` with no args should exit with a non-zero code.
     #[test]
@@ -223,4 +674,1148 @@ mod tests {
             .assert()
             .failure();
     }
+
+    // settings in a config file should be used to pick the store
+    #[test]
+    fn cli_config_file() -> Result<()> {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+        std::fs::write(
+            temp_dir.path().join("kvs.toml"),
+            "store = \"log\"\nlocation = \"log_dir\"\n",
+        )?;
+
+        Command::cargo_bin("cli")
+            .unwrap()
+            .args(&["-c", "kvs.toml", "set", "key1", "value1"])
+            .current_dir(&temp_dir)
+            .assert()
+            .success()
+            .stdout(is_empty());
+
+        let store = LogKvs::open(temp_dir.path().join("log_dir"))?;
+        assert_eq!(store.get("key1".to_owned())?, Some("value1".to_owned()));
+
+        Ok(())
+    }
+
+    // flags should override the values in a config file
+    #[test]
+    fn cli_config_file_overridden() -> Result<()> {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+        std::fs::write(
+            temp_dir.path().join("kvs.toml"),
+            "store = \"log\"\nlocation = \"log_dir\"\n",
+        )?;
+
+        Command::cargo_bin("cli")
+            .unwrap()
+            .args(&[
+                "-c", "kvs.toml", "-s", "hashmap", "-l", "kvs_file", "set",
+                "key1", "value1",
+            ])
+            .current_dir(&temp_dir)
+            .assert()
+            .success();
+
+        let store = HashMapKvs::open(temp_dir.path().join("kvs_file"))?;
+        assert_eq!(store.get("key1".to_owned())?, Some("value1".to_owned()));
+        assert!(!temp_dir.path().join("log_dir").exists());
+
+        Ok(())
+    }
+
+    // unknown settings in a config file should be rejected
+    #[test]
+    fn cli_invalid_config_file() -> Result<()> {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+        std::fs::write(temp_dir.path().join("kvs.toml"), "engine = \"log\"\n")?;
+
+        Command::cargo_bin("cli")
+            .unwrap()
+            .args(&["-c", "kvs.toml", "get", "key1"])
+            .current_dir(&temp_dir)
+            .assert()
+            .failure();
+
+        Ok(())
+    }
+
+    // environment variables should be used when no flags are given
+    #[test]
+    fn cli_env_vars() -> Result<()> {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+
+        Command::cargo_bin("cli")
+            .unwrap()
+            .args(&["set", "key1", "value1"])
+            .env("KVS_STORE", "log")
+            .env("KVS_LOCATION", "log_dir")
+            .current_dir(&temp_dir)
+            .assert()
+            .success();
+
+        let store = LogKvs::open(temp_dir.path().join("log_dir"))?;
+        assert_eq!(store.get("key1".to_owned())?, Some("value1".to_owned()));
+
+        Ok(())
+    }
+
+    // environment variables should override the config file, and flags
+    // should override environment variables
+    #[test]
+    fn cli_env_vars_precedence() -> Result<()> {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+        std::fs::write(
+            temp_dir.path().join("kvs.toml"),
+            "store = \"hashmap\"\nlocation = \"config_file\"\n",
+        )?;
+
+        Command::cargo_bin("cli")
+            .unwrap()
+            .args(&["-l", "flag_dir", "set", "key1", "value1"])
+            .env("KVS_CONFIG", "kvs.toml")
+            .env("KVS_STORE", "log")
+            .env("KVS_LOCATION", "env_dir")
+            .current_dir(&temp_dir)
+            .assert()
+            .success();
+
+        let store = LogKvs::open(temp_dir.path().join("flag_dir"))?;
+        assert_eq!(store.get("key1".to_owned())?, Some("value1".to_owned()));
+        assert!(!temp_dir.path().join("env_dir").exists());
+        assert!(!temp_dir.path().join("config_file").exists());
+
+        Ok(())
+    }
+
+    // invalid arguments should exit with the usage code
+    #[test]
+    fn cli_usage_exit_code() {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+
+        Command::cargo_bin("cli")
+            .unwrap()
+            .args(&["-l", "kvs_file", "get"])
+            .current_dir(&temp_dir)
+            .assert()
+            .code(ExitCode::Usage as i32);
+    }
+
+    // a store that can't be decoded should exit with the corrupt store code
+    // and explain itself on stderr
+    #[test]
+    fn cli_corrupt_store_exit_code() -> Result<()> {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+        std::fs::write(temp_dir.path().join("kvs_file"), "not json")?;
+
+        Command::cargo_bin("cli")
+            .unwrap()
+            .args(&["-l", "kvs_file", "get", "key1"])
+            .current_dir(&temp_dir)
+            .assert()
+            .code(ExitCode::CorruptStore as i32)
+            .stdout(is_empty())
+            .stderr(contains("unable to decode the store"));
+
+        Ok(())
+    }
+
+    // an invalid config file should exit with the config code
+    #[test]
+    fn cli_config_exit_code() -> Result<()> {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+        std::fs::write(temp_dir.path().join("kvs.toml"), "store = 1\n")?;
+
+        Command::cargo_bin("cli")
+            .unwrap()
+            .args(&["-c", "kvs.toml", "get", "key1"])
+            .current_dir(&temp_dir)
+            .assert()
+            .code(ExitCode::Config as i32)
+            .stderr(contains("unable to load config"))
+            .stderr(contains("`sync` and `capture` are supported"));
+
+        Ok(())
+    }
+
+    // `kvs exists <KEY>` should print nothing, and exit with zero only if
+    // the key has a value.
+    #[test]
+    fn cli_exists() -> Result<()> {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+        let mut store = LogKvs::open(temp_dir.path().join("kvs_dir"))?;
+        store.set("key1".to_owned(), "value1".to_owned())?;
+        drop(store);
+
+        for (key, code) in
+            &[("key1", ExitCode::Success), ("key2", ExitCode::KeyNotFound)]
+        {
+            Command::cargo_bin("cli")
+                .unwrap()
+                .args(&["-s", "log", "-l", "kvs_dir", "exists", key])
+                .current_dir(&temp_dir)
+                .assert()
+                .code(*code as i32)
+                .stdout(is_empty())
+                .stderr(is_empty());
+        }
+
+        Ok(())
+    }
+
+    // `kvs digest` should print the same digest for stores with the same
+    // contents, whatever their engine.
+    #[test]
+    fn cli_digest() -> Result<()> {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+        let mut a = HashMapKvs::open(temp_dir.path().join("kvs_file"))?;
+        let mut b = LogKvs::open(temp_dir.path().join("kvs_dir"))?;
+        for store in &mut [&mut a as &mut dyn KvStore, &mut b] {
+            store.set("key1".to_owned(), "value1".to_owned())?;
+            store.set("key2".to_owned(), "value2".to_owned())?;
+        }
+        drop(a);
+        drop(b);
+
+        let digest = |args: &[&str]| {
+            let output = Command::cargo_bin("cli")
+                .unwrap()
+                .args(args)
+                .current_dir(&temp_dir)
+                .output()
+                .unwrap();
+            assert!(output.status.success());
+            String::from_utf8(output.stdout).unwrap()
+        };
+        let a = digest(&["-l", "kvs_file", "digest"]);
+        assert!(a.trim().ends_with(" 2"));
+        assert_eq!(a, digest(&["-s", "log", "-l", "kvs_dir", "digest"]));
+        let first = digest(&["-l", "kvs_file", "digest", "--end", "key2"]);
+        assert!(first.trim().ends_with(" 1"));
+        assert_ne!(first, a);
+
+        Ok(())
+    }
+
+    // `kvs scan` should print the keys in a range, from either end and a
+    // page at a time.
+    #[test]
+    fn cli_scan() -> Result<()> {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+        let mut store = HashMapKvs::open(temp_dir.path().join("kvs_file"))?;
+        for key in &["log:1", "log:2", "log:3", "zone:1"] {
+            store.set(key.to_string(), "value".to_owned())?;
+        }
+        drop(store);
+
+        let scan = |args: &[&str]| {
+            Command::cargo_bin("cli")
+                .unwrap()
+                .args(&["-l", "kvs_file", "scan"])
+                .args(args)
+                .current_dir(&temp_dir)
+                .assert()
+                .success()
+        };
+        scan(&[]).stdout("log:1\nlog:2\nlog:3\nzone:1\n");
+        scan(&[
+            "--start",
+            "log:",
+            "--end",
+            "log;",
+            "--reverse",
+            "--limit",
+            "2",
+        ])
+        .stdout("log:3\nlog:2\n");
+        scan(&["--offset", "3"]).stdout("zone:1\n");
+
+        Ok(())
+    }
+
+    // `kvs query <QUERY>` should print the selected fields of matching keys,
+    // and fail on an invalid query.
+    #[test]
+    fn cli_query() -> Result<()> {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+        let mut store = HashMapKvs::open(temp_dir.path().join("kvs_file"))?;
+        store.set("ann".to_owned(), r#"{"age": 34}"#.to_owned())?;
+        store.set("bob".to_owned(), r#"{"age": 27}"#.to_owned())?;
+        drop(store);
+
+        Command::cargo_bin("cli")
+            .unwrap()
+            .args(&[
+                "-l",
+                "kvs_file",
+                "query",
+                "SELECT key, value.age WHERE value.age > 30",
+            ])
+            .current_dir(&temp_dir)
+            .assert()
+            .success()
+            .stdout("\"ann\"\t34\n");
+        Command::cargo_bin("cli")
+            .unwrap()
+            .args(&["-l", "kvs_file", "query", "SELECT size"])
+            .current_dir(&temp_dir)
+            .assert()
+            .failure();
+
+        Ok(())
+    }
+
+    // `kvs --strict get <KEY>` should report a missing key on stderr and exit
+    // with the key not found code.
+    #[test]
+    fn cli_strict_get_non_existent_key() {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+        Command::cargo_bin("cli")
+            .unwrap()
+            .args(&["-l", "kvs_file", "--strict", "get", "key1"])
+            .current_dir(&temp_dir)
+            .assert()
+            .code(ExitCode::KeyNotFound as i32)
+            .stdout(is_empty())
+            .stderr(eq("error: key not found: key1").trim());
+    }
+
+    // `kvs --strict rm <KEY>` should report a missing key on stderr and exit
+    // with the key not found code.
+    #[test]
+    fn cli_strict_rm_non_existent_key() {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+        Command::cargo_bin("cli")
+            .unwrap()
+            .args(&["-l", "kvs_file", "--strict", "rm", "key1"])
+            .current_dir(&temp_dir)
+            .assert()
+            .code(ExitCode::KeyNotFound as i32)
+            .stdout(is_empty())
+            .stderr(eq("error: key not found: key1").trim());
+    }
+
+    // strict mode shouldn't change the behavior for keys that exist
+    #[test]
+    fn cli_strict_get_stored() -> Result<()> {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+
+        let mut store = HashMapKvs::open(temp_dir.path().join("kvs_file"))?;
+        store.set("key1".to_owned(), "value1".to_owned())?;
+        drop(store);
+
+        Command::cargo_bin("cli")
+            .unwrap()
+            .args(&["-l", "kvs_file", "--strict", "get", "key1"])
+            .current_dir(&temp_dir)
+            .assert()
+            .success()
+            .stdout(eq("value1").trim());
+
+        Ok(())
+    }
+
+    // `kvs completions <SHELL>` should print a script that completes the
+    // store types, without creating a store.
+    #[test]
+    fn cli_completions() {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+        for shell in &["bash", "zsh", "fish", "powershell"] {
+            Command::cargo_bin("cli")
+                .unwrap()
+                .args(&["-l", "kvs_file", "completions", shell])
+                .current_dir(&temp_dir)
+                .assert()
+                .success()
+                .stdout(contains("hashmap"));
+        }
+        assert!(!temp_dir.path().join("kvs_file").exists());
+    }
+
+    #[test]
+    fn cli_invalid_completions() {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+        Command::cargo_bin("cli")
+            .unwrap()
+            .args(&["completions", "tcsh"])
+            .current_dir(&temp_dir)
+            .assert()
+            .code(ExitCode::Usage as i32);
+    }
+
+    // the sync policy should be accepted from flags, the environment and the
+    // config file
+    #[test]
+    fn cli_sync() -> Result<()> {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+        std::fs::write(
+            temp_dir.path().join("kvs.toml"),
+            "sync = \"always\"\n",
+        )?;
+
+        Command::cargo_bin("cli")
+            .unwrap()
+            .args(&["-l", "kvs_file", "--sync", "always", "set", "key1", "v1"])
+            .current_dir(&temp_dir)
+            .assert()
+            .success();
+        Command::cargo_bin("cli")
+            .unwrap()
+            .args(&["-l", "kvs_file", "set", "key2", "v2"])
+            .env("KVS_SYNC", "never")
+            .current_dir(&temp_dir)
+            .assert()
+            .success();
+        Command::cargo_bin("cli")
+            .unwrap()
+            .args(&["-l", "kvs_file", "-c", "kvs.toml", "set", "key3", "v3"])
+            .current_dir(&temp_dir)
+            .assert()
+            .success();
+        Command::cargo_bin("cli")
+            .unwrap()
+            .args(&["-l", "kvs_file", "--sync", "sometimes", "get", "key1"])
+            .current_dir(&temp_dir)
+            .assert()
+            .code(ExitCode::Usage as i32);
+
+        let store = HashMapKvs::open(temp_dir.path().join("kvs_file"))?;
+        assert_eq!(store.get("key3".to_owned())?, Some("v3".to_owned()));
+
+        Ok(())
+    }
+
+    // `cli run` should apply a script, including its transactions
+    #[test]
+    fn cli_run_script() -> Result<()> {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+        std::fs::write(
+            temp_dir.path().join("fixture.kvs"),
+            "# seed the store\nset key1 value 1\nset key2 value2\nrm \
+             key2\nassert-get key1 value 1\nassert-get key2\n\nbegin\nset \
+             key3 value3\nrm key1\nassert-get key1\ncommit\n",
+        )?;
+
+        Command::cargo_bin("cli")
+            .unwrap()
+            .args(&["-l", "kvs_file", "run", "fixture.kvs"])
+            .current_dir(&temp_dir)
+            .assert()
+            .success()
+            .stdout(is_empty());
+
+        let store = HashMapKvs::open(temp_dir.path().join("kvs_file"))?;
+        assert_eq!(store.get("key1".to_owned())?, None);
+        assert_eq!(store.get("key3".to_owned())?, Some("value3".to_owned()));
+
+        Ok(())
+    }
+
+    // a failed assertion should stop the script and drop the open
+    // transaction
+    #[test]
+    fn cli_run_script_assert() -> Result<()> {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+        std::fs::write(
+            temp_dir.path().join("fixture.kvs"),
+            "set key1 value1\nbegin\nset key2 value2\nassert-get key1 \
+             value2\ncommit\n",
+        )?;
+
+        Command::cargo_bin("cli")
+            .unwrap()
+            .args(&["-l", "kvs_file", "run", "fixture.kvs"])
+            .current_dir(&temp_dir)
+            .assert()
+            .code(ExitCode::ScriptFailed as i32)
+            .stderr(contains("line 4"));
+
+        let store = HashMapKvs::open(temp_dir.path().join("kvs_file"))?;
+        assert_eq!(store.get("key1".to_owned())?, Some("value1".to_owned()));
+        assert_eq!(store.get("key2".to_owned())?, None);
+
+        Ok(())
+    }
+
+    // an invalid script should be rejected before anything is written
+    #[test]
+    fn cli_run_script_invalid() -> Result<()> {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+        std::fs::write(
+            temp_dir.path().join("fixture.kvs"),
+            "set key1 value1\nbegin\nset key2\n",
+        )?;
+
+        Command::cargo_bin("cli")
+            .unwrap()
+            .args(&["-l", "kvs_file", "run", "fixture.kvs"])
+            .current_dir(&temp_dir)
+            .assert()
+            .code(ExitCode::ScriptFailed as i32)
+            .stderr(contains("line 3"));
+        assert!(!temp_dir.path().join("kvs_file").exists());
+
+        Command::cargo_bin("cli")
+            .unwrap()
+            .args(&["-l", "kvs_file", "run", "missing.kvs"])
+            .current_dir(&temp_dir)
+            .assert()
+            .code(ExitCode::ScriptFailed as i32);
+
+        Ok(())
+    }
+
+    // values with newlines should round-trip through hex and base64
+    #[test]
+    fn cli_encoded_values() -> Result<()> {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+
+        // "key 1" and "line1\nline2"
+        Command::cargo_bin("cli")
+            .unwrap()
+            .args(&["-l", "kvs_file", "--hex", "set", "6b65792031"])
+            .arg("6c696e65310a6c696e6532")
+            .current_dir(&temp_dir)
+            .assert()
+            .success();
+
+        let store = HashMapKvs::open(temp_dir.path().join("kvs_file"))?;
+        assert_eq!(
+            store.get("key 1".to_owned())?,
+            Some("line1\nline2".to_owned())
+        );
+        drop(store);
+
+        Command::cargo_bin("cli")
+            .unwrap()
+            .args(&["-l", "kvs_file", "--base64", "get", "a2V5IDE="])
+            .current_dir(&temp_dir)
+            .assert()
+            .success()
+            .stdout(eq("bGluZTEKbGluZTI=\n"));
+
+        Command::cargo_bin("cli")
+            .unwrap()
+            .args(&["-l", "kvs_file", "--hex", "get", "not hex"])
+            .current_dir(&temp_dir)
+            .assert()
+            .code(ExitCode::Usage as i32)
+            .stderr(contains("isn't valid hex"));
+
+        // decodes fine, but isn't UTF-8
+        Command::cargo_bin("cli")
+            .unwrap()
+            .args(&["-l", "kvs_file", "--hex", "set", "6b6579", "ff"])
+            .current_dir(&temp_dir)
+            .assert()
+            .code(ExitCode::Usage as i32)
+            .stderr(contains("UTF-8"));
+
+        Ok(())
+    }
+
+    // values should be read from and written to files as is
+    #[test]
+    fn cli_value_files() -> Result<()> {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+        std::fs::write(temp_dir.path().join("in.txt"), "line1\nline2\n")?;
+
+        Command::cargo_bin("cli")
+            .unwrap()
+            .args(&["-l", "kvs_file", "set", "key1", "--value-file", "in.txt"])
+            .current_dir(&temp_dir)
+            .assert()
+            .success();
+
+        Command::cargo_bin("cli")
+            .unwrap()
+            .args(&[
+                "-l",
+                "kvs_file",
+                "get",
+                "key1",
+                "--output-file",
+                "out.txt",
+            ])
+            .current_dir(&temp_dir)
+            .assert()
+            .success()
+            .stdout(is_empty());
+        assert_eq!(
+            std::fs::read_to_string(temp_dir.path().join("out.txt"))?,
+            "line1\nline2\n"
+        );
+
+        Command::cargo_bin("cli")
+            .unwrap()
+            .args(&["-l", "kvs_file", "set", "key1", "v", "--value-file"])
+            .arg("in.txt")
+            .current_dir(&temp_dir)
+            .assert()
+            .code(ExitCode::Usage as i32);
+
+        Command::cargo_bin("cli")
+            .unwrap()
+            .args(&["-l", "kvs_file", "set", "key1", "--value-file"])
+            .arg("missing.txt")
+            .current_dir(&temp_dir)
+            .assert()
+            .code(ExitCode::Io as i32);
+
+        Ok(())
+    }
+
+    // `cli set <KEY> --stdin` should store whatever is piped in, for both
+    // stores
+    #[test]
+    fn cli_set_stdin() -> Result<()> {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+        let value = "line1\nline2\n".repeat(10_000);
+
+        for store in &["hashmap", "log"] {
+            Command::cargo_bin("cli")
+                .unwrap()
+                .args(&["-s", store, "-l", store, "set", "key1", "--stdin"])
+                .current_dir(&temp_dir)
+                .with_stdin()
+                .buffer(value.clone())
+                .assert()
+                .success();
+        }
+
+        let store = HashMapKvs::open(temp_dir.path().join("hashmap"))?;
+        assert_eq!(store.get("key1".to_owned())?, Some(value.clone()));
+        let store = LogKvs::open(temp_dir.path().join("log"))?;
+        assert_eq!(store.get("key1".to_owned())?, Some(value));
+
+        Command::cargo_bin("cli")
+            .unwrap()
+            .args(&["-l", "hashmap", "set", "key1", "v", "--stdin"])
+            .current_dir(&temp_dir)
+            .assert()
+            .code(ExitCode::Usage as i32);
+
+        Ok(())
+    }
+
+    #[test]
+    fn cli_diff() -> Result<()> {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+
+        let mut a = HashMapKvs::open(temp_dir.path().join("a"))?;
+        let mut b = LogKvs::open(temp_dir.path().join("b"))?;
+        for store in &mut [&mut a as &mut dyn KvStore, &mut b] {
+            store.set("same".to_owned(), "value1".to_owned())?;
+            store.set("changed".to_owned(), "value2".to_owned())?;
+        }
+        a.set("changed".to_owned(), "value3".to_owned())?;
+        a.set("a1".to_owned(), "value4".to_owned())?;
+        b.set("b1".to_owned(), "value5".to_owned())?;
+        drop(a);
+        drop(b);
+
+        Command::cargo_bin("cli")
+            .unwrap()
+            .args(&["diff", "a", "b", "--b-store", "log"])
+            .current_dir(&temp_dir)
+            .assert()
+            .success()
+            .stdout(eq("- a1\n+ b1\n~ changed\n"));
+
+        Command::cargo_bin("cli")
+            .unwrap()
+            .args(&["--hex", "diff", "a", "b", "--b-store", "log", "--json"])
+            .current_dir(&temp_dir)
+            .assert()
+            .success()
+            .stdout(eq(concat!(
+                r#"{"only_in_a":["6131"],"only_in_b":["6231"],"#,
+                r#""changed":["6368616e676564"]}"#,
+                "\n"
+            )));
+
+        Command::cargo_bin("cli")
+            .unwrap()
+            .args(&["diff", "a", "missing"])
+            .current_dir(&temp_dir)
+            .assert()
+            .code(ExitCode::Io as i32)
+            .stderr(contains("no store at missing"));
+        assert!(!temp_dir.path().join("missing").exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn cli_merge() -> Result<()> {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+
+        let mut a = HashMapKvs::open(temp_dir.path().join("a"))?;
+        let mut b = HashMapKvs::open(temp_dir.path().join("b"))?;
+        a.set("a1".to_owned(), "value1".to_owned())?;
+        a.set("conflict".to_owned(), "value2".to_owned())?;
+        b.set("b1".to_owned(), "value3".to_owned())?;
+        b.set("conflict".to_owned(), "value4".to_owned())?;
+        drop(a);
+        drop(b);
+
+        Command::cargo_bin("cli")
+            .unwrap()
+            .args(&["merge", "a", "b", "dest", "--on-conflict", "fail"])
+            .current_dir(&temp_dir)
+            .assert()
+            .code(ExitCode::Conflict as i32)
+            .stdout(eq("~ conflict\n"));
+        assert!(!temp_dir.path().join("dest").exists());
+
+        Command::cargo_bin("cli")
+            .unwrap()
+            .args(&["merge", "a", "b", "dest"])
+            .current_dir(&temp_dir)
+            .assert()
+            .success()
+            .stdout(eq("~ conflict\n"));
+        let dest = HashMapKvs::open(temp_dir.path().join("dest"))?;
+        assert_eq!(dest.get("a1".to_owned())?, Some("value1".to_owned()));
+        assert_eq!(dest.get("b1".to_owned())?, Some("value3".to_owned()));
+        assert_eq!(dest.get("conflict".to_owned())?, Some("value4".to_owned()));
+        drop(dest);
+
+        // a store that was already there is kept
+        Command::cargo_bin("cli")
+            .unwrap()
+            .args(&["merge", "a", "b", "dest", "--on-conflict", "fail"])
+            .current_dir(&temp_dir)
+            .assert()
+            .code(ExitCode::Conflict as i32);
+        let dest = HashMapKvs::open(temp_dir.path().join("dest"))?;
+        assert_eq!(dest.get("a1".to_owned())?, Some("value1".to_owned()));
+
+        Command::cargo_bin("cli")
+            .unwrap()
+            .args(&["merge", "a"])
+            .current_dir(&temp_dir)
+            .assert()
+            .code(ExitCode::Usage as i32);
+
+        Ok(())
+    }
+
+    #[test]
+    fn cli_import_export() -> Result<()> {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+        let aof = "*3\r\n$3\r\nSET\r\n$4\r\nkey1\r\n$6\r\nvalue1\r\n";
+        std::fs::write(temp_dir.path().join("in.aof"), aof)?;
+
+        Command::cargo_bin("cli")
+            .unwrap()
+            .args(&["-l", "kvs", "import", "in.aof", "--format", "aof"])
+            .current_dir(&temp_dir)
+            .assert()
+            .success()
+            .stdout(eq("imported 1\n"));
+        Command::cargo_bin("cli")
+            .unwrap()
+            .args(&["-l", "kvs", "export", "out.aof"])
+            .current_dir(&temp_dir)
+            .assert()
+            .success()
+            .stdout(eq("exported 1\n"));
+        assert_eq!(
+            std::fs::read_to_string(temp_dir.path().join("out.aof"))?,
+            aof
+        );
+
+        std::fs::write(temp_dir.path().join("bad.rdb"), "not a dump")?;
+        Command::cargo_bin("cli")
+            .unwrap()
+            .args(&["-l", "kvs", "import", "bad.rdb", "--format", "rdb"])
+            .current_dir(&temp_dir)
+            .assert()
+            .code(ExitCode::Io as i32)
+            .stderr(contains("not an RDB file"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn cli_import_csv_jsonl() -> Result<()> {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+        std::fs::write(temp_dir.path().join("in.csv"), "id,name\n1,alice\n")?;
+        std::fs::write(
+            temp_dir.path().join("in.jsonl"),
+            "{\"name\": \"bob\", \"id\": 2}\n",
+        )?;
+
+        Command::cargo_bin("cli")
+            .unwrap()
+            .args(&["-l", "kvs", "import", "in.csv", "--format", "csv"])
+            .args(&["--headers", "--key-col", "1", "--value-col", "0"])
+            .current_dir(&temp_dir)
+            .assert()
+            .success()
+            .stdout(eq("imported 1\n"));
+        Command::cargo_bin("cli")
+            .unwrap()
+            .args(&["-l", "kvs", "import", "in.jsonl", "--format", "jsonl"])
+            .args(&["--key-field", "name", "--value-field", "id"])
+            .current_dir(&temp_dir)
+            .assert()
+            .success()
+            .stdout(eq("imported 1\n"));
+
+        let store = HashMapKvs::open(temp_dir.path().join("kvs"))?;
+        assert_eq!(store.get("alice".to_owned())?, Some("1".to_owned()));
+        assert_eq!(store.get("bob".to_owned())?, Some("2".to_owned()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn cli_backup_restore() -> Result<()> {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+        let cli = || {
+            let mut cmd = Command::cargo_bin("cli").unwrap();
+            cmd.current_dir(&temp_dir);
+            cmd
+        };
+
+        cli()
+            .args(&["-l", "kvs", "set", "key1", "value1"])
+            .assert()
+            .success();
+        cli()
+            .args(&["-l", "kvs", "backup", "backup"])
+            .assert()
+            .success()
+            .stdout(contains("backed up"));
+        cli()
+            .args(&["verify-backup", "backup"])
+            .assert()
+            .success()
+            .stdout(is_empty());
+        cli()
+            .args(&["-l", "restored", "restore", "backup", "--dry-run"])
+            .assert()
+            .success()
+            .stdout(starts_with("would restore 1 files, "));
+        assert!(!temp_dir.path().join("restored").exists());
+        cli()
+            .args(&["-l", "restored", "restore", "backup"])
+            .assert()
+            .success()
+            .stdout(eq("restored backup\n"));
+        cli()
+            .args(&["-l", "restored", "restore", "backup", "--dry-run"])
+            .assert()
+            .failure()
+            .stderr(contains("already exists"));
+        cli()
+            .args(&["-l", "restored", "get", "key1"])
+            .assert()
+            .success()
+            .stdout(eq("value1\n"));
+
+        cli()
+            .args(&["-l", "kvs", "export", "out.aof"])
+            .assert()
+            .success();
+        cli().args(&["verify-backup", "out.aof"]).assert().success();
+        std::fs::write(temp_dir.path().join("out.aof"), "tampered")?;
+        cli()
+            .args(&["verify-backup", "out.aof"])
+            .assert()
+            .code(ExitCode::CorruptStore as i32)
+            .stdout(contains("out.aof is 8 bytes"));
+        cli()
+            .args(&["-l", "kvs2", "import", "out.aof", "--format", "aof"])
+            .assert()
+            .code(ExitCode::CorruptStore as i32)
+            .stderr(contains("failed verification"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn cli_incremental_backup() -> Result<()> {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+        let cli = || {
+            let mut cmd = Command::cargo_bin("cli").unwrap();
+            cmd.current_dir(&temp_dir).args(&["-s", "log", "-l", "kvs"]);
+            cmd
+        };
+
+        cli().args(&["set", "key1", "value1"]).assert().success();
+        cli()
+            .args(&["backup", "--incremental", "chain"])
+            .assert()
+            .success()
+            .stdout(contains("in 0\n"));
+        cli().args(&["set", "key2", "value2"]).assert().success();
+        cli()
+            .args(&["backup", "--incremental", "chain"])
+            .assert()
+            .success()
+            .stdout(contains("in 1\n"));
+        cli().args(&["verify-backup", "chain"]).assert().success();
+
+        Command::cargo_bin("cli")
+            .unwrap()
+            .args(&["-s", "log", "-l", "restored", "restore", "chain"])
+            .current_dir(&temp_dir)
+            .assert()
+            .success();
+        Command::cargo_bin("cli")
+            .unwrap()
+            .args(&["-s", "log", "-l", "restored", "get", "key2"])
+            .current_dir(&temp_dir)
+            .assert()
+            .success()
+            .stdout(eq("value2\n"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn cli_audit() -> Result<()> {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+        let aof = "*3\r\n$3\r\nSET\r\n$4\r\nkey1\r\n$6\r\nvalue1\r\n";
+        std::fs::write(temp_dir.path().join("in.aof"), aof)?;
+
+        Command::cargo_bin("cli")
+            .unwrap()
+            .args(&["-l", "kvs", "audit"])
+            .current_dir(&temp_dir)
+            .assert()
+            .success()
+            .stdout(is_empty());
+        Command::cargo_bin("cli")
+            .unwrap()
+            .args(&["-l", "kvs", "import", "in.aof", "--format", "aof"])
+            .env("USER", "alice")
+            .current_dir(&temp_dir)
+            .assert()
+            .success();
+        // reads don't count as administrative operations
+        Command::cargo_bin("cli")
+            .unwrap()
+            .args(&["-l", "kvs", "get", "key1"])
+            .current_dir(&temp_dir)
+            .assert()
+            .success();
+        Command::cargo_bin("cli")
+            .unwrap()
+            .args(&["-l", "kvs", "audit"])
+            .current_dir(&temp_dir)
+            .assert()
+            .success()
+            .stdout(contains(" alice import 1 from in.aof\n"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn cli_repair() -> Result<()> {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+        let path = temp_dir.path().join("kvs");
+        let mut store = LogKvs::open(&path)?;
+        store.set("key1".to_owned(), "value1".to_owned())?;
+        drop(store);
+        // a torn write at the end of the log
+        let mut log = std::fs::OpenOptions::new()
+            .append(true)
+            .open(path.join("1"))?;
+        std::io::Write::write_all(&mut log, &[0xff; 7])?;
+        drop(log);
+
+        Command::cargo_bin("cli")
+            .unwrap()
+            .args(&["-l", "kvs", "repair"])
+            .current_dir(&temp_dir)
+            .assert()
+            .code(ExitCode::Usage as i32);
+        Command::cargo_bin("cli")
+            .unwrap()
+            .args(&["-s", "log", "-l", "kvs", "repair"])
+            .current_dir(&temp_dir)
+            .assert()
+            .success()
+            .stdout(contains(
+                "salvaged 1 records holding 1 keys, skipped 7 bytes\n",
+            ))
+            .stdout(contains("skipped bytes 54..61\n"));
+        assert!(path.join("REPAIR").is_file());
+        Command::cargo_bin("cli")
+            .unwrap()
+            .args(&["-s", "log", "-l", "kvs", "get", "key1"])
+            .current_dir(&temp_dir)
+            .assert()
+            .success()
+            .stdout(eq("value1").trim());
+
+        Ok(())
+    }
+
+    #[test]
+    fn cli_hotkeys() -> Result<()> {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+        let cli = || {
+            let mut cmd = Command::cargo_bin("cli").unwrap();
+            cmd.current_dir(&temp_dir)
+                .args(&["-l", "kvs", "--track-keys"]);
+            cmd
+        };
+
+        cli().args(&["set", "key1", "value1"]).assert().success();
+        cli().args(&["set", "key2", "value2"]).assert().success();
+        cli().args(&["get", "key2"]).assert().success();
+        cli().args(&["get", "key2"]).assert().success();
+        // untracked commands aren't counted
+        Command::cargo_bin("cli")
+            .unwrap()
+            .args(&["-l", "kvs", "get", "key1"])
+            .current_dir(&temp_dir)
+            .assert()
+            .success();
+
+        cli()
+            .args(&["hotkeys", "-n", "1"])
+            .assert()
+            .success()
+            .stdout(eq("key2: 2 reads, 1 writes\n"));
+        cli()
+            .args(&["hotkeys"])
+            .assert()
+            .success()
+            .stdout(contains("key1: 0 reads, 1 writes\n"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn cli_read_only() -> Result<()> {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+        let mut store = LogKvs::open(temp_dir.path().join("log_dir"))?;
+        store.set("key1".to_owned(), "value1".to_owned())?;
+
+        // the store is open for writing here, so only readers can open it
+        Command::cargo_bin("cli")
+            .unwrap()
+            .args(&["-s", "log", "-l", "log_dir", "set", "key1", "value2"])
+            .current_dir(&temp_dir)
+            .assert()
+            .code(ExitCode::Locked as i32)
+            .stderr(contains("already open for writing"));
+        Command::cargo_bin("cli")
+            .unwrap()
+            .args(&["-s", "log", "-l", "log_dir", "--read-only", "get", "key1"])
+            .current_dir(&temp_dir)
+            .assert()
+            .success()
+            .stdout(eq("value1").trim());
+        Command::cargo_bin("cli")
+            .unwrap()
+            .args(&["-s", "log", "-l", "log_dir", "--read-only", "rm", "key1"])
+            .current_dir(&temp_dir)
+            .assert()
+            .failure()
+            .stderr(contains("read-only"));
+
+        drop(store);
+        Command::cargo_bin("cli")
+            .unwrap()
+            .args(&["-s", "log", "-l", "log_dir", "set", "key1", "value2"])
+            .current_dir(&temp_dir)
+            .assert()
+            .success();
+
+        Ok(())
+    }
+
+    #[test]
+    fn cli_capture_replay() -> Result<()> {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+        let cli = || {
+            let mut cmd = Command::cargo_bin("cli").unwrap();
+            cmd.current_dir(&temp_dir);
+            cmd
+        };
+        let captured = ["-l", "kvs", "--capture", "ops"];
+
+        cli()
+            .args(&captured)
+            .args(&["set", "key1", "value1"])
+            .assert()
+            .success();
+        cli()
+            .args(&captured)
+            .args(&["set", "key2", "value2"])
+            .assert()
+            .success();
+        cli()
+            .args(&captured)
+            .args(&["rm", "key1"])
+            .assert()
+            .success();
+        cli()
+            .args(&captured)
+            .args(&["get", "key2"])
+            .assert()
+            .success();
+
+        cli()
+            .args(&["-s", "log", "-l", "replayed", "replay", "ops", "--speed"])
+            .arg("1000")
+            .assert()
+            .success()
+            .stdout(contains("replayed 4 operations"));
+        let store = LogKvs::open(temp_dir.path().join("replayed"))?;
+        assert_eq!(store.get("key1".to_owned())?, None);
+        assert_eq!(store.get("key2".to_owned())?, Some("value2".to_owned()));
+        drop(store);
+
+        // only into a new store
+        cli()
+            .args(&["-l", "kvs", "replay", "ops", "--unpaced"])
+            .assert()
+            .failure()
+            .stderr(contains("already exists"));
+
+        Ok(())
+    }
 }
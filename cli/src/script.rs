@@ -0,0 +1,223 @@
+/*!
+ * Command scripts run by `cli run`.
+ *
+ * A script holds one command per line. Blank lines and lines starting with
+ * `#` are ignored.
+ *
+ * - `set <key> <value>`: store the rest of the line as the key's value.
+ * - `rm <key>`: remove the key.
+ * - `assert-get <key> [value]`: fail unless the key holds the value, or
+ *   doesn't exist if no value is given.
+ * - `begin` / `commit`: buffer the writes in between, and only apply them
+ *   to the store at `commit`. If the script fails first, they're dropped.
+ *
+ * The whole script is parsed before anything runs, so a typo never leaves
+ * the store half-updated. Commits are not atomic if the process dies part
+ * way through applying them.
+ */
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use kvs::KvStore;
+
+use crate::errors::CliError;
+
+#[derive(Debug, Eq, PartialEq)]
+enum Statement {
+    Set { key: String, value: String },
+    Remove { key: String },
+    AssertGet { key: String, value: Option<String> },
+    Begin,
+    Commit,
+}
+
+/// A parsed script, ready to run against a store.
+#[derive(Debug)]
+pub(crate) struct Script {
+    /// Each statement, along with the line number it was read from.
+    statements: Vec<(usize, Statement)>,
+}
+
+impl Script {
+    /// Read and parse the script at the given path.
+    pub(crate) fn load(path: &Path) -> Result<Script, ScriptError> {
+        let text = fs::read_to_string(path).map_err(|err| ScriptError {
+            line: None,
+            msg: format!("unable to read {}: {}", path.display(), err),
+        })?;
+        Script::parse(&text)
+    }
+
+    fn parse(text: &str) -> Result<Script, ScriptError> {
+        let mut statements = Vec::new();
+        let mut begun_at = None;
+
+        for (index, line) in text.lines().enumerate() {
+            let line_no = index + 1;
+            let error = |msg: &str| ScriptError {
+                line: Some(line_no),
+                msg: msg.to_owned(),
+            };
+
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (word, rest) = split_word(line);
+            let statement = match word {
+                "set" => match split_word(rest) {
+                    ("", _) | (_, "") => {
+                        return Err(error("usage: set <key> <value>"))
+                    }
+                    (key, value) => Statement::Set {
+                        key: key.to_owned(),
+                        value: value.to_owned(),
+                    },
+                },
+                "rm" => match split_word(rest) {
+                    (key, "") if !key.is_empty() => Statement::Remove {
+                        key: key.to_owned(),
+                    },
+                    _ => return Err(error("usage: rm <key>")),
+                },
+                "assert-get" => match split_word(rest) {
+                    ("", _) => {
+                        return Err(error("usage: assert-get <key> [value]"))
+                    }
+                    (key, value) => Statement::AssertGet {
+                        key: key.to_owned(),
+                        value: Some(value.to_owned())
+                            .filter(|value| !value.is_empty()),
+                    },
+                },
+                "begin" if rest.is_empty() => {
+                    if begun_at.is_some() {
+                        return Err(error("transactions can't be nested"));
+                    }
+                    begun_at = Some(line_no);
+                    Statement::Begin
+                }
+                "commit" if rest.is_empty() => {
+                    if begun_at.take().is_none() {
+                        return Err(error("`commit` without `begin`"));
+                    }
+                    Statement::Commit
+                }
+                "begin" | "commit" => {
+                    return Err(error(&format!("usage: {}", word)))
+                }
+                _ => return Err(error(&format!("unknown command `{}`", word))),
+            };
+            statements.push((line_no, statement));
+        }
+
+        if let Some(line_no) = begun_at {
+            return Err(ScriptError {
+                line: Some(line_no),
+                msg: "`begin` without `commit`".to_owned(),
+            });
+        }
+        Ok(Script { statements })
+    }
+
+    /// Run each statement in order, stopping at the first failure.
+    pub(crate) fn run<S: KvStore + ?Sized>(
+        &self,
+        store: &mut S,
+    ) -> Result<(), CliError> {
+        // writes made since `begin`, with None marking a removal
+        let mut pending: Option<HashMap<&str, Option<&str>>> = None;
+
+        for (line_no, statement) in &self.statements {
+            match statement {
+                Statement::Set { key, value } => match pending.as_mut() {
+                    Some(pending) => {
+                        pending.insert(key, Some(value));
+                    }
+                    None => store
+                        .set(key.clone(), value.clone())
+                        .map_err(CliError::Store)?,
+                },
+                Statement::Remove { key } => match pending.as_mut() {
+                    Some(pending) => {
+                        pending.insert(key, None);
+                    }
+                    None => {
+                        store.remove_ref(key).map_err(CliError::Store)?;
+                    }
+                },
+                Statement::AssertGet { key, value } => {
+                    let actual = match pending
+                        .as_ref()
+                        .and_then(|pending| pending.get(key.as_str()))
+                    {
+                        Some(actual) => actual.map(str::to_owned),
+                        None => {
+                            store.get(key.clone()).map_err(CliError::Store)?
+                        }
+                    };
+                    if actual != *value {
+                        return Err(CliError::Script(ScriptError {
+                            line: Some(*line_no),
+                            msg: format!(
+                                "expected {} to be {}, found {}",
+                                key,
+                                describe(value),
+                                describe(&actual)
+                            ),
+                        }));
+                    }
+                }
+                Statement::Begin => pending = Some(HashMap::new()),
+                Statement::Commit => {
+                    for (key, value) in pending.take().unwrap_or_default() {
+                        match value {
+                            Some(value) => {
+                                store.set(key.to_owned(), value.to_owned())
+                            }
+                            None => store.remove_ref(key).map(|_| ()),
+                        }
+                        .map_err(CliError::Store)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Split off the first whitespace-separated word of a line.
+fn split_word(line: &str) -> (&str, &str) {
+    match line.find(char::is_whitespace) {
+        Some(index) => (&line[..index], line[index..].trim_start()),
+        None => (line, ""),
+    }
+}
+
+fn describe(value: &Option<String>) -> String {
+    match value {
+        Some(value) => format!("`{}`", value),
+        None => "missing".to_owned(),
+    }
+}
+
+/// A script that couldn't be read or parsed, or whose assertion failed.
+#[derive(Debug)]
+pub(crate) struct ScriptError {
+    /// The line the error happened on, if it's tied to one.
+    line: Option<usize>,
+    msg: String,
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "line {}: {}", line, self.msg),
+            None => write!(f, "{}", self.msg),
+        }
+    }
+}
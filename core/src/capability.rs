@@ -0,0 +1,31 @@
+/*!
+ * Optional operations, so callers can check for them before trying them.
+ */
+
+use crate::libcore::fmt;
+
+/// An operation that only some stores support.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Capability {
+    /// Reclaiming space taken by stale records, see
+    /// [`Compactable`](crate::Compactable).
+    Compaction,
+    /// Listing keys in order, see [`Scannable::scan`](crate::Scannable::scan).
+    OrderedScan,
+    /// Backing up only what was written since the last backup.
+    IncrementalBackup,
+    /// Reading files with direct I/O, see
+    /// [`StoreOptions::direct_io`](crate::StoreOptions::direct_io).
+    DirectIo,
+}
+
+impl fmt::Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Capability::Compaction => write!(f, "compaction"),
+            Capability::OrderedScan => write!(f, "ordered scans"),
+            Capability::IncrementalBackup => write!(f, "incremental backups"),
+            Capability::DirectIo => write!(f, "direct I/O"),
+        }
+    }
+}
@@ -0,0 +1,31 @@
+/*!
+ * Where stores get the time from, so behavior that depends on it can be
+ * tested without waiting.
+ */
+
+use std::fmt::Debug;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+/// A source of the current time, given to a store with
+/// [`StoreOptions::clock`](crate::StoreOptions::clock).
+pub trait Clock: Debug + Send + Sync {
+    /// The current time.
+    fn now(&self) -> SystemTime;
+}
+
+/// The clock stores use unless given another, reading the system time.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// The clock given in a store's options, or the system clock if there
+/// wasn't one.
+pub fn clock_or_system(clock: Option<Arc<dyn Clock>>) -> Arc<dyn Clock> {
+    clock.unwrap_or_else(|| Arc::new(SystemClock))
+}
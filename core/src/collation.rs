@@ -0,0 +1,110 @@
+/*!
+ * Comparing keys in each [`Collation`], for scans and range queries.
+ */
+
+use alloc::format;
+
+use crate::libcore::cmp::Ordering;
+use crate::libcore::fmt;
+use crate::libcore::str::FromStr;
+use crate::{Collation, Error, Result};
+
+impl Collation {
+    /// The order of two keys.
+    pub fn compare(self, a: &str, b: &str) -> Ordering {
+        let collated = match self {
+            Collation::Bytewise => Ordering::Equal,
+            Collation::CaseInsensitive => a
+                .chars()
+                .flat_map(char::to_lowercase)
+                .cmp(b.chars().flat_map(char::to_lowercase)),
+            Collation::Numeric => compare_numeric(a, b),
+        };
+        collated.then_with(|| a.cmp(b))
+    }
+
+    /// Whether the key is in the range from `start` up to but not
+    /// including `end`, or up to the last key if there's no end, in this
+    /// order. See [`in_range`](crate::in_range).
+    pub fn in_range(self, key: &str, start: &str, end: Option<&str>) -> bool {
+        self.compare(start, key) != Ordering::Greater
+            && match end {
+                Some(end) => self.compare(key, end) == Ordering::Less,
+                None => true,
+            }
+    }
+
+    /// The name the collation is parsed from.
+    pub fn name(self) -> &'static str {
+        match self {
+            Collation::Bytewise => "bytewise",
+            Collation::CaseInsensitive => "case-insensitive",
+            Collation::Numeric => "numeric",
+        }
+    }
+}
+
+impl fmt::Display for Collation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Collation {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Collation> {
+        match s {
+            "bytewise" => Ok(Collation::Bytewise),
+            "case-insensitive" => Ok(Collation::CaseInsensitive),
+            "numeric" => Ok(Collation::Numeric),
+            _ => Err(Error::config(format!(
+                "unknown collation '{}', expected `bytewise`, \
+                 `case-insensitive` or `numeric`",
+                s
+            ))),
+        }
+    }
+}
+
+/// Compare runs of digits by their value and everything else bytewise.
+/// Numbers with the same value, like `7` and `007`, compare the same.
+fn compare_numeric(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a, b);
+    loop {
+        let (a_run, a_rest) = next_run(a);
+        let (b_run, b_rest) = next_run(b);
+        if a_run.is_empty() || b_run.is_empty() {
+            return a_run.len().cmp(&b_run.len());
+        }
+        let digits = |run: &str| run.as_bytes()[0].is_ascii_digit();
+        let order = if digits(a_run) && digits(b_run) {
+            let a_value = a_run.trim_start_matches('0');
+            let b_value = b_run.trim_start_matches('0');
+            a_value
+                .len()
+                .cmp(&b_value.len())
+                .then_with(|| a_value.cmp(b_value))
+        } else {
+            a_run.cmp(b_run)
+        };
+        if order != Ordering::Equal {
+            return order;
+        }
+        a = a_rest;
+        b = b_rest;
+    }
+}
+
+/// Split off the leading run of digits, or of anything else.
+fn next_run(s: &str) -> (&str, &str) {
+    let digits = match s.bytes().next() {
+        Some(first) => first.is_ascii_digit(),
+        None => return ("", ""),
+    };
+    let len = s
+        .bytes()
+        .position(|byte| byte.is_ascii_digit() != digits)
+        .unwrap_or(s.len());
+    s.split_at(len)
+}
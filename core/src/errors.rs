@@ -1,122 +1,282 @@
-use std::fmt;
+use alloc::borrow::ToOwned;
+use alloc::boxed::Box;
+use alloc::string::{String, ToString};
+#[cfg(feature = "std")]
 use std::io;
+#[cfg(feature = "std")]
+use std::path::{Path, PathBuf};
 
-use failure::{Backtrace, Context, Fail};
+use crate::libcore::error::Error as StdError;
+use crate::libcore::fmt;
+use crate::Capability;
 
 /// A type alias for handling errors throughout the kvs library.
-pub type Result<T> = std::result::Result<T, Error>;
+pub type Result<T> = crate::libcore::result::Result<T, Error>;
 
-/// An error that can occur while interacting with the kvs.
+/// An error that can occur while interacting with the kvs. Implements
+/// `std::error::Error`, so it can be returned with `?` from functions that
+/// return a boxed error, and the error that caused it, if any, is available
+/// from `source`.
 #[derive(Debug)]
 pub struct Error {
-    ctx: Context<ErrorKind>,
+    kind: ErrorKind,
+    source: Option<Box<dyn StdError + Send + Sync + 'static>>,
 }
 
 impl Error {
     /// Return the kind of this error.
     pub fn kind(&self) -> &ErrorKind {
-        self.ctx.get_context()
+        &self.kind
     }
 
-    /// Shortcut for constructing an Io error.
-    pub fn io(err: io::Error) -> Error {
-        Error::from(ErrorKind::Io(err.to_string()))
+    /// Construct an error of the given kind, caused by another error.
+    pub fn with_source<E>(kind: ErrorKind, source: E) -> Error
+    where
+        E: StdError + Send + Sync + 'static,
+    {
+        Error {
+            kind,
+            source: Some(Box::new(source)),
+        }
     }
 
-    // TODO: find way to remove serde_json and bincode dependencies just for
-    // error handling
-    /// Shortcut for constructing a Serde error.
-    pub fn serde(err: serde_json::Error) -> Error {
-        Error::from(ErrorKind::Serde(err.to_string()))
+    /// Shortcut for constructing an Io error.
+    #[cfg(feature = "std")]
+    pub fn io(err: io::Error) -> Error {
+        let kind = ErrorKind::Io {
+            #[cfg(feature = "std")]
+            path: None,
+            message: err.to_string(),
+        };
+        Error::with_source(kind, err)
     }
 
-    /// Shortcut for constructing a Serde error from a Bincode error.
-    pub fn bincode(err: bincode::Error) -> Error {
-        Error::from(ErrorKind::Serde(err.to_string()))
+    /// Shortcut for constructing a Serde error from whatever error the
+    /// format being read or written gave.
+    pub fn serialization<D: fmt::Display>(err: D) -> Error {
+        Error::from(ErrorKind::Serde {
+            message: err.to_string(),
+        })
     }
 
     /// Shortcut for constructing a CorruptDatabase error
     pub fn corrupt_database(msg: String) -> Error {
-        Error::from(ErrorKind::CorruptDatabase(msg))
+        Error::from(ErrorKind::CorruptDatabase {
+            #[cfg(feature = "std")]
+            path: None,
+            message: msg,
+        })
     }
 
-    // /// Shortcut for constructing a KeyDoesNotExist error.
-    // pub(crate) fn key_does_not_exist<T: AsRef<str>>(key: T) -> Error {
-    //     Error::from(ErrorKind::KeyDoesNotExist(key.as_ref().to_string()))
-    // }
-}
+    /// Shortcut for constructing a Config error
+    pub fn config(msg: String) -> Error {
+        Error::from(ErrorKind::Config { message: msg })
+    }
+
+    /// Shortcut for constructing a Locked error
+    pub fn locked(msg: String) -> Error {
+        Error::from(ErrorKind::Locked { message: msg })
+    }
+
+    /// Shortcut for constructing an Unsupported error
+    pub fn unsupported(capability: Capability) -> Error {
+        Error::from(ErrorKind::Unsupported { capability })
+    }
+
+    /// Shortcut for constructing a Rejected error
+    pub fn rejected(msg: String) -> Error {
+        Error::from(ErrorKind::Rejected {
+            key: None,
+            message: msg,
+        })
+    }
+
+    /// Shortcut for constructing a Rejected error for a write to a key.
+    pub fn rejected_key<T: AsRef<str>>(key: T, msg: String) -> Error {
+        Error::from(ErrorKind::Rejected {
+            key: Some(key.as_ref().to_owned()),
+            message: msg,
+        })
+    }
 
-impl Fail for Error {
-    fn cause(&self) -> Option<&dyn Fail> {
-        self.ctx.cause()
+    /// Shortcut for constructing a KeyDoesNotExist error.
+    pub fn key_does_not_exist<T: AsRef<str>>(key: T) -> Error {
+        Error::from(ErrorKind::KeyDoesNotExist {
+            key: key.as_ref().to_owned(),
+        })
     }
 
-    fn backtrace(&self) -> Option<&Backtrace> {
-        self.ctx.backtrace()
+    /// Record the file or store an Io or CorruptDatabase error happened in,
+    /// unless a path was already given. Other kinds of error are returned
+    /// as they are.
+    #[cfg(feature = "std")]
+    pub fn at_path<P: AsRef<Path>>(mut self, at: P) -> Error {
+        match &mut self.kind {
+            ErrorKind::Io { path, .. }
+            | ErrorKind::CorruptDatabase { path, .. }
+                if path.is_none() =>
+            {
+                *path = Some(at.as_ref().to_owned());
+            }
+            _ => {}
+        }
+        self
+    }
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match &self.source {
+            Some(source) => Some(source.as_ref()),
+            None => None,
+        }
     }
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        self.ctx.fmt(f)
+        self.kind.fmt(f)
     }
 }
 
-/// The error type for the class
+/// The error type for the class. More kinds may be added, so matches on it
+/// need a wildcard arm outside this crate.
 #[derive(Clone, Debug, Eq, PartialEq)]
+#[non_exhaustive]
 pub enum ErrorKind {
     /// An unexpected I/O error occurred.
-    Io(String),
+    Io {
+        /// The file or store it occurred in, if known. Only with the `std`
+        /// feature.
+        #[cfg(feature = "std")]
+        path: Option<PathBuf>,
+        /// What went wrong.
+        message: String,
+    },
     /// An error occured while serializing or deserializing data
-    Serde(String),
-    /* /// An error while looking for an entry in the key-value store.
-     * ///
-     * /// The key does not exist.
-     * KeyDoesNotExist(String), */
+    Serde {
+        /// What couldn't be read or written, and why.
+        message: String,
+    },
+    /// The key does not exist. Only returned where a missing key is asked
+    /// to be treated as an error, see
+    /// [`KvStore::get_strict`](crate::KvStore::get_strict).
+    KeyDoesNotExist {
+        /// The key asked for.
+        key: String,
+    },
     /// The database has been corrupted (has an inconsistent state).
-    CorruptDatabase(String),
+    CorruptDatabase {
+        /// The file or store that's corrupt, if known. Only with the `std`
+        /// feature.
+        #[cfg(feature = "std")]
+        path: Option<PathBuf>,
+        /// What was found to be wrong.
+        message: String,
+    },
+    /// The store was given invalid or missing settings.
+    Config {
+        /// What was wrong with the settings.
+        message: String,
+    },
+    /// The store is already open for writing by another handle, in this
+    /// process or another.
+    Locked {
+        /// Which store is locked, and what can be done instead.
+        message: String,
+    },
+    /// The store does not support the requested operation.
+    Unsupported {
+        /// What the store would need to support.
+        capability: Capability,
+    },
+    /// A write was refused by a check the store was set up with, such as a
+    /// validator registered with a `HookKvs`.
+    Rejected {
+        /// The key written, if the write was to a single key.
+        key: Option<String>,
+        /// Why the write was refused.
+        message: String,
+    },
 }
 
-impl fmt::Display for ErrorKind {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match *self {
-            ErrorKind::Io(ref msg) => write!(f, "I/O error: {}", msg),
-            ErrorKind::Serde(ref msg) => write!(f, "Serde error: {}", msg),
-            ErrorKind::CorruptDatabase(ref msg) => {
-                write!(f, "CorruptDatabase error: {}", msg)
-            } /* ErrorKind::KeyDoesNotExist(ref key) => {
-               *     write!(f, "key does not exist: {}", key)
-               * } */
+impl ErrorKind {
+    /// The file or store the error happened in, if known.
+    #[cfg(feature = "std")]
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            ErrorKind::Io { path, .. }
+            | ErrorKind::CorruptDatabase { path, .. } => path.as_deref(),
+            _ => None,
         }
     }
-}
 
-impl From<io::Error> for Error {
-    fn from(err: io::Error) -> Error {
-        Error::io(err)
+    /// The key the error is about, if it's about one.
+    pub fn key(&self) -> Option<&str> {
+        match self {
+            ErrorKind::KeyDoesNotExist { key } => Some(key),
+            ErrorKind::Rejected { key, .. } => key.as_deref(),
+            _ => None,
+        }
     }
-}
 
-impl From<serde_json::Error> for Error {
-    fn from(err: serde_json::Error) -> Error {
-        Error::serde(err)
+    /// What the store would need to support, for an Unsupported error.
+    pub fn capability(&self) -> Option<Capability> {
+        match self {
+            ErrorKind::Unsupported { capability } => Some(*capability),
+            _ => None,
+        }
     }
 }
 
-impl From<bincode::Error> for Error {
-    fn from(err: bincode::Error) -> Error {
-        Error::bincode(err)
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ErrorKind::Io { message, .. } => {
+                write!(f, "I/O error: ")?;
+                #[cfg(feature = "std")]
+                {
+                    if let Some(path) = self.path() {
+                        write!(f, "{}: ", path.display())?;
+                    }
+                }
+                write!(f, "{}", message)
+            }
+            ErrorKind::Serde { message } => {
+                write!(f, "Serde error: {}", message)
+            }
+            // messages about corruption already say where it was found
+            ErrorKind::CorruptDatabase { message, .. } => {
+                write!(f, "CorruptDatabase error: {}", message)
+            }
+            ErrorKind::Config { message } => {
+                write!(f, "Config error: {}", message)
+            }
+            ErrorKind::Locked { message } => {
+                write!(f, "Locked error: {}", message)
+            }
+            ErrorKind::Unsupported { capability } => {
+                write!(f, "Unsupported error: {}", capability)
+            }
+            ErrorKind::KeyDoesNotExist { key } => {
+                write!(f, "key does not exist: {}", key)
+            }
+            ErrorKind::Rejected { message, .. } => {
+                write!(f, "Rejected: {}", message)
+            }
+        }
     }
 }
 
-impl From<ErrorKind> for Error {
-    fn from(kind: ErrorKind) -> Error {
-        Error::from(Context::new(kind))
+#[cfg(feature = "std")]
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::io(err)
     }
 }
 
-impl From<Context<ErrorKind>> for Error {
-    fn from(ctx: Context<ErrorKind>) -> Error {
-        Error { ctx }
+impl From<ErrorKind> for Error {
+    fn from(kind: ErrorKind) -> Error {
+        Error { kind, source: None }
     }
 }
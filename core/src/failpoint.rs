@@ -0,0 +1,122 @@
+/*!
+ * Named points in the stores where a failure can be injected, so tests and
+ * chaos tooling can check what happens when a write stops at exactly that
+ * point.
+ *
+ * Failpoints only do anything with the `failpoints` feature, and otherwise
+ * compile down to nothing. They're configured for the current thread with
+ * [`set_fail_point`], so tests running in parallel don't trip each other's,
+ * or for every thread through the `KVS_FAILPOINTS` environment variable, a
+ * `;` separated list of `<name>=<action>`, with the actions `error` and
+ * `panic`.
+ *
+ * The failpoints are:
+ *
+ * - `log::after_append`: a value's been appended to the log, but isn't in
+ *   the index yet.
+ * - `overwrite::rename`: a rewritten file, such as the log when it's
+ *   compacted, is being swapped in, and the old one's been moved aside.
+ * - `backup::after_manifest`: a backup's manifest has been written.
+ */
+
+#[cfg(feature = "failpoints")]
+use std::cell::RefCell;
+#[cfg(feature = "failpoints")]
+use std::collections::HashMap;
+#[cfg(feature = "failpoints")]
+use std::str::FromStr;
+
+use crate::Result;
+#[cfg(feature = "failpoints")]
+use crate::{Error, ErrorKind};
+
+/// The environment variable failpoints are configured from for every
+/// thread.
+pub const FAIL_POINTS_ENV: &str = "KVS_FAILPOINTS";
+
+/// What a failpoint does when it's reached.
+#[cfg(feature = "failpoints")]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FailAction {
+    /// Return an `Io` error, as if the disk had failed.
+    Error,
+    /// Panic, as if the process had crashed.
+    Panic,
+}
+
+#[cfg(feature = "failpoints")]
+impl FromStr for FailAction {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<FailAction> {
+        match s {
+            "error" => Ok(FailAction::Error),
+            "panic" => Ok(FailAction::Panic),
+            _ => Err(Error::config(format!(
+                "unknown failpoint action '{}', expected `error` or `panic`",
+                s
+            ))),
+        }
+    }
+}
+
+#[cfg(feature = "failpoints")]
+thread_local! {
+    static FAIL_POINTS: RefCell<HashMap<String, FailAction>> =
+        RefCell::new(from_env());
+}
+
+/// The failpoints configured in the environment. Ones that can't be parsed
+/// are left out, rather than failing wherever they're first checked.
+#[cfg(feature = "failpoints")]
+fn from_env() -> HashMap<String, FailAction> {
+    let config = std::env::var(FAIL_POINTS_ENV).unwrap_or_default();
+    config
+        .split(';')
+        .filter_map(|point| {
+            let mut parts = point.splitn(2, '=');
+            let name = parts.next()?.trim();
+            let action = parts.next()?.trim().parse().ok()?;
+            Some((name.to_owned(), action))
+        })
+        .collect()
+}
+
+/// Make the named failpoint take the action whenever the current thread
+/// reaches it, until it's cleared.
+#[cfg(feature = "failpoints")]
+pub fn set_fail_point(name: &str, action: FailAction) {
+    FAIL_POINTS.with(|points| {
+        points.borrow_mut().insert(name.to_owned(), action);
+    });
+}
+
+/// Stop the named failpoint doing anything on the current thread.
+#[cfg(feature = "failpoints")]
+pub fn clear_fail_point(name: &str) {
+    FAIL_POINTS.with(|points| {
+        points.borrow_mut().remove(name);
+    });
+}
+
+/// Check the named failpoint, failing as it's configured to if it is.
+#[cfg(feature = "failpoints")]
+pub fn fail_point(name: &str) -> Result<()> {
+    let action = FAIL_POINTS.with(|points| points.borrow().get(name).copied());
+    match action {
+        None => Ok(()),
+        Some(FailAction::Error) => Err(Error::from(ErrorKind::Io {
+            path: None,
+            message: format!("failpoint {} triggered", name),
+        })),
+        Some(FailAction::Panic) => panic!("failpoint {} triggered", name),
+    }
+}
+
+/// Check the named failpoint. Always passes without the `failpoints`
+/// feature.
+#[cfg(not(feature = "failpoints"))]
+#[inline(always)]
+pub fn fail_point(_name: &str) -> Result<()> {
+    Ok(())
+}
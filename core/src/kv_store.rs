@@ -1,17 +1,148 @@
-use crate::Result;
+use alloc::borrow::{Cow, ToOwned};
+use alloc::boxed::Box;
+use alloc::string::String;
+#[cfg(feature = "std")]
+use std::io::Read;
+
+use crate::{Error, Result};
 
 /// Trait for the key value store
+///
+/// This trait is object safe, so stores can be picked at runtime and used as
+/// `Box<dyn KvStore>`. Keep it that way: methods with type parameters belong
+/// on [`KvStoreExt`](crate::KvStoreExt) instead.
 pub trait KvStore {
     /// Set a value. If the key already existed, the old value is overwritten.
     fn set(&mut self, key: String, value: String) -> Result<()>;
 
+    /// Set a value read from the reader, which must be UTF-8. By default the
+    /// whole value is read into memory first, stores that can write it as
+    /// it's read should override this. Only with the `std` feature.
+    #[cfg(feature = "std")]
+    fn set_from_reader(
+        &mut self,
+        key: String,
+        value: &mut dyn Read,
+    ) -> Result<()> {
+        let mut buf = String::new();
+        value.read_to_string(&mut buf)?;
+        self.set(key, buf)
+    }
+
     /// Retrieve the value of a key. If the key does not exist, return None.
     /// Return an error if the value is not read successfully.
     fn get(&self, key: String) -> Result<Option<String>>;
 
+    /// Retrieve the value of a key without copying it, where the store can
+    /// lend out a value it holds in memory. Stores that have to read the
+    /// value anyway return it owned, which is the default.
+    fn get_ref(&self, key: &str) -> Result<Option<Cow<'_, str>>> {
+        Ok(self.get(key.to_owned())?.map(Cow::Owned))
+    }
+
+    /// Whether the key has a value. By default the value is read and thrown
+    /// away, stores that can tell without reading it should override this.
+    fn contains_key(&self, key: &str) -> Result<bool> {
+        Ok(self.get_ref(key)?.is_some())
+    }
+
     /// Remove a key-value, returning the value. If the key does not exist,
     /// return None. Return an error if the key is not removed successfully.
     fn remove(&mut self, key: String) -> Result<Option<String>>;
+
+    /// Remove a key-value, returning the value, without needing an owned
+    /// key. By default the key is copied and passed to `remove`.
+    fn remove_ref(&mut self, key: &str) -> Result<Option<String>> {
+        self.remove(key.to_owned())
+    }
+
+    /// Retrieve the value of a key, treating a missing key as a
+    /// `KeyDoesNotExist` error rather than None.
+    fn get_strict(&self, key: String) -> Result<String> {
+        match self.get(key.clone())? {
+            Some(value) => Ok(value),
+            None => Err(Error::key_does_not_exist(key)),
+        }
+    }
+
+    /// Remove a key-value, returning the value, treating a missing key as a
+    /// `KeyDoesNotExist` error rather than None.
+    fn remove_strict(&mut self, key: String) -> Result<String> {
+        match self.remove(key.clone())? {
+            Some(value) => Ok(value),
+            None => Err(Error::key_does_not_exist(key)),
+        }
+    }
+}
+
+impl<S: KvStore + ?Sized> KvStore for Box<S> {
+    fn set(&mut self, key: String, value: String) -> Result<()> {
+        (**self).set(key, value)
+    }
+
+    #[cfg(feature = "std")]
+    fn set_from_reader(
+        &mut self,
+        key: String,
+        value: &mut dyn Read,
+    ) -> Result<()> {
+        (**self).set_from_reader(key, value)
+    }
+
+    fn get(&self, key: String) -> Result<Option<String>> {
+        (**self).get(key)
+    }
+
+    fn get_ref(&self, key: &str) -> Result<Option<Cow<'_, str>>> {
+        (**self).get_ref(key)
+    }
+
+    fn contains_key(&self, key: &str) -> Result<bool> {
+        (**self).contains_key(key)
+    }
+
+    fn remove(&mut self, key: String) -> Result<Option<String>> {
+        (**self).remove(key)
+    }
+
+    fn remove_ref(&mut self, key: &str) -> Result<Option<String>> {
+        (**self).remove_ref(key)
+    }
+}
+
+impl<S: KvStore + ?Sized> KvStore for &mut S {
+    fn set(&mut self, key: String, value: String) -> Result<()> {
+        (**self).set(key, value)
+    }
+
+    #[cfg(feature = "std")]
+    fn set_from_reader(
+        &mut self,
+        key: String,
+        value: &mut dyn Read,
+    ) -> Result<()> {
+        (**self).set_from_reader(key, value)
+    }
+
+    fn get(&self, key: String) -> Result<Option<String>> {
+        (**self).get(key)
+    }
+
+    fn get_ref(&self, key: &str) -> Result<Option<Cow<'_, str>>> {
+        (**self).get_ref(key)
+    }
+
+    fn contains_key(&self, key: &str) -> Result<bool> {
+        (**self).contains_key(key)
+    }
+
+    fn remove(&mut self, key: String) -> Result<Option<String>> {
+        (**self).remove(key)
+    }
+
+    fn remove_ref(&mut self, key: &str) -> Result<Option<String>> {
+        (**self).remove_ref(key)
+    }
 }
 
 #[cfg(feature = "impl-tests")]
@@ -20,7 +151,7 @@ pub mod kv_store_tests {
     use super::*;
 
     use crate::tests::{TestContext, Testable};
-    use crate::Persistent;
+    use crate::{ErrorKind, KvStoreExt, Persistent};
 
     impl<S> CoreTests for S where S: Persistent + Testable {}
 
@@ -36,7 +167,13 @@ pub mod kv_store_tests {
                 test_overwrite_value,
                 test_get_nonexistent_value,
                 test_remove_non_existent_key,
-                test_remove_key
+                test_remove_key,
+                test_strict,
+                test_get_ref,
+                test_remove_ref,
+                test_contains_key,
+                test_typed_values,
+                test_set_from_reader
             );
         };
     }
@@ -120,5 +257,139 @@ pub mod kv_store_tests {
 
             Ok(())
         }
+
+        /// Should report missing keys as errors only when asked to
+        fn test_strict() -> Result<()> {
+            let context = Self::Context::init();
+            let mut store: Self = context.open_store()?;
+
+            store.set("key1".to_owned(), "value1".to_owned())?;
+            assert_eq!(store.get_strict("key1".to_owned())?, "value1");
+            assert_eq!(store.remove_strict("key1".to_owned())?, "value1");
+
+            let missing = ErrorKind::KeyDoesNotExist {
+                key: "key1".to_owned(),
+            };
+            let dynamic: &mut dyn KvStore = &mut store;
+            assert_eq!(
+                dynamic.get_strict("key1".to_owned()).unwrap_err().kind(),
+                &missing
+            );
+            assert_eq!(
+                dynamic.remove_strict("key1".to_owned()).unwrap_err().kind(),
+                &missing
+            );
+
+            Ok(())
+        }
+
+        /// Should lend out the same values get returns
+        fn test_get_ref() -> Result<()> {
+            let context = Self::Context::init();
+            let mut store: Self = context.open_store()?;
+
+            store.set("key1".to_owned(), "value1".to_owned())?;
+            assert_eq!(store.get_ref("key1")?, Some(Cow::from("value1")));
+            assert_eq!(store.get_ref("key2")?, None);
+
+            let dynamic: &dyn KvStore = &store;
+            assert_eq!(dynamic.get_ref("key1")?, Some(Cow::from("value1")));
+
+            Ok(())
+        }
+
+        /// Should only contain keys with values
+        fn test_contains_key() -> Result<()> {
+            let context = Self::Context::init();
+            let mut store: Self = context.open_store()?;
+
+            assert!(!store.contains_key("key1")?);
+            store.set("key1".to_owned(), "value1".to_owned())?;
+            assert!(store.contains_key("key1")?);
+            store.remove("key1".to_owned())?;
+            assert!(!store.contains_key("key1")?);
+
+            store.set("key2".to_owned(), String::new())?;
+            let dynamic: &dyn KvStore = &store;
+            assert!(dynamic.contains_key("key2")?);
+
+            Ok(())
+        }
+
+        /// Should remove keys given by reference
+        fn test_remove_ref() -> Result<()> {
+            let context = Self::Context::init();
+            let mut store: Self = context.open_store()?;
+
+            store.set("key1".to_owned(), "value1".to_owned())?;
+            assert_eq!(store.remove_ref("key1")?, Some("value1".to_owned()));
+            assert_eq!(store.remove_ref("key1")?, None);
+            assert_eq!(store.get_ref("key1")?, None);
+
+            drop(store);
+            let store: Self = context.open_store()?;
+            assert_eq!(store.get_ref("key1")?, None);
+
+            Ok(())
+        }
+
+        /// Should round-trip typed values, including through a trait object
+        fn test_typed_values() -> Result<()> {
+            let context = Self::Context::init();
+            let mut store: Self = context.open_store()?;
+
+            store.set_as("key1".to_owned(), &vec![1u32, 2, 3])?;
+            assert_eq!(
+                store.get("key1".to_owned())?,
+                Some("[1,2,3]".to_owned())
+            );
+
+            let dynamic: &mut dyn KvStore = &mut store;
+            dynamic.set_as("key2".to_owned(), &Some(4u32))?;
+            assert_eq!(
+                dynamic.get_as::<Vec<u32>>("key1".to_owned())?,
+                Some(vec![1, 2, 3])
+            );
+            assert_eq!(
+                dynamic.remove_as::<Option<u32>>("key2".to_owned())?,
+                Some(Some(4))
+            );
+            assert_eq!(dynamic.get_as::<Option<u32>>("key2".to_owned())?, None);
+
+            store.set("key3".to_owned(), "not json".to_owned())?;
+            assert!(store.get_as::<u32>("key3".to_owned()).is_err());
+
+            Ok(())
+        }
+
+        /// Should store a value read from a reader, and reject one that
+        /// isn't UTF-8
+        fn test_set_from_reader() -> Result<()> {
+            let context = Self::Context::init();
+            let mut store: Self = context.open_store()?;
+
+            let value = "line1\nline2 ✓\n".repeat(1000);
+            store.set_from_reader("key1".to_owned(), &mut value.as_bytes())?;
+            store.set("key2".to_owned(), "value2".to_owned())?;
+            assert_eq!(store.get("key1".to_owned())?, Some(value.clone()));
+
+            assert!(store
+                .set_from_reader("key2".to_owned(), &mut &b"bad \xff"[..])
+                .is_err());
+            assert_eq!(
+                store.get("key2".to_owned())?,
+                Some("value2".to_owned())
+            );
+
+            drop(store);
+            let store: Self = context.open_store()?;
+            assert_eq!(store.get("key1".to_owned())?, Some(value));
+            assert_eq!(
+                store.get("key2".to_owned())?,
+                Some("value2".to_owned())
+            );
+
+            Ok(())
+        }
     }
 }
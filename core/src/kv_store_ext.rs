@@ -0,0 +1,48 @@
+use alloc::string::String;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::{Error, KvStore, Result};
+
+/// Generic helpers built on top of [`KvStore`].
+///
+/// These methods have type parameters, which would stop `KvStore` from being
+/// used as a trait object. They are implemented for every store, including
+/// `dyn KvStore`, so importing this trait is all that's needed to use them.
+pub trait KvStoreExt: KvStore {
+    /// Set a value, storing it as JSON.
+    fn set_as<V: Serialize>(&mut self, key: String, value: &V) -> Result<()> {
+        let value =
+            serde_json::to_string(value).map_err(Error::serialization)?;
+        self.set(key, value)
+    }
+
+    /// Retrieve a value stored as JSON. If the key does not exist, return
+    /// None. Return an error if the value is not valid JSON for `V`.
+    fn get_as<V: DeserializeOwned>(&self, key: String) -> Result<Option<V>> {
+        match self.get(key)? {
+            Some(value) => Ok(Some(
+                serde_json::from_str(&value).map_err(Error::serialization)?,
+            )),
+            None => Ok(None),
+        }
+    }
+
+    /// Remove a key-value stored as JSON, returning the value. If the key
+    /// does not exist, return None. The key is removed even if the value is
+    /// not valid JSON for `V`.
+    fn remove_as<V: DeserializeOwned>(
+        &mut self,
+        key: String,
+    ) -> Result<Option<V>> {
+        match self.remove(key)? {
+            Some(value) => Ok(Some(
+                serde_json::from_str(&value).map_err(Error::serialization)?,
+            )),
+            None => Ok(None),
+        }
+    }
+}
+
+impl<S: KvStore + ?Sized> KvStoreExt for S {}
@@ -1,22 +1,81 @@
+#![cfg_attr(not(feature = "std"), no_std)]
 #![deny(missing_docs)]
 
 /*!
 A library containing the basic traits and common code for key-value storage.
+
+Everything that needs an operating system, like opening a `Persistent`
+store from a path, is behind the default `std` feature. Without it, the
+store traits and error types only need `alloc`, so stores can be written
+for embedded targets, such as ones kept on a flash filesystem.
 */
 
+extern crate alloc;
+
+// The standard `core` library, which can't be reached as `core` from the
+// doctests, since it's this crate's name there. `std` re-exports everything
+// used from it.
+#[cfg(not(feature = "std"))]
+use core as libcore;
+#[cfg(feature = "std")]
+use std as libcore;
+
 // TODO: Investigate why feature flags don't seem to be enforced
 // must be first because it contains a macro used in kv_store and compactable
 #[cfg(feature = "impl-tests")]
 pub mod tests;
 
+#[cfg(feature = "impl-tests")]
+pub mod simulation;
+
 mod kv_store;
 pub use self::kv_store::*;
 
+mod kv_store_ext;
+pub use self::kv_store_ext::*;
+
+#[cfg(feature = "std")]
 mod persistent;
+#[cfg(feature = "std")]
 pub use self::persistent::*;
 
+mod options;
+pub use self::options::*;
+
+mod observer;
+pub use self::observer::*;
+
+mod capability;
+pub use self::capability::*;
+
+#[cfg(feature = "std")]
 mod compactable;
+#[cfg(feature = "std")]
 pub use self::compactable::*;
 
+mod stats;
+pub use self::stats::*;
+
+#[cfg(feature = "std")]
+mod scrub;
+#[cfg(feature = "std")]
+pub use self::scrub::*;
+
+mod scan;
+pub use self::scan::*;
+
+mod collation;
+
+mod trash;
+pub use self::trash::*;
+
+#[cfg(feature = "std")]
+mod clock;
+#[cfg(feature = "std")]
+pub use self::clock::*;
+
+mod failpoint;
+pub use self::failpoint::*;
+
 mod errors;
 pub use self::errors::*;
@@ -0,0 +1,110 @@
+/*!
+ * Hooks that let an embedding application follow what a store does.
+ */
+
+use alloc::string::String;
+use alloc::sync::Arc;
+
+use crate::libcore::fmt;
+use crate::libcore::time::Duration;
+use crate::{Error, Result};
+
+/// Called as a store is used, so embedding applications can feed their own
+/// metrics or audit pipelines. Register one with
+/// [`StoreOptions::observer`](crate::StoreOptions::observer) when opening the
+/// store. Every hook does nothing by default.
+///
+/// Hooks are called after the operation, on the thread that ran it, so they
+/// should return quickly.
+pub trait StoreObserver: fmt::Debug + Send + Sync {
+    /// A key was read, whether or not it had a value.
+    fn on_get(&self, _key: &str) {}
+
+    /// A key was set to a value of the given length in bytes.
+    fn on_set(&self, _key: &str, _value_len: u64) {}
+
+    /// A key was removed. Not called when removing a key that isn't there.
+    fn on_remove(&self, _key: &str) {}
+
+    /// A compaction is starting.
+    fn on_compaction_start(&self) {}
+
+    /// A compaction finished successfully. If it fails, `on_error` is
+    /// called instead.
+    fn on_compaction_end(&self) {}
+
+    /// An operation, such as `"set"` or `"compact"`, returned an error.
+    fn on_error(&self, _operation: &str, _err: &Error) {}
+
+    /// The store crossed a threshold that suggests writes are about to
+    /// back up, see [`FlowWarning`].
+    fn on_warning(&self, _warning: &FlowWarning) {}
+
+    /// Building the index from the store's files has read `indexed` of the
+    /// `total` bytes it has to. Called as it goes while a store is opened,
+    /// or backfilled after a lazy open, so a long wait can show progress,
+    /// and with `indexed` equal to `total` once it's done.
+    fn on_index_progress(&self, _indexed: u64, _total: u64) {}
+}
+
+/// A sign a store is falling behind its writes, passed to
+/// [`StoreObserver::on_warning`] before it shows up as timeouts.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum FlowWarning {
+    /// A write, such as `"set"` or `"remove"`, took longer than expected,
+    /// usually waiting on the disk.
+    WriteStall {
+        /// The operation that stalled.
+        operation: String,
+        /// How long it took.
+        took: Duration,
+    },
+    /// More stale bytes are waiting to be reclaimed by compaction than
+    /// expected.
+    CompactionDebt {
+        /// How many.
+        stale_bytes: u64,
+    },
+    /// The store takes up more space on disk than expected.
+    DiskUsage {
+        /// How much.
+        disk_bytes: u64,
+    },
+}
+
+impl fmt::Display for FlowWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FlowWarning::WriteStall { operation, took } => {
+                write!(f, "{} stalled for {}ms", operation, took.as_millis())
+            }
+            FlowWarning::CompactionDebt { stale_bytes } => {
+                write!(f, "{} stale bytes waiting for compaction", stale_bytes)
+            }
+            FlowWarning::DiskUsage { disk_bytes } => {
+                write!(f, "{} bytes on disk", disk_bytes)
+            }
+        }
+    }
+}
+
+/// Pass the result of an operation to the observer, if there is one: to
+/// `on_error` if it failed, or to `on_ok` if it succeeded. Returns the
+/// result, so it can end the operation.
+pub fn observe<T, F>(
+    observer: &Option<Arc<dyn StoreObserver>>,
+    operation: &str,
+    result: Result<T>,
+    on_ok: F,
+) -> Result<T>
+where
+    F: FnOnce(&dyn StoreObserver, &T),
+{
+    if let Some(observer) = observer {
+        match &result {
+            Ok(value) => on_ok(observer.as_ref(), value),
+            Err(err) => observer.on_error(operation, err),
+        }
+    }
+    result
+}
@@ -0,0 +1,169 @@
+/*!
+ * Options that control how a persistent store is opened.
+ */
+
+#[cfg(feature = "std")]
+use std::sync::Arc;
+#[cfg(feature = "std")]
+use std::time::Duration;
+
+#[cfg(feature = "std")]
+use crate::{Clock, StoreObserver};
+
+/// How eagerly a persistent store makes its writes durable.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum SyncPolicy {
+    /// Sync every write to disk before returning from it.
+    Always,
+    /// Leave it to the store and the OS when writes reach disk. Writes are
+    /// saved when the store is dropped at the latest.
+    #[default]
+    Never,
+}
+
+/// How a store indexes its keys in memory.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum IndexKind {
+    /// A hash map, the fastest for looking up single keys.
+    #[default]
+    Hash,
+    /// A sorted tree, which can also list keys in order.
+    Ordered,
+}
+
+/// How keys are ordered when scanned, and so which keys a range covers.
+/// Keys that collate the same, like `a` and `A` without regard to case,
+/// are still different keys, and are ordered bytewise among themselves so
+/// every key has one place.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum Collation {
+    /// By the keys' UTF-8 bytes.
+    #[default]
+    Bytewise,
+    /// By the keys' lowercase letters, so `Apple` comes before `banana`.
+    CaseInsensitive,
+    /// With runs of digits compared as numbers, so `file2` comes before
+    /// `file10`.
+    Numeric,
+}
+
+/// How thoroughly a persistent store checks what's on disk when it's
+/// opened, trading startup time against how much damage is caught before
+/// it's read.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum IntegrityLevel {
+    /// Trust the index saved when the store was last closed, only reading
+    /// what was written after it. Falls back to `Standard` if there's no
+    /// saved index that matches the store.
+    Fast,
+    /// Read every record, checking each can be decoded, and check what was
+    /// last written against the checksum it was committed with.
+    #[default]
+    Standard,
+    /// Also check everything written before against its checksum, read
+    /// every current value, check content-addressed blobs against their
+    /// names, and cross-check the index with the records, failing to open
+    /// if anything is wrong.
+    Paranoid,
+}
+
+/// The byte order a store's records are written in.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum ByteOrder {
+    /// Least significant byte first, as on most machines.
+    #[default]
+    LittleEndian,
+    /// Most significant byte first.
+    BigEndian,
+}
+
+/// The options used to open a persistent store. Only with the `std`
+/// feature.
+#[cfg(feature = "std")]
+#[derive(Clone, Debug, Default)]
+pub struct StoreOptions {
+    /// When writes are synced to disk.
+    pub sync: SyncPolicy,
+    /// Values longer than this many bytes are kept in their own files,
+    /// outside the main store. Only the log store does this, others ignore
+    /// it. Defaults to None, keeping every value in the store.
+    pub blob_threshold: Option<u64>,
+    /// Store identical blobs once, naming them by a hash of their contents.
+    /// Only applies to values kept in blobs, see `blob_threshold`.
+    pub dedup: bool,
+    /// Write overwrites of long values as a change to the previous value,
+    /// chaining at most this many changes before writing a value in full.
+    /// Values kept in blobs, see `blob_threshold`, are always written in
+    /// full. Only the log store does this, others ignore it. Defaults to None,
+    /// always writing values in full.
+    pub delta_depth: Option<u32>,
+    /// How keys are indexed in memory. Only the log store has a choice,
+    /// others ignore it. Defaults to `IndexKind::Hash`.
+    pub index: IndexKind,
+    /// Soft delete: keep removed values in the trash for at least this long,
+    /// so they can be put back with
+    /// [`Trash::restore_key`](crate::Trash::restore_key). Each is kept
+    /// under its key with [`TRASH_PREFIX`](crate::TRASH_PREFIX) added, and
+    /// purged once it's expired by compaction, or by opening stores that
+    /// don't compact. Defaults to None, removing values for good.
+    pub trash_retention: Option<Duration>,
+    /// Called as the store is used, see [`StoreObserver`].
+    pub observer: Option<Arc<dyn StoreObserver>>,
+    /// Open the store for reading only, alongside the one handle that
+    /// writes to it, which may be in another process. Writes fail, and new
+    /// writes are only seen after refreshing. Only the log store does this,
+    /// others ignore it.
+    pub read_only: bool,
+    /// Where the store gets the time from, for anything that depends on it,
+    /// like expiring the trash. Defaults to None, using the system clock.
+    pub clock: Option<Arc<dyn Clock>>,
+    /// How thoroughly the store is checked when opened. Only the log store
+    /// has a choice, others ignore it. Defaults to
+    /// `IntegrityLevel::Standard`.
+    pub integrity: IntegrityLevel,
+    /// Read the store's files with direct I/O, bypassing the page cache, so
+    /// benchmarks measure the disk rather than memory, and reads don't push
+    /// other data out of the cache. Writes still go through the cache. Only
+    /// the log store does this, others ignore it, and opening fails on
+    /// platforms without it. Defaults to false.
+    pub direct_io: bool,
+    /// Reserve disk for the log this many bytes at a time, ahead of
+    /// appending to it, so appends don't allocate blocks one by one and the
+    /// log isn't fragmented. The log's length is unchanged, so nothing
+    /// reading it notices. Only the log store does this, on Linux, and
+    /// others ignore it. Defaults to None, reserving nothing.
+    pub preallocate: Option<u64>,
+    /// The byte order records are written in when the store is created.
+    /// Existing stores keep the one they were created with, which is read
+    /// from their files. Only the log store has a choice, others ignore
+    /// it. Defaults to `ByteOrder::LittleEndian`.
+    pub byte_order: ByteOrder,
+    /// Keep the log as a history of every write, for as long as this. When
+    /// the store is compacted, instead of dropping overwritten values, it
+    /// drops the records older than this, to within a second, and the keys
+    /// last written by them. Only the log store does this, others ignore
+    /// it. Defaults to None, compacting down to the current values.
+    pub history_retention: Option<Duration>,
+    /// Save the index every this many writes, as well as when the store is
+    /// closed, so opening it after a crash only reads the log written since.
+    /// Only the log store does this, when opened with
+    /// `IntegrityLevel::Fast`, and others ignore it. Defaults to None,
+    /// saving the index only when the store is closed.
+    pub checkpoint_every: Option<u64>,
+    /// Save the index with the first write once this long has passed since
+    /// it was last saved, like `checkpoint_every`. Defaults to None.
+    pub checkpoint_interval: Option<Duration>,
+    /// Open the store without reading its log into the index, so it can be
+    /// used straight away, and fill the index in afterwards with
+    /// `LogKvs::backfill` or a `Backfiller`. Until then, reads of keys
+    /// that haven't been written since fall back to reading the rest of
+    /// the log. A store that wasn't closed cleanly is read in full anyway,
+    /// to find any write that was cut short. Only the log store does this,
+    /// when it can write, and others ignore it. Defaults to false.
+    pub lazy_open: bool,
+    /// The order keys are scanned in, and so which keys a range covers,
+    /// when the store is created. Existing stores keep the one they were
+    /// created with, which is recorded next to them. Only the log store
+    /// has a choice, others ignore it. Defaults to `Collation::Bytewise`.
+    pub collation: Collation,
+}
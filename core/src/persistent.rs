@@ -1,6 +1,6 @@
 use std::path::Path;
 
-use crate::{KvStore, Result};
+use crate::{KvStore, Result, StoreOptions};
 
 /// Defines a trait for persistent key value stores
 pub trait Persistent: KvStore + Sized + Drop {
@@ -9,10 +9,31 @@ pub trait Persistent: KvStore + Sized + Drop {
 
     /// Instantiate the Persistent KvStore using the given path.
     /// If the location doesn't exist yet, create it.
-    fn open<P: AsRef<Path>>(path: P) -> Result<Self>;
+    fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::open_with(path, StoreOptions::default())
+    }
+
+    /// Instantiate the Persistent KvStore using the given path and options.
+    /// If the location doesn't exist yet, create it.
+    fn open_with<P: AsRef<Path>>(
+        path: P,
+        options: StoreOptions,
+    ) -> Result<Self>;
 
     /// Saves the key value store to some kind of persistant storage
     fn save(&mut self) -> Result<()>;
+
+    /// Write an independent copy of the store to the given path, which
+    /// mustn't exist yet. The copy can be opened like any other store, and
+    /// changes to either don't affect the other.
+    fn fork_to<P: AsRef<Path>>(&self, path: P) -> Result<()>;
+
+    /// Stop using the store without saving it, leaving it the way a crash
+    /// would, for testing recovery. Only what the operating system frees
+    /// when a process exits, like locks, should be released.
+    fn simulate_crash(self) {
+        std::mem::forget(self);
+    }
 }
 
 /// The options for the type of path the Persisent KvStore uses
@@ -29,7 +50,13 @@ pub enum PathType {
 pub mod persistent_tests {
     use super::*;
 
-    use crate::tests::{TestContext, Testable};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use tempfile::TempDir;
+
+    use crate::tests::{RecordingObserver, TestContext, Testable};
+    use crate::{trash_key, StoreObserver, SyncPolicy, Trash};
 
     #[macro_export]
     /// Generate tests for the given type using all the PersistentTests
@@ -43,7 +70,11 @@ pub mod persistent_tests {
                 test_storing_values,
                 test_overwriting_values,
                 test_nonexistent_values,
-                test_removals
+                test_removals,
+                test_sync_always,
+                test_fork,
+                test_observer,
+                test_soft_delete
             );
         };
     }
@@ -141,5 +172,141 @@ pub mod persistent_tests {
 
             Ok(())
         }
+
+        /// Should contain values written with SyncPolicy::Always, even if the
+        /// store never gets to save itself on drop
+        fn test_sync_always() -> Result<()> {
+            let context = Self::Context::init();
+            let options = StoreOptions {
+                sync: SyncPolicy::Always,
+                ..StoreOptions::default()
+            };
+
+            {
+                let mut store: Self = context.open_store_with(options)?;
+                store.set("key1".to_owned(), "value1".to_owned())?;
+                store.set("key2".to_owned(), "value2".to_owned())?;
+                store.remove("key2".to_owned())?;
+                store.simulate_crash();
+            }
+
+            {
+                let store: Self = context.open_store()?;
+                assert_eq!(
+                    store.get("key1".to_owned())?,
+                    Some("value1".to_owned())
+                );
+                assert_eq!(store.get("key2".to_owned())?, None);
+            }
+
+            Ok(())
+        }
+
+        /// Should copy every value, then change independently of the
+        /// original
+        fn test_fork() -> Result<()> {
+            let context = Self::Context::init();
+            let fork_dir = TempDir::new()
+                .expect("unable to create temporary working directory");
+            let fork_path = match Self::PATH_TYPE {
+                PathType::File => fork_dir.path().join("fork.kvs"),
+                PathType::Directory => fork_dir.path().join("fork"),
+            };
+
+            let mut store: Self = context.open_store()?;
+            store.set("key1".to_owned(), "value1".to_owned())?;
+            store.set("key2".to_owned(), "value2".to_owned())?;
+            store.fork_to(&fork_path)?;
+            // the fork is there now, so it can't be overwritten
+            assert!(store.fork_to(&fork_path).is_err());
+
+            store.set("key1".to_owned(), "value3".to_owned())?;
+            let mut fork = Self::open(&fork_path)?;
+            assert_eq!(fork.get("key1".to_owned())?, Some("value1".to_owned()));
+            assert_eq!(fork.get("key2".to_owned())?, Some("value2".to_owned()));
+            fork.remove("key2".to_owned())?;
+            drop(fork);
+
+            assert_eq!(
+                store.get("key1".to_owned())?,
+                Some("value3".to_owned())
+            );
+            assert_eq!(
+                store.get("key2".to_owned())?,
+                Some("value2".to_owned())
+            );
+
+            Ok(())
+        }
+
+        /// Should tell the observer about each change made
+        fn test_observer() -> Result<()> {
+            let context = Self::Context::init();
+            let observer = Arc::new(RecordingObserver::default());
+            let options = StoreOptions {
+                observer: Some(observer.clone() as Arc<dyn StoreObserver>),
+                ..StoreOptions::default()
+            };
+
+            let mut store: Self = context.open_store_with(options)?;
+            store.set("key1".to_owned(), "value1".to_owned())?;
+            store.set_from_reader("key2".to_owned(), &mut &b"value22"[..])?;
+            store.get("key1".to_owned())?;
+            store.remove("key1".to_owned())?;
+            // nothing was removed, so there's nothing to report
+            store.remove("key3".to_owned())?;
+
+            assert_eq!(
+                *observer.events.lock().unwrap(),
+                vec!["set key1 6", "set key2 7", "get key1", "remove key1"]
+            );
+
+            Ok(())
+        }
+
+        /// Should keep removed values in the trash until they're put back,
+        /// including after reopening
+        fn test_soft_delete() -> Result<()> {
+            let context = Self::Context::init();
+            let options = StoreOptions {
+                trash_retention: Some(Duration::from_secs(60 * 60)),
+                ..StoreOptions::default()
+            };
+
+            {
+                let mut store: Self =
+                    context.open_store_with(options.clone())?;
+                store.set("key1".to_owned(), "value1".to_owned())?;
+                store.set("key2".to_owned(), "value2".to_owned())?;
+                assert_eq!(
+                    store.remove("key1".to_owned())?,
+                    Some("value1".to_owned())
+                );
+                store.remove("key2".to_owned())?;
+                assert_eq!(store.get("key1".to_owned())?, None);
+                assert!(store.get(trash_key("key1"))?.is_some());
+                // removing from the trash deletes for good
+                store.remove(trash_key("key2"))?;
+                assert_eq!(store.get(trash_key(&trash_key("key2")))?, None);
+                // and the trash can't be written to directly
+                let err = store
+                    .set(trash_key("key3"), "value3".to_owned())
+                    .unwrap_err();
+                assert_eq!(err.kind().key(), Some(&trash_key("key3")[..]));
+            }
+
+            {
+                let mut store: Self = context.open_store_with(options)?;
+                assert!(store.restore_key("key1".to_owned())?);
+                assert!(!store.restore_key("key2".to_owned())?);
+                assert_eq!(
+                    store.get("key1".to_owned())?,
+                    Some("value1".to_owned())
+                );
+                assert_eq!(store.get(trash_key("key1"))?, None);
+            }
+
+            Ok(())
+        }
     }
 }
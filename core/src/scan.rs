@@ -0,0 +1,307 @@
+/*!
+ * Traits and tests related to listing the keys in a store.
+ */
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::{Collation, KvStore, Result};
+
+/// Trait for key value stores that can list their keys.
+pub trait Scannable: KvStore {
+    /// Every key that currently has a value, in no particular order.
+    fn keys(&self) -> Result<Vec<String>>;
+
+    /// The keys from `start` up to but not including `end`, or up to the
+    /// last key if there's no end, in ascending order. Returns an
+    /// `Unsupported` error if the store doesn't keep its keys in order.
+    fn scan(&self, start: &str, end: Option<&str>) -> Result<Vec<String>>;
+
+    /// The keys in the same range as `scan`, in descending order.
+    fn scan_rev(&self, start: &str, end: Option<&str>) -> Result<Vec<String>> {
+        let options = ScanOptions {
+            reverse: true,
+            ..ScanOptions::default()
+        };
+        self.scan_with(start, end, options)
+    }
+
+    /// The keys in the same range as `scan`, in the order and page of the
+    /// options, such as the last 10 keys under a prefix. Stores that keep
+    /// their keys in order should only walk the keys they return, rather
+    /// than scanning the whole range and dropping the rest.
+    fn scan_with(
+        &self,
+        start: &str,
+        end: Option<&str>,
+        options: ScanOptions,
+    ) -> Result<Vec<String>> {
+        let mut keys = self.scan(start, end)?;
+        if options.reverse {
+            keys.reverse();
+        }
+        Ok(options.page(keys))
+    }
+
+    /// Roughly how many keys are in the same range as `scan`, and how many
+    /// bytes they take up, worked out from what the store keeps in memory
+    /// instead of reading any values. Works whether or not the store keeps
+    /// its keys in order.
+    fn estimate_range_size(
+        &self,
+        start: &str,
+        end: Option<&str>,
+    ) -> Result<RangeEstimate>;
+
+    /// The keys in the same range as `scan` and their values, in ascending
+    /// order, keeping only the entries `predicate` accepts. The predicate is
+    /// given each key and value without them being copied, so only the
+    /// matching entries cost an allocation. Works whether or not the store
+    /// keeps its keys in order.
+    fn scan_filtered(
+        &self,
+        start: &str,
+        end: Option<&str>,
+        predicate: &dyn Fn(&str, &str) -> bool,
+    ) -> Result<Vec<(String, String)>> {
+        let mut keys: Vec<String> = self
+            .keys()?
+            .into_iter()
+            .filter(|key| in_range(key, start, end))
+            .collect();
+        keys.sort();
+
+        let mut entries = Vec::new();
+        for key in keys {
+            if let Some(value) = self.get_ref(&key)? {
+                if predicate(&key, &value) {
+                    let value = value.into_owned();
+                    entries.push((key, value));
+                }
+            }
+        }
+        Ok(entries)
+    }
+
+    /// The order the store keeps its keys in, which `scan` and the other
+    /// range queries follow. Stores without a choice of collation are
+    /// bytewise.
+    fn collation(&self) -> Collation {
+        Collation::Bytewise
+    }
+}
+
+/// The approximate size of a range of keys, see
+/// [`Scannable::estimate_range_size`].
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct RangeEstimate {
+    /// How many keys in the range have a value.
+    pub keys: u64,
+    /// How many bytes the keys and their values take up.
+    pub bytes: u64,
+}
+
+/// Which part of a range [`Scannable::scan_with`] returns, and in which
+/// order.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct ScanOptions {
+    /// Start from the last key in the range and go backwards.
+    pub reverse: bool,
+    /// How many keys to skip, counted from whichever end the scan starts.
+    pub offset: usize,
+    /// The most keys to return after the offset, or every one if None.
+    pub limit: Option<usize>,
+}
+
+impl ScanOptions {
+    /// Skip the offset and stop at the limit, for keys already in the
+    /// scan's order.
+    pub fn page<T, I: IntoIterator<Item = T>>(&self, keys: I) -> Vec<T> {
+        keys.into_iter()
+            .skip(self.offset)
+            .take(self.limit.unwrap_or(usize::MAX))
+            .collect()
+    }
+}
+
+/// Whether the key is in the range from `start` up to but not including
+/// `end`, or up to the last key if there's no end.
+pub fn in_range(key: &str, start: &str, end: Option<&str>) -> bool {
+    match end {
+        Some(end) => start <= key && key < end,
+        None => start <= key,
+    }
+}
+
+#[cfg(feature = "impl-tests")]
+/// Functions, traits, and macros for easily testing Scannable
+/// implementations.
+pub mod scan_tests {
+    use super::*;
+
+    use crate::tests::{TestContext, Testable};
+    use crate::{IndexKind, Persistent, StoreOptions};
+
+    impl<S> ScannableTests for S where S: Scannable + Persistent + Testable {}
+
+    #[macro_export]
+    /// Generate tests for the given type using all the ScannableTests
+    /// functions
+    macro_rules! generate_scannable_tests {
+        ( $t: ty ) => {
+            use $crate::scan_tests::ScannableTests;
+
+            test_functions!(
+                $t,
+                test_keys,
+                test_estimate_range_size,
+                test_scan_filtered,
+                test_scan_with
+            );
+        };
+    }
+
+    /// Functions to test Scannable implementations.
+    pub trait ScannableTests: Scannable + Persistent + Testable {
+        /// Should list each key with a value once, before and after reopening
+        fn test_keys() -> Result<()> {
+            let context = Self::Context::init();
+
+            {
+                let mut store: Self = context.open_store()?;
+                assert!(store.keys()?.is_empty());
+
+                store.set("key1".to_owned(), "value1".to_owned())?;
+                store.set("key2".to_owned(), "value2".to_owned())?;
+                store.set("key2".to_owned(), "value3".to_owned())?;
+                store.set("key3".to_owned(), "value4".to_owned())?;
+                store.remove("key3".to_owned())?;
+
+                let mut keys = store.keys()?;
+                keys.sort();
+                assert_eq!(keys, vec!["key1".to_owned(), "key2".to_owned()]);
+            }
+
+            {
+                let store: Self = context.open_store()?;
+                let mut keys = store.keys()?;
+                keys.sort();
+                assert_eq!(keys, vec!["key1".to_owned(), "key2".to_owned()]);
+            }
+
+            Ok(())
+        }
+
+        /// Should count the keys in a range, and give them a size
+        fn test_estimate_range_size() -> Result<()> {
+            let context = Self::Context::init();
+            let mut store: Self = context.open_store()?;
+            assert_eq!(
+                store.estimate_range_size("", None)?,
+                RangeEstimate::default()
+            );
+
+            for key in &["user:1", "user:2", "user:3", "zone:1"] {
+                store.set(key.to_string(), "value".repeat(10))?;
+            }
+            store.remove("user:3".to_owned())?;
+
+            let users = store.estimate_range_size("user:", Some("user;"))?;
+            assert_eq!(users.keys, 2);
+            assert!(users.bytes > 0);
+            let all = store.estimate_range_size("", None)?;
+            assert_eq!(all.keys, 3);
+            assert!(all.bytes > users.bytes);
+            assert_eq!(
+                store.estimate_range_size("zone:1", Some("user:1"))?,
+                RangeEstimate::default()
+            );
+
+            Ok(())
+        }
+
+        /// Should return the entries in a range the predicate accepts, in
+        /// order
+        fn test_scan_filtered() -> Result<()> {
+            let context = Self::Context::init();
+            let mut store: Self = context.open_store()?;
+
+            for (key, value) in &[
+                ("user:3", "admin"),
+                ("user:1", "admin"),
+                ("group:1", "admin"),
+                ("user:2", "guest"),
+                ("user:4", "admin"),
+            ] {
+                store.set(key.to_string(), value.to_string())?;
+            }
+            store.remove("user:4".to_owned())?;
+
+            let admins =
+                store.scan_filtered("user:", Some("user;"), &|_, value| {
+                    value == "admin"
+                })?;
+            assert_eq!(
+                admins,
+                vec![
+                    ("user:1".to_owned(), "admin".to_owned()),
+                    ("user:3".to_owned(), "admin".to_owned()),
+                ]
+            );
+            let odd = store.scan_filtered("", None, &|key, _| {
+                key.ends_with('1') || key.ends_with('3')
+            })?;
+            assert_eq!(odd.len(), 3);
+            assert!(store.scan_filtered("", None, &|_, _| false)?.is_empty());
+
+            Ok(())
+        }
+
+        /// Should page through a range from either end. Opened with an
+        /// ordered index, which stores with a choice of index need to scan
+        /// in order.
+        fn test_scan_with() -> Result<()> {
+            let context = Self::Context::init();
+            let options = StoreOptions {
+                index: IndexKind::Ordered,
+                ..StoreOptions::default()
+            };
+            let mut store: Self = context.open_store_with(options)?;
+            for key in &["log:1", "log:2", "log:3", "log:4", "zone:1"] {
+                store.set(key.to_string(), "value".to_owned())?;
+            }
+            store.remove("log:4".to_owned())?;
+
+            let latest = ScanOptions {
+                reverse: true,
+                limit: Some(2),
+                ..ScanOptions::default()
+            };
+            assert_eq!(
+                store.scan_with("log:", Some("log;"), latest)?,
+                vec!["log:3", "log:2"]
+            );
+            assert_eq!(
+                store.scan_rev("", None)?,
+                vec!["zone:1", "log:3", "log:2", "log:1"]
+            );
+            let page = ScanOptions {
+                offset: 1,
+                limit: Some(2),
+                ..ScanOptions::default()
+            };
+            assert_eq!(
+                store.scan_with("", None, page)?,
+                vec!["log:2", "log:3"]
+            );
+            let past_end = ScanOptions {
+                offset: 10,
+                ..ScanOptions::default()
+            };
+            assert!(store.scan_with("", None, past_end)?.is_empty());
+            assert!(store.scan_rev("zone:1", Some("log:1"))?.is_empty());
+
+            Ok(())
+        }
+    }
+}
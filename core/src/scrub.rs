@@ -0,0 +1,182 @@
+/*!
+ * Traits and tests related to scrubbing, checking a store's contents for
+ * problems before they turn into read errors.
+ */
+
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::{KvStore, Result};
+
+/// What a scrub found.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ScrubReport {
+    /// The number of records that were read.
+    pub records: u64,
+    /// A description of each problem found, sorted.
+    pub problems: Vec<String>,
+}
+
+impl ScrubReport {
+    /// Whether no problems were found.
+    pub fn is_clean(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+/// Trait for key value stores that can check their own contents.
+pub trait Scrubbable: KvStore {
+    /// Read every record, checking that it can be decoded and that it
+    /// agrees with what the store holds in memory. Problems with the
+    /// contents are returned in the report, errors are only returned if the
+    /// scrub couldn't run.
+    fn scrub(&self) -> Result<ScrubReport>;
+}
+
+/// Scrubs a shared store on a background thread, pausing between passes so
+/// it doesn't compete with other users. Stops when dropped.
+pub struct Scrubber {
+    report: Arc<Mutex<Option<ScrubReport>>>,
+    stop: Option<Sender<()>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Scrubber {
+    /// Start scrubbing the store, waiting `interval` after each pass. Only
+    /// takes a read lock while a pass runs.
+    pub fn spawn<S>(store: Arc<RwLock<S>>, interval: Duration) -> Scrubber
+    where
+        S: Scrubbable + Send + Sync + 'static,
+    {
+        let report = Arc::new(Mutex::new(None));
+        let (stop, stopped) = mpsc::channel();
+
+        let shared_report = report.clone();
+        let handle = thread::spawn(move || loop {
+            let result = match store.read() {
+                Ok(store) => store.scrub().unwrap_or_else(|err| ScrubReport {
+                    records: 0,
+                    problems: vec![format!("unable to scrub: {}", err)],
+                }),
+                // a writer panicked, so there's nothing sensible to check
+                Err(_) => return,
+            };
+            *shared_report.lock().expect("scrub report lock poisoned") =
+                Some(result);
+
+            match stopped.recv_timeout(interval) {
+                Err(RecvTimeoutError::Timeout) => continue,
+                _ => return,
+            }
+        });
+
+        Scrubber {
+            report,
+            stop: Some(stop),
+            handle: Some(handle),
+        }
+    }
+
+    /// The report from the most recent pass, or None if the first pass
+    /// hasn't finished.
+    pub fn last_report(&self) -> Option<ScrubReport> {
+        self.report
+            .lock()
+            .expect("scrub report lock poisoned")
+            .clone()
+    }
+}
+
+impl Drop for Scrubber {
+    fn drop(&mut self) {
+        // dropping the sender wakes the thread up if it's waiting
+        self.stop.take();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+#[cfg(feature = "impl-tests")]
+/// Functions, traits, and macros for easily testing Scrubbable
+/// implementations.
+pub mod scrub_tests {
+    use super::*;
+
+    use crate::tests::{TestContext, Testable};
+    use crate::Persistent;
+
+    impl<S> ScrubbableTests for S where
+        S: Scrubbable + Persistent + Testable + Send + Sync + 'static
+    {
+    }
+
+    #[macro_export]
+    /// Generate tests for the given type using all the ScrubbableTests
+    /// functions
+    macro_rules! generate_scrubbable_tests {
+        ( $t: ty ) => {
+            use $crate::scrub_tests::ScrubbableTests;
+
+            test_functions!($t, test_scrub_clean, test_scrubber);
+        };
+    }
+
+    /// Functions to test Scrubbable implementations.
+    pub trait ScrubbableTests:
+        Scrubbable + Persistent + Testable + Send + Sync + 'static
+    {
+        /// Should find no problems in a store that was used normally
+        fn test_scrub_clean() -> Result<()> {
+            let context = Self::Context::init();
+
+            {
+                let mut store: Self = context.open_store()?;
+                assert!(store.scrub()?.is_clean());
+
+                store.set("key1".to_owned(), "value1".to_owned())?;
+                store.set("key2".to_owned(), "value2".to_owned())?;
+                store.set("key2".to_owned(), "value3".to_owned())?;
+                store.remove("key1".to_owned())?;
+                assert!(store.scrub()?.is_clean());
+            }
+
+            {
+                let store: Self = context.open_store()?;
+                let report = store.scrub()?;
+                assert_eq!(report.problems, Vec::<String>::new());
+                assert!(report.records > 0);
+            }
+
+            Ok(())
+        }
+
+        /// Should report on a background thread, and stop when dropped
+        fn test_scrubber() -> Result<()> {
+            let context = Self::Context::init();
+            let mut store: Self = context.open_store()?;
+            store.set("key1".to_owned(), "value1".to_owned())?;
+
+            let store = Arc::new(RwLock::new(store));
+            let scrubber =
+                Scrubber::spawn(store.clone(), Duration::from_millis(10));
+            let mut report = None;
+            for _ in 0..500 {
+                report = scrubber.last_report();
+                if report.is_some() {
+                    break;
+                }
+                thread::sleep(Duration::from_millis(10));
+            }
+            drop(scrubber);
+
+            assert!(report.expect("no scrub finished").is_clean());
+            // the scrubber should have let go of the store
+            assert_eq!(Arc::strong_count(&store), 1);
+
+            Ok(())
+        }
+    }
+}
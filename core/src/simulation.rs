@@ -0,0 +1,318 @@
+/*!
+ * Deterministic simulation testing for Persistent KvStores.
+ *
+ * Each run generates a random schedule of writes, reads, compactions,
+ * reopens and crashes from a seed, plays it against a store and against a
+ * `BTreeMap`, and fails as soon as the two disagree. Time is a
+ * [`MockClock`](crate::tests::MockClock) moved only by the schedule, so the
+ * same seed always plays out the same way. A failing schedule is shrunk to
+ * the fewest steps that still fail before being reported, along with its
+ * seed.
+ *
+ * Crashes happen between steps: the store is leaked without being dropped,
+ * so it never gets to save or clean up, and is then opened again. Stores
+ * are opened with [`SyncPolicy::Always`], since nothing unsynced is
+ * expected to survive a crash.
+ *
+ * `KVS_SIMULATION_RUNS` sets how many seeds to try, and
+ * `KVS_SIMULATION_SEED` the first one, so a reported failure can be
+ * replayed with `KVS_SIMULATION_RUNS=1 KVS_SIMULATION_SEED=<seed>`.
+ */
+
+use std::collections::BTreeMap;
+use std::env;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::tests::{MockClock, TestContext, Testable};
+use crate::{Persistent, Result, StoreOptions, SyncPolicy};
+
+#[macro_export]
+/// Generate a test that simulates randomized schedules against the given
+/// type. Pass `compactable` for stores that implement Compactable, so
+/// schedules include compactions.
+macro_rules! generate_simulation_tests {
+    ( $t: ty ) => {
+        #[test]
+        fn test_simulation() {
+            $crate::simulation::simulate::<$t>(|_| Ok(()));
+        }
+    };
+    ( $t: ty, compactable ) => {
+        #[test]
+        fn test_simulation() {
+            $crate::simulation::simulate::<$t>(|store| {
+                $crate::Compactable::compact(store)
+            });
+        }
+    };
+}
+
+/// How many seeds to try when `KVS_SIMULATION_RUNS` isn't set.
+const DEFAULT_RUNS: u64 = 200;
+/// How many steps each schedule has.
+const STEPS: usize = 60;
+/// The keys schedules use. Few enough that they're often overwritten and
+/// removed.
+const KEYS: &[&str] = &["key0", "key1", "key2", "key3", "key4", "key5"];
+
+/// A small, fast random number generator (SplitMix64), so schedules only
+/// depend on their seed.
+#[derive(Clone, Debug)]
+pub struct SimRng {
+    state: u64,
+}
+
+impl SimRng {
+    /// Start generating from the given seed.
+    pub fn new(seed: u64) -> SimRng {
+        SimRng { state: seed }
+    }
+
+    /// The next random number.
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// A random number below `n`, which mustn't be 0.
+    pub fn below(&mut self, n: u64) -> u64 {
+        self.next_u64() % n
+    }
+
+    /// True the given percentage of the time.
+    pub fn chance(&mut self, percent: u64) -> bool {
+        self.below(100) < percent
+    }
+}
+
+/// One step of a schedule.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Step {
+    /// Set a key.
+    Set(String, String),
+    /// Remove a key, checking the value it had.
+    Remove(String),
+    /// Check a key's value.
+    Get(String),
+    /// Compact the store, if it can be.
+    Compact,
+    /// Drop the store and open it again.
+    Reopen,
+    /// Leak the store without dropping it, then open it again.
+    Crash,
+    /// Move the clock forward.
+    Advance(Duration),
+}
+
+/// A randomly generated schedule and the options the store is opened with.
+#[derive(Clone, Debug)]
+pub struct Schedule {
+    /// The seed it was generated from.
+    pub seed: u64,
+    /// See [`StoreOptions::blob_threshold`].
+    pub blob_threshold: Option<u64>,
+    /// See [`StoreOptions::dedup`].
+    pub dedup: bool,
+    /// See [`StoreOptions::trash_retention`].
+    pub trash_retention: Option<Duration>,
+    /// What to do, in order.
+    pub steps: Vec<Step>,
+}
+
+impl Schedule {
+    /// Generate a schedule of `len` steps from the given seed.
+    pub fn generate(seed: u64, len: usize) -> Schedule {
+        let mut rng = SimRng::new(seed);
+        let blob_threshold = if rng.chance(50) { Some(24) } else { None };
+        let dedup = rng.chance(50);
+        let trash_retention = if rng.chance(50) {
+            Some(Duration::from_secs(60))
+        } else {
+            None
+        };
+
+        let steps = (0..len)
+            .map(|_| {
+                let key = KEYS[rng.below(KEYS.len() as u64) as usize];
+                match rng.below(100) {
+                    0..=39 => Step::Set(key.to_owned(), value(&mut rng)),
+                    40..=59 => Step::Remove(key.to_owned()),
+                    60..=79 => Step::Get(key.to_owned()),
+                    80..=87 => Step::Compact,
+                    88..=91 => Step::Reopen,
+                    92..=95 => Step::Crash,
+                    _ => Step::Advance(Duration::from_secs(rng.below(120))),
+                }
+            })
+            .collect();
+
+        Schedule {
+            seed,
+            blob_threshold,
+            dedup,
+            trash_retention,
+            steps,
+        }
+    }
+
+    fn options(&self, clock: &Arc<MockClock>) -> StoreOptions {
+        StoreOptions {
+            sync: SyncPolicy::Always,
+            blob_threshold: self.blob_threshold,
+            dedup: self.dedup,
+            trash_retention: self.trash_retention,
+            clock: Some(clock.clone()),
+            ..StoreOptions::default()
+        }
+    }
+}
+
+/// A value to set. Values repeat often enough to be deduplicated, and are
+/// sometimes long enough to be stored as blobs.
+fn value(rng: &mut SimRng) -> String {
+    let n = rng.below(8);
+    if rng.chance(30) {
+        format!("a value long enough to be stored as a blob {}", n)
+    } else {
+        format!("value{}", n)
+    }
+}
+
+/// Play a schedule against a new store, returning a description of the
+/// first step where the store and the model disagree.
+pub fn run<S>(
+    schedule: &Schedule,
+    compact: fn(&mut S) -> Result<()>,
+) -> std::result::Result<(), String>
+where
+    S: Persistent + Testable,
+{
+    let context = S::Context::init();
+    let clock = Arc::new(MockClock::default());
+    let open = || {
+        context
+            .open_store_with(schedule.options(&clock))
+            .map_err(|err| format!("unable to open the store: {}", err))
+    };
+    let mut model: BTreeMap<String, String> = BTreeMap::new();
+    let mut store: S = open()?;
+
+    for (i, step) in schedule.steps.iter().enumerate() {
+        let fail = |what: String| format!("step {} ({:?}): {}", i, step, what);
+        match step {
+            Step::Set(key, value) => {
+                store
+                    .set(key.clone(), value.clone())
+                    .map_err(|err| fail(err.to_string()))?;
+                model.insert(key.clone(), value.clone());
+            }
+            Step::Remove(key) => {
+                let removed = store
+                    .remove(key.clone())
+                    .map_err(|err| fail(err.to_string()))?;
+                let expected = model.remove(key);
+                if removed != expected {
+                    return Err(fail(format!(
+                        "removed {:?}, expected {:?}",
+                        removed, expected
+                    )));
+                }
+            }
+            Step::Get(key) => check(&store, &model, key).map_err(fail)?,
+            Step::Compact => {
+                compact(&mut store).map_err(|err| fail(err.to_string()))?
+            }
+            Step::Reopen => {
+                drop(store);
+                store = open().map_err(fail)?;
+                check_all(&store, &model).map_err(fail)?;
+            }
+            Step::Crash => {
+                store.simulate_crash();
+                store = open().map_err(fail)?;
+                check_all(&store, &model).map_err(fail)?;
+            }
+            Step::Advance(by) => clock.advance(*by),
+        }
+    }
+
+    drop(store);
+    let store: S = open()?;
+    check_all(&store, &model)
+        .map_err(|what| format!("after the last step: {}", what))
+}
+
+fn check<S: Persistent>(
+    store: &S,
+    model: &BTreeMap<String, String>,
+    key: &str,
+) -> std::result::Result<(), String> {
+    let found = store.get(key.to_owned()).map_err(|err| err.to_string())?;
+    let expected = model.get(key);
+    if found.as_ref() == expected {
+        Ok(())
+    } else {
+        Err(format!("{} is {:?}, expected {:?}", key, found, expected))
+    }
+}
+
+fn check_all<S: Persistent>(
+    store: &S,
+    model: &BTreeMap<String, String>,
+) -> std::result::Result<(), String> {
+    KEYS.iter().try_for_each(|key| check(store, model, key))
+}
+
+/// Remove steps from a failing schedule, one at a time, for as long as it
+/// keeps failing. Returns the shortest schedule found and how it failed.
+pub fn shrink<S>(
+    mut schedule: Schedule,
+    mut failure: String,
+    compact: fn(&mut S) -> Result<()>,
+) -> (Schedule, String)
+where
+    S: Persistent + Testable,
+{
+    let mut i = 0;
+    while i < schedule.steps.len() {
+        let mut candidate = schedule.clone();
+        candidate.steps.remove(i);
+        match run(&candidate, compact) {
+            Err(candidate_failure) => {
+                schedule = candidate;
+                failure = candidate_failure;
+            }
+            Ok(()) => i += 1,
+        }
+    }
+    (schedule, failure)
+}
+
+/// Play the configured number of schedules against the given type,
+/// panicking with the shrunk schedule if any fails.
+pub fn simulate<S>(compact: fn(&mut S) -> Result<()>)
+where
+    S: Persistent + Testable,
+{
+    let runs = env_u64("KVS_SIMULATION_RUNS").unwrap_or(DEFAULT_RUNS);
+    let first = env_u64("KVS_SIMULATION_SEED").unwrap_or(0);
+
+    for seed in first..first.saturating_add(runs) {
+        let schedule = Schedule::generate(seed, STEPS);
+        if let Err(failure) = run(&schedule, compact) {
+            let (shrunk, failure) = shrink(schedule, failure, compact);
+            panic!(
+                "simulation failed with seed {}: {}\nshrunk schedule: {:#?}",
+                seed, failure, shrunk
+            );
+        }
+    }
+}
+
+fn env_u64(name: &str) -> Option<u64> {
+    env::var(name).ok().and_then(|value| value.parse().ok())
+}
@@ -0,0 +1,90 @@
+/*!
+ * Traits and tests related to reporting statistics about a store.
+ */
+
+use crate::{KvStore, Result};
+
+/// Statistics describing the contents of a key value store.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct StoreStats {
+    /// The number of keys that currently have a value.
+    pub keys: u64,
+    /// The number of records on disk that no longer hold a current value,
+    /// and could be reclaimed by compaction.
+    pub stale_records: u64,
+    /// The number of bytes taken up by stale records, which compaction
+    /// would reclaim.
+    pub stale_bytes: u64,
+    /// The number of bytes the store takes up on disk.
+    pub disk_bytes: u64,
+}
+
+/// Trait for key value stores that can report statistics about themselves.
+pub trait Measurable: KvStore {
+    /// Gather statistics about the store. This may read the entire store
+    /// from disk, so it can also be used to check that it is readable.
+    fn stats(&self) -> Result<StoreStats>;
+}
+
+#[cfg(feature = "impl-tests")]
+/// Functions, traits, and macros for easily testing Measurable
+/// implementations.
+pub mod stats_tests {
+    use super::*;
+
+    use crate::tests::{TestContext, Testable};
+    use crate::Persistent;
+
+    impl<S> MeasurableTests for S where S: Measurable + Persistent + Testable {}
+
+    #[macro_export]
+    /// Generate tests for the given type using all the MeasurableTests
+    /// functions
+    macro_rules! generate_measurable_tests {
+        ( $t: ty ) => {
+            use $crate::stats_tests::MeasurableTests;
+
+            test_functions!($t, test_stats_empty, test_stats_keys);
+        };
+    }
+
+    /// Functions to test Measurable implementations.
+    pub trait MeasurableTests: Measurable + Persistent + Testable {
+        /// Should have no keys when nothing has been stored
+        fn test_stats_empty() -> Result<()> {
+            let context = Self::Context::init();
+            let store: Self = context.open_store()?;
+
+            let stats = store.stats()?;
+            assert_eq!(stats.keys, 0);
+            assert_eq!(stats.stale_records, 0);
+            assert_eq!(stats.stale_bytes, 0);
+
+            Ok(())
+        }
+
+        /// Should count each live key once, and take up space once saved
+        fn test_stats_keys() -> Result<()> {
+            let context = Self::Context::init();
+
+            {
+                let mut store: Self = context.open_store()?;
+                store.set("key1".to_owned(), "value1".to_owned())?;
+                store.set("key2".to_owned(), "value2".to_owned())?;
+                store.set("key2".to_owned(), "value3".to_owned())?;
+                store.set("key3".to_owned(), "value4".to_owned())?;
+                store.remove("key3".to_owned())?;
+                assert_eq!(store.stats()?.keys, 2);
+            }
+
+            {
+                let store: Self = context.open_store()?;
+                let stats = store.stats()?;
+                assert_eq!(stats.keys, 2);
+                assert!(stats.disk_bytes > 0);
+            }
+
+            Ok(())
+        }
+    }
+}
@@ -3,10 +3,15 @@
  */
 
 use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use tempfile::TempDir;
 
-use crate::{KvStore, PathType, Persistent, Result};
+use crate::{
+    Clock, Error, FlowWarning, KvStore, PathType, Persistent, Result,
+    StoreObserver, StoreOptions,
+};
 
 /// Mark a KvStore as testable
 pub trait Testable: KvStore + Sized {
@@ -27,6 +32,9 @@ pub trait TestContext<S: KvStore>: Sized {
 
     /// Get a new KvStore using this context.
     fn open_store(&self) -> Result<S>;
+
+    /// Get a new KvStore using this context and the given options.
+    fn open_store_with(&self, options: StoreOptions) -> Result<S>;
 }
 
 /// Needed functions for persistent tests
@@ -65,6 +73,12 @@ impl<S: Persistent> TestContext<S> for DefaultTestContext {
     fn open_store(&self) -> Result<S> {
         Persistent::open(&self.path)
     }
+
+    /// Use the TestContext to get a new KvStore in the same context, opened
+    /// with the given options.
+    fn open_store_with(&self, options: StoreOptions) -> Result<S> {
+        Persistent::open_with(&self.path, options)
+    }
 }
 
 impl<S: Persistent> PersistentTestContext<S> for DefaultTestContext {
@@ -74,6 +88,83 @@ impl<S: Persistent> PersistentTestContext<S> for DefaultTestContext {
     }
 }
 
+/// Records each hook called, as a line of text, for tests to compare.
+#[derive(Debug, Default)]
+pub struct RecordingObserver {
+    /// The hooks called so far, in order.
+    pub events: Mutex<Vec<String>>,
+}
+
+impl RecordingObserver {
+    fn record(&self, event: String) {
+        self.events.lock().unwrap().push(event);
+    }
+}
+
+impl StoreObserver for RecordingObserver {
+    fn on_get(&self, key: &str) {
+        self.record(format!("get {}", key));
+    }
+
+    fn on_set(&self, key: &str, value_len: u64) {
+        self.record(format!("set {} {}", key, value_len));
+    }
+
+    fn on_remove(&self, key: &str) {
+        self.record(format!("remove {}", key));
+    }
+
+    fn on_compaction_start(&self) {
+        self.record("compaction start".to_owned());
+    }
+
+    fn on_compaction_end(&self) {
+        self.record("compaction end".to_owned());
+    }
+
+    fn on_error(&self, operation: &str, _err: &Error) {
+        self.record(format!("{} failed", operation));
+    }
+
+    fn on_warning(&self, warning: &FlowWarning) {
+        self.record(format!("warning: {}", warning));
+    }
+}
+
+/// A clock that only moves when told to, for testing behavior that depends
+/// on the time.
+#[derive(Debug)]
+pub struct MockClock {
+    now: Mutex<SystemTime>,
+}
+
+impl MockClock {
+    /// Start the clock at the given time.
+    pub fn at(now: SystemTime) -> MockClock {
+        MockClock {
+            now: Mutex::new(now),
+        }
+    }
+
+    /// Move the clock forward.
+    pub fn advance(&self, by: Duration) {
+        *self.now.lock().unwrap() += by;
+    }
+}
+
+impl Default for MockClock {
+    /// Start the clock at a fixed time, well after the Unix epoch.
+    fn default() -> MockClock {
+        MockClock::at(UNIX_EPOCH + Duration::from_secs(1_000_000_000))
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> SystemTime {
+        *self.now.lock().unwrap()
+    }
+}
+
 #[macro_export]
 /// Generate a test that calls the given function on the given type
 macro_rules! test_functions {
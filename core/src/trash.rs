@@ -0,0 +1,121 @@
+/*!
+ * Soft deletes, where removed values are kept for a while so they can be
+ * put back.
+ */
+
+use alloc::borrow::ToOwned;
+use alloc::format;
+use alloc::string::String;
+#[cfg(feature = "std")]
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::{Error, KvStore, Result};
+
+/// The prefix of the keys removed values are kept under when soft deleting,
+/// see [`StoreOptions::trash_retention`](crate::StoreOptions::trash_retention).
+/// Removing a key with this prefix deletes it for good. Stores that soft
+/// delete keep the prefix to themselves: keys with it can't be set, and are
+/// left out of listings, scans and statistics.
+pub const TRASH_PREFIX: &str = ".trash/";
+
+/// The key a removed key's value is kept under when soft deleting.
+pub fn trash_key(key: &str) -> String {
+    format!("{}{}", TRASH_PREFIX, key)
+}
+
+/// Whether the key holds a removed value, rather than a live one.
+pub fn is_trash_key(key: &str) -> bool {
+    key.starts_with(TRASH_PREFIX)
+}
+
+/// Fail if the key has the trash's prefix, for stores that soft delete to
+/// check keys they're asked to set.
+pub fn check_not_trash_key(key: &str) -> Result<()> {
+    if is_trash_key(key) {
+        Err(Error::rejected_key(
+            key,
+            format!(
+                "'{}' can't be set, since keys starting with {} hold removed \
+                 values",
+                key, TRASH_PREFIX
+            ),
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// A value removed while soft deleting, as kept in the trash: the time it
+/// was removed, in seconds since the Unix epoch, then a line break and the
+/// value.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TrashedValue {
+    /// When the value was removed, in seconds since the Unix epoch.
+    pub removed_at: u64,
+    /// The value the key held.
+    pub value: String,
+}
+
+impl TrashedValue {
+    /// A value being removed at the given time. Only with the `std`
+    /// feature.
+    #[cfg(feature = "std")]
+    pub fn removed(value: String, now: SystemTime) -> TrashedValue {
+        TrashedValue {
+            removed_at: unix_time(now),
+            value,
+        }
+    }
+
+    /// Read a value written by `encode`.
+    pub fn decode(encoded: &str) -> Result<TrashedValue> {
+        let mut parts = encoded.splitn(2, '\n');
+        match (parts.next().map(str::parse), parts.next()) {
+            (Some(Ok(removed_at)), Some(value)) => Ok(TrashedValue {
+                removed_at,
+                value: value.to_owned(),
+            }),
+            _ => Err(Error::serialization(
+                "not a value removed by a soft delete",
+            )),
+        }
+    }
+
+    /// Write the value the way it's kept in the trash.
+    pub fn encode(&self) -> String {
+        format!("{}\n{}", self.removed_at, self.value)
+    }
+
+    /// Whether the value will have been in the trash for at least
+    /// `retention` by `now`, and can be purged. Only with the `std`
+    /// feature.
+    #[cfg(feature = "std")]
+    pub fn expired(&self, retention: Duration, now: SystemTime) -> bool {
+        unix_time(now).saturating_sub(self.removed_at) >= retention.as_secs()
+    }
+}
+
+#[cfg(feature = "std")]
+fn unix_time(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs())
+}
+
+/// Putting back values removed while soft deleting. Implemented for every
+/// store, including `dyn KvStore`.
+pub trait Trash: KvStore {
+    /// Put a removed key's value back, replacing any value it has been
+    /// given since, and take it out of the trash. Returns false if the key
+    /// isn't in the trash.
+    fn restore_key(&mut self, key: String) -> Result<bool> {
+        let trashed = match self.get(trash_key(&key))? {
+            Some(trashed) => TrashedValue::decode(&trashed)?,
+            None => return Ok(false),
+        };
+        self.set(key.clone(), trashed.value)?;
+        self.remove(trash_key(&key))?;
+        Ok(true)
+    }
+}
+
+impl<S: KvStore + ?Sized> Trash for S {}
@@ -0,0 +1,23 @@
+use std::env;
+use std::path::PathBuf;
+
+/// Generate the C header into `OUT_DIR`, leaving the source tree alone. The
+/// copy in `include/` is committed, and a test checks it matches.
+fn main() {
+    let crate_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let out_dir = PathBuf::from(
+        env::var_os("OUT_DIR").expect("cargo sets OUT_DIR for build scripts"),
+    );
+    let config = cbindgen::Config::from_file(crate_dir.join("cbindgen.toml"))
+        .expect("unable to read cbindgen.toml");
+
+    cbindgen::Builder::new()
+        .with_crate(&crate_dir)
+        .with_config(config)
+        .generate()
+        .expect("unable to generate C bindings")
+        .write_to_file(out_dir.join("kvs.h"));
+
+    println!("cargo:rerun-if-changed=src/lib.rs");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+}
@@ -0,0 +1,129 @@
+#ifndef KVS_H
+#define KVS_H
+
+/* Generated by cbindgen from ffi/src/lib.rs. Do not edit by hand. */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/*
+ The result of a call into the library.
+ */
+typedef enum KvsStatus {
+  /*
+   The call succeeded.
+   */
+  KVS_STATUS_OK,
+  /*
+   The key does not exist in the store.
+   */
+  KVS_STATUS_NOT_FOUND,
+  /*
+   An argument was null, not valid UTF-8, or an unknown engine.
+   */
+  KVS_STATUS_INVALID_ARGUMENT,
+  /*
+   An I/O error occurred.
+   */
+  KVS_STATUS_IO,
+  /*
+   Stored data could not be serialized or deserialized.
+   */
+  KVS_STATUS_SERDE,
+  /*
+   The store has an inconsistent state.
+   */
+  KVS_STATUS_CORRUPT_DATABASE,
+  /*
+   The library panicked. The store should not be used further.
+   */
+  KVS_STATUS_PANIC,
+  /*
+   The store does not support the requested operation.
+   */
+  KVS_STATUS_UNSUPPORTED,
+  /*
+   The store is already open for writing by another handle.
+   */
+  KVS_STATUS_LOCKED,
+  /*
+   The write was refused by a check the store was set up with.
+   */
+  KVS_STATUS_REJECTED,
+} KvsStatus;
+
+/*
+ An open key-value store. Only ever handled through a pointer.
+ */
+typedef struct KvsStore KvsStore;
+
+/*
+ Open the store of the given engine (`"hashmap"` or `"log"`) at `path`,
+ creating it if it doesn't exist. On success, `*store` is set to the open
+ store, which must later be passed to `kvs_close`.
+
+ # Safety
+
+ `engine` and `path` must be null or valid null-terminated strings, and
+ `store` must be a valid pointer to write to.
+ */
+enum KvsStatus kvs_open(const char *engine, const char *path, struct KvsStore **store);
+
+/*
+ Look up the value of `key`. On success, `*value` is set to a newly
+ allocated string that must be freed with `kvs_string_free`. If the key
+ doesn't exist, `KVS_STATUS_NOT_FOUND` is returned and `*value` is set to
+ null.
+
+ # Safety
+
+ `store` must be a store returned by `kvs_open` that hasn't been closed,
+ `key` must be a valid null-terminated string, and `value` must be a valid
+ pointer to write to.
+ */
+enum KvsStatus kvs_get(const struct KvsStore *store, const char *key, char **value);
+
+/*
+ Set `key` to `value`, overwriting any previous value.
+
+ # Safety
+
+ `store` must be a store returned by `kvs_open` that hasn't been closed,
+ and `key` and `value` must be valid null-terminated strings.
+ */
+enum KvsStatus kvs_set(struct KvsStore *store, const char *key, const char *value);
+
+/*
+ Remove `key` from the store. Returns `KVS_STATUS_NOT_FOUND` if it didn't
+ exist.
+
+ # Safety
+
+ `store` must be a store returned by `kvs_open` that hasn't been closed,
+ and `key` must be a valid null-terminated string.
+ */
+enum KvsStatus kvs_remove(struct KvsStore *store, const char *key);
+
+/*
+ Save and close the store, freeing it. Passing null does nothing.
+
+ # Safety
+
+ `store` must be null or a store returned by `kvs_open` that hasn't
+ already been closed. It must not be used after this call.
+ */
+enum KvsStatus kvs_close(struct KvsStore *store);
+
+/*
+ Free a string returned by the library. Passing null does nothing.
+
+ # Safety
+
+ `string` must be null or a string returned by `kvs_get` that hasn't
+ already been freed.
+ */
+void kvs_string_free(char *string);
+
+#endif /* KVS_H */
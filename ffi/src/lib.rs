@@ -0,0 +1,363 @@
+#![deny(missing_docs)]
+
+/*!
+ * A C ABI for opening and using the key-value stores, so they can be
+ * embedded from C or any language with a C FFI (e.g. Python's ctypes).
+ *
+ * The matching header is committed as `include/kvs.h`. It's generated into
+ * the build's `OUT_DIR` whenever the crate is built, and the
+ * `header_is_current` test fails until the committed copy is updated to
+ * match.
+ */
+
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+use kvs::{Engine, Error, ErrorKind, KvStore, Kvs};
+
+/// An open key-value store. Only ever handled through a pointer.
+pub struct KvsStore {
+    inner: Box<dyn KvStore>,
+}
+
+/// The result of a call into the library.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum KvsStatus {
+    /// The call succeeded.
+    Ok,
+    /// The key does not exist in the store.
+    NotFound,
+    /// An argument was null, not valid UTF-8, or an unknown engine.
+    InvalidArgument,
+    /// An I/O error occurred.
+    Io,
+    /// Stored data could not be serialized or deserialized.
+    Serde,
+    /// The store has an inconsistent state.
+    CorruptDatabase,
+    /// The library panicked. The store should not be used further.
+    Panic,
+    /// The store does not support the requested operation.
+    Unsupported,
+    /// The store is already open for writing by another handle.
+    Locked,
+    /// The write was refused by a check the store was set up with.
+    Rejected,
+}
+
+impl From<Error> for KvsStatus {
+    fn from(err: Error) -> KvsStatus {
+        match err.kind() {
+            ErrorKind::Io { .. } => KvsStatus::Io,
+            ErrorKind::Serde { .. } => KvsStatus::Serde,
+            ErrorKind::CorruptDatabase { .. } => KvsStatus::CorruptDatabase,
+            ErrorKind::Config { .. } => KvsStatus::InvalidArgument,
+            ErrorKind::Unsupported { .. } => KvsStatus::Unsupported,
+            ErrorKind::Locked { .. } => KvsStatus::Locked,
+            ErrorKind::Rejected { .. } => KvsStatus::Rejected,
+            ErrorKind::KeyDoesNotExist { .. } => KvsStatus::NotFound,
+            _ => KvsStatus::Io,
+        }
+    }
+}
+
+/// Run the function, turning any panic into a status instead of unwinding
+/// into foreign code.
+fn guard<F: FnOnce() -> KvsStatus>(func: F) -> KvsStatus {
+    panic::catch_unwind(AssertUnwindSafe(func)).unwrap_or(KvsStatus::Panic)
+}
+
+/// Borrow a C string as a `&str`, or None if it's null or not UTF-8.
+unsafe fn to_str<'a>(ptr: *const c_char) -> Option<&'a str> {
+    if ptr.is_null() {
+        None
+    } else {
+        CStr::from_ptr(ptr).to_str().ok()
+    }
+}
+
+/// Open the store of the given engine (`"hashmap"` or `"log"`) at `path`,
+/// creating it if it doesn't exist. On success, `*store` is set to the open
+/// store, which must later be passed to `kvs_close`.
+///
+/// # Safety
+///
+/// `engine` and `path` must be null or valid null-terminated strings, and
+/// `store` must be a valid pointer to write to.
+#[no_mangle]
+pub unsafe extern "C" fn kvs_open(
+    engine: *const c_char,
+    path: *const c_char,
+    store: *mut *mut KvsStore,
+) -> KvsStatus {
+    guard(|| {
+        let (engine, path) = match (to_str(engine), to_str(path)) {
+            (Some(engine), Some(path)) if !store.is_null() => (engine, path),
+            _ => return KvsStatus::InvalidArgument,
+        };
+
+        let engine = match engine.parse::<Engine>() {
+            Ok(engine) => engine,
+            Err(err) => return err.into(),
+        };
+        let inner = match Kvs::builder().engine(engine).path(path).open() {
+            Ok(inner) => inner,
+            Err(err) => return err.into(),
+        };
+
+        *store = Box::into_raw(Box::new(KvsStore { inner }));
+        KvsStatus::Ok
+    })
+}
+
+/// Look up the value of `key`. On success, `*value` is set to a newly
+/// allocated string that must be freed with `kvs_string_free`. If the key
+/// doesn't exist, `KVS_STATUS_NOT_FOUND` is returned and `*value` is set to
+/// null.
+///
+/// # Safety
+///
+/// `store` must be a store returned by `kvs_open` that hasn't been closed,
+/// `key` must be a valid null-terminated string, and `value` must be a valid
+/// pointer to write to.
+#[no_mangle]
+pub unsafe extern "C" fn kvs_get(
+    store: *const KvsStore,
+    key: *const c_char,
+    value: *mut *mut c_char,
+) -> KvsStatus {
+    guard(|| {
+        let key = match to_str(key) {
+            Some(key) if !store.is_null() && !value.is_null() => key,
+            _ => return KvsStatus::InvalidArgument,
+        };
+        *value = ptr::null_mut();
+
+        match (*store).inner.get(key.to_owned()) {
+            Ok(Some(found)) => match CString::new(found) {
+                Ok(found) => {
+                    *value = found.into_raw();
+                    KvsStatus::Ok
+                }
+                // values with interior nul bytes can't be returned as C
+                // strings
+                Err(_) => KvsStatus::Serde,
+            },
+            Ok(None) => KvsStatus::NotFound,
+            Err(err) => err.into(),
+        }
+    })
+}
+
+/// Set `key` to `value`, overwriting any previous value.
+///
+/// # Safety
+///
+/// `store` must be a store returned by `kvs_open` that hasn't been closed,
+/// and `key` and `value` must be valid null-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn kvs_set(
+    store: *mut KvsStore,
+    key: *const c_char,
+    value: *const c_char,
+) -> KvsStatus {
+    guard(|| {
+        let (key, value) = match (to_str(key), to_str(value)) {
+            (Some(key), Some(value)) if !store.is_null() => (key, value),
+            _ => return KvsStatus::InvalidArgument,
+        };
+
+        match (*store).inner.set(key.to_owned(), value.to_owned()) {
+            Ok(()) => KvsStatus::Ok,
+            Err(err) => err.into(),
+        }
+    })
+}
+
+/// Remove `key` from the store. Returns `KVS_STATUS_NOT_FOUND` if it didn't
+/// exist.
+///
+/// # Safety
+///
+/// `store` must be a store returned by `kvs_open` that hasn't been closed,
+/// and `key` must be a valid null-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn kvs_remove(
+    store: *mut KvsStore,
+    key: *const c_char,
+) -> KvsStatus {
+    guard(|| {
+        let key = match to_str(key) {
+            Some(key) if !store.is_null() => key,
+            _ => return KvsStatus::InvalidArgument,
+        };
+
+        match (*store).inner.remove(key.to_owned()) {
+            Ok(Some(_)) => KvsStatus::Ok,
+            Ok(None) => KvsStatus::NotFound,
+            Err(err) => err.into(),
+        }
+    })
+}
+
+/// Save and close the store, freeing it. Passing null does nothing.
+///
+/// # Safety
+///
+/// `store` must be null or a store returned by `kvs_open` that hasn't
+/// already been closed. It must not be used after this call.
+#[no_mangle]
+pub unsafe extern "C" fn kvs_close(store: *mut KvsStore) -> KvsStatus {
+    if store.is_null() {
+        return KvsStatus::Ok;
+    }
+    // stores save themselves when dropped
+    guard(|| {
+        drop(Box::from_raw(store));
+        KvsStatus::Ok
+    })
+}
+
+/// Free a string returned by the library. Passing null does nothing.
+///
+/// # Safety
+///
+/// `string` must be null or a string returned by `kvs_get` that hasn't
+/// already been freed.
+#[no_mangle]
+pub unsafe extern "C" fn kvs_string_free(string: *mut c_char) {
+    if !string.is_null() {
+        drop(CString::from_raw(string));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tempfile::TempDir;
+
+    fn c_string(string: &str) -> CString {
+        CString::new(string).unwrap()
+    }
+
+    unsafe fn open(engine: &str, path: &str) -> *mut KvsStore {
+        let mut store = ptr::null_mut();
+        let status = kvs_open(
+            c_string(engine).as_ptr(),
+            c_string(path).as_ptr(),
+            &mut store,
+        );
+        assert_eq!(status, KvsStatus::Ok);
+        assert!(!store.is_null());
+        store
+    }
+
+    unsafe fn get(store: *const KvsStore, key: &str) -> Option<String> {
+        let mut value = ptr::null_mut();
+        match kvs_get(store, c_string(key).as_ptr(), &mut value) {
+            KvsStatus::Ok => {
+                let found = CStr::from_ptr(value).to_str().unwrap().to_owned();
+                kvs_string_free(value);
+                Some(found)
+            }
+            KvsStatus::NotFound => {
+                assert!(value.is_null());
+                None
+            }
+            status => panic!("unexpected status {:?}", status),
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+
+        for engine in &["hashmap", "log"] {
+            let path = temp_dir.path().join(engine);
+            let path = path.to_str().unwrap();
+
+            unsafe {
+                let store = open(engine, path);
+                let key = c_string("key1");
+                assert_eq!(
+                    kvs_set(store, key.as_ptr(), c_string("value1").as_ptr()),
+                    KvsStatus::Ok
+                );
+                assert_eq!(get(store, "key1"), Some("value1".to_owned()));
+                assert_eq!(kvs_close(store), KvsStatus::Ok);
+
+                let store = open(engine, path);
+                assert_eq!(get(store, "key1"), Some("value1".to_owned()));
+                assert_eq!(kvs_remove(store, key.as_ptr()), KvsStatus::Ok);
+                assert_eq!(
+                    kvs_remove(store, key.as_ptr()),
+                    KvsStatus::NotFound
+                );
+                assert_eq!(get(store, "key1"), None);
+                assert_eq!(kvs_close(store), KvsStatus::Ok);
+            }
+        }
+    }
+
+    #[test]
+    fn locked() {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+        let path = temp_dir.path().join("kvs");
+        let path = c_string(path.to_str().unwrap());
+
+        unsafe {
+            let store = open("log", path.to_str().unwrap());
+            let mut second = ptr::null_mut();
+            assert_eq!(
+                kvs_open(c_string("log").as_ptr(), path.as_ptr(), &mut second),
+                KvsStatus::Locked
+            );
+            assert!(second.is_null());
+            assert_eq!(kvs_close(store), KvsStatus::Ok);
+        }
+    }
+
+    #[test]
+    fn invalid_arguments() {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+        let path = temp_dir.path().join("kvs");
+        let path = c_string(path.to_str().unwrap());
+
+        unsafe {
+            let mut store = ptr::null_mut();
+            assert_eq!(
+                kvs_open(c_string("btree").as_ptr(), path.as_ptr(), &mut store),
+                KvsStatus::InvalidArgument
+            );
+            assert_eq!(
+                kvs_open(ptr::null(), path.as_ptr(), &mut store),
+                KvsStatus::InvalidArgument
+            );
+            assert!(store.is_null());
+
+            assert_eq!(
+                kvs_set(ptr::null_mut(), path.as_ptr(), path.as_ptr()),
+                KvsStatus::InvalidArgument
+            );
+            assert_eq!(kvs_close(ptr::null_mut()), KvsStatus::Ok);
+        }
+    }
+
+    #[test]
+    fn header_is_current() {
+        let generated = include_str!(concat!(env!("OUT_DIR"), "/kvs.h"));
+        let committed = include_str!("../include/kvs.h");
+        assert!(
+            generated == committed,
+            "include/kvs.h is out of date, copy it from {}/kvs.h",
+            env!("OUT_DIR")
+        );
+    }
+}
@@ -2,8 +2,13 @@ use std::collections::HashMap;
 use std::fs::File;
 use std::io::BufReader;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
 
-use core::{Persistent, Result};
+use core::{
+    clock_or_system, is_trash_key, Clock, Error, Persistent, Result,
+    StoreObserver, StoreOptions, SyncPolicy, TrashedValue,
+};
 
 /// An implementation of a key-value store using an in memory hashmap that
 /// only saves the store on close.
@@ -12,29 +17,80 @@ pub struct HashMapKvs {
     pub(crate) map: HashMap<String, String>,
     pub(crate) backing: PathBuf,
     pub(crate) mutated: bool,
+    pub(crate) sync: SyncPolicy,
+    pub(crate) trash_retention: Option<Duration>,
+    pub(crate) clock: Arc<dyn Clock>,
+    pub(crate) observer: Option<Arc<dyn StoreObserver>>,
 }
 
 impl HashMapKvs {
-    pub(crate) fn new<P: AsRef<Path>>(path: P) -> Result<Self> {
+    pub(crate) fn new<P: AsRef<Path>>(
+        path: P,
+        options: StoreOptions,
+    ) -> Result<Self> {
         let mut kvs = HashMapKvs {
             map: HashMap::new(),
             backing: PathBuf::from(path.as_ref()),
             mutated: true,
+            sync: options.sync,
+            trash_retention: options.trash_retention,
+            clock: clock_or_system(options.clock),
+            observer: options.observer,
         };
 
         kvs.save()?;
         Ok(kvs)
     }
 
-    pub(crate) fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+    pub(crate) fn load<P: AsRef<Path>>(
+        path: P,
+        options: StoreOptions,
+    ) -> Result<Self> {
         let backing_file = File::open(&path)?;
         let reader = BufReader::new(backing_file);
-        let map: HashMap<String, String> = serde_json::from_reader(reader)?;
+        let map: HashMap<String, String> =
+            serde_json::from_reader(reader).map_err(Error::serialization)?;
 
-        Ok(HashMapKvs {
+        let mut kvs = HashMapKvs {
             map,
             backing: PathBuf::from(path.as_ref()),
             mutated: false,
-        })
+            sync: options.sync,
+            trash_retention: options.trash_retention,
+            clock: clock_or_system(options.clock),
+            observer: options.observer,
+        };
+        // there's no compaction to purge the trash, so do it here
+        kvs.purge_trash();
+        Ok(kvs)
+    }
+
+    /// Drop every value that's been in the trash long enough. Values that
+    /// weren't put there by a soft delete are kept.
+    fn purge_trash(&mut self) {
+        let retention = match self.trash_retention {
+            Some(retention) => retention,
+            None => return,
+        };
+        let now = self.clock.now();
+        let before = self.map.len();
+        self.map.retain(|key, value| {
+            !is_trash_key(key)
+                || match TrashedValue::decode(value) {
+                    Ok(trashed) => !trashed.expired(retention, now),
+                    Err(_) => true,
+                }
+        });
+        if self.map.len() != before {
+            self.mutated = true;
+        }
+    }
+
+    /// Save the store if the sync policy requires every write to be synced.
+    pub(crate) fn sync_write(&mut self) -> Result<()> {
+        match self.sync {
+            SyncPolicy::Always => self.save(),
+            SyncPolicy::Never => Ok(()),
+        }
     }
 }
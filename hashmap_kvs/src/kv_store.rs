@@ -1,4 +1,9 @@
-use core::{KvStore, Result};
+use std::borrow::Cow;
+
+use core::{
+    check_not_trash_key, is_trash_key, observe, trash_key, KvStore, Result,
+    TrashedValue,
+};
 
 use crate::HashMapKvs;
 
@@ -17,9 +22,14 @@ impl KvStore for HashMapKvs {
     /// store.set("key1".to_owned(), "value1".to_owned());
     /// ```
     fn set(&mut self, key: String, value: String) -> Result<()> {
+        check_not_trash_key(&key)?;
+        let (name, len) = (key.clone(), value.len() as u64);
         self.map.insert(key, value);
         self.mutated = true;
-        Ok(())
+        let result = self.sync_write();
+        observe(&self.observer, "set", result, |observer, _| {
+            observer.on_set(&name, len)
+        })
     }
 
     /// Retrieve the value of a key. If the key does not exist, return None.
@@ -37,7 +47,30 @@ impl KvStore for HashMapKvs {
     /// store.get("key1".to_owned());
     /// ```
     fn get(&self, key: String) -> Result<Option<String>> {
-        Ok(self.map.get(&key).cloned())
+        let value = self.map.get(&key).cloned();
+        observe(&self.observer, "get", Ok(value), |observer, _| {
+            observer.on_get(&key)
+        })
+    }
+
+    /// Retrieve the value of a key without copying it out of the map.
+    ///
+    /// ```rust
+    /// # use tempfile::TempDir;
+    /// # use core::{KvStore, Persistent};
+    /// # use hashmap_kvs::HashMapKvs;
+    /// #
+    /// # let temp_dir =
+    /// #    TempDir::new().expect("unable to create temporary working directory");
+    /// # let mut store = HashMapKvs::open(temp_dir.path().join("kvs")).unwrap();
+    /// store.set("key1".to_owned(), "value1".to_owned());
+    /// assert_eq!(store.get_ref("key1").unwrap(), Some("value1".into()));
+    /// ```
+    fn get_ref(&self, key: &str) -> Result<Option<Cow<'_, str>>> {
+        let value = self.map.get(key).map(|value| Cow::Borrowed(&value[..]));
+        observe(&self.observer, "get", Ok(value), |observer, _| {
+            observer.on_get(key)
+        })
     }
 
     /// Remove a key-value. Return an error if the key does not exist or is not
@@ -55,9 +88,23 @@ impl KvStore for HashMapKvs {
     /// store.remove("key1".to_owned());
     /// ```
     fn remove(&mut self, key: String) -> Result<Option<String>> {
-        let status = self.map.remove(&key);
-        if status.is_some() {
+        self.remove_ref(&key)
+    }
+
+    /// Remove a key-value without needing an owned key.
+    fn remove_ref(&mut self, key: &str) -> Result<Option<String>> {
+        let status = self.map.remove(key);
+        if let Some(value) = &status {
+            if self.trash_retention.is_some() && !is_trash_key(key) {
+                let trashed =
+                    TrashedValue::removed(value.clone(), self.clock.now());
+                self.map.insert(trash_key(key), trashed.encode());
+            }
             self.mutated = true;
+            let result = self.sync_write();
+            observe(&self.observer, "remove", result, |observer, _| {
+                observer.on_remove(key)
+            })?;
         }
         Ok(status)
     }
@@ -11,5 +11,8 @@ extern crate core;
 mod hashmap_core;
 mod kv_store;
 mod persistent;
+mod scan;
+mod scrub;
+mod stats;
 
 pub use hashmap_core::HashMapKvs;
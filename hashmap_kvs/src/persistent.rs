@@ -1,8 +1,8 @@
-use std::fs::File;
-use std::io::BufWriter;
+use std::fs::{File, OpenOptions};
+use std::io::{BufWriter, Write};
 use std::path::Path;
 
-use core::{PathType, Persistent, Result};
+use core::{Error, PathType, Persistent, Result, StoreOptions, SyncPolicy};
 use io::safe_overwrite;
 
 use crate::HashMapKvs;
@@ -10,20 +10,46 @@ use crate::HashMapKvs;
 impl Persistent for HashMapKvs {
     const PATH_TYPE: PathType = PathType::File;
 
-    fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
-        if path.as_ref().is_file() {
-            HashMapKvs::load(path)
+    fn open_with<P: AsRef<Path>>(
+        path: P,
+        options: StoreOptions,
+    ) -> Result<Self> {
+        let path = path.as_ref();
+        let opened = if path.is_file() {
+            HashMapKvs::load(path, options)
         } else {
-            HashMapKvs::new(path)
-        }
+            HashMapKvs::new(path, options)
+        };
+        opened.map_err(|err| err.at_path(path))
     }
 
     fn save(&mut self) -> Result<()> {
         safe_overwrite(self.backing.clone(), |writer: BufWriter<File>| {
-            serde_json::to_writer(writer, &self.map)?;
+            serde_json::to_writer(writer, &self.map)
+                .map_err(Error::serialization)?;
             self.mutated = false;
             Ok(())
-        })
+        })?;
+
+        if self.sync == SyncPolicy::Always {
+            File::open(&self.backing)?.sync_all()?;
+        }
+        Ok(())
+    }
+
+    /// Write the map to a new file, including any unsaved changes.
+    fn fork_to<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let file =
+            OpenOptions::new().write(true).create_new(true).open(path)?;
+        let mut writer = BufWriter::new(file);
+        serde_json::to_writer(&mut writer, &self.map)
+            .map_err(Error::serialization)?;
+        writer.flush()?;
+
+        if self.sync == SyncPolicy::Always {
+            writer.get_ref().sync_all()?;
+        }
+        Ok(())
     }
 }
 
@@ -39,5 +65,48 @@ impl Drop for HashMapKvs {
 mod tests {
     use super::*;
 
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use core::tests::{DefaultTestContext, MockClock, TestContext};
+    use core::{trash_key, KvStore};
+
     generate_persistent_tests!(HashMapKvs);
+    generate_simulation_tests!(HashMapKvs);
+
+    #[test]
+    fn purge_trash_on_open() -> Result<()> {
+        let context: DefaultTestContext = TestContext::<HashMapKvs>::init();
+        let clock = Arc::new(MockClock::default());
+        let options = StoreOptions {
+            trash_retention: Some(Duration::from_secs(60)),
+            clock: Some(clock.clone()),
+            ..StoreOptions::default()
+        };
+
+        {
+            let mut store: HashMapKvs =
+                context.open_store_with(options.clone())?;
+            store.set("key1".to_owned(), "value1".to_owned())?;
+            // set directly, before the trash's prefix was kept back
+            store
+                .map
+                .insert(trash_key("key2"), "set directly".to_owned());
+            store.remove("key1".to_owned())?;
+            assert!(store.get(trash_key("key1"))?.is_some());
+        }
+
+        {
+            let store: HashMapKvs = context.open_store_with(options.clone())?;
+            assert!(store.get(trash_key("key1"))?.is_some());
+        }
+
+        clock.advance(Duration::from_secs(60));
+        let store: HashMapKvs = context.open_store_with(options)?;
+        assert_eq!(store.get(trash_key("key1"))?, None);
+        // only values removed by a soft delete are purged
+        assert!(store.get(trash_key("key2"))?.is_some());
+
+        Ok(())
+    }
 }
@@ -0,0 +1,133 @@
+use core::{in_range, is_trash_key, RangeEstimate, Result, Scannable};
+
+use crate::HashMapKvs;
+
+impl Scannable for HashMapKvs {
+    /// Every key that currently has a value. Removed values kept in the
+    /// trash aren't included.
+    fn keys(&self) -> Result<Vec<String>> {
+        Ok(self.live_keys().cloned().collect())
+    }
+
+    /// The keys in the range, in order. The map isn't ordered, so this
+    /// sorts the matching keys each time.
+    ///
+    /// ```rust
+    /// # use tempfile::TempDir;
+    /// # use core::{KvStore, Persistent, Scannable};
+    /// # use hashmap_kvs::HashMapKvs;
+    /// #
+    /// # let temp_dir =
+    /// #     TempDir::new().expect("unable to create temporary working directory");
+    /// # let mut store = HashMapKvs::open(temp_dir.path().join("kvs")).unwrap();
+    /// store.set("b".to_owned(), "value1".to_owned());
+    /// store.set("a".to_owned(), "value2".to_owned());
+    /// assert_eq!(store.scan("a", None).unwrap(), vec!["a", "b"]);
+    /// ```
+    fn scan(&self, start: &str, end: Option<&str>) -> Result<Vec<String>> {
+        let mut keys: Vec<String> = self
+            .live_keys()
+            .filter(|key| in_range(key, start, end))
+            .cloned()
+            .collect();
+        keys.sort();
+        Ok(keys)
+    }
+
+    /// The exact number of keys in the range, and the length of each key
+    /// and value in it, since they're all in memory.
+    fn estimate_range_size(
+        &self,
+        start: &str,
+        end: Option<&str>,
+    ) -> Result<RangeEstimate> {
+        Ok(self
+            .map
+            .iter()
+            .filter(|(key, _)| !is_trash_key(key) && in_range(key, start, end))
+            .fold(RangeEstimate::default(), |estimate, (key, value)| {
+                RangeEstimate {
+                    keys: estimate.keys + 1,
+                    bytes: estimate.bytes + (key.len() + value.len()) as u64,
+                }
+            }))
+    }
+
+    /// Filters the map in place, so only the matching entries are copied.
+    fn scan_filtered(
+        &self,
+        start: &str,
+        end: Option<&str>,
+        predicate: &dyn Fn(&str, &str) -> bool,
+    ) -> Result<Vec<(String, String)>> {
+        let mut entries: Vec<(String, String)> = self
+            .map
+            .iter()
+            .filter(|(key, value)| {
+                !is_trash_key(key)
+                    && in_range(key, start, end)
+                    && predicate(key, value)
+            })
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+        entries.sort();
+        Ok(entries)
+    }
+}
+
+impl HashMapKvs {
+    /// The keys with a value, leaving out the trash.
+    pub(crate) fn live_keys(&self) -> impl Iterator<Item = &String> {
+        self.map.keys().filter(|key| !is_trash_key(key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+
+    use core::tests::{DefaultTestContext, TestContext};
+    use core::{trash_key, KvStore, Measurable, StoreOptions};
+
+    generate_scannable_tests!(HashMapKvs);
+
+    #[test]
+    fn ordered_scan() -> Result<()> {
+        let context = <DefaultTestContext as TestContext<HashMapKvs>>::init();
+        let mut store: HashMapKvs = context.open_store()?;
+
+        for key in &["user:3", "user:1", "group:1", "user:2"] {
+            store.set(key.to_string(), "value".to_owned())?;
+        }
+        assert_eq!(
+            store.scan("user:", Some("user:3"))?,
+            vec!["user:1".to_owned(), "user:2".to_owned()]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn trash_hidden() -> Result<()> {
+        let context = <DefaultTestContext as TestContext<HashMapKvs>>::init();
+        let options = StoreOptions {
+            trash_retention: Some(Duration::from_secs(60)),
+            ..StoreOptions::default()
+        };
+        let mut store: HashMapKvs = context.open_store_with(options)?;
+        store.set("key1".to_owned(), "value1".to_owned())?;
+        store.set("key2".to_owned(), "value2".to_owned())?;
+        store.remove("key1".to_owned())?;
+        assert!(store.contains_key(&trash_key("key1"))?);
+
+        assert_eq!(store.keys()?, vec!["key2"]);
+        assert_eq!(store.scan("", None)?, vec!["key2"]);
+        assert_eq!(store.scan_filtered("", None, &|_, _| true)?.len(), 1);
+        assert_eq!(store.estimate_range_size("", None)?.keys, 1);
+        assert_eq!(store.stats()?.keys, 1);
+
+        Ok(())
+    }
+}
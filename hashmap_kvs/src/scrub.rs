@@ -0,0 +1,42 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+
+use core::{Result, ScrubReport, Scrubbable};
+
+use crate::HashMapKvs;
+
+impl Scrubbable for HashMapKvs {
+    /// Check that the saved copy of the store can be decoded. If there are
+    /// no unsaved changes, also check that it matches the copy in memory.
+    fn scrub(&self) -> Result<ScrubReport> {
+        let mut report = ScrubReport::default();
+        if !self.backing.is_file() {
+            return Ok(report);
+        }
+
+        let reader = BufReader::new(File::open(&self.backing)?);
+        match serde_json::from_reader::<_, HashMap<String, String>>(reader) {
+            Ok(saved) => {
+                report.records = saved.len() as u64;
+                if !self.mutated && saved != self.map {
+                    report.problems.push(
+                        "the saved store doesn't match the one in memory"
+                            .to_owned(),
+                    );
+                }
+            }
+            Err(err) => report
+                .problems
+                .push(format!("the saved store can't be decoded: {}", err)),
+        }
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    generate_scrubbable_tests!(HashMapKvs);
+}
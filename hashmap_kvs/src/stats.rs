@@ -0,0 +1,35 @@
+use core::{Measurable, Result, StoreStats};
+
+use crate::HashMapKvs;
+
+impl Measurable for HashMapKvs {
+    /// Gather statistics about the store. The size on disk is that of the
+    /// last save, so it doesn't include unsaved changes.
+    ///
+    /// ```rust
+    /// # use tempfile::TempDir;
+    /// # use core::{KvStore, Measurable, Persistent};
+    /// # use hashmap_kvs::HashMapKvs;
+    /// #
+    /// # let temp_dir =
+    /// #     TempDir::new().expect("unable to create temporary working directory");
+    /// # let mut store = HashMapKvs::open(temp_dir.path().join("kvs")).unwrap();
+    /// store.set("key1".to_owned(), "value1".to_owned());
+    /// assert_eq!(store.stats().unwrap().keys, 1);
+    /// ```
+    fn stats(&self) -> Result<StoreStats> {
+        Ok(StoreStats {
+            keys: self.live_keys().count() as u64,
+            stale_records: 0,
+            stale_bytes: 0,
+            disk_bytes: std::fs::metadata(&self.backing)?.len(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    generate_measurable_tests!(HashMapKvs);
+}
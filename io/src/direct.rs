@@ -0,0 +1,260 @@
+/*!
+ * Reading files with direct I/O, bypassing the page cache.
+ */
+
+use std::alloc::{self, Layout};
+use std::fs::{File, OpenOptions};
+use std::io::{Error, ErrorKind, Read, Result, Seek, SeekFrom};
+use std::ops::{Deref, DerefMut};
+use std::path::Path;
+
+/// What buffers, offsets and lengths have to be a multiple of for direct
+/// I/O. Devices may need less, but none need more.
+pub const DIRECT_IO_ALIGNMENT: usize = 4096;
+
+/// A zeroed buffer whose start is aligned to [`DIRECT_IO_ALIGNMENT`].
+pub struct AlignedBuf {
+    ptr: *mut u8,
+    len: usize,
+}
+
+impl AlignedBuf {
+    /// Allocate a buffer of at least `len` bytes, rounded up to a multiple
+    /// of [`DIRECT_IO_ALIGNMENT`].
+    pub fn new(len: usize) -> AlignedBuf {
+        let len = align_up(len.max(1) as u64) as usize;
+        let layout = Self::layout(len);
+        // the layout's size is never 0
+        let ptr = unsafe { alloc::alloc_zeroed(layout) };
+        if ptr.is_null() {
+            alloc::handle_alloc_error(layout);
+        }
+        AlignedBuf { ptr, len }
+    }
+
+    fn layout(len: usize) -> Layout {
+        Layout::from_size_align(len, DIRECT_IO_ALIGNMENT)
+            .expect("the alignment is a power of two")
+    }
+}
+
+impl Deref for AlignedBuf {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.ptr, self.len) }
+    }
+}
+
+impl DerefMut for AlignedBuf {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        unsafe { std::slice::from_raw_parts_mut(self.ptr, self.len) }
+    }
+}
+
+impl Drop for AlignedBuf {
+    fn drop(&mut self) {
+        unsafe { alloc::dealloc(self.ptr, Self::layout(self.len)) }
+    }
+}
+
+impl std::fmt::Debug for AlignedBuf {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("AlignedBuf")
+            .field("len", &self.len)
+            .finish()
+    }
+}
+
+// it owns its memory, like a Vec<u8>
+unsafe impl Send for AlignedBuf {}
+unsafe impl Sync for AlignedBuf {}
+
+fn align_down(offset: u64) -> u64 {
+    offset - offset % DIRECT_IO_ALIGNMENT as u64
+}
+
+fn align_up(offset: u64) -> u64 {
+    align_down(offset + DIRECT_IO_ALIGNMENT as u64 - 1)
+}
+
+/// Open a file for reading with direct I/O, so reads go to the device
+/// instead of the page cache. Uses `O_DIRECT` on Linux and `F_NOCACHE` on
+/// macOS, and fails on other platforms. Reads from the file have to be
+/// aligned, see [`DirectReader`] for one that handles that.
+pub fn open_direct<P: AsRef<Path>>(path: P) -> Result<File> {
+    open_direct_impl(path.as_ref())
+}
+
+#[cfg(target_os = "linux")]
+fn open_direct_impl(path: &Path) -> Result<File> {
+    use std::os::unix::fs::OpenOptionsExt;
+
+    OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_DIRECT)
+        .open(path)
+}
+
+#[cfg(target_os = "macos")]
+fn open_direct_impl(path: &Path) -> Result<File> {
+    use std::os::unix::io::AsRawFd;
+
+    let file = OpenOptions::new().read(true).open(path)?;
+    if unsafe { libc::fcntl(file.as_raw_fd(), libc::F_NOCACHE, 1) } == -1 {
+        return Err(Error::last_os_error());
+    }
+    Ok(file)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn open_direct_impl(_path: &Path) -> Result<File> {
+    Err(Error::new(
+        ErrorKind::Other,
+        "direct I/O isn't supported on this platform",
+    ))
+}
+
+/// Reads a file opened with [`open_direct`], at any offset and into any
+/// buffer, by reading whole aligned blocks into an [`AlignedBuf`] and
+/// copying out of it.
+#[derive(Debug)]
+pub struct DirectReader {
+    file: File,
+    buf: AlignedBuf,
+    /// The offset in the file of the start of the buffer.
+    buf_start: u64,
+    /// How much of the buffer holds data read from the file.
+    buf_len: usize,
+    pos: u64,
+}
+
+impl DirectReader {
+    /// The number of bytes read from the file at once.
+    pub const BLOCK_SIZE: usize = 16 * DIRECT_IO_ALIGNMENT;
+
+    /// Open the file at the given path with direct I/O.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<DirectReader> {
+        Ok(DirectReader::new(open_direct(path)?))
+    }
+
+    /// Read a file already opened with direct I/O, from the start.
+    pub fn new(file: File) -> DirectReader {
+        DirectReader {
+            file,
+            buf: AlignedBuf::new(Self::BLOCK_SIZE),
+            buf_start: 0,
+            buf_len: 0,
+            pos: 0,
+        }
+    }
+
+    /// Fill the buffer with the block holding the current position.
+    fn fill(&mut self) -> Result<()> {
+        self.buf_start = align_down(self.pos);
+        self.buf_len = 0;
+        self.file.seek(SeekFrom::Start(self.buf_start))?;
+        while self.buf_len < self.buf.len() {
+            match self.file.read(&mut self.buf[self.buf_len..]) {
+                Ok(0) => break,
+                Ok(read) => self.buf_len += read,
+                Err(ref err) if err.kind() == ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+            // reads past the end of the file come back short, and the next
+            // would start unaligned
+            if align_down(self.buf_len as u64) != self.buf_len as u64 {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Read for DirectReader {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let buf_end = self.buf_start + self.buf_len as u64;
+        if self.pos < self.buf_start || self.pos >= buf_end {
+            self.fill()?;
+        }
+        let start = (self.pos - self.buf_start) as usize;
+        if start >= self.buf_len {
+            // at or past the end of the file
+            return Ok(0);
+        }
+        let read = buf.len().min(self.buf_len - start);
+        buf[..read].copy_from_slice(&self.buf[start..start + read]);
+        self.pos += read as u64;
+        Ok(read)
+    }
+}
+
+impl Seek for DirectReader {
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(pos) => Some(pos),
+            SeekFrom::Current(offset) => offset_by(self.pos, offset),
+            SeekFrom::End(offset) => {
+                offset_by(self.file.metadata()?.len(), offset)
+            }
+        };
+        self.pos = pos.ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )
+        })?;
+        Ok(self.pos)
+    }
+}
+
+fn offset_by(pos: u64, offset: i64) -> Option<u64> {
+    if offset < 0 {
+        pos.checked_sub(offset.wrapping_neg() as u64)
+    } else {
+        pos.checked_add(offset as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tempfile::TempDir;
+
+    #[test]
+    fn aligned() {
+        let buf = AlignedBuf::new(100);
+        assert_eq!(buf.len(), DIRECT_IO_ALIGNMENT);
+        assert_eq!(buf.as_ptr() as usize % DIRECT_IO_ALIGNMENT, 0);
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    #[test]
+    fn read_unaligned() -> Result<()> {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+        let path = temp_dir.path().join("file");
+        let data: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+        std::fs::write(&path, &data)?;
+
+        let mut reader = DirectReader::open(&path)?;
+        let mut read = Vec::new();
+        reader.read_to_end(&mut read)?;
+        assert_eq!(read, data);
+
+        // across a block boundary
+        let start = DirectReader::BLOCK_SIZE as u64 - 3;
+        assert_eq!(reader.seek(SeekFrom::Start(start))?, start);
+        let mut part = [0; 10];
+        reader.read_exact(&mut part)?;
+        assert_eq!(&part[..], &data[start as usize..start as usize + 10]);
+
+        assert_eq!(reader.seek(SeekFrom::End(-1))?, data.len() as u64 - 1);
+        assert_eq!(reader.read(&mut part)?, 1);
+        assert_eq!(reader.read(&mut part)?, 0);
+        assert!(reader.seek(SeekFrom::Current(-200_000)).is_err());
+
+        Ok(())
+    }
+}
@@ -1,12 +1,23 @@
 #![deny(missing_docs)]
-#![feature(seek_convenience)]
 
 /*!
  * Crate containing useful things for safe io.
  */
 
+mod direct;
+pub use direct::*;
+
 mod overwrite;
 pub use overwrite::*;
 
+mod preallocate;
+pub use preallocate::*;
+
+mod seek;
+pub use seek::*;
+
 mod tracker;
 pub use tracker::*;
+
+mod utf8;
+pub use utf8::*;
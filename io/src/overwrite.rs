@@ -6,7 +6,7 @@ use std::fs::{File, OpenOptions};
 use std::io::{BufReader, BufWriter};
 use std::path::Path;
 
-use core::Result;
+use core::{fail_point, Result};
 
 /// Used to write a file without overwriting the file that already existed. Does
 /// so by writing to a temporary file, then renaming the actual file to
@@ -40,9 +40,7 @@ where
     write_func(writer)?;
 
     if !direct {
-        std::fs::rename(&target, &backup)?;
-        std::fs::rename(&tmp, &target)?;
-        std::fs::remove_file(&backup)?;
+        swap_in(target, &tmp, &backup)?;
     }
 
     Ok(())
@@ -66,9 +64,33 @@ where
     let writer = BufWriter::new(writer);
     write_func(reader, writer)?;
 
-    std::fs::rename(&target, &backup)?;
-    std::fs::rename(&tmp, &target)?;
-    std::fs::remove_file(&backup)?;
+    swap_in(target, &tmp, &backup)
+}
+
+/// Replace the target with the file written at `tmp`, moving the target
+/// aside to `backup` until the new file is in place. If that fails, the
+/// target is put back.
+fn swap_in(target: &Path, tmp: &Path, backup: &Path) -> Result<()> {
+    std::fs::rename(target, backup)?;
+    if let Err(err) = fail_point("overwrite::rename")
+        .and_then(|_| Ok(std::fs::rename(tmp, target)?))
+    {
+        std::fs::rename(backup, target)?;
+        return Err(err);
+    }
+    std::fs::remove_file(backup)?;
+    Ok(())
+}
 
+/// Finish or undo an overwrite of the target that was cut short, by a
+/// crash between moving the old file aside and moving the new one in. The
+/// old file is put back, since the new one may not have been synced. Does
+/// nothing if the target is there.
+pub fn recover_overwrite<P: AsRef<Path>>(path: P) -> Result<()> {
+    let target = path.as_ref();
+    let backup = target.with_extension("backup");
+    if !target.exists() && backup.is_file() {
+        std::fs::rename(&backup, target)?;
+    }
     Ok(())
 }
@@ -0,0 +1,64 @@
+/*!
+ * Reserving disk space for a file ahead of writing to it.
+ */
+
+use std::fs::File;
+use std::io::Result;
+
+/// Reserve `len` bytes of disk for the file from `offset`, without changing
+/// its length, so appends there don't need to allocate blocks one at a time
+/// and the file is less fragmented. Uses `fallocate` with
+/// `FALLOC_FL_KEEP_SIZE` on Linux, and does nothing on other platforms.
+/// Fails if the file system doesn't support it, or is out of space.
+pub fn preallocate(file: &File, offset: u64, len: u64) -> Result<()> {
+    preallocate_impl(file, offset, len)
+}
+
+#[cfg(target_os = "linux")]
+fn preallocate_impl(file: &File, offset: u64, len: u64) -> Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    let result = unsafe {
+        libc::fallocate(
+            file.as_raw_fd(),
+            libc::FALLOC_FL_KEEP_SIZE,
+            offset as libc::off_t,
+            len as libc::off_t,
+        )
+    };
+    if result == -1 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn preallocate_impl(_file: &File, _offset: u64, _len: u64) -> Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tempfile::TempDir;
+
+    #[test]
+    fn keeps_length() -> Result<()> {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+        let path = temp_dir.path().join("file");
+        std::fs::write(&path, b"test")?;
+
+        let file = std::fs::OpenOptions::new().append(true).open(&path)?;
+        preallocate(&file, 4, 1 << 20)?;
+        assert_eq!(file.metadata()?.len(), 4);
+        #[cfg(target_os = "linux")]
+        {
+            use std::os::unix::fs::MetadataExt;
+            assert!(file.metadata()?.blocks() * 512 >= 1 << 20);
+        }
+
+        Ok(())
+    }
+}
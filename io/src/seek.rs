@@ -0,0 +1,37 @@
+/*!
+ * A stable replacement for `Seek::stream_len`, which is still nightly-only.
+ */
+
+use std::io::{Result, Seek, SeekFrom};
+
+/// Get the total length of the stream, leaving the seeker where it was.
+pub fn stream_len<S: Seek>(seeker: &mut S) -> Result<u64> {
+    let pos = seeker.stream_position()?;
+    let len = seeker.seek(SeekFrom::End(0))?;
+    if pos != len {
+        seeker.seek(SeekFrom::Start(pos))?;
+    }
+    Ok(len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::{Cursor, Read};
+
+    #[test]
+    fn len_keeps_position() -> Result<()> {
+        let mut cursor = Cursor::new(b"test\n123\n".to_vec());
+        cursor.seek(SeekFrom::Start(5))?;
+
+        assert_eq!(stream_len(&mut cursor)?, 9);
+        assert_eq!(cursor.stream_position()?, 5);
+
+        let mut s = String::new();
+        cursor.read_to_string(&mut s)?;
+        assert_eq!(s, "123\n");
+
+        Ok(())
+    }
+}
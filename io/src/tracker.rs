@@ -18,6 +18,11 @@ impl<R> Tracker<R> {
     pub fn new(item: R) -> Self {
         Tracker { item, pos: 0 }
     }
+
+    /// Initializes the tracker at a known current location.
+    pub fn with_pos(item: R, pos: u64) -> Self {
+        Tracker { item, pos }
+    }
 }
 
 impl<R: Read> Trackable for Tracker<R> {
@@ -0,0 +1,92 @@
+/*!
+ * Copying text without holding all of it in memory.
+ */
+
+use std::io::{Error, ErrorKind, Read, Result, Write};
+
+/// Copy everything from the reader to the writer, checking that it's valid
+/// UTF-8 along the way. Returns the number of bytes copied.
+///
+/// On invalid UTF-8 an `InvalidData` error is returned, and some of the
+/// text may already have been written.
+pub fn copy_utf8<R, W>(reader: &mut R, writer: &mut W) -> Result<u64>
+where
+    R: Read + ?Sized,
+    W: Write + ?Sized,
+{
+    let mut buf = [0; 8 * 1024];
+    // the bytes of a character split across two reads
+    let mut carry = 0;
+    let mut copied = 0;
+
+    loop {
+        let read = match reader.read(&mut buf[carry..]) {
+            Ok(0) if carry == 0 => return Ok(copied),
+            Ok(0) => return Err(invalid_utf8()),
+            Ok(read) => read,
+            Err(ref err) if err.kind() == ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        };
+        let filled = carry + read;
+
+        let valid = match std::str::from_utf8(&buf[..filled]) {
+            Ok(_) => filled,
+            // an incomplete character at the end may finish in the next read
+            Err(err) if err.error_len().is_none() => err.valid_up_to(),
+            Err(_) => return Err(invalid_utf8()),
+        };
+        writer.write_all(&buf[..valid])?;
+        copied += valid as u64;
+
+        carry = filled - valid;
+        buf.copy_within(valid..filled, 0);
+    }
+}
+
+fn invalid_utf8() -> Error {
+    Error::new(ErrorKind::InvalidData, "stream did not contain valid UTF-8")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Hands out one byte per read, to split every multi-byte character.
+    struct Trickle<'a>(&'a [u8]);
+
+    impl<'a> Read for Trickle<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+            match self.0.split_first() {
+                Some((first, rest)) if !buf.is_empty() => {
+                    buf[0] = *first;
+                    self.0 = rest;
+                    Ok(1)
+                }
+                _ => Ok(0),
+            }
+        }
+    }
+
+    #[test]
+    fn copies_split_characters() -> Result<()> {
+        let text = "line1\nłine2 ✓\n";
+        let mut out = Vec::new();
+
+        let copied = copy_utf8(&mut Trickle(text.as_bytes()), &mut out)?;
+        assert_eq!(copied, text.len() as u64);
+        assert_eq!(out, text.as_bytes());
+
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_invalid() {
+        let mut out = Vec::new();
+        let err = copy_utf8(&mut &b"ok\xff"[..], &mut out).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+
+        // a character cut off by the end of the stream
+        let err = copy_utf8(&mut Trickle(b"ok\xe2\x9c"), &mut out).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,347 @@
+/*!
+ * Opening a store without reading its log into the index first, and
+ * filling the index in afterwards, so a large store can be written to
+ * straight away.
+ */
+
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::sync::mpsc::{self, Sender, TryRecvError};
+use std::sync::{Arc, RwLock};
+use std::thread::{self, JoinHandle};
+
+use core::Result;
+
+use crate::{Command, Index, LogCommandPointer, LogKvs};
+
+/// How much of the log is still to be indexed.
+#[derive(Debug)]
+pub(crate) struct Backfill {
+    /// The offset of the next record to index.
+    next: u64,
+    /// How long the log was when the store was opened. Records written
+    /// since are indexed as they're written.
+    end: u64,
+    /// The keys written since the store was opened, whose earlier records
+    /// are out of date.
+    touched: HashSet<String>,
+}
+
+impl Backfill {
+    pub fn new(next: u64, end: u64) -> Backfill {
+        Backfill {
+            next,
+            end,
+            touched: HashSet::new(),
+        }
+    }
+}
+
+impl LogKvs {
+    /// How much of the log a [`Backfiller`] indexes at a time, holding the
+    /// store's lock.
+    const BACKFILL_CHUNK: u64 = 1 << 20;
+
+    /// Whether every record in the log is in the index. Only false for a
+    /// store opened with `StoreOptions::lazy_open` that hasn't finished
+    /// being backfilled.
+    pub fn is_backfilled(&self) -> bool {
+        self.backfill.is_none()
+    }
+
+    /// Add about `max_bytes` more of the log to the index of a store opened
+    /// with `StoreOptions::lazy_open`, returning whether it's all indexed.
+    /// Usually left to a [`Backfiller`].
+    pub fn backfill(&mut self, max_bytes: u64) -> Result<bool> {
+        let (next, end) = match &self.backfill {
+            Some(backfill) => (backfill.next, backfill.end),
+            None => return Ok(true),
+        };
+        let stop = next.saturating_add(max_bytes).min(end);
+        let mut records = self.log.iter_from(next)?;
+        while records.pos() < stop {
+            let (command, pointer) = match records.next() {
+                Some(record) => record?,
+                None => break,
+            };
+            let touched = match &self.backfill {
+                Some(backfill) => &backfill.touched,
+                None => break,
+            };
+            match self.index.removed_by(&command) {
+                Some(removed) => {
+                    // only the keys written before the store was opened
+                    for key in removed {
+                        if !touched.contains(&key) {
+                            self.index.remove(&key);
+                        }
+                    }
+                }
+                None => {
+                    if !touched.contains(command.key()) {
+                        self.replay(command, pointer)?;
+                    }
+                }
+            }
+        }
+
+        let pos = records.pos();
+        self.report_progress(pos.min(end), end);
+        match &mut self.backfill {
+            Some(backfill) if pos < end => {
+                backfill.next = pos;
+                Ok(false)
+            }
+            _ => {
+                self.backfill = None;
+                Ok(true)
+            }
+        }
+    }
+
+    /// Index the rest of the log, for operations that need all of it.
+    pub(crate) fn finish_backfill(&mut self) -> Result<()> {
+        self.backfill(u64::MAX).map(|_| ())
+    }
+
+    /// Note that the key's been written, so its earlier records are skipped
+    /// when backfilling.
+    pub(crate) fn touch(&mut self, key: &str) {
+        if let Some(backfill) = &mut self.backfill {
+            backfill.touched.insert(key.to_owned());
+        }
+    }
+
+    /// Where the current value of the key is, if it has one. While the
+    /// index is being backfilled, the rest of the log is read for keys that
+    /// haven't been written since the store was opened, since it may hold a
+    /// later record than the index.
+    pub(crate) fn lookup(
+        &self,
+        key: &str,
+    ) -> Result<Option<LogCommandPointer>> {
+        let indexed = self.index.get(key).copied();
+        let backfill = match &self.backfill {
+            Some(backfill) if !backfill.touched.contains(key) => backfill,
+            _ => return Ok(indexed),
+        };
+        let mut latest = indexed;
+        for record in self.log.iter_from(backfill.next)? {
+            let (command, pointer) = record?;
+            if pointer.offset() >= backfill.end {
+                break;
+            }
+            if command.removes(key, self.collation()) {
+                latest = None;
+            } else if command.key() == key {
+                latest = Some(pointer);
+            }
+        }
+        Ok(latest)
+    }
+
+    /// The index, with the rest of the log added to a copy of it while it's
+    /// being backfilled, for reads that need every key.
+    pub(crate) fn full_index(&self) -> Result<Cow<'_, Index>> {
+        let backfill = match &self.backfill {
+            Some(backfill) => backfill,
+            None => return Ok(Cow::Borrowed(&self.index)),
+        };
+        let mut index = self.index.clone();
+        for record in self.log.iter_from(backfill.next)? {
+            let (command, pointer) = record?;
+            if pointer.offset() >= backfill.end {
+                break;
+            }
+            match (index.removed_by(&command), command) {
+                (Some(removed), _) => {
+                    for key in removed {
+                        if !backfill.touched.contains(&key) {
+                            index.remove(&key);
+                        }
+                    }
+                }
+                (None, command) if backfill.touched.contains(command.key()) => {
+                }
+                (None, Command::Remove { key }) => {
+                    index.remove(&key);
+                }
+                (None, command) => {
+                    index.insert(command.key().to_owned(), pointer);
+                }
+            }
+        }
+        Ok(Cow::Owned(index))
+    }
+}
+
+/// Backfills the index of a shared store opened with
+/// `StoreOptions::lazy_open` on a background thread, a chunk at a time so
+/// reads and writes aren't held up for long. Stops once it's done, or when
+/// dropped.
+///
+/// ```rust
+/// # use std::sync::{Arc, RwLock};
+/// # use tempfile::TempDir;
+/// # use core::{KvStore, Persistent, StoreOptions};
+/// # use log_kvs::{Backfiller, LogKvs};
+/// #
+/// # let temp_dir =
+/// #     TempDir::new().expect("unable to create temporary working directory");
+/// # let mut store = LogKvs::open(temp_dir.path()).unwrap();
+/// # store.set("key1".to_owned(), "value1".to_owned()).unwrap();
+/// # drop(store);
+/// let options = StoreOptions {
+///     lazy_open: true,
+///     ..StoreOptions::default()
+/// };
+/// let store = LogKvs::open_with(temp_dir.path(), options).unwrap();
+/// let store = Arc::new(RwLock::new(store));
+/// let backfiller = Backfiller::spawn(store.clone());
+///
+/// // served straight away, whether or not it's been indexed yet
+/// store
+///     .write()
+///     .unwrap()
+///     .set("key2".to_owned(), "value2".to_owned())
+///     .unwrap();
+/// assert_eq!(
+///     store.read().unwrap().get("key1".to_owned()).unwrap(),
+///     Some("value1".to_owned())
+/// );
+/// backfiller.join().unwrap();
+/// assert!(store.read().unwrap().is_backfilled());
+/// ```
+pub struct Backfiller {
+    stop: Option<Sender<()>>,
+    handle: Option<JoinHandle<Result<()>>>,
+}
+
+impl Backfiller {
+    /// Start backfilling the store's index. Only takes a write lock while
+    /// indexing a chunk.
+    pub fn spawn(store: Arc<RwLock<LogKvs>>) -> Backfiller {
+        let (stop, stopped) = mpsc::channel::<()>();
+        let handle = thread::spawn(move || loop {
+            match stopped.try_recv() {
+                Err(TryRecvError::Empty) => {}
+                _ => return Ok(()),
+            }
+            let done = match store.write() {
+                Ok(mut store) => store.backfill(LogKvs::BACKFILL_CHUNK)?,
+                // a writer panicked, so the handle can't be trusted
+                Err(_) => return Ok(()),
+            };
+            if done {
+                return Ok(());
+            }
+            // let reads and writes waiting on the lock in
+            thread::yield_now();
+        });
+
+        Backfiller {
+            stop: Some(stop),
+            handle: Some(handle),
+        }
+    }
+
+    /// Wait until the whole index is backfilled, returning the error that
+    /// stopped it if there was one.
+    pub fn join(mut self) -> Result<()> {
+        match self.handle.take().map(JoinHandle::join) {
+            Some(Ok(result)) => result,
+            Some(Err(panic)) => std::panic::resume_unwind(panic),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for Backfiller {
+    fn drop(&mut self) {
+        // dropping the sender tells the thread to stop when it next checks
+        self.stop.take();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Mutex;
+
+    use core::tests::{DefaultTestContext, TestContext};
+    use core::{
+        IndexKind, KvStore, Measurable, Scannable, Scrubbable, StoreObserver,
+        StoreOptions,
+    };
+
+    #[derive(Debug, Default)]
+    struct Progress(Mutex<Vec<(u64, u64)>>);
+
+    impl StoreObserver for Progress {
+        fn on_index_progress(&self, indexed: u64, total: u64) {
+            self.0.lock().unwrap().push((indexed, total));
+        }
+    }
+
+    #[test]
+    fn lazy_open() -> Result<()> {
+        let context = <DefaultTestContext as TestContext<LogKvs>>::init();
+        let mut store: LogKvs = context.open_store_with(StoreOptions {
+            index: IndexKind::Ordered,
+            ..StoreOptions::default()
+        })?;
+        for i in 0..100 {
+            store.set(format!("key{:02}", i), format!("value{}", i))?;
+        }
+        store.remove("key10".to_owned())?;
+        store.set("key20".to_owned(), "changed".to_owned())?;
+        drop(store);
+
+        let progress = Arc::new(Progress::default());
+        let lazy = StoreOptions {
+            index: IndexKind::Ordered,
+            lazy_open: true,
+            observer: Some(progress.clone()),
+            ..StoreOptions::default()
+        };
+        let mut store: LogKvs = context.open_store_with(lazy)?;
+        assert!(!store.is_backfilled());
+        assert!(progress.0.lock().unwrap().is_empty());
+        store.backfill(500)?;
+        assert!(!store.is_backfilled());
+        let indexed = store.index.len();
+
+        // reads fall back to the log until it's indexed
+        assert_eq!(store.get("key99".to_owned())?, Some("value99".to_owned()));
+        assert_eq!(store.get("key20".to_owned())?, Some("changed".to_owned()));
+        assert_eq!(store.get("key10".to_owned())?, None);
+        assert_eq!(store.scan("key0", Some("key1"))?.len(), 10);
+        assert_eq!(store.keys()?.len(), 99);
+        assert_eq!(store.stats()?.keys, 99);
+        assert!(store.scrub()?.problems.is_empty());
+
+        // and writes aren't undone by older records
+        store.set("key98".to_owned(), "new".to_owned())?;
+        assert_eq!(
+            store.remove("key97".to_owned())?,
+            Some("value97".to_owned())
+        );
+        assert_eq!(store.remove("key97".to_owned())?, None);
+        assert_eq!(store.index.len(), indexed + 1);
+
+        while !store.backfill(500)? {}
+        assert_eq!(store.index.len(), 98);
+        assert_eq!(store.get("key98".to_owned())?, Some("new".to_owned()));
+        assert_eq!(store.get("key97".to_owned())?, None);
+        let progress = progress.0.lock().unwrap();
+        let (indexed, total) = *progress.last().unwrap();
+        assert_eq!(indexed, total);
+        assert!(progress.len() > 2);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,240 @@
+use std::collections::HashSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use sha2::{Digest, Sha256};
+
+use core::{Error, Result};
+
+use crate::{Command, LogKvs};
+
+impl LogKvs {
+    /// The name of the file holding the sequence an increment starts from.
+    const SINCE_FILE_NAME: &'static str = "since";
+
+    /// The store's current sequence number, which is the length of its log.
+    /// A record's sequence number is its offset in the log, so every record
+    /// written from now on has a higher one, until the store is compacted.
+    pub fn sequence(&self) -> Result<u64> {
+        self.log.len()
+    }
+
+    /// The SHA-256 digest of the log up to the given sequence, in lowercase
+    /// hex. If it changes, the log was rewritten by a compaction and
+    /// sequence numbers from before then no longer apply.
+    pub fn log_digest(&self, sequence: u64) -> Result<String> {
+        self.range_digest(0, sequence)
+    }
+
+    /// The SHA-256 digest of the log between two offsets, in lowercase hex.
+    pub(crate) fn range_digest(&self, start: u64, end: u64) -> Result<String> {
+        let mut hash = HashWriter(Sha256::new());
+        self.log.copy_range(start, end, &mut hash)?;
+        Ok(format!("{:x}", (hash.0).result()))
+    }
+
+    /// Write every record after the given sequence to a new directory, along
+    /// with the blobs they refer to. Returns the sequence the increment ends
+    /// at, to pass as `since` next time.
+    ///
+    /// ```rust
+    /// # use tempfile::TempDir;
+    /// # use core::{KvStore, Persistent};
+    /// # use log_kvs::LogKvs;
+    ///
+    /// # let temp_dir =
+    /// #     TempDir::new().expect("unable to create temporary working directory");
+    /// # let mut store = LogKvs::open(temp_dir.path().join("kvs")).unwrap();
+    /// store.fork_to(temp_dir.path().join("base")).unwrap();
+    /// let since = store.sequence().unwrap();
+    /// store.set("key1".to_owned(), "value1".to_owned()).unwrap();
+    /// store
+    ///     .write_increment(since, temp_dir.path().join("increment"))
+    ///     .unwrap();
+    ///
+    /// LogKvs::apply_increment(
+    ///     temp_dir.path().join("base"),
+    ///     temp_dir.path().join("increment"),
+    /// )
+    /// .unwrap();
+    /// let base = LogKvs::open(temp_dir.path().join("base")).unwrap();
+    /// assert_eq!(
+    ///     base.get("key1".to_owned()).unwrap(),
+    ///     Some("value1".to_owned())
+    /// );
+    /// ```
+    pub fn write_increment<P: AsRef<Path>>(
+        &self,
+        since: u64,
+        path: P,
+    ) -> Result<u64> {
+        let path = path.as_ref();
+        let end = self.sequence()?;
+        if since > end {
+            return Err(Error::config(format!(
+                "sequence {} is past the end of the log at {}, it may have \
+                 been compacted",
+                since, end
+            )));
+        }
+        fs::create_dir(path)?;
+        fs::write(path.join(Self::SINCE_FILE_NAME), since.to_string())?;
+
+        let mut blobs = HashSet::new();
+        if since < end {
+            for record in self.log.iter_from(since)? {
+                let (command, pointer) = record?;
+                if pointer.offset() >= end {
+                    break;
+                }
+                if let Command::SetBlob { blob, .. } = command {
+                    blobs.insert(blob);
+                }
+            }
+        }
+
+        let mut writer =
+            BufWriter::new(File::create(path.join(Self::DEFAULT_LOG_NAME))?);
+        self.log.copy_range(since, end, &mut writer)?;
+        writer.flush()?;
+        self.blobs
+            .link_some_to(blobs, path.join(Self::BLOB_DIR_NAME))?;
+        Ok(end)
+    }
+
+    /// Append an increment written by `write_increment` to a closed copy of
+    /// the store, which must end at the sequence the increment starts from.
+    pub fn apply_increment<P: AsRef<Path>, Q: AsRef<Path>>(
+        path: P,
+        increment: Q,
+    ) -> Result<()> {
+        let (path, increment) = (path.as_ref(), increment.as_ref());
+        let since_path = increment.join(Self::SINCE_FILE_NAME);
+        let since: u64 = fs::read_to_string(&since_path)?
+            .trim()
+            .parse()
+            .map_err(|_| {
+                Error::corrupt_database(format!(
+                    "{} doesn't hold a sequence number",
+                    since_path.display()
+                ))
+            })?;
+
+        let log_path = path.join(Self::DEFAULT_LOG_NAME);
+        let end = if log_path.is_file() {
+            fs::metadata(&log_path)?.len()
+        } else {
+            0
+        };
+        if end != since {
+            return Err(Error::corrupt_database(format!(
+                "the increment starts at sequence {}, but the store ends at {}",
+                since, end
+            )));
+        }
+
+        // blobs first, so the records are never written without them
+        let blob_dir = increment.join(Self::BLOB_DIR_NAME);
+        if blob_dir.is_dir() {
+            fs::create_dir_all(path.join(Self::BLOB_DIR_NAME))?;
+            for entry in fs::read_dir(&blob_dir)? {
+                let entry = entry?;
+                fs::copy(
+                    entry.path(),
+                    path.join(Self::BLOB_DIR_NAME).join(entry.file_name()),
+                )?;
+            }
+        }
+
+        let mut log = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&log_path)?;
+        std::io::copy(
+            &mut File::open(increment.join(Self::DEFAULT_LOG_NAME))?,
+            &mut log,
+        )?;
+        log.sync_all()?;
+        Ok(())
+    }
+}
+
+/// Hashes everything written to it.
+struct HashWriter(Sha256);
+
+impl Write for HashWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.input(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use core::{Compactable, KvStore, Persistent, Result, StoreOptions};
+
+    use crate::LogKvs;
+
+    #[test]
+    fn increments() -> Result<()> {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+        let options = StoreOptions {
+            blob_threshold: Some(8),
+            ..StoreOptions::default()
+        };
+        let mut store =
+            LogKvs::open_with(temp_dir.path().join("kvs"), options.clone())?;
+        store.set("key1".to_owned(), "value1".to_owned())?;
+        store.fork_to(temp_dir.path().join("restored"))?;
+        let base = store.sequence()?;
+        let digest = store.log_digest(base)?;
+
+        store.set("key2".to_owned(), "a value kept as a blob".to_owned())?;
+        store.remove("key1".to_owned())?;
+        let since = store.write_increment(base, temp_dir.path().join("1"))?;
+        assert_eq!(since, store.sequence()?);
+        // appending leaves the earlier part of the log as it was
+        assert_eq!(store.log_digest(base)?, digest);
+        // an increment with nothing in it is still part of the chain
+        let since = store.write_increment(since, temp_dir.path().join("2"))?;
+
+        LogKvs::apply_increment(
+            temp_dir.path().join("restored"),
+            temp_dir.path().join("1"),
+        )?;
+        LogKvs::apply_increment(
+            temp_dir.path().join("restored"),
+            temp_dir.path().join("2"),
+        )?;
+        // increments can't be applied out of order
+        assert!(LogKvs::apply_increment(
+            temp_dir.path().join("restored"),
+            temp_dir.path().join("1"),
+        )
+        .is_err());
+
+        let restored =
+            LogKvs::open_with(temp_dir.path().join("restored"), options)?;
+        assert_eq!(restored.get("key1".to_owned())?, None);
+        assert_eq!(
+            restored.get("key2".to_owned())?,
+            Some("a value kept as a blob".to_owned())
+        );
+
+        // compaction shrinks the log past the last sequence backed up
+        store.compact()?;
+        assert!(store
+            .write_increment(since, temp_dir.path().join("3"))
+            .is_err());
+
+        Ok(())
+    }
+}
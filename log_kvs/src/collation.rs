@@ -0,0 +1,48 @@
+/*!
+ * Recording the order a store's keys are scanned in when it's created, so
+ * scans stay in the same order whatever options it's opened with later.
+ */
+
+use std::fs;
+use std::path::Path;
+
+use core::{Collation, Error, Result};
+
+use crate::LogKvs;
+
+impl LogKvs {
+    /// The name of the file the collation is recorded in.
+    pub(crate) const COLLATION_FILE_NAME: &'static str = "COLLATION";
+
+    /// The order keys are scanned in, as picked when the store was created.
+    pub fn collation(&self) -> Collation {
+        self.index.collation()
+    }
+
+    /// The collation recorded in the store's directory, if there is one.
+    /// Stores from before collations were recorded are bytewise.
+    pub(crate) fn read_collation(dir: &Path) -> Result<Option<Collation>> {
+        let path = dir.join(Self::COLLATION_FILE_NAME);
+        if !path.is_file() {
+            return Ok(None);
+        }
+        fs::read_to_string(&path)?
+            .trim()
+            .parse()
+            .map(Some)
+            .map_err(|_| {
+                Error::corrupt_database(format!(
+                    "{} doesn't hold a collation",
+                    path.display()
+                ))
+            })
+    }
+
+    pub(crate) fn write_collation(
+        dir: &Path,
+        collation: Collation,
+    ) -> Result<()> {
+        fs::write(dir.join(Self::COLLATION_FILE_NAME), collation.name())?;
+        Ok(())
+    }
+}
@@ -0,0 +1,258 @@
+/*!
+ * Recording how long the log was, and its checksums, whenever it's left in
+ * a known good state: when the store is closed and after it's compacted.
+ * Opening it again can then check what was committed without decoding it,
+ * and knows that a record it can't read after that is from a write that
+ * was cut short, so it can be cut off.
+ *
+ * The log is checksummed in fixed-size chunks, so committing only hashes
+ * the chunks written to since the last commit, and a normal open only
+ * checks those. `IntegrityLevel::Paranoid` opens check every chunk.
+ */
+
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Write};
+
+use serde::{Deserialize, Serialize};
+
+use core::{Error, Result};
+
+use crate::LogKvs;
+
+/// The log as it was when it was committed.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub(crate) struct Commit {
+    generation: u64,
+    pub log_len: u64,
+    /// How long the log was when it was committed before, so where the
+    /// writes this commit covers start.
+    tail_from: u64,
+    /// The SHA-256 of each chunk of the log, in order. The last one may be
+    /// short.
+    chunks: Vec<String>,
+}
+
+impl Commit {
+    /// How many bytes of the log each checksum covers.
+    const CHUNK_LEN: u64 = 1 << 20;
+
+    /// How many chunks a log of the given length is split into.
+    fn chunk_count(log_len: u64) -> usize {
+        (0..log_len).step_by(Self::CHUNK_LEN as usize).count()
+    }
+}
+
+impl LogKvs {
+    /// The name of the file the commit is recorded in.
+    pub(crate) const COMMIT_FILE_NAME: &'static str = "COMMIT";
+
+    /// How long the log was when it was last committed, if that's known.
+    pub(crate) fn committed_len(&self) -> Option<u64> {
+        self.commit.as_ref().map(|commit| commit.log_len)
+    }
+
+    /// Record the log's length and checksums, unless they're already
+    /// recorded. Only reads what's been written since the last commit, or
+    /// the whole log if it's been rewritten since.
+    pub(crate) fn write_commit(&mut self) -> Result<()> {
+        let log_len = self.log.len()?;
+        let (tail_from, mut chunks) = match &self.commit {
+            Some(commit) if commit.generation == self.generation => {
+                if commit.log_len == log_len {
+                    return Ok(());
+                }
+                (commit.log_len, commit.chunks.clone())
+            }
+            _ => (0, Vec::new()),
+        };
+        // the last chunk committed may have been short
+        let first = tail_from / Commit::CHUNK_LEN;
+        chunks.truncate(first as usize);
+        for start in (first * Commit::CHUNK_LEN..log_len)
+            .step_by(Commit::CHUNK_LEN as usize)
+        {
+            let end = (start + Commit::CHUNK_LEN).min(log_len);
+            chunks.push(self.range_digest(start, end)?);
+        }
+        let commit = Commit {
+            generation: self.generation,
+            log_len,
+            tail_from,
+            chunks,
+        };
+        let path = self.path.join(Self::COMMIT_FILE_NAME);
+        let tmp = path.with_extension("tmp");
+        let mut writer = BufWriter::new(File::create(&tmp)?);
+        bincode::serialize_into(&mut writer, &commit)
+            .map_err(Error::serialization)?;
+        writer.flush()?;
+        writer.get_ref().sync_all()?;
+        fs::rename(&tmp, &path)?;
+        self.commit = Some(commit);
+        Ok(())
+    }
+
+    /// Read the last commit, if there is one and it's for the current log.
+    /// Fails if the log is shorter than it was committed at.
+    pub(crate) fn read_commit(&self) -> Result<Option<Commit>> {
+        let path = self.path.join(Self::COMMIT_FILE_NAME);
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let commit: Commit =
+            match bincode::deserialize_from(BufReader::new(File::open(&path)?))
+            {
+                Ok(commit) => commit,
+                // only costs the checks it would have allowed
+                Err(_) => return Ok(None),
+            };
+        if commit.generation != self.generation
+            || commit.chunks.len() != Commit::chunk_count(commit.log_len)
+        {
+            return Ok(None);
+        }
+        let log_len = self.log.len()?;
+        if log_len < commit.log_len {
+            return Err(Error::corrupt_database(format!(
+                "{} was committed at {} bytes, but it's been cut short to {}",
+                self.path.display(),
+                commit.log_len,
+                log_len
+            )));
+        }
+        Ok(Some(commit))
+    }
+
+    /// Check the committed part of the log against its checksums: all of
+    /// it, or just the chunks the last commit wrote to.
+    pub(crate) fn verify_commit(
+        &self,
+        commit: &Commit,
+        all: bool,
+    ) -> Result<()> {
+        let first = if all {
+            0
+        } else {
+            (commit.tail_from / Commit::CHUNK_LEN) as usize
+        };
+        for (i, digest) in commit.chunks.iter().enumerate().skip(first) {
+            let start = i as u64 * Commit::CHUNK_LEN;
+            let end = (start + Commit::CHUNK_LEN).min(commit.log_len);
+            if self.range_digest(start, end)? != *digest {
+                return Err(Error::corrupt_database(format!(
+                    "bytes {} to {} of {} don't match the checksum they were \
+                     committed with",
+                    start,
+                    end,
+                    self.path.display()
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::{Seek, SeekFrom};
+
+    use core::tests::{DefaultTestContext, PersistentTestContext, TestContext};
+    use core::{ErrorKind, IntegrityLevel, KvStore, Persistent, StoreOptions};
+
+    fn corrupt(err: Error) {
+        match err.kind() {
+            ErrorKind::CorruptDatabase { .. } => {}
+            kind => panic!("unexpected error {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn cut_off_torn_write() -> Result<()> {
+        let context = <DefaultTestContext as TestContext<LogKvs>>::init();
+        let path = PersistentTestContext::<LogKvs>::get_path(&context).clone();
+        let mut store: LogKvs = context.open_store()?;
+        store.set("key1".to_owned(), "value1".to_owned())?;
+        drop(store);
+
+        // crashes partway through a write, after one that finished
+        let mut store: LogKvs = context.open_store()?;
+        store.set("key2".to_owned(), "value2".to_owned())?;
+        let len = store.log.len()?;
+        store.simulate_crash();
+        let mut log = fs::OpenOptions::new()
+            .append(true)
+            .open(path.join(LogKvs::DEFAULT_LOG_NAME))?;
+        log.write_all(&[0xff; 7])?;
+        drop(log);
+
+        let store: LogKvs = context.open_store()?;
+        assert_eq!(store.log.len()?, len);
+        assert_eq!(store.get("key2".to_owned())?, Some("value2".to_owned()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn check_committed() -> Result<()> {
+        let context = <DefaultTestContext as TestContext<LogKvs>>::init();
+        let log_path = PersistentTestContext::<LogKvs>::get_path(&context)
+            .join(LogKvs::DEFAULT_LOG_NAME);
+        let mut store: LogKvs = context.open_store()?;
+        store.set("key1".to_owned(), "value1".to_owned())?;
+        drop(store);
+
+        // still decodes, but isn't what was committed
+        let log = fs::read(&log_path)?;
+        let mut file = fs::OpenOptions::new().write(true).open(&log_path)?;
+        file.seek(SeekFrom::Start(log.len() as u64 - 1))?;
+        file.write_all(b"2")?;
+        drop(file);
+        corrupt(TestContext::<LogKvs>::open_store(&context).unwrap_err());
+
+        // cut short
+        fs::write(&log_path, &log[..log.len() - 1])?;
+        corrupt(TestContext::<LogKvs>::open_store(&context).unwrap_err());
+
+        fs::write(&log_path, &log)?;
+        let store: LogKvs = context.open_store()?;
+        assert_eq!(store.get("key1".to_owned())?, Some("value1".to_owned()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn check_earlier_commits_when_paranoid() -> Result<()> {
+        let context = <DefaultTestContext as TestContext<LogKvs>>::init();
+        let log_path = PersistentTestContext::<LogKvs>::get_path(&context)
+            .join(LogKvs::DEFAULT_LOG_NAME);
+        let mut store: LogKvs = context.open_store()?;
+        // fills the first chunk, so the next commit only writes after it
+        let value = "a".repeat(Commit::CHUNK_LEN as usize);
+        store.set("key1".to_owned(), value)?;
+        drop(store);
+        let mut store: LogKvs = context.open_store()?;
+        store.set("key2".to_owned(), "value2".to_owned())?;
+        drop(store);
+
+        // still decodes, but isn't what was first committed
+        let mut file = fs::OpenOptions::new().write(true).open(&log_path)?;
+        file.seek(SeekFrom::Start(Commit::CHUNK_LEN / 2))?;
+        file.write_all(b"b")?;
+        drop(file);
+        let store: LogKvs = context.open_store()?;
+        assert_eq!(store.get("key2".to_owned())?, Some("value2".to_owned()));
+        drop(store);
+        let options = StoreOptions {
+            integrity: IntegrityLevel::Paranoid,
+            ..StoreOptions::default()
+        };
+        corrupt(
+            TestContext::<LogKvs>::open_store_with(&context, options)
+                .unwrap_err(),
+        );
+
+        Ok(())
+    }
+}
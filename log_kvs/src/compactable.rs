@@ -1,6 +1,8 @@
-use core::{Compactable, Result};
+use std::collections::HashSet;
 
-use crate::{Command, LogKvs};
+use core::{is_trash_key, observe, Compactable, Result, TrashedValue};
+
+use crate::{Command, LogCommandPointer, LogKvs, Timeline};
 
 impl Compactable for LogKvs {
     /// Compact the key-value store. Return an error if unsuccessful.
@@ -18,18 +20,64 @@ impl Compactable for LogKvs {
     /// store.compact();
     /// ```
     fn compact(&mut self) -> Result<()> {
+        self.check_writable()?;
+        self.finish_backfill()?;
+        if let Some(observer) = &self.observer {
+            observer.on_compaction_start();
+        }
+        let result = match self.history_retention {
+            Some(retention) => self.truncate_history(retention),
+            None => self.rewrite_live(),
+        };
+        observe(&self.observer, "compact", result, |observer, _| {
+            observer.on_compaction_end()
+        })
+    }
+}
+
+impl LogKvs {
+    /// Rewrite the log with only the current value of each key.
+    fn rewrite_live(&mut self) -> Result<()> {
+        // nothing has been written yet, so there's nothing to rewrite
+        if !self.log.exists() {
+            return Ok(());
+        }
+        // readers' pointers stop working as soon as the log is replaced, so
+        // they're told before it starts and after it's done
+        self.bump_generation()?;
+        let result = self.rewrite_log();
+        self.bump_generation()?;
+        result?;
+        // the offsets it noted have moved
+        Timeline::remove_from(&self.path)?;
+        self.write_commit()
+    }
+
+    fn rewrite_log(&mut self) -> Result<()> {
+        let mut live_blobs = HashSet::new();
+        let byte_order = self.log.byte_order()?;
         self.log.rewrite(|iter, mut writer| {
             for record in iter {
                 let (command, pointer) = record?;
-                match command {
-                    Command::Set { key, value } => {
-                        match self.index.get(&key) {
+                match &command {
+                    Command::Set { key, .. } | Command::SetBlob { key, .. } => {
+                        match self.index.get(key) {
+                            Some(current_pointer)
+                                if pointer == *current_pointer
+                                    && self.purgeable(key, &pointer)? =>
+                            {
+                                // this is the current value, but it's been
+                                // in the trash long enough to go
+                            }
                             Some(current_pointer)
                                 if pointer == *current_pointer =>
                             {
                                 // this is a valid key and the current value
-                                Command::Set { key, value }
-                                    .append(&mut writer)?;
+                                if let Command::SetBlob { blob, .. } = &command
+                                {
+                                    live_blobs.insert(blob.clone());
+                                }
+                                command.append(&mut writer, byte_order)?;
                             }
                             Some(_) => {
                                 // this is a valid key, but not the current
@@ -40,19 +88,193 @@ impl Compactable for LogKvs {
                             }
                         }
                     }
-                    Command::Remove { .. } => {
-                        // once removed, the key is no longer needed
+                    Command::SetDelta { key, .. } => {
+                        if self.index.get(key) == Some(&pointer)
+                            && !self.purgeable(key, &pointer)?
+                        {
+                            // the records it was based on are about to go,
+                            // so write the whole value
+                            Command::Set {
+                                key: key.clone(),
+                                value: self.get_key(&pointer)?,
+                            }
+                            .append(&mut writer, byte_order)?;
+                        }
+                    }
+                    Command::Remove { .. }
+                    | Command::RemovePrefix { .. }
+                    | Command::RemoveRange { .. } => {
+                        // once removed, the key is no longer needed. The
+                        // whole log is rewritten at once, so none of the
+                        // key's older values survive to be brought back
+                        // without the tombstone
                     }
                 }
             }
             Ok(())
-        })
+        })?;
+
+        self.blobs.retain(&live_blobs)?;
+        // every record has moved, so the old pointers are no longer valid
+        self.rebuild_index()
+    }
+
+    /// Whether the record holds a value that's been in the trash long
+    /// enough to be purged. Values that weren't put there by a soft delete
+    /// are kept.
+    fn purgeable(
+        &self,
+        key: &str,
+        pointer: &LogCommandPointer,
+    ) -> Result<bool> {
+        match self.trash_retention {
+            Some(retention) if is_trash_key(key) => {
+                match TrashedValue::decode(&self.get_key(pointer)?) {
+                    Ok(trashed) => {
+                        Ok(trashed.expired(retention, self.clock.now()))
+                    }
+                    Err(_) => Ok(false),
+                }
+            }
+            _ => Ok(false),
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    // use super::*;
+    use super::*;
+
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use core::tests::PersistentTestContext;
+    use core::tests::{
+        DefaultTestContext, MockClock, RecordingObserver, TestContext,
+    };
+    use core::{
+        clear_fail_point, set_fail_point, trash_key, FailAction, KvStore,
+        StoreObserver, StoreOptions,
+    };
 
     // generate_compactable_tests!(LogKvs);
+
+    #[test]
+    fn purge_trash() -> Result<()> {
+        let context: DefaultTestContext = TestContext::<LogKvs>::init();
+        let clock = Arc::new(MockClock::default());
+        let options = StoreOptions {
+            trash_retention: Some(Duration::from_secs(60)),
+            blob_threshold: Some(16),
+            clock: Some(clock.clone()),
+            ..StoreOptions::default()
+        };
+
+        let mut store: LogKvs = context.open_store_with(options)?;
+        store.set("key1".to_owned(), "value1".to_owned())?;
+        store
+            .set("key2".to_owned(), "a value long enough for a blob".into())?;
+        // set directly, before the trash's prefix was kept back
+        let pointer = store.log.append(Command::Set {
+            key: trash_key("key3"),
+            value: "set directly".to_owned(),
+        })?;
+        store.index.insert(trash_key("key3"), pointer);
+        store.remove("key1".to_owned())?;
+        store.remove("key2".to_owned())?;
+        assert!(store.get(trash_key("key1"))?.is_some());
+
+        // kept until the retention has passed
+        store.compact()?;
+        assert!(store.get(trash_key("key1"))?.is_some());
+
+        clock.advance(Duration::from_secs(60));
+        store.compact()?;
+        assert_eq!(store.get(trash_key("key1"))?, None);
+        assert_eq!(store.get(trash_key("key2"))?, None);
+        // only values removed by a soft delete are purged
+        assert!(store.get(trash_key("key3"))?.is_some());
+        assert!(store.blobs.names()?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn removed_keys_stay_removed() -> Result<()> {
+        let context: DefaultTestContext = TestContext::<LogKvs>::init();
+        let mut store: LogKvs = context.open_store()?;
+        store.set("key1".to_owned(), "value1".to_owned())?;
+        store.set("key2".to_owned(), "value2".to_owned())?;
+        store.remove("key1".to_owned())?;
+        store.remove("key2".to_owned())?;
+        store.set("key2".to_owned(), "value3".to_owned())?;
+
+        store.compact()?;
+        assert_eq!(store.get("key1".to_owned())?, None);
+        // the old values are gone from disk too, not just the index
+        drop(store);
+        let store: LogKvs = context.open_store()?;
+        assert_eq!(store.get("key1".to_owned())?, None);
+        assert_eq!(store.get("key2".to_owned())?, Some("value3".to_owned()));
+        assert!(store.log.iter()?.all(|record| match record {
+            Ok((Command::Set { key, .. }, _)) => key == "key2",
+            _ => false,
+        }));
+
+        Ok(())
+    }
+
+    #[test]
+    fn interrupted_swap() -> Result<()> {
+        let context: DefaultTestContext = TestContext::<LogKvs>::init();
+        let log = PersistentTestContext::<LogKvs>::get_path(&context)
+            .join(LogKvs::DEFAULT_LOG_NAME);
+        let mut store: LogKvs = context.open_store()?;
+        store.set("key1".to_owned(), "value1".to_owned())?;
+        store.set("key1".to_owned(), "value2".to_owned())?;
+
+        // the old log is put back
+        set_fail_point("overwrite::rename", FailAction::Error);
+        assert!(store.compact().is_err());
+        clear_fail_point("overwrite::rename");
+        assert_eq!(store.get("key1".to_owned())?, Some("value2".to_owned()));
+        store.compact()?;
+        drop(store);
+
+        // or recovered when reopened, after a crash
+        std::fs::rename(&log, log.with_extension("backup"))?;
+        let store: LogKvs = context.open_store()?;
+        assert_eq!(store.get("key1".to_owned())?, Some("value2".to_owned()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn observed_compaction() -> Result<()> {
+        let context: DefaultTestContext = TestContext::<LogKvs>::init();
+        let observer = Arc::new(RecordingObserver::default());
+        let options = StoreOptions {
+            observer: Some(observer.clone() as Arc<dyn StoreObserver>),
+            ..StoreOptions::default()
+        };
+
+        let mut store: LogKvs = context.open_store_with(options)?;
+        store.set("key1".to_owned(), "value1".to_owned())?;
+        store.compact()?;
+        assert!(store
+            .set_from_reader("key2".to_owned(), &mut &b"bad \xff"[..])
+            .is_err());
+
+        assert_eq!(
+            *observer.events.lock().unwrap(),
+            vec![
+                "set key1 6",
+                "compaction start",
+                "compaction end",
+                "set failed"
+            ]
+        );
+
+        Ok(())
+    }
 }
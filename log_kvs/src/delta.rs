@@ -0,0 +1,215 @@
+/*!
+ * Writing overwrites as a change to the previous value, rather than in
+ * full.
+ */
+
+use core::{Error, Result};
+
+use crate::{Command, LogCommandPointer, LogKvs};
+
+impl LogKvs {
+    /// Values shorter than this are always written in full, since a delta
+    /// wouldn't save much.
+    pub(crate) const MIN_DELTA_LEN: usize = 64;
+
+    /// Describe the new value of a key as a change to its current value, if
+    /// deltas are enabled, the chain of deltas isn't too long already, and
+    /// the change is less than half the size of the value.
+    pub(crate) fn delta_for(
+        &self,
+        key: &str,
+        value: &str,
+    ) -> Result<Option<Command>> {
+        let max_depth = match self.delta_depth {
+            Some(max_depth) if value.len() >= Self::MIN_DELTA_LEN => max_depth,
+            _ => return Ok(None),
+        };
+        let pointer = match self.index.get(key) {
+            Some(pointer) => pointer,
+            None => return Ok(None),
+        };
+        let depth = match self.log.get_command(pointer)? {
+            Command::SetDelta { depth, .. } => depth + 1,
+            _ => 1,
+        };
+        if depth > max_depth {
+            return Ok(None);
+        }
+
+        let old = self.get_key(pointer)?;
+        let (prefix, suffix) = common_ends(&old, value);
+        let middle = &value[prefix..value.len() - suffix];
+        if middle.len() * 2 > value.len() {
+            return Ok(None);
+        }
+
+        Ok(Some(Command::SetDelta {
+            key: key.to_owned(),
+            base: pointer.offset(),
+            depth,
+            prefix: prefix as u64,
+            suffix: suffix as u64,
+            middle: middle.to_owned(),
+        }))
+    }
+
+    /// Rebuild a value from the record its delta was taken against.
+    pub(crate) fn apply_delta(
+        &self,
+        base: u64,
+        prefix: u64,
+        suffix: u64,
+        middle: &str,
+    ) -> Result<String> {
+        let pointer = LogCommandPointer::new(Self::DEFAULT_LOG_ID, base);
+        let old = self.get_key(&pointer)?;
+
+        let (prefix, suffix) = (prefix as usize, suffix as usize);
+        if prefix + suffix > old.len()
+            || !old.is_char_boundary(prefix)
+            || !old.is_char_boundary(old.len() - suffix)
+        {
+            return Err(Error::corrupt_database(format!(
+                "delta against {:?} keeps more than the value it's based on",
+                pointer
+            )));
+        }
+
+        let mut value = String::with_capacity(prefix + middle.len() + suffix);
+        value.push_str(&old[..prefix]);
+        value.push_str(middle);
+        value.push_str(&old[old.len() - suffix..]);
+        Ok(value)
+    }
+}
+
+/// The lengths of the longest common prefix and suffix of two strings, in
+/// bytes, that don't overlap or split a character.
+fn common_ends(old: &str, new: &str) -> (usize, usize) {
+    let (old_bytes, new_bytes) = (old.as_bytes(), new.as_bytes());
+
+    let mut prefix = old_bytes
+        .iter()
+        .zip(new_bytes)
+        .take_while(|(a, b)| a == b)
+        .count();
+    while !old.is_char_boundary(prefix) || !new.is_char_boundary(prefix) {
+        prefix -= 1;
+    }
+
+    let max_suffix = old.len().min(new.len()) - prefix;
+    let mut suffix = old_bytes
+        .iter()
+        .rev()
+        .zip(new_bytes.iter().rev())
+        .take(max_suffix)
+        .take_while(|(a, b)| a == b)
+        .count();
+    while !old.is_char_boundary(old.len() - suffix)
+        || !new.is_char_boundary(new.len() - suffix)
+    {
+        suffix -= 1;
+    }
+
+    (prefix, suffix)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use core::tests::{DefaultTestContext, TestContext};
+    use core::{Compactable, KvStore, Measurable, Scrubbable, StoreOptions};
+
+    fn is_delta(store: &LogKvs, key: &str) -> Result<bool> {
+        let command = store.log.get_command(store.index.get(key).unwrap())?;
+        match command {
+            Command::SetDelta { .. } => Ok(true),
+            _ => Ok(false),
+        }
+    }
+
+    #[test]
+    fn ends() {
+        assert_eq!(common_ends("abcdef", "abXXef"), (2, 2));
+        assert_eq!(common_ends("aaaa", "aaaaaa"), (4, 0));
+        assert_eq!(common_ends("", "abc"), (0, 0));
+        // 'é' and 'è' share their first byte, which can't be split off
+        assert_eq!(common_ends("xéy", "xèy"), (1, 1));
+    }
+
+    #[test]
+    fn overwrites() -> Result<()> {
+        let context = <DefaultTestContext as TestContext<LogKvs>>::init();
+        let options = StoreOptions {
+            delta_depth: Some(2),
+            ..StoreOptions::default()
+        };
+        let mut store: LogKvs = context.open_store_with(options.clone())?;
+
+        let base = "ł".repeat(100);
+        let values: Vec<String> =
+            (0..4).map(|i| format!("{}{}{}", base, i, base)).collect();
+        let mut deltas = 0;
+        for value in &values {
+            store.set("key1".to_owned(), value.clone())?;
+            if is_delta(&store, "key1")? {
+                deltas += 1;
+            }
+            assert_eq!(store.get("key1".to_owned())?, Some(value.clone()));
+        }
+        // the first write is full, then two deltas, then full again
+        assert_eq!(deltas, 2);
+
+        // a completely different value isn't worth a delta
+        let other = "x".repeat(400);
+        store.set("key1".to_owned(), values[0].clone())?;
+        store.set("key1".to_owned(), other.clone())?;
+        assert!(!is_delta(&store, "key1")?);
+
+        store.set("key1".to_owned(), values[1].clone())?;
+        store.set("key1".to_owned(), values[2].clone())?;
+        assert!(store.scrub()?.is_clean());
+        let before = store.stats()?.disk_bytes;
+
+        // compaction collapses the live delta into a full value
+        store.compact()?;
+        assert!(store.stats()?.disk_bytes < before);
+        assert_eq!(store.get("key1".to_owned())?, Some(values[2].clone()));
+        drop(store);
+
+        let store: LogKvs = context.open_store_with(options)?;
+        assert_eq!(store.get("key1".to_owned())?, Some(values[2].clone()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn blobs_over_deltas() -> Result<()> {
+        let context = <DefaultTestContext as TestContext<LogKvs>>::init();
+        let options = StoreOptions {
+            delta_depth: Some(2),
+            blob_threshold: Some(200),
+            ..StoreOptions::default()
+        };
+        let mut store: LogKvs = context.open_store_with(options)?;
+
+        // a long value goes in a blob, even when a delta would be smaller
+        let long = "x".repeat(300);
+        store.set("key1".to_owned(), long.clone())?;
+        store.set("key1".to_owned(), format!("{}y", long))?;
+        match store.log.get_command(store.index.get("key1").unwrap())? {
+            Command::SetBlob { .. } => {}
+            command => panic!("expected a blob, found {:?}", command),
+        }
+
+        // while a short one can still be a delta
+        let short = "x".repeat(100);
+        store.set("key2".to_owned(), short.clone())?;
+        store.set("key2".to_owned(), format!("{}y", short))?;
+        assert!(is_delta(&store, "key2")?);
+        assert_eq!(store.get("key2".to_owned())?, Some(format!("{}y", short)));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,267 @@
+/*!
+ * Reading the log as a stream of writes, for consumers that keep their own
+ * copy of the store's history.
+ */
+
+use core::Result;
+
+use crate::{Command, LogFileIterator, LogKvs, LogReader, Timeline};
+
+/// What a [`LogEvent`] did to its key.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum LogOp {
+    /// The key was given a value.
+    Set,
+    /// The key's value was removed.
+    Remove,
+    /// Every key starting with the event's key, as a prefix, was removed.
+    RemovePrefix,
+    /// Every key from the event's key up to but not including its value,
+    /// or up to the last key if it has no value, was removed.
+    RemoveRange,
+}
+
+/// A write, as recorded in the log.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LogEvent {
+    /// Where the write is in the log. Increases with each write, though not
+    /// by one, until the log is compacted and the writes are renumbered, see
+    /// [`LogKvs::events`].
+    pub sequence: u64,
+    /// What was done.
+    pub op: LogOp,
+    /// The key written, or the prefix of the keys removed.
+    pub key: String,
+    /// The value set. None for removals, except that for a `RemoveRange`
+    /// it's the key the range stops before, if there is one.
+    pub value: Option<String>,
+    /// When it was written, in milliseconds since the Unix epoch, or up to
+    /// a second before. Only known when the log is kept as a history, see
+    /// [`StoreOptions::history_retention`](core::StoreOptions::history_retention).
+    pub time: Option<u64>,
+}
+
+/// The writes in the log, oldest first. See [`LogKvs::events`].
+pub struct LogEvents<'a> {
+    store: &'a LogKvs,
+    records: Option<LogFileIterator<LogReader>>,
+    after: Option<u64>,
+    /// The timeline's marks, and the next one to pass.
+    marks: Vec<(u64, u64)>,
+    next_mark: usize,
+    time: Option<u64>,
+}
+
+impl LogKvs {
+    /// Every write in the log, oldest first, with the values set, even ones
+    /// since overwritten or removed.
+    ///
+    /// Writes come in the order they were made, and each one's `sequence`
+    /// stays the same until the log is compacted. Compacting keeps only
+    /// what's needed for the current value of each key, and renumbers it,
+    /// so a consumer that needs every write should either keep the log as a
+    /// history with
+    /// [`StoreOptions::history_retention`](core::StoreOptions::history_retention),
+    /// or record writes as they're made with a change feed. Either way, it
+    /// can tell the log's been compacted since it last read by the change
+    /// in [`generation`](LogKvs::generation).
+    ///
+    /// ```rust
+    /// # use tempfile::TempDir;
+    /// # use core::{KvStore, Persistent};
+    /// # use log_kvs::{LogKvs, LogOp};
+    /// #
+    /// # let temp_dir =
+    /// #     TempDir::new().expect("unable to create temporary working directory");
+    /// let mut store = LogKvs::open(temp_dir.path()).unwrap();
+    /// store.set("key1".to_owned(), "value1".to_owned()).unwrap();
+    /// store.remove("key1".to_owned()).unwrap();
+    ///
+    /// let events: Vec<_> = store.events().unwrap().map(Result::unwrap).collect();
+    /// assert_eq!(events.len(), 2);
+    /// assert_eq!(events[0].value, Some("value1".to_owned()));
+    /// assert_eq!(events[1].op, LogOp::Remove);
+    ///
+    /// // carry on from the last one seen
+    /// store.set("key2".to_owned(), "value2".to_owned()).unwrap();
+    /// let newer: Vec<_> = store
+    ///     .events_after(events[1].sequence)
+    ///     .unwrap()
+    ///     .map(Result::unwrap)
+    ///     .collect();
+    /// assert_eq!(newer.len(), 1);
+    /// assert_eq!(newer[0].key, "key2");
+    /// ```
+    pub fn events(&self) -> Result<LogEvents<'_>> {
+        self.events_from(0, None)
+    }
+
+    /// The writes after the one with the given sequence, which has to be
+    /// from the current [`generation`](LogKvs::generation).
+    pub fn events_after(&self, sequence: u64) -> Result<LogEvents<'_>> {
+        self.events_from(sequence, Some(sequence))
+    }
+
+    /// The writes from the given sequence on, such as one from
+    /// [`sequence`](LogKvs::sequence) before the writes were made, which has
+    /// to be from the current [`generation`](LogKvs::generation).
+    pub fn events_since(&self, sequence: u64) -> Result<LogEvents<'_>> {
+        self.events_from(sequence, None)
+    }
+
+    /// Counts up each time the log is compacted, so the sequences of
+    /// [`events`](LogKvs::events) from before then no longer apply.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    fn events_from(
+        &self,
+        start: u64,
+        after: Option<u64>,
+    ) -> Result<LogEvents<'_>> {
+        // sequences are only meaningful in the handle's generation
+        self.check_generation()?;
+        let records = if self.log.exists() {
+            Some(self.log.iter_from(start)?)
+        } else {
+            None
+        };
+        Ok(LogEvents {
+            store: self,
+            records,
+            after,
+            marks: Timeline::new(&self.path).marks()?,
+            next_mark: 0,
+            time: None,
+        })
+    }
+}
+
+impl<'a> LogEvents<'a> {
+    fn event(&mut self, command: Command, sequence: u64) -> Result<LogEvent> {
+        while let Some(&(time, offset)) = self.marks.get(self.next_mark) {
+            if offset > sequence {
+                break;
+            }
+            self.time = Some(time);
+            self.next_mark += 1;
+        }
+        let store = self.store;
+        let (op, key, value) = match command {
+            Command::Set { key, value } => (LogOp::Set, key, Some(value)),
+            Command::SetBlob { key, blob } => {
+                (LogOp::Set, key, Some(store.blobs.read(&blob)?))
+            }
+            Command::SetDelta {
+                key,
+                base,
+                prefix,
+                suffix,
+                middle,
+                ..
+            } => (
+                LogOp::Set,
+                key,
+                Some(store.apply_delta(base, prefix, suffix, &middle)?),
+            ),
+            Command::Remove { key } => (LogOp::Remove, key, None),
+            Command::RemovePrefix { prefix } => {
+                (LogOp::RemovePrefix, prefix, None)
+            }
+            Command::RemoveRange { start, end } => {
+                (LogOp::RemoveRange, start, end)
+            }
+        };
+        Ok(LogEvent {
+            sequence,
+            op,
+            key,
+            value,
+            time: self.time,
+        })
+    }
+}
+
+impl<'a> Iterator for LogEvents<'a> {
+    type Item = Result<LogEvent>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (command, pointer) = match self.records.as_mut()?.next()? {
+                Ok(record) => record,
+                Err(err) => return Some(Err(err)),
+            };
+            match self.after {
+                Some(after) if pointer.offset() <= after => continue,
+                _ => return Some(self.event(command, pointer.offset())),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use core::tests::{DefaultTestContext, MockClock, TestContext};
+    use core::{Compactable, KvStore, StoreOptions};
+
+    fn collect(events: LogEvents) -> Result<Vec<LogEvent>> {
+        events.collect()
+    }
+
+    #[test]
+    fn events() -> Result<()> {
+        let context = <DefaultTestContext as TestContext<LogKvs>>::init();
+        let clock = Arc::new(MockClock::default());
+        let options = StoreOptions {
+            history_retention: Some(Duration::from_secs(3600)),
+            delta_depth: Some(4),
+            blob_threshold: Some(256),
+            clock: Some(clock.clone()),
+            ..StoreOptions::default()
+        };
+        let long = |c: char| c.to_string().repeat(300);
+        let mut store: LogKvs = context.open_store_with(options)?;
+        store.set("key1".to_owned(), long('a'))?;
+        clock.advance(Duration::from_secs(5));
+        store.set("key2".to_owned(), format!("{:0>80}", 1))?;
+        store.set("key2".to_owned(), format!("{:0>80}", 2))?;
+        store.remove("key1".to_owned())?;
+
+        let events = collect(store.events()?)?;
+        let ops: Vec<_> = events.iter().map(|event| event.op).collect();
+        assert_eq!(
+            ops,
+            vec![LogOp::Set, LogOp::Set, LogOp::Set, LogOp::Remove]
+        );
+        // read back from a blob and a delta
+        assert_eq!(events[0].value, Some(long('a')));
+        assert_eq!(events[2].value, Some(format!("{:0>80}", 2)));
+        assert!(events.windows(2).all(|w| w[0].sequence < w[1].sequence));
+        let start = events[0].time.unwrap();
+        assert_eq!(events[3].time, Some(start + 5000));
+
+        assert_eq!(
+            collect(store.events_after(events[1].sequence)?)?,
+            events[2..]
+        );
+        assert!(collect(store.events_after(events[3].sequence)?)?.is_empty());
+
+        // compacting renumbers what's left
+        let generation = store.generation();
+        store.history_retention = None;
+        store.compact()?;
+        assert_ne!(store.generation(), generation);
+        let events = collect(store.events()?)?;
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].key, "key2");
+        assert_eq!(events[0].time, None);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,265 @@
+/*!
+ * Saving the index when a store is closed, and at checkpoints while it's
+ * open, so opening it again with `IntegrityLevel::Fast` doesn't have to
+ * read the whole log, even after a crash.
+ */
+
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Write};
+
+use serde::{Deserialize, Serialize};
+
+use core::{Error, IntegrityLevel, Result};
+
+use crate::{LogCommandPointer, LogKvs};
+
+/// The index as it was when the log was `log_len` bytes long.
+#[derive(Debug, Serialize, Deserialize)]
+struct Hint {
+    generation: u64,
+    log_len: u64,
+    entries: Vec<(String, u64)>,
+}
+
+impl LogKvs {
+    /// The name of the file the index is saved to.
+    pub(crate) const HINT_FILE_NAME: &'static str = "HINT";
+
+    /// Save the index, replacing any saved before. An index that's still
+    /// being backfilled isn't saved.
+    pub(crate) fn write_hint(&self) -> Result<()> {
+        if !self.is_backfilled() {
+            return Ok(());
+        }
+        let hint = Hint {
+            generation: self.generation,
+            log_len: self.log.len()?,
+            entries: self
+                .index
+                .iter()
+                .map(|(key, pointer)| (key.clone(), pointer.offset()))
+                .collect(),
+        };
+        let path = self.path.join(Self::HINT_FILE_NAME);
+        let tmp = path.with_extension("tmp");
+        let mut writer = BufWriter::new(File::create(&tmp)?);
+        bincode::serialize_into(&mut writer, &hint)
+            .map_err(Error::serialization)?;
+        writer.flush()?;
+        fs::rename(&tmp, &path)?;
+        Ok(())
+    }
+
+    /// Count a write to the log, and save the index if a checkpoint is due.
+    /// The log is synced first, so the saved index never points past what's
+    /// on disk, where a crash could cut a record off after all.
+    ///
+    /// The checkpoint is taken as part of the write that makes it due,
+    /// rather than in the background, since the index can't be read while
+    /// the store is being written to. The write has already happened by
+    /// then, so a failed checkpoint is passed to the observer as a
+    /// `checkpoint` error instead of failing it, and is tried again on the
+    /// next write.
+    pub(crate) fn count_write(&mut self) {
+        // other opens read the whole log, and never the hint
+        if self.integrity != IntegrityLevel::Fast {
+            return;
+        }
+        self.writes_since_checkpoint += 1;
+        let now = self.clock.now();
+        let writes_due = match self.checkpoint_every {
+            Some(every) => self.writes_since_checkpoint >= every,
+            None => false,
+        };
+        let time_due = match (
+            self.checkpoint_interval,
+            now.duration_since(self.last_checkpoint),
+        ) {
+            (Some(interval), Ok(elapsed)) => elapsed >= interval,
+            _ => false,
+        };
+        if !(writes_due || time_due) {
+            return;
+        }
+        match self.log.sync().and_then(|()| self.write_hint()) {
+            Ok(()) => {
+                self.writes_since_checkpoint = 0;
+                self.last_checkpoint = now;
+            }
+            Err(err) => {
+                if let Some(observer) = &self.observer {
+                    observer.on_error("checkpoint", &err);
+                }
+            }
+        }
+    }
+
+    /// Fill the index from the saved hint, returning how far into the log
+    /// it goes. Returns None, leaving the index alone, if there's no hint
+    /// or it doesn't match the log, because the log has been rewritten or
+    /// cut short since.
+    pub(crate) fn read_hint(&mut self) -> Result<Option<u64>> {
+        let path = self.path.join(Self::HINT_FILE_NAME);
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let hint: Hint =
+            match bincode::deserialize_from(BufReader::new(File::open(&path)?))
+            {
+                Ok(hint) => hint,
+                // an unreadable hint only costs the time to read the log
+                Err(_) => return Ok(None),
+            };
+        if hint.generation != self.generation
+            || hint.log_len > self.log.len()?
+        {
+            return Ok(None);
+        }
+
+        self.index.clear();
+        for (key, offset) in hint.entries {
+            self.index.insert(
+                key,
+                LogCommandPointer::new(LogKvs::DEFAULT_LOG_ID, offset),
+            );
+        }
+        Ok(Some(hint.log_len))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::path::Path;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use core::tests::{
+        DefaultTestContext, MockClock, PersistentTestContext,
+        RecordingObserver, TestContext,
+    };
+    use core::{Compactable, KvStore, Persistent, StoreObserver, StoreOptions};
+
+    #[test]
+    fn fast_open() -> Result<()> {
+        let context = <DefaultTestContext as TestContext<LogKvs>>::init();
+        let fast = StoreOptions {
+            integrity: IntegrityLevel::Fast,
+            ..StoreOptions::default()
+        };
+        let mut store: LogKvs = context.open_store_with(fast.clone())?;
+        store.set("key1".to_owned(), "value1".to_owned())?;
+        store.set("key2".to_owned(), "value2".to_owned())?;
+        drop(store);
+        let hint = PersistentTestContext::<LogKvs>::get_path(&context)
+            .join(LogKvs::HINT_FILE_NAME);
+        assert!(hint.is_file());
+
+        // writes after the hint are read from the log
+        let mut store: LogKvs = context.open_store()?;
+        store.remove("key1".to_owned())?;
+        store.set("key3".to_owned(), "value3".to_owned())?;
+        drop(store);
+        let store: LogKvs = context.open_store_with(fast.clone())?;
+        assert_eq!(store.get("key1".to_owned())?, None);
+        assert_eq!(store.get("key3".to_owned())?, Some("value3".to_owned()));
+        drop(store);
+
+        // a hint from before a compaction is ignored
+        let hint_before = fs::read(&hint)?;
+        let mut store: LogKvs = context.open_store()?;
+        store.compact()?;
+        drop(store);
+        fs::write(&hint, hint_before)?;
+        let store: LogKvs = context.open_store_with(fast)?;
+        assert_eq!(store.get("key2".to_owned())?, Some("value2".to_owned()));
+        assert_eq!(store.get("key3".to_owned())?, Some("value3".to_owned()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn checkpoints() -> Result<()> {
+        let context = <DefaultTestContext as TestContext<LogKvs>>::init();
+        let path = PersistentTestContext::<LogKvs>::get_path(&context).clone();
+        let clock = Arc::new(MockClock::default());
+        let options = StoreOptions {
+            integrity: IntegrityLevel::Fast,
+            checkpoint_every: Some(3),
+            checkpoint_interval: Some(Duration::from_secs(60)),
+            clock: Some(clock.clone()),
+            ..StoreOptions::default()
+        };
+        let mut store: LogKvs = context.open_store_with(options.clone())?;
+        store.set("key1".to_owned(), "value1".to_owned())?;
+        store.set("key2".to_owned(), "value2".to_owned())?;
+        assert!(!path.join(LogKvs::HINT_FILE_NAME).is_file());
+        store.remove("key1".to_owned())?;
+        let checkpointed = store.log.len()?;
+        store.set("key3".to_owned(), "value3".to_owned())?;
+
+        // as if the store had crashed here
+        let crashed = path.with_extension("crashed");
+        fs::create_dir(&crashed)?;
+        for entry in fs::read_dir(&path)? {
+            let entry = entry?;
+            if entry.file_type()?.is_file() {
+                fs::copy(entry.path(), crashed.join(entry.file_name()))?;
+            }
+        }
+        let hint = |dir: &Path| -> Result<Hint> {
+            let file = File::open(dir.join(LogKvs::HINT_FILE_NAME))?;
+            bincode::deserialize_from(file).map_err(Error::serialization)
+        };
+        assert_eq!(hint(&crashed)?.log_len, checkpointed);
+        let recovered = LogKvs::open_with(&crashed, options)?;
+        assert_eq!(recovered.get("key1".to_owned())?, None);
+        assert_eq!(
+            recovered.get("key3".to_owned())?,
+            Some("value3".to_owned())
+        );
+        drop(recovered);
+
+        // or once enough time has passed
+        clock.advance(Duration::from_secs(61));
+        store.set("key4".to_owned(), "value4".to_owned())?;
+        assert_eq!(hint(&path)?.log_len, store.log.len()?);
+        fs::remove_dir_all(crashed)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn failed_checkpoint() -> Result<()> {
+        let context = <DefaultTestContext as TestContext<LogKvs>>::init();
+        let path = PersistentTestContext::<LogKvs>::get_path(&context).clone();
+        let observer = Arc::new(RecordingObserver::default());
+        let options = StoreOptions {
+            integrity: IntegrityLevel::Fast,
+            checkpoint_every: Some(1),
+            observer: Some(observer.clone() as Arc<dyn StoreObserver>),
+            ..StoreOptions::default()
+        };
+        let mut store: LogKvs = context.open_store_with(options)?;
+
+        // the hint can't be written while a directory is in its way, but
+        // the write still goes through
+        let tmp = path.join(LogKvs::HINT_FILE_NAME).with_extension("tmp");
+        fs::create_dir(&tmp)?;
+        store.set("key1".to_owned(), "value1".to_owned())?;
+        assert_eq!(store.get("key1".to_owned())?, Some("value1".to_owned()));
+        assert!(!path.join(LogKvs::HINT_FILE_NAME).is_file());
+
+        // and the checkpoint is tried again on the next one
+        fs::remove_dir(&tmp)?;
+        store.remove("key1".to_owned())?;
+        assert!(path.join(LogKvs::HINT_FILE_NAME).is_file());
+        assert_eq!(
+            *observer.events.lock().unwrap(),
+            vec!["checkpoint failed", "set key1 6", "get key1", "remove key1"]
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,217 @@
+/*!
+ * Keeping the log as a history of every write, and cutting off the records
+ * older than a retention window instead of compacting it.
+ */
+
+use std::collections::HashSet;
+use std::time::Duration;
+
+use core::Result;
+
+use crate::{Command, LogHeader, LogKvs, Timeline};
+
+impl LogKvs {
+    /// Drop the records appended more than `retention` ago, going by the
+    /// timeline, so they're dropped a second at a time. Records written
+    /// before there was a timeline are only dropped along with the ones
+    /// after them.
+    pub(crate) fn truncate_history(
+        &mut self,
+        retention: Duration,
+    ) -> Result<()> {
+        if !self.log.exists() {
+            return Ok(());
+        }
+        let marks = match self.log.timeline() {
+            Some(timeline) => timeline.marks()?,
+            None => return Ok(()),
+        };
+        if marks.is_empty() {
+            // no idea how old any of it is
+            return Ok(());
+        }
+        let cutoff = Timeline::millis(self.clock.now())
+            .saturating_sub(retention.as_millis() as u64);
+        let cut = match marks
+            .iter()
+            .find(|&&(time, _)| time + Timeline::INTERVAL_MS > cutoff)
+        {
+            Some(&(_, offset)) => offset,
+            None => self.log.len()?,
+        };
+        if cut <= LogHeader::LEN {
+            return Ok(());
+        }
+
+        self.bump_generation()?;
+        let result = self.rewrite_history(cut, &marks);
+        self.bump_generation()?;
+        result?;
+        self.write_commit()
+    }
+
+    /// Rewrite the log from `cut` on, moving the timeline's marks with the
+    /// records they point to.
+    fn rewrite_history(
+        &mut self,
+        cut: u64,
+        marks: &[(u64, u64)],
+    ) -> Result<()> {
+        let byte_order = self.log.byte_order()?;
+        let mut live_blobs = HashSet::new();
+        // where each record that's kept was, and where it's gone, in order
+        let mut moved: Vec<(u64, u64)> = Vec::new();
+        let mut end = LogHeader::LEN;
+        let collation = self.collation();
+        self.log.rewrite(|iter, mut writer| {
+            let mut live_keys = HashSet::new();
+            for record in iter {
+                let (command, pointer) = record?;
+                if pointer.offset() < cut {
+                    continue;
+                }
+                let command = match command {
+                    Command::SetDelta {
+                        key,
+                        base,
+                        depth,
+                        prefix,
+                        suffix,
+                        middle,
+                    } => match moved.binary_search_by_key(&base, |m| m.0) {
+                        Ok(i) => Command::SetDelta {
+                            key,
+                            base: moved[i].1,
+                            depth,
+                            prefix,
+                            suffix,
+                            middle,
+                        },
+                        // the value it's based on is being dropped, so
+                        // write the whole value
+                        Err(_) => Command::Set {
+                            value: self.get_key(&pointer)?,
+                            key,
+                        },
+                    },
+                    command => command,
+                };
+                match &command {
+                    Command::Set { key, .. }
+                    | Command::SetDelta { key, .. } => {
+                        live_keys.insert(key.clone());
+                    }
+                    Command::SetBlob { key, blob } => {
+                        live_keys.insert(key.clone());
+                        live_blobs.insert(blob.clone());
+                    }
+                    Command::Remove { key } => {
+                        if !live_keys.remove(key) {
+                            // what it removed is being dropped too
+                            continue;
+                        }
+                    }
+                    Command::RemovePrefix { .. }
+                    | Command::RemoveRange { .. } => {
+                        let removed: Vec<String> = live_keys
+                            .iter()
+                            .filter(|key| command.removes(key, collation))
+                            .cloned()
+                            .collect();
+                        if removed.is_empty() {
+                            continue;
+                        }
+                        for key in removed {
+                            live_keys.remove(&key);
+                        }
+                    }
+                }
+                let mut bytes = Vec::new();
+                command.append(&mut bytes, byte_order)?;
+                std::io::Write::write_all(&mut writer, &bytes)?;
+                moved.push((pointer.offset(), end));
+                end += bytes.len() as u64;
+            }
+            Ok(())
+        })?;
+
+        if let Some(timeline) = self.log.timeline() {
+            let marks: Vec<(u64, u64)> = marks
+                .iter()
+                .filter(|&&(_, offset)| offset >= cut)
+                .map(|&(time, offset)| {
+                    let i = match moved.binary_search_by_key(&offset, |m| m.0) {
+                        Ok(i) | Err(i) => i,
+                    };
+                    (time, moved.get(i).map_or(end, |m| m.1))
+                })
+                .collect();
+            timeline.replace(&marks)?;
+        }
+        self.blobs.retain(&live_blobs)?;
+        // every record has moved, so the old pointers are no longer valid
+        self.rebuild_index()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+
+    use core::tests::{DefaultTestContext, MockClock, TestContext};
+    use core::{Compactable, KvStore, StoreOptions};
+
+    #[test]
+    fn truncate_history() -> Result<()> {
+        let context = <DefaultTestContext as TestContext<LogKvs>>::init();
+        let clock = Arc::new(MockClock::default());
+        let options = StoreOptions {
+            history_retention: Some(Duration::from_secs(60)),
+            delta_depth: Some(4),
+            clock: Some(clock.clone()),
+            ..StoreOptions::default()
+        };
+        let long = |n: u32| format!("{:0>80}", n);
+        let mut store: LogKvs = context.open_store_with(options.clone())?;
+        store.set("key1".to_owned(), "value1".to_owned())?;
+        store.set("key2".to_owned(), long(1))?;
+        clock.advance(Duration::from_secs(50));
+        store.remove("key1".to_owned())?;
+        store.set("key2".to_owned(), long(2))?;
+        store.set("key3".to_owned(), "value3".to_owned())?;
+        store.set("key3".to_owned(), "value3b".to_owned())?;
+
+        // nothing's old enough to go, so every version is kept
+        store.compact()?;
+        let len = store.log.len()?;
+        assert_eq!(store.log.iter()?.count(), 6);
+
+        // the first second goes, taking the write key2's delta was based on
+        // with it, and leaving key1's removal with nothing to remove
+        clock.advance(Duration::from_secs(30));
+        store.compact()?;
+        assert!(store.log.len()? < len);
+        let mut kept = Vec::new();
+        for record in store.log.iter()? {
+            kept.push(record?.0.to_string());
+        }
+        assert_eq!(kept, vec!["Set", "Set", "Set"]);
+        assert_eq!(store.get("key1".to_owned())?, None);
+        assert_eq!(store.get("key2".to_owned())?, Some(long(2)));
+        assert_eq!(store.get("key3".to_owned())?, Some("value3b".to_owned()));
+
+        drop(store);
+        let mut store: LogKvs = context.open_store_with(options)?;
+        assert_eq!(store.get("key2".to_owned())?, Some(long(2)));
+
+        // and the rest once they're old enough too
+        clock.advance(Duration::from_secs(60));
+        store.compact()?;
+        assert_eq!(store.log.len()?, LogHeader::LEN);
+        assert_eq!(store.get("key3".to_owned())?, None);
+
+        Ok(())
+    }
+}
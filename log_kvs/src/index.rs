@@ -0,0 +1,292 @@
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Bound;
+
+use core::{Capability, Collation, Error, IndexKind, Result, ScanOptions};
+
+use crate::{Command, LogCommandPointer};
+
+/// Where the current value of each key is in the log, held in whichever
+/// structure was picked when the store was opened.
+#[derive(Clone, Debug)]
+pub(crate) enum Index {
+    Hash(HashMap<String, LogCommandPointer>, Collation),
+    Ordered(BTreeMap<String, LogCommandPointer>),
+    /// An ordered index in any collation but bytewise, which `Ordered`
+    /// covers without copying keys to look them up.
+    Collated(BTreeMap<CollatedKey, LogCommandPointer>, Collation),
+}
+
+impl Index {
+    pub fn new(kind: IndexKind, collation: Collation) -> Index {
+        match (kind, collation) {
+            (IndexKind::Hash, _) => Index::Hash(HashMap::new(), collation),
+            (IndexKind::Ordered, Collation::Bytewise) => {
+                Index::Ordered(BTreeMap::new())
+            }
+            (IndexKind::Ordered, _) => {
+                Index::Collated(BTreeMap::new(), collation)
+            }
+        }
+    }
+
+    pub fn kind(&self) -> IndexKind {
+        match self {
+            Index::Hash(..) => IndexKind::Hash,
+            Index::Ordered(_) | Index::Collated(..) => IndexKind::Ordered,
+        }
+    }
+
+    pub fn collation(&self) -> Collation {
+        match self {
+            Index::Hash(_, collation) | Index::Collated(_, collation) => {
+                *collation
+            }
+            Index::Ordered(_) => Collation::Bytewise,
+        }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&LogCommandPointer> {
+        match self {
+            Index::Hash(map, _) => map.get(key),
+            Index::Ordered(map) => map.get(key),
+            Index::Collated(map, collation) => {
+                map.get(&CollatedKey::new(*collation, key.to_owned()))
+            }
+        }
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.get(key).is_some()
+    }
+
+    pub fn insert(
+        &mut self,
+        key: String,
+        pointer: LogCommandPointer,
+    ) -> Option<LogCommandPointer> {
+        match self {
+            Index::Hash(map, _) => map.insert(key, pointer),
+            Index::Ordered(map) => map.insert(key, pointer),
+            Index::Collated(map, collation) => {
+                map.insert(CollatedKey::new(*collation, key), pointer)
+            }
+        }
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<LogCommandPointer> {
+        match self {
+            Index::Hash(map, _) => map.remove(key),
+            Index::Ordered(map) => map.remove(key),
+            Index::Collated(map, collation) => {
+                map.remove(&CollatedKey::new(*collation, key.to_owned()))
+            }
+        }
+    }
+
+    /// The keys starting with the prefix. Only an index in bytewise order
+    /// can find them without checking every key.
+    pub fn keys_with_prefix(&self, prefix: &str) -> Vec<String> {
+        match self {
+            Index::Ordered(map) => map
+                .range::<str, _>((Bound::Included(prefix), Bound::Unbounded))
+                .map(|(key, _)| key)
+                .take_while(|key| key.starts_with(prefix))
+                .cloned()
+                .collect(),
+            _ => self
+                .iter()
+                .map(|(key, _)| key)
+                .filter(|key| key.starts_with(prefix))
+                .cloned()
+                .collect(),
+        }
+    }
+
+    /// The keys from `start` up to but not including `end`, in the index's
+    /// collation, in no particular order.
+    pub fn keys_in_range(&self, start: &str, end: Option<&str>) -> Vec<String> {
+        match self {
+            Index::Hash(map, collation) => map
+                .keys()
+                .filter(|key| collation.in_range(key, start, end))
+                .cloned()
+                .collect(),
+            _ => self.range(start, end).unwrap_or_default(),
+        }
+    }
+
+    /// The keys with a value in the index that a ranged tombstone removes,
+    /// or None if the record isn't one.
+    pub fn removed_by(&self, command: &Command) -> Option<Vec<String>> {
+        match command {
+            Command::RemovePrefix { prefix } => {
+                Some(self.keys_with_prefix(prefix))
+            }
+            Command::RemoveRange { start, end } => Some(
+                self.keys_in_range(start, end.as_ref().map(|end| &end[..])),
+            ),
+            _ => None,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        match self {
+            Index::Hash(map, _) => map.clear(),
+            Index::Ordered(map) => map.clear(),
+            Index::Collated(map, _) => map.clear(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            Index::Hash(map, _) => map.len(),
+            Index::Ordered(map) => map.len(),
+            Index::Collated(map, _) => map.len(),
+        }
+    }
+
+    pub fn iter<'a>(
+        &'a self,
+    ) -> Box<dyn Iterator<Item = (&'a String, &'a LogCommandPointer)> + 'a>
+    {
+        match self {
+            Index::Hash(map, _) => Box::new(map.iter()),
+            Index::Ordered(map) => Box::new(map.iter()),
+            Index::Collated(map, _) => {
+                Box::new(map.iter().map(|(key, pointer)| (&key.key, pointer)))
+            }
+        }
+    }
+
+    /// How many keys are from `start` up to but not including `end`. Every
+    /// key is checked when the index isn't ordered.
+    pub fn count_range(&self, start: &str, end: Option<&str>) -> usize {
+        match self {
+            Index::Hash(map, collation) => map
+                .keys()
+                .filter(|key| collation.in_range(key, start, end))
+                .count(),
+            Index::Ordered(map) => match bounds(start, end) {
+                Some(bounds) => map.range::<str, _>(bounds).count(),
+                None => 0,
+            },
+            Index::Collated(map, collation) => {
+                match collated_bounds(*collation, start, end) {
+                    Some(bounds) => map.range(bounds).count(),
+                    None => 0,
+                }
+            }
+        }
+    }
+
+    /// The keys from `start` up to but not including `end`, in order. Only
+    /// an ordered index can do this without sorting every key.
+    pub fn range(&self, start: &str, end: Option<&str>) -> Result<Vec<String>> {
+        self.range_with(start, end, ScanOptions::default())
+    }
+
+    /// The keys in the range in the order and page of the options, walking
+    /// only the keys that are returned and the ones skipped to get to them.
+    pub fn range_with(
+        &self,
+        start: &str,
+        end: Option<&str>,
+        options: ScanOptions,
+    ) -> Result<Vec<String>> {
+        Ok(
+            options
+                .page(self.range_iter(start, end, options.reverse)?.cloned()),
+        )
+    }
+
+    /// Walk the keys in the range, in order or in reverse. Only an ordered
+    /// index can.
+    pub fn range_iter<'a>(
+        &'a self,
+        start: &str,
+        end: Option<&str>,
+        reverse: bool,
+    ) -> Result<Box<dyn Iterator<Item = &'a String> + 'a>> {
+        let keys: Box<dyn DoubleEndedIterator<Item = &String>> = match self {
+            Index::Hash(..) => {
+                return Err(Error::unsupported(Capability::OrderedScan))
+            }
+            Index::Ordered(map) => match bounds(start, end) {
+                Some(bounds) => {
+                    Box::new(map.range::<str, _>(bounds).map(|(key, _)| key))
+                }
+                None => return Ok(Box::new(std::iter::empty())),
+            },
+            Index::Collated(map, collation) => {
+                match collated_bounds(*collation, start, end) {
+                    Some(bounds) => {
+                        Box::new(map.range(bounds).map(|(key, _)| &key.key))
+                    }
+                    None => return Ok(Box::new(std::iter::empty())),
+                }
+            }
+        };
+        Ok(if reverse { Box::new(keys.rev()) } else { keys })
+    }
+}
+
+/// The bounds of the range for a `BTreeMap`, or None if it's empty, since
+/// the map panics if the end comes before the start.
+fn bounds<'a>(
+    start: &'a str,
+    end: Option<&'a str>,
+) -> Option<(Bound<&'a str>, Bound<&'a str>)> {
+    let end = match end {
+        Some(end) if end <= start => return None,
+        Some(end) => Bound::Excluded(end),
+        None => Bound::Unbounded,
+    };
+    Some((Bound::Included(start), end))
+}
+
+/// Like `bounds`, for a collated index.
+fn collated_bounds(
+    collation: Collation,
+    start: &str,
+    end: Option<&str>,
+) -> Option<(Bound<CollatedKey>, Bound<CollatedKey>)> {
+    let end = match end {
+        Some(end) if collation.compare(end, start) != Ordering::Greater => {
+            return None
+        }
+        Some(end) => {
+            Bound::Excluded(CollatedKey::new(collation, end.to_owned()))
+        }
+        None => Bound::Unbounded,
+    };
+    Some((
+        Bound::Included(CollatedKey::new(collation, start.to_owned())),
+        end,
+    ))
+}
+
+/// A key in a collated index, ordered by its collation.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct CollatedKey {
+    collation: Collation,
+    key: String,
+}
+
+impl CollatedKey {
+    fn new(collation: Collation, key: String) -> CollatedKey {
+        CollatedKey { collation, key }
+    }
+}
+
+impl Ord for CollatedKey {
+    fn cmp(&self, other: &CollatedKey) -> Ordering {
+        self.collation.compare(&self.key, &other.key)
+    }
+}
+
+impl PartialOrd for CollatedKey {
+    fn partial_cmp(&self, other: &CollatedKey) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
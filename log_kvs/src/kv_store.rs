@@ -1,5 +1,12 @@
+use std::borrow::Cow;
+use std::io::Read;
+
 use crate::{Command, LogKvs};
-use core::{KvStore, Result};
+use core::{
+    check_not_trash_key, fail_point, is_trash_key, observe, trash_key, KvStore,
+    Result, TrashedValue,
+};
+use io::{Trackable, Tracker};
 
 impl KvStore for LogKvs {
     /// Set a value. If the key already existed, the old value is overwritten.
@@ -15,12 +22,27 @@ impl KvStore for LogKvs {
     /// store.set("key1".to_owned(), "value1".to_owned());
     /// ```
     fn set(&mut self, key: String, value: String) -> Result<()> {
-        let pointer = self.log.append(Command::Set {
-            key: key.clone(),
-            value: value.clone(),
-        })?;
-        self.index.insert(key, pointer);
-        Ok(())
+        let (name, len) = (key.clone(), value.len() as u64);
+        let result =
+            check_not_trash_key(&key).and_then(|_| self.write_set(key, value));
+        observe(&self.observer, "set", result, |observer, _| {
+            observer.on_set(&name, len)
+        })
+    }
+
+    /// Set a value read from the reader, without holding it in memory.
+    fn set_from_reader(
+        &mut self,
+        key: String,
+        value: &mut dyn Read,
+    ) -> Result<()> {
+        let name = key.clone();
+        let mut value = Tracker::new(value);
+        let result = check_not_trash_key(&key)
+            .and_then(|_| self.write_set_from_reader(key, &mut value));
+        observe(&self.observer, "set", result, |observer, _| {
+            observer.on_set(&name, value.current_pos())
+        })
     }
 
     /// Retrieve the value of a key. If the key does not exist, return None.
@@ -38,12 +60,47 @@ impl KvStore for LogKvs {
     /// store.get("key1".to_owned());
     /// ```
     fn get(&self, key: String) -> Result<Option<String>> {
-        match self.index.get(&key) {
-            Some(pointer) => {
-                self.get_key(pointer).and_then(|value| Ok(Some(value)))
+        Ok(self.get_ref(&key)?.map(Cow::into_owned))
+    }
+
+    /// Retrieve the value of a key without needing an owned key. The value
+    /// is read from disk, so it's always returned owned.
+    fn get_ref(&self, key: &str) -> Result<Option<Cow<'_, str>>> {
+        let result = match self.lookup(key) {
+            Ok(Some(pointer)) => {
+                self.get_key(&pointer).map(|value| Some(Cow::Owned(value)))
             }
-            None => Ok(None),
+            Ok(None) => Ok(None),
+            Err(err) => Err(err),
         }
+        .and_then(|value| {
+            self.check_generation()?;
+            Ok(value)
+        });
+        observe(&self.observer, "get", result, |observer, _| {
+            observer.on_get(key)
+        })
+    }
+
+    /// Whether the key has a value, answered from the index without reading
+    /// the value from disk.
+    ///
+    /// ```rust
+    /// # use tempfile::TempDir;
+    /// # use core::{Persistent, KvStore};
+    /// # use log_kvs::LogKvs;
+    /// #
+    /// # let temp_dir =
+    /// #    TempDir::new().expect("unable to create temporary working directory");
+    /// # let mut store = LogKvs::open(temp_dir.path()).unwrap();
+    /// store.set("key1".to_owned(), "value1".to_owned());
+    /// assert!(store.contains_key("key1").unwrap());
+    /// ```
+    fn contains_key(&self, key: &str) -> Result<bool> {
+        let result = self.lookup(key).map(|pointer| pointer.is_some());
+        observe(&self.observer, "get", result, |observer, _| {
+            observer.on_get(key)
+        })
     }
 
     /// Remove a key-value. Return an error if the key does not exist or is not
@@ -61,10 +118,106 @@ impl KvStore for LogKvs {
     /// store.remove("key1".to_owned());
     /// ```
     fn remove(&mut self, key: String) -> Result<Option<String>> {
-        match self.index.remove(&key) {
+        self.remove_ref(&key)
+    }
+
+    /// Remove a key-value without needing an owned key. The key is only
+    /// copied if it has a value, to write its removal to the log.
+    fn remove_ref(&mut self, key: &str) -> Result<Option<String>> {
+        let result = self.write_remove(key);
+        observe(&self.observer, "remove", result, |observer, old| {
+            if old.is_some() {
+                observer.on_remove(key)
+            }
+        })
+    }
+}
+
+impl LogKvs {
+    fn write_set(&mut self, key: String, value: String) -> Result<()> {
+        self.check_writable()?;
+        // values big enough to go in a blob never go in the log, even as a
+        // delta
+        let command = match self.blob_threshold {
+            Some(threshold) if value.len() as u64 > threshold => {
+                Command::SetBlob {
+                    key: key.clone(),
+                    blob: self.blobs.write(&value)?,
+                }
+            }
+            _ => match self.delta_for(&key, &value)? {
+                Some(delta) => delta,
+                None => Command::Set {
+                    key: key.clone(),
+                    value: value.clone(),
+                },
+            },
+        };
+        let pointer = self.log.append(command)?;
+        fail_point("log::after_append")?;
+        self.touch(&key);
+        self.index.insert(key, pointer);
+        self.count_write();
+        Ok(())
+    }
+
+    fn write_set_from_reader(
+        &mut self,
+        key: String,
+        value: &mut dyn Read,
+    ) -> Result<()> {
+        self.check_writable()?;
+        let pointer = match self.blob_threshold {
+            Some(threshold) => {
+                // the length isn't known until it's all been read, so write
+                // it as a blob and move it into the log if it's small
+                let (blob, len, created) =
+                    self.blobs.write_from_reader(value)?;
+                if len <= threshold {
+                    let value = self.blobs.read(&blob)?;
+                    // a deduplicated blob may be shared with other keys
+                    if created {
+                        self.blobs.remove(&blob)?;
+                    }
+                    return self.write_set(key, value);
+                }
+                self.log.append(Command::SetBlob {
+                    key: key.clone(),
+                    blob,
+                })?
+            }
+            None => self.log.append_set_from_reader(&key, value)?,
+        };
+        fail_point("log::after_append")?;
+        self.touch(&key);
+        self.index.insert(key, pointer);
+        self.count_write();
+        Ok(())
+    }
+
+    fn write_remove(&mut self, key: &str) -> Result<Option<String>> {
+        self.check_writable()?;
+        if self.trash_retention.is_some() && !is_trash_key(key) {
+            // keep the value in the trash before it's removed, so it's
+            // never lost
+            if let Some(pointer) = self.lookup(key)? {
+                let trashed = TrashedValue::removed(
+                    self.get_key(&pointer)?,
+                    self.clock.now(),
+                );
+                self.write_set(trash_key(key), trashed.encode())?;
+            }
+        }
+
+        match self.lookup(key)? {
             Some(old_pointer) => {
+                self.index.remove(key);
+                self.touch(key);
                 // TODO: If append fails, index is now inconsistent
-                self.log.append(Command::Remove { key })?;
+                self.log.append(Command::Remove {
+                    key: key.to_owned(),
+                })?;
+                self.count_write();
                 self.get_key(&old_pointer).and_then(|value| Ok(Some(value)))
             }
             None => Ok(None),
@@ -76,11 +229,29 @@ impl KvStore for LogKvs {
 mod tests {
     use super::*;
 
-    use core::tests::{DefaultTestContext, Testable};
+    use core::tests::{DefaultTestContext, TestContext, Testable};
+    use core::{clear_fail_point, set_fail_point, FailAction};
 
     impl Testable for LogKvs {
         type Context = DefaultTestContext;
     }
 
     generate_core_tests!(LogKvs);
+
+    #[test]
+    fn failed_after_append() -> Result<()> {
+        let context = <DefaultTestContext as TestContext<LogKvs>>::init();
+        let mut store: LogKvs = context.open_store()?;
+        set_fail_point("log::after_append", FailAction::Error);
+        assert!(store.set("key1".to_owned(), "value1".to_owned()).is_err());
+        clear_fail_point("log::after_append");
+        assert_eq!(store.get("key1".to_owned())?, None);
+        drop(store);
+
+        // the write made it to the log, so it's there once replayed
+        let store: LogKvs = context.open_store()?;
+        assert_eq!(store.get("key1".to_owned())?, Some("value1".to_owned()));
+
+        Ok(())
+    }
 }
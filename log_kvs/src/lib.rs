@@ -1,5 +1,4 @@
 #![deny(missing_docs)]
-#![feature(seek_convenience)]
 
 /*!
  * An implemetation of KvStore defined in core using append-only log files.
@@ -12,9 +11,33 @@ extern crate core;
 mod log;
 pub(crate) use log::*;
 
+mod backfill;
+pub(crate) use backfill::Backfill;
+pub use backfill::Backfiller;
+mod backup;
+mod collation;
+mod commit;
+pub(crate) use commit::Commit;
 mod compactable;
+mod delta;
+mod events;
+pub use events::{LogEvent, LogEvents, LogOp};
+mod hint;
+mod history;
+mod index;
+pub(crate) use index::*;
 mod kv_store;
 mod persistent;
+mod repair;
+mod tombstone;
+pub use repair::RepairReport;
+mod scan;
+mod scrub;
+mod shared;
+pub(crate) use shared::*;
+mod stats;
+mod watch;
+pub use watch::Refresher;
 
 mod log_core;
 pub use log_core::LogKvs;
@@ -0,0 +1,393 @@
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+
+use core::{Result, StoreOptions, SyncPolicy};
+use io::copy_utf8;
+
+/// A directory of values too large to keep in the log, one file each.
+///
+/// Blobs are only referenced once the log record pointing at them is
+/// written, so a blob left behind by a failed write is never read and is
+/// cleaned up by the next compaction.
+///
+/// When deduplicating, each blob is named by the SHA-256 of its contents
+/// and shared by every record holding that value. Compaction keeps a blob
+/// as long as any live record names it, so no reference counts are stored.
+#[derive(Debug)]
+pub(crate) struct BlobDir {
+    path: PathBuf,
+    sync: SyncPolicy,
+    dedup: bool,
+    next_id: u64,
+}
+
+impl BlobDir {
+    pub fn open<P: AsRef<Path>>(
+        path: P,
+        options: &StoreOptions,
+    ) -> Result<BlobDir> {
+        let mut blobs = BlobDir {
+            path: PathBuf::from(path.as_ref()),
+            sync: options.sync,
+            dedup: options.dedup,
+            next_id: 0,
+        };
+        blobs.next_id = blobs
+            .names()?
+            .iter()
+            .filter_map(|name| name.parse::<u64>().ok())
+            .max()
+            .map_or(0, |id| id + 1);
+        Ok(blobs)
+    }
+
+    /// The names of every blob in the directory.
+    pub fn names(&self) -> Result<Vec<String>> {
+        if !self.path.is_dir() {
+            return Ok(Vec::new());
+        }
+        let mut names = Vec::new();
+        for entry in fs::read_dir(&self.path)? {
+            if let Some(name) = entry?.file_name().to_str() {
+                names.push(name.to_owned());
+            }
+        }
+        Ok(names)
+    }
+
+    /// Create an empty blob, returning its name.
+    fn create(&mut self) -> Result<(String, File)> {
+        fs::create_dir_all(&self.path)?;
+        let name = self.next_id.to_string();
+        self.next_id += 1;
+        let file = File::create(self.path.join(&name))?;
+        Ok((name, file))
+    }
+
+    fn finish(&self, mut writer: BufWriter<File>) -> Result<()> {
+        writer.flush()?;
+        if self.sync == SyncPolicy::Always {
+            writer.get_ref().sync_all()?;
+        }
+        Ok(())
+    }
+
+    /// Give a finished blob its content-addressed name, or drop it if a
+    /// blob with the same contents already exists. Returns the name, and
+    /// whether the blob is new rather than one other records may share.
+    fn dedup(&self, name: String, hash: Sha256) -> Result<(String, bool)> {
+        let hashed = format!("{:x}", hash.result());
+        if self.exists(&hashed) {
+            self.remove(&name)?;
+            Ok((hashed, false))
+        } else {
+            fs::rename(self.path.join(&name), self.path.join(&hashed))?;
+            Ok((hashed, true))
+        }
+    }
+
+    /// Write a value to a blob, returning its name.
+    pub fn write(&mut self, value: &str) -> Result<String> {
+        if self.dedup {
+            let hashed = format!("{:x}", Sha256::digest(value.as_bytes()));
+            if self.exists(&hashed) {
+                return Ok(hashed);
+            }
+        }
+
+        let (name, file) = self.create()?;
+        let mut writer = HashingWriter::new(BufWriter::new(file));
+        writer.write_all(value.as_bytes())?;
+        let (writer, hash) = writer.into_inner();
+        self.finish(writer)?;
+        if self.dedup {
+            self.dedup(name, hash).map(|(name, _)| name)
+        } else {
+            Ok(name)
+        }
+    }
+
+    /// Write a value read from the reader to a new blob, returning its name,
+    /// its length, and whether the blob is new. It isn't when deduplicating
+    /// finds a blob with the same contents, which other records may share,
+    /// so only a new one can be removed again. The blob is removed again if
+    /// the value isn't UTF-8.
+    pub fn write_from_reader(
+        &mut self,
+        value: &mut dyn Read,
+    ) -> Result<(String, u64, bool)> {
+        let (name, file) = self.create()?;
+        let mut writer = HashingWriter::new(BufWriter::new(file));
+        let written = copy_utf8(value, &mut writer)
+            .map_err(From::from)
+            .and_then(|len| {
+                let (writer, hash) = writer.into_inner();
+                self.finish(writer).map(|_| (len, hash))
+            });
+        match written {
+            Ok((len, hash)) if self.dedup => {
+                let (name, created) = self.dedup(name, hash)?;
+                Ok((name, len, created))
+            }
+            Ok((len, _)) => Ok((name, len, true)),
+            Err(err) => {
+                self.remove(&name)?;
+                Err(err)
+            }
+        }
+    }
+
+    pub fn read(&self, name: &str) -> Result<String> {
+        Ok(fs::read_to_string(self.path.join(name))?)
+    }
+
+    /// Whether a value matches the name of the blob holding it. Only
+    /// deduplicated blobs are named by their contents, so any value matches
+    /// the others.
+    pub fn matches_name(&self, name: &str, value: &str) -> bool {
+        let content_addressed =
+            name.len() == 64 && name.chars().all(|c| c.is_ascii_hexdigit());
+        !content_addressed
+            || format!("{:x}", Sha256::digest(value.as_bytes())) == name
+    }
+
+    pub fn exists(&self, name: &str) -> bool {
+        self.path.join(name).is_file()
+    }
+
+    pub fn size(&self, name: &str) -> Result<u64> {
+        Ok(fs::metadata(self.path.join(name))?.len())
+    }
+
+    pub fn remove(&self, name: &str) -> Result<()> {
+        Ok(fs::remove_file(self.path.join(name))?)
+    }
+
+    /// Hard link every blob into a new directory, copying them instead if
+    /// they can't be linked, e.g. because it's on another filesystem.
+    pub fn link_to<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        self.link_some_to(self.names()?, path)
+    }
+
+    /// Like `link_to`, for only the named blobs.
+    pub fn link_some_to<I, P>(&self, names: I, path: P) -> Result<()>
+    where
+        I: IntoIterator<Item = String>,
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let mut names = names.into_iter().peekable();
+        if names.peek().is_none() {
+            return Ok(());
+        }
+
+        fs::create_dir(path)?;
+        for name in names {
+            let (from, to) = (self.path.join(&name), path.join(&name));
+            if fs::hard_link(&from, &to).is_err() {
+                fs::copy(&from, &to)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Remove every blob that isn't in `live`.
+    pub fn retain(&self, live: &HashSet<String>) -> Result<()> {
+        for name in self.names()? {
+            if !live.contains(&name) {
+                self.remove(&name)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Hashes everything written through it.
+struct HashingWriter<W: Write> {
+    inner: W,
+    hash: Sha256,
+}
+
+impl<W: Write> HashingWriter<W> {
+    fn new(inner: W) -> HashingWriter<W> {
+        HashingWriter {
+            inner,
+            hash: Sha256::new(),
+        }
+    }
+
+    fn into_inner(self) -> (W, Sha256) {
+        (self.inner, self.hash)
+    }
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hash.input(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::tests::{DefaultTestContext, PersistentTestContext, TestContext};
+    use core::{
+        Compactable, KvStore, Measurable, Persistent, Result, Scrubbable,
+        StoreOptions,
+    };
+
+    use crate::LogKvs;
+
+    fn open(context: &DefaultTestContext) -> Result<LogKvs> {
+        open_with_dedup(context, false)
+    }
+
+    fn open_with_dedup(
+        context: &DefaultTestContext,
+        dedup: bool,
+    ) -> Result<LogKvs> {
+        let options = StoreOptions {
+            blob_threshold: Some(16),
+            dedup,
+            ..StoreOptions::default()
+        };
+        TestContext::<LogKvs>::open_store_with(context, options)
+    }
+
+    fn blob_count(context: &DefaultTestContext) -> usize {
+        let path = PersistentTestContext::<LogKvs>::get_path(context)
+            .join(LogKvs::BLOB_DIR_NAME);
+        std::fs::read_dir(path).map_or(0, |dir| dir.count())
+    }
+
+    #[test]
+    fn large_values() -> Result<()> {
+        let context = <DefaultTestContext as TestContext<LogKvs>>::init();
+        let large = "a value longer than the threshold".to_owned();
+        let mut store = open(&context)?;
+
+        store.set("small".to_owned(), "short".to_owned())?;
+        store.set("large".to_owned(), large.clone())?;
+        store.set_from_reader("streamed".to_owned(), &mut large.as_bytes())?;
+        assert_eq!(blob_count(&context), 2);
+        assert_eq!(store.get("small".to_owned())?, Some("short".to_owned()));
+        assert_eq!(store.get("large".to_owned())?, Some(large.clone()));
+        assert_eq!(store.get("streamed".to_owned())?, Some(large.clone()));
+        drop(store);
+
+        let mut store = open(&context)?;
+        assert_eq!(store.get("large".to_owned())?, Some(large.clone()));
+        assert!(store.scrub()?.is_clean());
+
+        // replacing and removing leaves stale blobs until compaction
+        store.set("large".to_owned(), large.to_uppercase())?;
+        assert_eq!(store.remove("streamed".to_owned())?, Some(large.clone()));
+        assert_eq!(blob_count(&context), 3);
+        assert!(store.stats()?.stale_bytes as usize > 2 * large.len());
+
+        store.compact()?;
+        assert_eq!(blob_count(&context), 1);
+        assert_eq!(store.get("large".to_owned())?, Some(large.to_uppercase()));
+        assert_eq!(store.get("small".to_owned())?, Some("short".to_owned()));
+        assert!(store.scrub()?.is_clean());
+
+        Ok(())
+    }
+
+    #[test]
+    fn dedup() -> Result<()> {
+        let context = <DefaultTestContext as TestContext<LogKvs>>::init();
+        let large = "a value longer than the threshold".to_owned();
+        let mut store = open_with_dedup(&context, true)?;
+
+        store.set("key1".to_owned(), large.clone())?;
+        store.set("key2".to_owned(), large.clone())?;
+        store.set_from_reader("key3".to_owned(), &mut large.as_bytes())?;
+        store.set("key4".to_owned(), large.to_uppercase())?;
+        assert_eq!(blob_count(&context), 2);
+
+        // the shared blob is kept while any key still holds it
+        store.remove("key1".to_owned())?;
+        store.remove("key2".to_owned())?;
+        store.compact()?;
+        assert_eq!(blob_count(&context), 2);
+        assert_eq!(store.get("key3".to_owned())?, Some(large.clone()));
+        let stats = store.stats()?;
+        assert_eq!(stats.stale_bytes, 0);
+
+        store.remove("key3".to_owned())?;
+        store.compact()?;
+        assert_eq!(blob_count(&context), 1);
+        assert_eq!(store.get("key4".to_owned())?, Some(large.to_uppercase()));
+        assert!(store.scrub()?.is_clean());
+
+        Ok(())
+    }
+
+    #[test]
+    fn dedup_small_streamed() -> Result<()> {
+        let context = <DefaultTestContext as TestContext<LogKvs>>::init();
+        let value = "twenty bytes value!!".to_owned();
+        let mut store = open_with_dedup(&context, true)?;
+        store.set("key1".to_owned(), value.clone())?;
+        assert_eq!(blob_count(&context), 1);
+        drop(store);
+
+        // small enough for the log now, so the streamed value is moved out
+        // of the blob it shares with key1, which has to be kept
+        let options = StoreOptions {
+            blob_threshold: Some(32),
+            dedup: true,
+            ..StoreOptions::default()
+        };
+        let mut store: LogKvs =
+            TestContext::<LogKvs>::open_store_with(&context, options)?;
+        store.set_from_reader("key2".to_owned(), &mut value.as_bytes())?;
+        assert_eq!(blob_count(&context), 1);
+        assert_eq!(store.get("key1".to_owned())?, Some(value.clone()));
+        assert_eq!(store.get("key2".to_owned())?, Some(value));
+        assert!(store.scrub()?.is_clean());
+
+        Ok(())
+    }
+
+    #[test]
+    fn fork() -> Result<()> {
+        let context = <DefaultTestContext as TestContext<LogKvs>>::init();
+        let fork_context = <DefaultTestContext as TestContext<LogKvs>>::init();
+        let fork_path =
+            PersistentTestContext::<LogKvs>::get_path(&fork_context)
+                .join("fork");
+        let large = "a value longer than the threshold".to_owned();
+        let mut store = open(&context)?;
+
+        store.set("key1".to_owned(), large.clone())?;
+        store.set("key2".to_owned(), large.to_uppercase())?;
+        store.fork_to(&fork_path)?;
+
+        // the original dropping its blob mustn't take the fork's with it
+        store.remove("key1".to_owned())?;
+        store.compact()?;
+        assert_eq!(blob_count(&context), 1);
+
+        let options = StoreOptions {
+            blob_threshold: Some(16),
+            ..StoreOptions::default()
+        };
+        let fork = LogKvs::open_with(&fork_path, options)?;
+        assert_eq!(fork.get("key1".to_owned())?, Some(large.clone()));
+        assert_eq!(fork.get("key2".to_owned())?, Some(large.to_uppercase()));
+        assert!(fork.scrub()?.is_clean());
+
+        Ok(())
+    }
+}
@@ -3,8 +3,11 @@ use std::io::{Read, Write};
 use serde::{Deserialize, Serialize};
 use strum_macros::Display;
 
-use core::{Error, Result};
+use core::{ByteOrder, Collation, Error, Result};
 
+/// A record in the log. Records are written with their variant's index, so
+/// new variants only ever go at the end, after the `Set` and `Remove` that
+/// logs have always held.
 #[derive(Debug, Display, Serialize, Deserialize)]
 pub(crate) enum Command {
     /// Add a value to the key-value store.
@@ -19,19 +22,151 @@ pub(crate) enum Command {
         /// The item to delete.
         key: String,
     },
+    /// Add a value to the key-value store that is kept in a blob file.
+    SetBlob {
+        /// The name to store the value under.
+        key: String,
+        /// The name of the blob holding the value.
+        blob: String,
+    },
+    /// Add a value to the key-value store, as a change to an earlier value.
+    /// The new value is the first `prefix` bytes of the earlier value, then
+    /// `middle`, then its last `suffix` bytes.
+    SetDelta {
+        /// The name to store the value under.
+        key: String,
+        /// The offset of the record holding the earlier value.
+        base: u64,
+        /// How many deltas have been chained, including this one.
+        depth: u32,
+        /// How many bytes to keep from the start of the earlier value.
+        prefix: u64,
+        /// How many bytes to keep from the end of the earlier value.
+        suffix: u64,
+        /// What goes in between.
+        middle: String,
+    },
+    /// Remove every key starting with a prefix, as of this record. Keys set
+    /// after it are kept.
+    RemovePrefix {
+        /// What the keys to delete start with.
+        prefix: String,
+    },
+    /// Remove every key from `start` up to but not including `end`, or up
+    /// to the last key if there's no end, in the store's collation, as of
+    /// this record. Keys set after it are kept.
+    RemoveRange {
+        /// The first key to delete.
+        start: String,
+        /// The key the range stops before.
+        end: Option<String>,
+    },
+}
+
+/// How records are encoded, see the `header` module.
+// bincode's later versions deprecate `Config` for `Options`, which the
+// version this is built against doesn't have
+#[allow(deprecated)]
+fn codec(byte_order: ByteOrder) -> bincode::Config {
+    let mut config = bincode::config();
+    if byte_order == ByteOrder::BigEndian {
+        config.big_endian();
+    }
+    config
 }
 
+#[allow(deprecated)]
 impl Command {
-    pub fn append<W: Write>(&self, writer: &mut W) -> Result<()> {
-        bincode::serialize_into(writer, self).map_err(Error::bincode)
+    /// The key the record sets or removes, or for a ranged tombstone the
+    /// prefix or the first key of the range it removes.
+    pub fn key(&self) -> &str {
+        match self {
+            Command::Set { key, .. }
+            | Command::SetBlob { key, .. }
+            | Command::SetDelta { key, .. }
+            | Command::Remove { key }
+            | Command::RemovePrefix { prefix: key }
+            | Command::RemoveRange { start: key, .. } => key,
+        }
     }
 
-    pub fn read<R: Read>(reader: &mut R) -> Result<Command> {
-        bincode::deserialize_from(reader).map_err(Error::bincode)
+    /// Whether the record leaves the key without a value, whatever it had
+    /// before, with ranges in the collation.
+    pub fn removes(&self, key: &str, collation: Collation) -> bool {
+        match self {
+            Command::Remove { key: removed } => removed == key,
+            Command::RemovePrefix { prefix } => key.starts_with(prefix),
+            Command::RemoveRange { start, end } => {
+                collation.in_range(key, start, end.as_ref().map(|end| &end[..]))
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether the record is one the first version of the store could
+    /// write, from before logs had headers.
+    pub fn is_baseline(&self) -> bool {
+        matches!(self, Command::Set { .. } | Command::Remove { .. })
+    }
+
+    pub fn append<W: Write>(
+        &self,
+        writer: &mut W,
+        byte_order: ByteOrder,
+    ) -> Result<()> {
+        codec(byte_order)
+            .serialize_into(writer, self)
+            .map_err(Error::serialization)
+    }
+
+    /// Write everything in a serialized `Command::Set` up to its value, so
+    /// a value of `value_len` bytes can be written straight after it.
+    pub fn append_set_header<W: Write>(
+        writer: &mut W,
+        key: &str,
+        value_len: u64,
+        byte_order: ByteOrder,
+    ) -> Result<()> {
+        // bincode writes the variant index, then each field with its length
+        // first
+        const SET_VARIANT: u32 = 0;
+        let codec = codec(byte_order);
+        codec
+            .serialize_into(&mut *writer, &SET_VARIANT)
+            .map_err(Error::serialization)?;
+        codec
+            .serialize_into(&mut *writer, key)
+            .map_err(Error::serialization)?;
+        codec
+            .serialize_into(writer, &value_len)
+            .map_err(Error::serialization)
+    }
+
+    pub fn read<R: Read>(
+        reader: &mut R,
+        byte_order: ByteOrder,
+    ) -> Result<Command> {
+        codec(byte_order)
+            .deserialize_from(reader)
+            .map_err(Error::serialization)
+    }
+
+    /// Decode the record at the start of the bytes, returning it and its
+    /// length, or None if it can't be. Lengths are checked against the
+    /// bytes before anything is allocated, so garbage can't ask for more
+    /// memory than there is data.
+    pub fn decode(
+        bytes: &[u8],
+        byte_order: ByteOrder,
+    ) -> Option<(Command, u64)> {
+        let codec = codec(byte_order);
+        let command: Command = codec.deserialize(bytes).ok()?;
+        let len = codec.serialized_size(&command).ok()?;
+        Some((command, len))
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub(crate) struct LogCommandPointer {
     pub(in crate::log) file_id: usize,
     pub(in crate::log) offset: u64,
@@ -41,4 +176,71 @@ impl LogCommandPointer {
     pub fn new(file_id: usize, offset: u64) -> LogCommandPointer {
         LogCommandPointer { file_id, offset }
     }
+
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_header_matches_serialize() -> Result<()> {
+        let command = Command::Set {
+            key: "key1".to_owned(),
+            value: "value1".to_owned(),
+        };
+        for &byte_order in &[ByteOrder::LittleEndian, ByteOrder::BigEndian] {
+            let mut expected = Vec::new();
+            command.append(&mut expected, byte_order)?;
+
+            let mut written = Vec::new();
+            Command::append_set_header(&mut written, "key1", 6, byte_order)?;
+            written.extend_from_slice(b"value1");
+            assert_eq!(written, expected);
+        }
+
+        Ok(())
+    }
+
+    /// The records as the first version of the store wrote them.
+    #[derive(Serialize)]
+    enum BaselineCommand {
+        Set { key: String, value: String },
+        Remove { key: String },
+    }
+
+    #[test]
+    fn reads_baseline_records() -> Result<()> {
+        let mut log = Vec::new();
+        for command in &[
+            BaselineCommand::Set {
+                key: "key1".to_owned(),
+                value: "value1".to_owned(),
+            },
+            BaselineCommand::Remove {
+                key: "key1".to_owned(),
+            },
+        ] {
+            bincode::serialize_into(&mut log, command)
+                .map_err(Error::serialization)?;
+        }
+
+        let mut reader = &log[..];
+        match Command::read(&mut reader, ByteOrder::LittleEndian)? {
+            Command::Set { key, value } => {
+                assert_eq!((&key[..], &value[..]), ("key1", "value1"))
+            }
+            command => panic!("unexpected record {:?}", command),
+        }
+        match Command::read(&mut reader, ByteOrder::LittleEndian)? {
+            Command::Remove { key } => assert_eq!(key, "key1"),
+            command => panic!("unexpected record {:?}", command),
+        }
+        assert!(reader.is_empty());
+
+        Ok(())
+    }
 }
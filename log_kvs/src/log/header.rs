@@ -0,0 +1,147 @@
+/*!
+ * The header every log file starts with, and how records are laid out
+ * after it.
+ *
+ * The header is 24 bytes, with its integers always little-endian:
+ *
+ * | Bytes    | Field                                                   |
+ * | -------- | ------------------------------------------------------- |
+ * | `0..8`   | magic, `\x89KVSLOG\n`                                   |
+ * | `8..12`  | format version, a `u32`                                 |
+ * | `12..16` | codec, a `u32`: 0 for little-endian, 1 for big-endian   |
+ * | `16..24` | when the file was created, a `u64` of ms since the epoch |
+ *
+ * Records follow back to back, with no other framing. Each is a `Command`
+ * written by bincode: its variant index as a `u32`, then its fields in
+ * order, with integers at their full width and strings as a `u64` length
+ * followed by their UTF-8 bytes, all in the codec's byte order. Records
+ * are addressed by their offset from the start of the file, so the first
+ * is at [`LogHeader::LEN`].
+ */
+
+use std::convert::TryInto;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use core::{ByteOrder, Error, Result};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) struct LogHeader {
+    pub version: u32,
+    pub byte_order: ByteOrder,
+    pub created: SystemTime,
+}
+
+impl LogHeader {
+    pub const LEN: u64 = 24;
+    const MAGIC: &'static [u8; 8] = b"\x89KVSLOG\n";
+    /// The format written by this version.
+    pub const VERSION: u32 = 1;
+
+    pub fn new(byte_order: ByteOrder, created: SystemTime) -> LogHeader {
+        LogHeader {
+            version: Self::VERSION,
+            byte_order,
+            created,
+        }
+    }
+
+    pub fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
+        let codec: u32 = match self.byte_order {
+            ByteOrder::LittleEndian => 0,
+            ByteOrder::BigEndian => 1,
+        };
+        let created = self
+            .created
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        writer.write_all(Self::MAGIC)?;
+        writer.write_all(&self.version.to_le_bytes())?;
+        writer.write_all(&codec.to_le_bytes())?;
+        writer.write_all(&created.to_le_bytes())?;
+        Ok(())
+    }
+
+    /// Read the header of the log at the given path, failing if the file
+    /// doesn't start with one this version can read.
+    pub fn read<R: Read>(reader: &mut R, path: &Path) -> Result<LogHeader> {
+        let mut bytes = [0; Self::LEN as usize];
+        reader.read_exact(&mut bytes).map_err(|err| {
+            if err.kind() == std::io::ErrorKind::UnexpectedEof {
+                Self::unknown(path)
+            } else {
+                Error::io(err)
+            }
+        })?;
+        Self::parse(&bytes).ok_or_else(|| Self::unknown(path))?
+    }
+
+    /// Parse a header, returning None if the bytes aren't one, or an error
+    /// if they're one this version can't read.
+    pub fn parse(bytes: &[u8]) -> Option<Result<LogHeader>> {
+        if bytes.len() < Self::LEN as usize || &bytes[..8] != Self::MAGIC {
+            return None;
+        }
+        let u32_at = |at: usize| {
+            u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+        };
+        let version = u32_at(8);
+        if version != Self::VERSION {
+            return Some(Err(Error::corrupt_database(format!(
+                "the log is in format version {}, but only version {} can be \
+                 read",
+                version,
+                Self::VERSION
+            ))));
+        }
+        let byte_order = match u32_at(12) {
+            0 => ByteOrder::LittleEndian,
+            1 => ByteOrder::BigEndian,
+            codec => {
+                return Some(Err(Error::corrupt_database(format!(
+                    "the log's records are in an unknown codec, {}",
+                    codec
+                ))))
+            }
+        };
+        let created = u64::from_le_bytes(bytes[16..24].try_into().unwrap());
+        Some(Ok(LogHeader {
+            version,
+            byte_order,
+            created: UNIX_EPOCH + Duration::from_millis(created),
+        }))
+    }
+
+    fn unknown(path: &Path) -> Error {
+        Error::corrupt_database(format!(
+            "{} isn't a log, or is from a version before logs had headers, \
+             which is upgraded when the store is first opened for writing",
+            path.display()
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() -> Result<()> {
+        let created = UNIX_EPOCH + Duration::from_millis(1_570_000_000_000);
+        let header = LogHeader::new(ByteOrder::BigEndian, created);
+        let mut bytes = Vec::new();
+        header.write(&mut bytes)?;
+        assert_eq!(bytes.len() as u64, LogHeader::LEN);
+        assert_eq!(LogHeader::read(&mut &bytes[..], Path::new("1"))?, header);
+
+        // a log without a header
+        assert!(LogHeader::read(&mut &b"\0\0\0\0"[..], Path::new("1")).is_err());
+        // from a later version
+        bytes[8] = 2;
+        assert!(LogHeader::parse(&bytes).unwrap().is_err());
+
+        Ok(())
+    }
+}
@@ -1,36 +1,231 @@
 use std::fs::{File, OpenOptions};
-use std::io::{BufReader, BufWriter, Read, Seek};
+use std::io::{BufReader, BufWriter, Read, Seek, Write};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+use std::sync::Arc;
 
-use core::{Error, Result};
-use io::save_overwrite_with_reader;
+use core::{
+    clock_or_system, ByteOrder, Clock, Result, StoreOptions, SyncPolicy,
+};
+use io::{
+    copy_utf8, preallocate, save_overwrite_with_reader, stream_len,
+    DirectReader, Trackable, Tracker,
+};
 
-use super::{Command, LogCommandPointer};
+use super::{Command, LogCommandPointer, LogHeader, Timeline};
 use crate::LogKvs;
 
 #[derive(Debug)]
 pub(crate) struct LogFile {
     path: PathBuf,
+    sync: SyncPolicy,
+    /// Read with direct I/O, bypassing the page cache.
+    direct_io: bool,
+    /// How much disk to reserve ahead of appends at a time.
+    preallocate: Option<u64>,
+    /// How far into the log disk has been reserved.
+    reserved_to: AtomicU64,
+    /// The byte order a new log's records are written in.
+    new_byte_order: ByteOrder,
+    /// The byte order read from the log's header, once it's been read, as
+    /// one of the `BYTE_ORDER_*` constants.
+    byte_order: AtomicU8,
+    clock: Arc<dyn Clock>,
+    /// When records were appended, if the log is kept as a history.
+    timeline: Option<Timeline>,
 }
 
 impl LogFile {
-    pub fn new<P: AsRef<Path>>(path: P) -> LogFile {
+    pub fn new<P: AsRef<Path>>(path: P, options: &StoreOptions) -> LogFile {
         LogFile {
             path: PathBuf::from(path.as_ref()),
+            sync: options.sync,
+            direct_io: options.direct_io,
+            preallocate: options.preallocate,
+            reserved_to: AtomicU64::new(0),
+            new_byte_order: options.byte_order,
+            byte_order: AtomicU8::new(Self::BYTE_ORDER_UNKNOWN),
+            clock: clock_or_system(options.clock.clone()),
+            timeline: options.history_retention.map(|_| {
+                Timeline::new(path.as_ref().parent().unwrap_or(Path::new("")))
+            }),
         }
     }
 
-    pub fn iter(&self) -> Result<LogFileIterator<File>> {
-        let file = File::open(&self.path)?;
-        let reader = BufReader::new(file);
-        LogFileIterator::new(reader)
+    /// When records were appended, if the log is kept as a history.
+    pub fn timeline(&self) -> Option<&Timeline> {
+        self.timeline.as_ref()
     }
 
-    pub fn get_command(&self, pointer: &LogCommandPointer) -> Result<Command> {
+    const BYTE_ORDER_UNKNOWN: u8 = 0;
+    const BYTE_ORDER_LITTLE: u8 = 1;
+    const BYTE_ORDER_BIG: u8 = 2;
+
+    /// The byte order the log's records are in, from its header. Fails if
+    /// the log doesn't start with a header this version can read. A log
+    /// that hasn't been written to yet will be in the one it was opened
+    /// with.
+    pub fn byte_order(&self) -> Result<ByteOrder> {
+        match self.byte_order.load(Ordering::SeqCst) {
+            Self::BYTE_ORDER_LITTLE => return Ok(ByteOrder::LittleEndian),
+            Self::BYTE_ORDER_BIG => return Ok(ByteOrder::BigEndian),
+            _ => {}
+        }
+        if self.len()? == 0 {
+            return Ok(self.new_byte_order);
+        }
+        let header = LogHeader::read(&mut File::open(&self.path)?, &self.path)?;
+        self.set_byte_order(header.byte_order);
+        Ok(header.byte_order)
+    }
+
+    fn set_byte_order(&self, byte_order: ByteOrder) {
+        let byte_order = match byte_order {
+            ByteOrder::LittleEndian => Self::BYTE_ORDER_LITTLE,
+            ByteOrder::BigEndian => Self::BYTE_ORDER_BIG,
+        };
+        self.byte_order.store(byte_order, Ordering::SeqCst);
+    }
+
+    /// Seek a writer opened on the log to its end, first writing the header
+    /// if nothing has been, and return where the next record goes.
+    fn start_append(&self, writer: &mut BufWriter<File>) -> Result<u64> {
+        let byte_order = self.byte_order()?;
+        let mut pos = writer.seek(std::io::SeekFrom::End(0))?;
+        if pos == 0 {
+            LogHeader::new(byte_order, self.clock.now()).write(writer)?;
+            self.set_byte_order(byte_order);
+            pos = LogHeader::LEN;
+        }
+        if let Some(timeline) = &self.timeline {
+            timeline.record(self.clock.now(), pos)?;
+        }
+        Ok(pos)
+    }
+
+    /// Give a log from before logs had headers one, in front of its
+    /// records, which were always little-endian. Returns whether it needed
+    /// one. A log that doesn't start with a header or a record the first
+    /// version wrote is left for reads to reject.
+    pub fn upgrade(&self) -> Result<bool> {
+        if self.len()? == 0 {
+            return Ok(false);
+        }
+        let mut reader = BufReader::new(File::open(&self.path)?);
+        let mut start = Vec::new();
+        (&mut reader).take(LogHeader::LEN).read_to_end(&mut start)?;
+        if LogHeader::parse(&start).is_some() {
+            return Ok(false);
+        }
+        reader.seek(std::io::SeekFrom::Start(0))?;
+        match Command::read(&mut reader, ByteOrder::LittleEndian) {
+            Ok(ref command) if command.is_baseline() => {}
+            _ => return Ok(false),
+        }
+        drop(reader);
+
+        save_overwrite_with_reader(&self.path, |mut reader, mut writer| {
+            LogHeader::new(ByteOrder::LittleEndian, self.clock.now())
+                .write(&mut writer)?;
+            std::io::copy(&mut reader, &mut writer)?;
+            writer.flush()?;
+            writer.get_ref().sync_all()?;
+            Ok(())
+        })?;
+        self.set_byte_order(ByteOrder::LittleEndian);
+        Ok(true)
+    }
+
+    pub fn exists(&self) -> bool {
+        self.path.is_file()
+    }
+
+    pub fn size(&self) -> Result<u64> {
+        Ok(std::fs::metadata(&self.path)?.len())
+    }
+
+    /// The length of the log, or 0 if nothing has been written to it yet.
+    pub fn len(&self) -> Result<u64> {
+        if self.exists() {
+            self.size()
+        } else {
+            Ok(0)
+        }
+    }
+
+    /// Make sure everything appended so far is on disk.
+    pub fn sync(&self) -> Result<()> {
+        if self.exists() {
+            File::open(&self.path)?.sync_data()?;
+        }
+        Ok(())
+    }
+
+    /// Cut the log off at the given length.
+    pub fn truncate(&self, len: u64) -> Result<()> {
+        let file = OpenOptions::new().write(true).open(&self.path)?;
+        file.set_len(len)?;
+        if self.sync == SyncPolicy::Always {
+            file.sync_all()?;
+        }
+        Ok(())
+    }
+
+    /// Write the log from `start` up to `end` to the writer.
+    pub fn copy_range(
+        &self,
+        start: u64,
+        end: u64,
+        writer: &mut dyn Write,
+    ) -> Result<()> {
+        if start == end {
+            return Ok(());
+        }
         let mut file = File::open(&self.path)?;
+        file.seek(std::io::SeekFrom::Start(start))?;
+        std::io::copy(&mut file.take(end - start), writer)?;
+        Ok(())
+    }
+
+    /// Copy the log to a new file, if anything has been written to it.
+    pub fn copy_to<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        if self.exists() {
+            std::fs::copy(&self.path, &path)?;
+            if self.sync == SyncPolicy::Always {
+                File::open(&path)?.sync_all()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Open the log for reading, with direct I/O if it was asked for.
+    fn open_reader(&self) -> Result<LogReader> {
+        Ok(if self.direct_io {
+            LogReader::Direct(DirectReader::open(&self.path)?)
+        } else {
+            LogReader::Cached(File::open(&self.path)?)
+        })
+    }
+
+    pub fn iter(&self) -> Result<LogFileIterator<LogReader>> {
+        self.iter_from(0)
+    }
+
+    /// Iterate over the records starting at the given offset, or the first
+    /// record if it's inside the header.
+    pub fn iter_from(&self, start: u64) -> Result<LogFileIterator<LogReader>> {
+        let byte_order = self.byte_order()?;
+        let mut file = self.open_reader()?;
+        file.seek(std::io::SeekFrom::Start(start.max(LogHeader::LEN)))?;
+        LogFileIterator::new(BufReader::new(file), byte_order)
+    }
+
+    pub fn get_command(&self, pointer: &LogCommandPointer) -> Result<Command> {
+        let byte_order = self.byte_order()?;
+        let mut file = self.open_reader()?;
         file.seek(std::io::SeekFrom::Start(pointer.offset))?;
         let mut reader = BufReader::new(file);
-        Command::read(&mut reader)
+        Command::read(&mut reader, byte_order)
     }
 
     pub fn append(&self, command: Command) -> Result<LogCommandPointer> {
@@ -40,30 +235,139 @@ impl LogFile {
             .append(true)
             .open(&self.path)?;
         let mut writer = BufWriter::new(file);
-        let pos = writer.seek(std::io::SeekFrom::End(0))?;
-        command.append(&mut writer)?;
+        let pos = self.start_append(&mut writer)?;
+        self.reserve(writer.get_ref(), pos);
+        command.append(&mut writer, self.byte_order()?)?;
+        if self.sync == SyncPolicy::Always {
+            writer.flush()?;
+            writer.get_ref().sync_data()?;
+        }
+        Ok(LogCommandPointer::new(LogKvs::DEFAULT_LOG_ID, pos))
+    }
+
+    /// Append a `Command::Set` whose value is read from the reader, without
+    /// holding the value in memory. The value is first copied to a spool
+    /// file next to the log, since its length has to be written before it.
+    pub fn append_set_from_reader(
+        &self,
+        key: &str,
+        value: &mut dyn Read,
+    ) -> Result<LogCommandPointer> {
+        let spool_path = self.path.with_extension("incoming");
+        let result = self.append_spooled(key, value, &spool_path);
+        // the spool may not exist if creating it failed
+        let _ = std::fs::remove_file(&spool_path);
+        result
+    }
+
+    fn append_spooled(
+        &self,
+        key: &str,
+        value: &mut dyn Read,
+        spool_path: &Path,
+    ) -> Result<LogCommandPointer> {
+        let mut spool = BufWriter::new(File::create(spool_path)?);
+        let len = copy_utf8(value, &mut spool)?;
+        spool.flush()?;
+        drop(spool);
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        let mut writer = BufWriter::new(file);
+        let pos = self.start_append(&mut writer)?;
+        self.reserve(writer.get_ref(), pos);
+        Command::append_set_header(&mut writer, key, len, self.byte_order()?)?;
+        std::io::copy(&mut File::open(spool_path)?, &mut writer)?;
+        writer.flush()?;
+        if self.sync == SyncPolicy::Always {
+            writer.get_ref().sync_data()?;
+        }
         Ok(LogCommandPointer::new(LogKvs::DEFAULT_LOG_ID, pos))
     }
 
+    /// Reserve the next stretch of disk once appends reach `pos`. Failing
+    /// to is ignored, since appending works without it.
+    fn reserve(&self, file: &File, pos: u64) {
+        if let Some(chunk) = self.preallocate {
+            if pos >= self.reserved_to.load(Ordering::SeqCst) {
+                let _ = preallocate(file, pos, chunk);
+                self.reserved_to.store(pos + chunk, Ordering::SeqCst);
+            }
+        }
+    }
+
+    /// Replace the log with what `write_func` writes, given the records in
+    /// the current one. The new log gets a new header, in the same byte
+    /// order.
     pub fn rewrite<F>(&self, write_func: F) -> Result<()>
     where
         F: FnOnce(LogFileIterator<File>, BufWriter<File>) -> Result<()>,
     {
-        save_overwrite_with_reader(&self.path, |reader, writer| {
-            write_func(LogFileIterator::new(reader)?, writer)
-        })
+        let byte_order = self.byte_order()?;
+        save_overwrite_with_reader(&self.path, |mut reader, mut writer| {
+            reader.seek(std::io::SeekFrom::Start(LogHeader::LEN))?;
+            LogHeader::new(byte_order, self.clock.now()).write(&mut writer)?;
+            write_func(LogFileIterator::new(reader, byte_order)?, writer)
+        })?;
+        // the new log has nothing reserved
+        self.reserved_to.store(0, Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+/// Reads the log, through the page cache or around it.
+pub(crate) enum LogReader {
+    Cached(File),
+    Direct(DirectReader),
+}
+
+impl Read for LogReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            LogReader::Cached(file) => file.read(buf),
+            LogReader::Direct(reader) => reader.read(buf),
+        }
+    }
+}
+
+impl Seek for LogReader {
+    fn seek(&mut self, pos: std::io::SeekFrom) -> std::io::Result<u64> {
+        match self {
+            LogReader::Cached(file) => file.seek(pos),
+            LogReader::Direct(reader) => reader.seek(pos),
+        }
     }
 }
 
 pub(crate) struct LogFileIterator<R: Read + Seek> {
-    reader: BufReader<R>,
+    // tracks the position as records are read, since seeking to find it
+    // would throw away the buffer
+    reader: Tracker<BufReader<R>>,
     end_pos: u64,
+    byte_order: ByteOrder,
+}
+
+impl<R: Read + Seek> LogFileIterator<R> {
+    pub fn new(
+        mut reader: BufReader<R>,
+        byte_order: ByteOrder,
+    ) -> Result<LogFileIterator<R>> {
+        let pos = reader.stream_position()?;
+        let end_pos = stream_len(&mut reader)?;
+        Ok(LogFileIterator {
+            reader: Tracker::with_pos(reader, pos),
+            end_pos,
+            byte_order,
+        })
+    }
 }
 
 impl<R: Read + Seek> LogFileIterator<R> {
-    pub fn new(mut reader: BufReader<R>) -> Result<LogFileIterator<R>> {
-        let end_pos = reader.stream_len()?;
-        Ok(LogFileIterator { reader, end_pos })
+    /// The offset of the next record to be read.
+    pub fn pos(&self) -> u64 {
+        self.reader.current_pos()
     }
 }
 
@@ -71,21 +375,18 @@ impl<R: Read + Seek> Iterator for LogFileIterator<R> {
     type Item = Result<(Command, LogCommandPointer)>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        match self.reader.stream_position() {
-            Ok(current_pos) if current_pos < self.end_pos => {
-                Some(match Command::read(&mut self.reader) {
-                    Ok(command) => Ok((
-                        command,
-                        LogCommandPointer::new(
-                            LogKvs::DEFAULT_LOG_ID,
-                            current_pos,
-                        ),
-                    )),
-                    Err(err) => Err(err),
-                })
-            }
-            Ok(_) => None,
-            Err(err) => Some(Err(Error::from(err))),
+        let current_pos = self.reader.current_pos();
+        if current_pos >= self.end_pos {
+            return None;
         }
+
+        Some(
+            Command::read(&mut self.reader, self.byte_order).map(|command| {
+                (
+                    command,
+                    LogCommandPointer::new(LogKvs::DEFAULT_LOG_ID, current_pos),
+                )
+            }),
+        )
     }
 }
@@ -1,5 +1,11 @@
+mod blob;
 mod command;
+mod header;
 mod log_file;
+mod timeline;
 
+pub(crate) use blob::*;
 pub(crate) use command::*;
+pub(crate) use header::*;
 pub(crate) use log_file::*;
+pub(crate) use timeline::*;
@@ -0,0 +1,107 @@
+/*!
+ * When records were appended to the log, to within a second, so history
+ * older than a retention window can be cut off.
+ */
+
+use std::convert::TryInto;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use core::Result;
+
+/// A list of marks, each the time a record was appended in ms since the
+/// epoch, and its offset, as two little-endian `u64`s. A mark is added for
+/// the first record appended each second, so every record between two
+/// marks was appended less than a second after the first of them.
+#[derive(Debug)]
+pub(crate) struct Timeline {
+    path: PathBuf,
+    /// When the last mark was added by this handle.
+    last_mark: AtomicU64,
+}
+
+impl Timeline {
+    pub const FILE_NAME: &'static str = "TIMELINE";
+    /// How long after a mark records can be appended without another.
+    pub const INTERVAL_MS: u64 = 1000;
+    const MARK_LEN: usize = 16;
+
+    /// The timeline of the store in the given directory.
+    pub fn new<P: AsRef<Path>>(dir: P) -> Timeline {
+        Timeline {
+            path: dir.as_ref().join(Self::FILE_NAME),
+            last_mark: AtomicU64::new(0),
+        }
+    }
+
+    pub fn millis(time: SystemTime) -> u64 {
+        time.duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64
+    }
+
+    /// Note that a record is being appended at the given offset, adding a
+    /// mark unless there's been one in the last second.
+    pub fn record(&self, time: SystemTime, offset: u64) -> Result<()> {
+        let time = Self::millis(time);
+        let last_mark = self.last_mark.load(Ordering::SeqCst);
+        if last_mark != 0 && time < last_mark + Self::INTERVAL_MS {
+            return Ok(());
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        file.write_all(&Self::encode(time, offset))?;
+        self.last_mark.store(time, Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// Every mark, oldest first. A mark cut short by a crash is left out.
+    pub fn marks(&self) -> Result<Vec<(u64, u64)>> {
+        if !self.path.is_file() {
+            return Ok(Vec::new());
+        }
+        let bytes = fs::read(&self.path)?;
+        Ok(bytes
+            .chunks_exact(Self::MARK_LEN)
+            .map(|mark| {
+                let time = u64::from_le_bytes(mark[..8].try_into().unwrap());
+                let offset = u64::from_le_bytes(mark[8..].try_into().unwrap());
+                (time, offset)
+            })
+            .collect())
+    }
+
+    /// Replace every mark, after the log has been rewritten.
+    pub fn replace(&self, marks: &[(u64, u64)]) -> Result<()> {
+        let tmp = self.path.with_extension("tmp");
+        let mut writer = BufWriter::new(File::create(&tmp)?);
+        for &(time, offset) in marks {
+            writer.write_all(&Self::encode(time, offset))?;
+        }
+        writer.flush()?;
+        fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+
+    /// Remove the timeline of the store in the given directory, once the
+    /// log's been rewritten without keeping it up to date.
+    pub fn remove_from<P: AsRef<Path>>(dir: P) -> Result<()> {
+        let path = dir.as_ref().join(Self::FILE_NAME);
+        if path.is_file() {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+
+    fn encode(time: u64, offset: u64) -> [u8; Self::MARK_LEN] {
+        let mut mark = [0; Self::MARK_LEN];
+        mark[..8].copy_from_slice(&time.to_le_bytes());
+        mark[8..].copy_from_slice(&offset.to_le_bytes());
+        mark
+    }
+}
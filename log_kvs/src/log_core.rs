@@ -1,57 +1,256 @@
-use std::collections::HashMap;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 
-use core::{Error, Result};
+use core::{
+    clock_or_system, Clock, Collation, Error, IndexKind, IntegrityLevel,
+    Result, Scrubbable, StoreObserver, StoreOptions,
+};
 
-use crate::{Command, LogCommandPointer, LogFile};
+use crate::{
+    Backfill, BlobDir, Command, Commit, Index, LogCommandPointer, LogFile,
+    WriteLock,
+};
 
 /// An implementation of a key-value store using an append-only log store.
 #[derive(Debug)]
 pub struct LogKvs {
-    pub(crate) index: HashMap<String, LogCommandPointer>,
+    pub(crate) index: Index,
     pub(crate) log: LogFile,
+    pub(crate) blobs: BlobDir,
+    pub(crate) blob_threshold: Option<u64>,
+    pub(crate) delta_depth: Option<u32>,
+    pub(crate) trash_retention: Option<Duration>,
+    pub(crate) history_retention: Option<Duration>,
+    pub(crate) checkpoint_every: Option<u64>,
+    pub(crate) checkpoint_interval: Option<Duration>,
+    /// Writes made since the index was last saved.
+    pub(crate) writes_since_checkpoint: u64,
+    /// When the index was last saved, or the store opened.
+    pub(crate) last_checkpoint: SystemTime,
+    /// What's left to index after a lazy open.
+    pub(crate) backfill: Option<Backfill>,
+    pub(crate) integrity: IntegrityLevel,
+    pub(crate) clock: Arc<dyn Clock>,
+    pub(crate) observer: Option<Arc<dyn StoreObserver>>,
+    pub(crate) path: PathBuf,
+    /// None for a read-only handle.
+    pub(crate) lock: Option<WriteLock>,
+    /// The generation the index was built from.
+    pub(crate) generation: u64,
+    /// How far into the log a read-only handle's index goes.
+    pub(crate) indexed_to: u64,
+    /// The last commit, if it's known.
+    pub(crate) commit: Option<Commit>,
 }
 
 impl LogKvs {
     pub(crate) const DEFAULT_LOG_NAME: &'static str = "1";
     pub(crate) const DEFAULT_LOG_ID: usize = 1;
+    pub(crate) const BLOB_DIR_NAME: &'static str = "blobs";
+    /// How much of the log is read between reports of the index's
+    /// progress.
+    const PROGRESS_STEP: u64 = 1 << 20;
 
-    pub(crate) fn new<P: AsRef<Path>>(path: P) -> Result<Self> {
+    pub(crate) fn new<P: AsRef<Path>>(
+        path: P,
+        options: StoreOptions,
+    ) -> Result<Self> {
         let path = Path::new(path.as_ref());
-        let default_file = path.join(Self::DEFAULT_LOG_NAME);
-
-        let kvs = LogKvs {
-            index: HashMap::new(),
-            log: LogFile::new(default_file),
-        };
+        // a store that's never been written to may have recorded one
+        let collation =
+            Self::read_collation(path)?.unwrap_or(options.collation);
 
+        let mut kvs = Self::from_parts(path, &options, collation)?;
+        if !options.read_only {
+            kvs.lock = Some(WriteLock::acquire(path)?);
+            kvs.generation = Self::writer_generation(path)?;
+            Self::write_collation(path, collation)?;
+        }
         Ok(kvs)
     }
 
-    pub(crate) fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+    pub(crate) fn load<P: AsRef<Path>>(
+        path: P,
+        options: StoreOptions,
+    ) -> Result<Self> {
         let path = Path::new(path.as_ref());
-        let default_file = path.join(Self::DEFAULT_LOG_NAME);
+        let collation = Self::read_collation(path)?.unwrap_or_default();
 
-        let mut kvs = LogKvs {
-            index: HashMap::new(),
-            log: LogFile::new(default_file),
-        };
-
-        for record in kvs.log.iter()? {
-            let (command, pointer) = record?;
-            // println!("replaying {:?}, {:?}", command, pointer);
-            kvs.replay(command, pointer)?;
+        let mut kvs = Self::from_parts(path, &options, collation)?;
+        if options.read_only {
+            kvs.generation = Self::UNKNOWN_GENERATION;
+            kvs.refresh()?;
+        } else {
+            kvs.lock = Some(WriteLock::acquire(path)?);
+            kvs.generation = Self::writer_generation(path)?;
+            kvs.log.upgrade()?;
+            let commit = kvs.read_commit()?;
+            if let Some(commit) = &commit {
+                // fast opens trust what was committed, like the hint, and
+                // only paranoid ones check more than the last commit's
+                // writes
+                match options.integrity {
+                    IntegrityLevel::Fast => {}
+                    IntegrityLevel::Standard => {
+                        kvs.verify_commit(commit, false)?
+                    }
+                    IntegrityLevel::Paranoid => {
+                        kvs.verify_commit(commit, true)?
+                    }
+                }
+            }
+            kvs.commit = commit;
+            let hinted_to = match options.integrity {
+                IntegrityLevel::Fast => kvs.read_hint()?,
+                _ => None,
+            };
+            let log_len = kvs.log.len()?;
+            if options.lazy_open && kvs.committed_len() == Some(log_len) {
+                kvs.backfill =
+                    Some(Backfill::new(hinted_to.unwrap_or(0), log_len));
+            } else {
+                match hinted_to {
+                    Some(hinted_to) => kvs.extend_index_from(hinted_to)?,
+                    None => kvs.rebuild_index()?,
+                }
+            }
+        }
+        if options.integrity == IntegrityLevel::Paranoid {
+            kvs.verify()?;
         }
         Ok(kvs)
     }
 
-    fn replay(
+    /// A handle on the store in the directory, with nothing indexed,
+    /// locked or committed yet, and its keys in the given collation.
+    fn from_parts(
+        path: &Path,
+        options: &StoreOptions,
+        collation: Collation,
+    ) -> Result<Self> {
+        let clock = clock_or_system(options.clock.clone());
+        Ok(LogKvs {
+            index: Index::new(options.index, collation),
+            log: LogFile::new(path.join(Self::DEFAULT_LOG_NAME), options),
+            blobs: BlobDir::open(path.join(Self::BLOB_DIR_NAME), options)?,
+            blob_threshold: options.blob_threshold,
+            delta_depth: options.delta_depth,
+            trash_retention: options.trash_retention,
+            history_retention: options.history_retention,
+            checkpoint_every: options.checkpoint_every,
+            checkpoint_interval: options.checkpoint_interval,
+            writes_since_checkpoint: 0,
+            last_checkpoint: clock.now(),
+            backfill: None,
+            integrity: options.integrity,
+            clock,
+            observer: options.observer.clone(),
+            path: path.to_owned(),
+            lock: None,
+            generation: 0,
+            indexed_to: 0,
+            commit: None,
+        })
+    }
+
+    /// How the keys are indexed in memory, as picked when the store was
+    /// opened.
+    pub fn index_kind(&self) -> IndexKind {
+        self.index.kind()
+    }
+
+    /// Add the records from the given offset on to the index, failing at
+    /// any that can't be read. A handle that can write cuts the log off at
+    /// one after the last commit instead, since it's from a write that was
+    /// cut short.
+    fn extend_index_from(&mut self, offset: u64) -> Result<()> {
+        let total = self.log.len()?;
+        let mut reported = offset;
+        let mut records = self.log.iter_from(offset)?;
+        loop {
+            let at = records.pos();
+            if at >= reported + Self::PROGRESS_STEP {
+                self.report_progress(at, total);
+                reported = at;
+            }
+            match records.next() {
+                None => {
+                    self.report_progress(total, total);
+                    return Ok(());
+                }
+                Some(Ok((command, pointer))) => {
+                    self.replay(command, pointer)?
+                }
+                Some(Err(err)) => match self.committed_len() {
+                    Some(committed)
+                        if at >= committed && !self.is_read_only() =>
+                    {
+                        return self.log.truncate(at);
+                    }
+                    _ => return Err(err),
+                },
+            }
+        }
+    }
+
+    /// Tell the observer how much of the log has been indexed.
+    pub(crate) fn report_progress(&self, indexed: u64, total: u64) {
+        if let Some(observer) = &self.observer {
+            observer.on_index_progress(indexed, total);
+        }
+    }
+
+    /// Check everything `IntegrityLevel::Paranoid` promises: the scrub's
+    /// checks, and that every current value, including those in blobs, can
+    /// be read and matches the name of its blob.
+    fn verify(&self) -> Result<()> {
+        let mut problems = self.scrub()?.problems;
+        for (key, pointer) in self.full_index()?.iter() {
+            let blob = match self.log.get_command(pointer) {
+                Ok(Command::SetBlob { blob, .. }) => blob,
+                // the scrub has read every other kind of value
+                _ => continue,
+            };
+            match self.blobs.read(&blob) {
+                Ok(value) if self.blobs.matches_name(&blob, &value) => {}
+                Ok(_) => problems.push(format!(
+                    "the blob holding '{}' doesn't match its name",
+                    key
+                )),
+                Err(err) => problems.push(format!(
+                    "the blob holding '{}' can't be read: {}",
+                    key, err
+                )),
+            }
+        }
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            problems.sort();
+            Err(Error::corrupt_database(format!(
+                "{} failed its integrity check: {}",
+                self.path.display(),
+                problems.join(", ")
+            )))
+        }
+    }
+
+    /// Replace the index with one built by replaying the whole log.
+    pub(crate) fn rebuild_index(&mut self) -> Result<()> {
+        self.index.clear();
+        self.extend_index_from(0)
+    }
+
+    pub(crate) fn replay(
         &mut self,
         command: Command,
         pointer: LogCommandPointer,
     ) -> Result<()> {
         match command {
-            Command::Set { key, .. } => {
+            Command::Set { key, .. }
+            | Command::SetBlob { key, .. }
+            | Command::SetDelta { key, .. } => {
                 self.index.insert(key, pointer);
             }
             Command::Remove { key } => {
@@ -63,6 +262,11 @@ impl LogKvs {
                     ))
                 })?;
             }
+            Command::RemovePrefix { .. } | Command::RemoveRange { .. } => {
+                for key in self.index.removed_by(&command).unwrap_or_default() {
+                    self.index.remove(&key);
+                }
+            }
         }
         Ok(())
     }
@@ -73,10 +277,24 @@ impl LogKvs {
     ) -> Result<String> {
         match self.log.get_command(pointer)? {
             Command::Set { value, .. } => Ok(value),
+            Command::SetBlob { blob, .. } => self.blobs.read(&blob),
+            Command::SetDelta {
+                base,
+                prefix,
+                suffix,
+                middle,
+                ..
+            } => self.apply_delta(base, prefix, suffix, &middle),
             Command::Remove { key } => Err(Error::corrupt_database(format!(
                 "Command at {:?} should set key '{}', not remove it",
                 pointer, key
             ))),
+            Command::RemovePrefix { .. } | Command::RemoveRange { .. } => {
+                Err(Error::corrupt_database(format!(
+                    "Command at {:?} should set a key, not remove a range",
+                    pointer
+                )))
+            }
         }
     }
 }
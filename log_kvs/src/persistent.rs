@@ -1,32 +1,82 @@
 use std::path::Path;
 
-use core::{Error, PathType, Persistent, Result};
+use core::{
+    Capability, Error, IntegrityLevel, PathType, Persistent, Result,
+    StoreOptions,
+};
 
 use crate::LogKvs;
 
 impl Persistent for LogKvs {
     const PATH_TYPE: PathType = PathType::Directory;
 
-    fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+    fn open_with<P: AsRef<Path>>(
+        path: P,
+        options: StoreOptions,
+    ) -> Result<Self> {
         let path = Path::new(path.as_ref());
+        if options.direct_io
+            && !cfg!(any(target_os = "linux", target_os = "macos"))
+        {
+            return Err(Error::unsupported(Capability::DirectIo));
+        }
 
-        // create directory if need be
-        if let Err(err) = std::fs::create_dir(path) {
-            if err.kind() != std::io::ErrorKind::AlreadyExists {
-                return Err(Error::io(err));
+        // create directory if need be, unless only reading
+        if options.read_only {
+            if !path.is_dir() {
+                return Err(Error::config(format!(
+                    "no store at {} to read",
+                    path.display()
+                )));
+            }
+        } else {
+            if let Err(err) = std::fs::create_dir(path) {
+                if err.kind() != std::io::ErrorKind::AlreadyExists {
+                    return Err(Error::io(err).at_path(path));
+                }
             }
+            // a compaction may have been cut short while swapping logs
+            io::recover_overwrite(path.join(Self::DEFAULT_LOG_NAME))
+                .map_err(|err| err.at_path(path))?;
         }
 
-        if path.join(Self::DEFAULT_LOG_NAME).is_file() {
-            Self::load(path)
+        let opened = if path.join(Self::DEFAULT_LOG_NAME).is_file() {
+            Self::load(path, options)
         } else {
-            Self::new(path)
-        }
+            Self::new(path, options)
+        };
+        opened.map_err(|err| err.at_path(path))
     }
 
+    /// Commit the log, if this handle can write, and save the index for the
+    /// next `IntegrityLevel::Fast` open if it was opened that way.
     fn save(&mut self) -> Result<()> {
+        if self.is_read_only() || !self.log.exists() {
+            return Ok(());
+        }
+        self.write_commit()?;
+        if self.integrity == IntegrityLevel::Fast {
+            self.write_hint()?;
+        }
         Ok(())
     }
+
+    /// Copy the log, and hard link the blobs since they're never changed
+    /// once written.
+    fn fork_to<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = Path::new(path.as_ref());
+        std::fs::create_dir(path)?;
+
+        self.log.copy_to(path.join(Self::DEFAULT_LOG_NAME))?;
+        Self::write_collation(path, self.collation())?;
+        self.blobs.link_to(path.join(Self::BLOB_DIR_NAME))
+    }
+
+    /// Releases the write lock, as the process exiting would.
+    fn simulate_crash(mut self) {
+        self.lock.take();
+        std::mem::forget(self);
+    }
 }
 
 impl Drop for LogKvs {
@@ -39,5 +89,160 @@ impl Drop for LogKvs {
 mod tests {
     use super::*;
 
+    use core::tests::{DefaultTestContext, PersistentTestContext, TestContext};
+    use core::{ByteOrder, Compactable, ErrorKind, KvStore, Scannable};
+
+    use crate::{Command, LogHeader};
+
     generate_persistent_tests!(LogKvs);
+    generate_simulation_tests!(LogKvs, compactable);
+
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    #[test]
+    fn direct_io() -> Result<()> {
+        let context = <DefaultTestContext as TestContext<LogKvs>>::init();
+        let direct = StoreOptions {
+            direct_io: true,
+            ..StoreOptions::default()
+        };
+        let mut store: LogKvs = context.open_store_with(direct.clone())?;
+        for i in 0..1000 {
+            store.set(format!("key{}", i), format!("value{}", i))?;
+        }
+        assert_eq!(
+            store.get("key500".to_owned())?,
+            Some("value500".to_owned())
+        );
+        drop(store);
+
+        // the index is rebuilt by reading the log around the cache
+        let store: LogKvs = context.open_store_with(direct)?;
+        assert_eq!(
+            store.get("key999".to_owned())?,
+            Some("value999".to_owned())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn preallocate() -> Result<()> {
+        let context = <DefaultTestContext as TestContext<LogKvs>>::init();
+        let options = StoreOptions {
+            preallocate: Some(1 << 20),
+            ..StoreOptions::default()
+        };
+        let mut store: LogKvs = context.open_store_with(options.clone())?;
+        store.set("key1".to_owned(), "value1".to_owned())?;
+        let len = store.log.len()?;
+        #[cfg(target_os = "linux")]
+        {
+            use std::os::unix::fs::MetadataExt;
+            let log = PersistentTestContext::<LogKvs>::get_path(&context)
+                .join(LogKvs::DEFAULT_LOG_NAME);
+            assert!(std::fs::metadata(log)?.blocks() * 512 >= 1 << 20);
+        }
+        store.set("key2".to_owned(), "value2".to_owned())?;
+        assert!(store.log.len()? < 1 << 20);
+        drop(store);
+
+        // the reserved space isn't read as records
+        let store: LogKvs = context.open_store_with(options)?;
+        assert!(store.log.len()? > len);
+        assert_eq!(store.get("key2".to_owned())?, Some("value2".to_owned()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn byte_order() -> Result<()> {
+        let context = <DefaultTestContext as TestContext<LogKvs>>::init();
+        let big = StoreOptions {
+            byte_order: ByteOrder::BigEndian,
+            ..StoreOptions::default()
+        };
+        let mut store: LogKvs = context.open_store_with(big)?;
+        store.set("key1".to_owned(), "value1".to_owned())?;
+        drop(store);
+
+        // the header decides, not the options it's opened with
+        let mut store: LogKvs = context.open_store()?;
+        assert_eq!(store.log.byte_order()?, ByteOrder::BigEndian);
+        assert_eq!(store.get("key1".to_owned())?, Some("value1".to_owned()));
+        store.set("key2".to_owned(), "value2".to_owned())?;
+        store.compact()?;
+        drop(store);
+        let store: LogKvs = context.open_store()?;
+        assert_eq!(store.log.byte_order()?, ByteOrder::BigEndian);
+        assert_eq!(store.get("key2".to_owned())?, Some("value2".to_owned()));
+
+        Ok(())
+    }
+
+    /// Write a log the way the first version did, without a header.
+    fn write_baseline_log(path: &Path) -> Result<()> {
+        std::fs::create_dir_all(path)?;
+        let mut log = Vec::new();
+        for command in &[
+            Command::Set {
+                key: "key1".to_owned(),
+                value: "value1".to_owned(),
+            },
+            Command::Set {
+                key: "key2".to_owned(),
+                value: "value2".to_owned(),
+            },
+            Command::Remove {
+                key: "key1".to_owned(),
+            },
+        ] {
+            command.append(&mut log, ByteOrder::LittleEndian)?;
+        }
+        std::fs::write(path.join(LogKvs::DEFAULT_LOG_NAME), log)?;
+        Ok(())
+    }
+
+    #[test]
+    fn no_header() -> Result<()> {
+        let context = <DefaultTestContext as TestContext<LogKvs>>::init();
+        let path = PersistentTestContext::<LogKvs>::get_path(&context).clone();
+        write_baseline_log(&path)?;
+
+        // readers can't upgrade it, so fail cleanly rather than read garbage
+        let read_only = StoreOptions {
+            read_only: true,
+            ..StoreOptions::default()
+        };
+        let err = TestContext::<LogKvs>::open_store_with(&context, read_only)
+            .unwrap_err();
+        match err.kind() {
+            ErrorKind::CorruptDatabase { .. } => {}
+            kind => panic!("unexpected error {:?}", kind),
+        }
+        assert_eq!(err.kind().path(), Some(path.as_path()));
+
+        // but a writer gives it a header
+        let mut store: LogKvs = context.open_store()?;
+        assert_eq!(store.get("key1".to_owned())?, None);
+        assert_eq!(store.get("key2".to_owned())?, Some("value2".to_owned()));
+        store.set("key3".to_owned(), "value3".to_owned())?;
+        drop(store);
+        let log = std::fs::read(path.join(LogKvs::DEFAULT_LOG_NAME))?;
+        assert!(LogHeader::parse(&log).is_some());
+        let store: LogKvs = context.open_store()?;
+        assert_eq!(store.get("key3".to_owned())?, Some("value3".to_owned()));
+        assert_eq!(store.keys()?.len(), 2);
+        drop(store);
+
+        // and repair reads it the same way
+        write_baseline_log(&path)?;
+        let report = LogKvs::repair(&path)?;
+        assert!(report.is_clean());
+        assert_eq!((report.records, report.keys), (3, 1));
+        let store: LogKvs = context.open_store()?;
+        assert_eq!(store.get("key1".to_owned())?, None);
+        assert_eq!(store.get("key2".to_owned())?, Some("value2".to_owned()));
+
+        Ok(())
+    }
 }
@@ -0,0 +1,330 @@
+/*!
+ * Salvaging what can be read from a damaged store.
+ */
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as _;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::ops::Range;
+use std::path::Path;
+use std::time::SystemTime;
+
+use core::{ByteOrder, Result, StoreOptions};
+
+use crate::{BlobDir, Command, LogHeader, LogKvs, Timeline, WriteLock};
+
+/// What [`LogKvs::repair`] salvaged, and what it had to leave behind.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct RepairReport {
+    /// The number of records that could be decoded.
+    pub records: u64,
+    /// The number of keys with a value after the repair.
+    pub keys: u64,
+    /// The ranges of the log, in bytes, that couldn't be decoded.
+    pub skipped: Vec<Range<u64>>,
+    /// Values that were lost, because their blob or the record their delta
+    /// was based on couldn't be read.
+    pub problems: Vec<String>,
+}
+
+impl RepairReport {
+    /// The name of the file the report is written to, in the store's
+    /// directory.
+    pub const FILE_NAME: &'static str = "REPAIR";
+
+    /// The number of bytes of the log that were skipped.
+    pub fn skipped_bytes(&self) -> u64 {
+        self.skipped
+            .iter()
+            .map(|range| range.end - range.start)
+            .sum()
+    }
+
+    /// Whether everything in the log was salvaged.
+    pub fn is_clean(&self) -> bool {
+        self.skipped.is_empty() && self.problems.is_empty()
+    }
+}
+
+/// A salvaged value.
+enum Live {
+    Value(String),
+    Blob(String),
+}
+
+impl LogKvs {
+    /// Rebuild a damaged store from whatever records in its log can still
+    /// be decoded, as a last resort when it won't open. Doesn't trust the
+    /// saved index: every byte of the log is read, and where a record can't
+    /// be decoded, the bytes are skipped one at a time until one can.
+    /// Records have no framing, so resyncing relies on decoding alone, and
+    /// records with an empty key aren't trusted there, since runs of zeros
+    /// decode as one. A log without a header, from before logs had them,
+    /// is read as little-endian records from its start, taking only the
+    /// kinds of record the first version wrote, but one from a later
+    /// version is left alone.
+    ///
+    /// The log is replaced with the current value of each key, the way
+    /// compaction would, so readers rebuild their index. The report is
+    /// returned, and written to [`RepairReport::FILE_NAME`] in the store's
+    /// directory. Fails if another process has the store open for writing.
+    pub fn repair<P: AsRef<Path>>(path: P) -> Result<RepairReport> {
+        let path = path.as_ref();
+        let _lock = WriteLock::acquire(path)?;
+        let log_path = path.join(Self::DEFAULT_LOG_NAME);
+        let data = if log_path.is_file() {
+            fs::read(&log_path)?
+        } else {
+            Vec::new()
+        };
+        let blobs = BlobDir::open(
+            path.join(Self::BLOB_DIR_NAME),
+            &StoreOptions::default(),
+        )?;
+        let collation = Self::read_collation(path)?.unwrap_or_default();
+
+        let mut report = RepairReport::default();
+        let mut live: BTreeMap<String, Live> = BTreeMap::new();
+        // values by the offset of their record, for deltas to be based on
+        let mut values: HashMap<u64, String> = HashMap::new();
+        let mut blob_records: HashMap<u64, String> = HashMap::new();
+        let (byte_order, mut offset) = match LogHeader::parse(&data) {
+            Some(header) => (header?.byte_order, LogHeader::LEN as usize),
+            None => (ByteOrder::LittleEndian, 0),
+        };
+        let baseline = offset == 0;
+        let mut skipping: Option<u64> = None;
+        while offset < data.len() {
+            let decoded = Command::decode(&data[offset..], byte_order)
+                .filter(|(command, _)| !baseline || command.is_baseline());
+            let (command, len) = match decoded {
+                Some((command, _))
+                    if skipping.is_some() && command.key().is_empty() =>
+                {
+                    offset += 1;
+                    continue;
+                }
+                Some(decoded) => decoded,
+                None => {
+                    skipping = skipping.or(Some(offset as u64));
+                    offset += 1;
+                    continue;
+                }
+            };
+            if let Some(start) = skipping.take() {
+                report.skipped.push(start..offset as u64);
+            }
+            report.records += 1;
+            let at = offset as u64;
+            offset += len as usize;
+
+            match command {
+                Command::Set { key, value } => {
+                    values.insert(at, value.clone());
+                    live.insert(key, Live::Value(value));
+                }
+                Command::SetBlob { key, blob } => {
+                    if blobs.exists(&blob) {
+                        blob_records.insert(at, blob.clone());
+                        live.insert(key, Live::Blob(blob));
+                    } else {
+                        report.problems.push(format!(
+                            "the blob holding '{}' is missing",
+                            key
+                        ));
+                        live.remove(&key);
+                    }
+                }
+                Command::SetDelta {
+                    key,
+                    base,
+                    prefix,
+                    suffix,
+                    middle,
+                    ..
+                } => {
+                    let old = match values.get(&base) {
+                        Some(old) => Some(old.clone()),
+                        None => blob_records
+                            .get(&base)
+                            .and_then(|blob| blobs.read(blob).ok()),
+                    };
+                    match old.and_then(|old| {
+                        splice(&old, prefix as usize, suffix as usize, &middle)
+                    }) {
+                        Some(value) => {
+                            values.insert(at, value.clone());
+                            live.insert(key, Live::Value(value));
+                        }
+                        None => {
+                            report.problems.push(format!(
+                                "the value '{}' was changed from can't be read",
+                                key
+                            ));
+                            live.remove(&key);
+                        }
+                    }
+                }
+                Command::Remove { key } => {
+                    live.remove(&key);
+                }
+                command @ Command::RemovePrefix { .. }
+                | command @ Command::RemoveRange { .. } => {
+                    let removed: Vec<String> = live
+                        .keys()
+                        .filter(|key| command.removes(key, collation))
+                        .cloned()
+                        .collect();
+                    for key in removed {
+                        live.remove(&key);
+                    }
+                }
+            }
+        }
+        if let Some(start) = skipping {
+            report.skipped.push(start..data.len() as u64);
+        }
+        report.keys = live.len() as u64;
+
+        // the new log replaces the old one the same way compaction's does,
+        // and readers rebuild their index when the generation changes
+        let tmp = log_path.with_extension("repair");
+        let mut writer = BufWriter::new(File::create(&tmp)?);
+        LogHeader::new(byte_order, SystemTime::now()).write(&mut writer)?;
+        for (key, value) in live {
+            match value {
+                Live::Value(value) => Command::Set { key, value },
+                Live::Blob(blob) => Command::SetBlob { key, blob },
+            }
+            .append(&mut writer, byte_order)?;
+        }
+        writer.flush()?;
+        writer.get_ref().sync_all()?;
+        drop(writer);
+        let generation = Self::writer_generation(path)?;
+        fs::write(
+            path.join(Self::GENERATION_FILE_NAME),
+            (generation + 1).to_string(),
+        )?;
+        fs::rename(&tmp, &log_path)?;
+        fs::write(
+            path.join(Self::GENERATION_FILE_NAME),
+            (generation + 2).to_string(),
+        )?;
+        Timeline::remove_from(path)?;
+        let hint = path.join(Self::HINT_FILE_NAME);
+        if hint.is_file() {
+            fs::remove_file(hint)?;
+        }
+
+        fs::write(path.join(RepairReport::FILE_NAME), describe(&report))?;
+        Ok(report)
+    }
+}
+
+/// Apply a delta, or None if it doesn't fit the value it's based on.
+fn splice(
+    old: &str,
+    prefix: usize,
+    suffix: usize,
+    middle: &str,
+) -> Option<String> {
+    if prefix + suffix > old.len()
+        || !old.is_char_boundary(prefix)
+        || !old.is_char_boundary(old.len() - suffix)
+    {
+        return None;
+    }
+    Some(format!(
+        "{}{}{}",
+        &old[..prefix],
+        middle,
+        &old[old.len() - suffix..]
+    ))
+}
+
+fn describe(report: &RepairReport) -> String {
+    let mut text = String::new();
+    let _ = writeln!(
+        text,
+        "salvaged {} records holding {} keys, skipped {} bytes",
+        report.records,
+        report.keys,
+        report.skipped_bytes()
+    );
+    for range in &report.skipped {
+        let _ = writeln!(text, "skipped bytes {}..{}", range.start, range.end);
+    }
+    for problem in &report.problems {
+        let _ = writeln!(text, "{}", problem);
+    }
+    text
+}
+
+impl std::fmt::Display for RepairReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(&describe(self))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use core::tests::{DefaultTestContext, PersistentTestContext, TestContext};
+    use core::KvStore;
+
+    #[test]
+    fn salvage() -> Result<()> {
+        let context = <DefaultTestContext as TestContext<LogKvs>>::init();
+        let path = PersistentTestContext::<LogKvs>::get_path(&context).clone();
+        let options = StoreOptions {
+            blob_threshold: Some(32),
+            ..StoreOptions::default()
+        };
+        let mut store: LogKvs = context.open_store_with(options.clone())?;
+        store.set("key1".to_owned(), "value1".to_owned())?;
+        let damaged_from = store.log.len()?;
+        store.set("key2".to_owned(), "value2".to_owned())?;
+        let damaged_to = store.log.len()?;
+        store.set(
+            "key3".to_owned(),
+            "a value long enough to be kept in a blob".to_owned(),
+        )?;
+        store.set("key1".to_owned(), "value1, changed".to_owned())?;
+        store.remove("key3".to_owned())?;
+        store.set("key4".to_owned(), "value4".to_owned())?;
+        drop(store);
+
+        // overwrite the second record, so it can't be decoded
+        let log_path = path.join(LogKvs::DEFAULT_LOG_NAME);
+        let mut log = fs::read(&log_path)?;
+        for byte in &mut log[damaged_from as usize..damaged_to as usize] {
+            *byte = 0xff;
+        }
+        fs::write(&log_path, log)?;
+        assert!(TestContext::<LogKvs>::open_store(&context).is_err());
+
+        let report = LogKvs::repair(&path)?;
+        assert_eq!(report.skipped, vec![damaged_from..damaged_to]);
+        assert_eq!((report.records, report.keys), (5, 2));
+        assert!(report.problems.is_empty());
+        assert!(path.join(RepairReport::FILE_NAME).is_file());
+
+        let store: LogKvs = context.open_store_with(options)?;
+        assert_eq!(
+            store.get("key1".to_owned())?,
+            Some("value1, changed".to_owned())
+        );
+        assert_eq!(store.get("key2".to_owned())?, None);
+        assert_eq!(store.get("key3".to_owned())?, None);
+        assert_eq!(store.get("key4".to_owned())?, Some("value4".to_owned()));
+        drop(store);
+
+        // nothing left to salvage
+        assert!(LogKvs::repair(&path)?.is_clean());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,311 @@
+use core::{
+    is_trash_key, Collation, KvStore, RangeEstimate, Result, ScanOptions,
+    Scannable, TRASH_PREFIX,
+};
+
+use crate::LogKvs;
+
+impl Scannable for LogKvs {
+    /// Every key that currently has a value, read from the index. Removed
+    /// values kept in the trash aren't included.
+    fn keys(&self) -> Result<Vec<String>> {
+        let index = self.full_index()?;
+        Ok(index
+            .iter()
+            .map(|(key, _)| key)
+            .filter(|key| !is_trash_key(key))
+            .cloned()
+            .collect())
+    }
+
+    /// The keys in the range, in the store's collation. Only supported when
+    /// the store was opened with `IndexKind::Ordered`.
+    ///
+    /// ```rust
+    /// # use tempfile::TempDir;
+    /// # use core::{IndexKind, KvStore, Persistent, Scannable, StoreOptions};
+    /// # use log_kvs::LogKvs;
+    /// #
+    /// # let temp_dir =
+    /// #     TempDir::new().expect("unable to create temporary working directory");
+    /// let options = StoreOptions {
+    ///     index: IndexKind::Ordered,
+    ///     ..StoreOptions::default()
+    /// };
+    /// let mut store = LogKvs::open_with(temp_dir.path(), options).unwrap();
+    /// store.set("b".to_owned(), "value1".to_owned());
+    /// store.set("a".to_owned(), "value2".to_owned());
+    /// assert_eq!(store.scan("a", None).unwrap(), vec!["a", "b"]);
+    /// ```
+    fn scan(&self, start: &str, end: Option<&str>) -> Result<Vec<String>> {
+        self.scan_with(start, end, ScanOptions::default())
+    }
+
+    /// Walks the index from whichever end the options start at, so only
+    /// the keys up to the end of the page are visited.
+    fn scan_with(
+        &self,
+        start: &str,
+        end: Option<&str>,
+        options: ScanOptions,
+    ) -> Result<Vec<String>> {
+        let index = self.full_index()?;
+        let keys = index
+            .range_iter(start, end, options.reverse)?
+            .filter(|key| !is_trash_key(key));
+        Ok(options.page(keys.cloned()))
+    }
+
+    /// Counts the keys in the range from the index, and gives each the
+    /// average size of a key on disk, so no records are read. The average
+    /// includes stale records and blobs, so it's high until the store is
+    /// compacted.
+    fn estimate_range_size(
+        &self,
+        start: &str,
+        end: Option<&str>,
+    ) -> Result<RangeEstimate> {
+        let index = self.full_index()?;
+        let trash = index
+            .keys_with_prefix(TRASH_PREFIX)
+            .iter()
+            .filter(|key| self.collation().in_range(key, start, end))
+            .count();
+        let keys = (index.count_range(start, end) - trash) as u64;
+        if keys == 0 {
+            return Ok(RangeEstimate::default());
+        }
+        let mut disk_bytes = self.log.size()?;
+        for blob in self.blobs.names()? {
+            disk_bytes += self.blobs.size(&blob)?;
+        }
+        let per_key = disk_bytes / index.len() as u64;
+        Ok(RangeEstimate {
+            keys,
+            bytes: keys * per_key,
+        })
+    }
+
+    /// Like the default, with the range and order in the store's collation.
+    fn scan_filtered(
+        &self,
+        start: &str,
+        end: Option<&str>,
+        predicate: &dyn Fn(&str, &str) -> bool,
+    ) -> Result<Vec<(String, String)>> {
+        self.check_generation()?;
+        let collation = self.collation();
+        let mut keys: Vec<String> = self
+            .keys()?
+            .into_iter()
+            .filter(|key| collation.in_range(key, start, end))
+            .collect();
+        keys.sort_by(|a, b| collation.compare(a, b));
+
+        let mut entries = Vec::new();
+        for key in keys {
+            if let Some(value) = self.get_ref(&key)? {
+                if predicate(&key, &value) {
+                    let value = value.into_owned();
+                    entries.push((key, value));
+                }
+            }
+        }
+        Ok(entries)
+    }
+
+    fn collation(&self) -> Collation {
+        self.index.collation()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use core::tests::{DefaultTestContext, PersistentTestContext, TestContext};
+    use std::time::Duration;
+
+    use core::{
+        trash_key, Capability, Collation, ErrorKind, IndexKind, KvStore,
+        Measurable, Persistent, StoreOptions,
+    };
+
+    generate_scannable_tests!(LogKvs);
+
+    #[test]
+    fn ordered_scan() -> Result<()> {
+        let context = <DefaultTestContext as TestContext<LogKvs>>::init();
+        let options = StoreOptions {
+            index: IndexKind::Ordered,
+            ..StoreOptions::default()
+        };
+        let mut store: LogKvs = context.open_store_with(options.clone())?;
+
+        for key in &["user:3", "user:1", "group:1", "user:2", "zone:1"] {
+            store.set(key.to_string(), "value".to_owned())?;
+        }
+        store.remove("user:2".to_owned())?;
+        drop(store);
+
+        let store: LogKvs = context.open_store_with(options)?;
+        assert_eq!(
+            store.scan("user:", Some("user;"))?,
+            vec!["user:1".to_owned(), "user:3".to_owned()]
+        );
+        assert_eq!(store.scan("user:3", None)?.len(), 2);
+        assert!(store.scan("zone:1", Some("user:1"))?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn unordered_scan() -> Result<()> {
+        let context = <DefaultTestContext as TestContext<LogKvs>>::init();
+        let mut store: LogKvs = context.open_store()?;
+        store.set("key1".to_owned(), "value1".to_owned())?;
+
+        let err = store.scan("", None).unwrap_err();
+        assert_eq!(
+            err.kind(),
+            &ErrorKind::Unsupported {
+                capability: Capability::OrderedScan
+            }
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn paged_scan() -> Result<()> {
+        let context = <DefaultTestContext as TestContext<LogKvs>>::init();
+        let options = StoreOptions {
+            index: IndexKind::Ordered,
+            ..StoreOptions::default()
+        };
+        let mut store: LogKvs = context.open_store_with(options)?;
+        for i in 0..20 {
+            store.set(format!("log:{:02}", i), "value".to_owned())?;
+        }
+        store.set("zone:1".to_owned(), "value".to_owned())?;
+
+        let latest = ScanOptions {
+            reverse: true,
+            limit: Some(3),
+            ..ScanOptions::default()
+        };
+        assert_eq!(
+            store.scan_with("log:", Some("log;"), latest)?,
+            vec!["log:19", "log:18", "log:17"]
+        );
+        let page = ScanOptions {
+            offset: 18,
+            ..ScanOptions::default()
+        };
+        assert_eq!(
+            store.scan_with("log:", None, page)?,
+            vec!["log:18", "log:19", "zone:1"]
+        );
+        assert_eq!(store.scan_rev("log:18", None)?.len(), 3);
+
+        // and in the store's collation
+        let context = <DefaultTestContext as TestContext<LogKvs>>::init();
+        let mut store: LogKvs = context.open_store_with(StoreOptions {
+            index: IndexKind::Ordered,
+            collation: Collation::Numeric,
+            ..StoreOptions::default()
+        })?;
+        for key in &["file10", "file2", "file1"] {
+            store.set(key.to_string(), "value".to_owned())?;
+        }
+        assert_eq!(
+            store.scan_rev("file", None)?,
+            vec!["file10", "file2", "file1"]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn collated_scan() -> Result<()> {
+        let context = <DefaultTestContext as TestContext<LogKvs>>::init();
+        let options = StoreOptions {
+            index: IndexKind::Ordered,
+            collation: Collation::Numeric,
+            ..StoreOptions::default()
+        };
+        let mut store: LogKvs = context.open_store_with(options)?;
+        for key in &["file10", "file2", "File3", "file007", "file1"] {
+            store.set(key.to_string(), "value".to_owned())?;
+        }
+        assert_eq!(
+            store.scan("file", None)?,
+            vec!["file1", "file2", "file007", "file10"]
+        );
+        assert_eq!(
+            store.scan("file2", Some("file10"))?,
+            vec!["file2", "file007"]
+        );
+        assert_eq!(store.estimate_range_size("file2", Some("file10"))?.keys, 2);
+        drop(store);
+
+        // kept whatever the store's opened with later, and in forks
+        let store: LogKvs = context.open_store_with(StoreOptions {
+            index: IndexKind::Ordered,
+            ..StoreOptions::default()
+        })?;
+        assert_eq!(store.collation(), Collation::Numeric);
+        assert_eq!(store.scan("file8", None)?, vec!["file10"]);
+        let fork_context = <DefaultTestContext as TestContext<LogKvs>>::init();
+        let fork = PersistentTestContext::<LogKvs>::get_path(&fork_context)
+            .join("fork");
+        store.fork_to(&fork)?;
+        drop(store);
+        let store = LogKvs::open(&fork)?;
+        assert_eq!(store.collation(), Collation::Numeric);
+        let keys: Vec<String> = store
+            .scan_filtered("File", None, &|_, _| true)?
+            .into_iter()
+            .map(|(key, _)| key)
+            .collect();
+        assert_eq!(keys, vec!["File3", "file1", "file2", "file007", "file10"]);
+        drop(store);
+
+        let store: LogKvs = context.open_store_with(StoreOptions {
+            index: IndexKind::Ordered,
+            collation: Collation::CaseInsensitive,
+            ..StoreOptions::default()
+        })?;
+        assert_eq!(store.collation(), Collation::Numeric);
+
+        Ok(())
+    }
+
+    #[test]
+    fn trash_hidden() -> Result<()> {
+        let context = <DefaultTestContext as TestContext<LogKvs>>::init();
+        let options = StoreOptions {
+            index: IndexKind::Ordered,
+            trash_retention: Some(Duration::from_secs(60)),
+            ..StoreOptions::default()
+        };
+        let mut store: LogKvs = context.open_store_with(options)?;
+        store.set("key1".to_owned(), "value1".to_owned())?;
+        store.set("key2".to_owned(), "value2".to_owned())?;
+        store.remove("key1".to_owned())?;
+        assert!(store.contains_key(&trash_key("key1"))?);
+
+        assert_eq!(store.keys()?, vec!["key2"]);
+        assert_eq!(store.scan("", None)?, vec!["key2"]);
+        let first = ScanOptions {
+            limit: Some(1),
+            ..ScanOptions::default()
+        };
+        assert_eq!(store.scan_with("", None, first)?, vec!["key2"]);
+        assert_eq!(store.scan_filtered("", None, &|_, _| true)?.len(), 1);
+        assert_eq!(store.estimate_range_size("", None)?.keys, 1);
+        assert_eq!(store.stats()?.keys, 1);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,183 @@
+use std::collections::HashMap;
+
+use core::{Result, ScrubReport, Scrubbable};
+
+use crate::{Command, LogKvs};
+
+impl Scrubbable for LogKvs {
+    /// Read every record in the log, checking that each can be decoded, that
+    /// the index points at the latest value of every key, and that the blobs
+    /// of those values exist.
+    fn scrub(&self) -> Result<ScrubReport> {
+        self.check_generation()?;
+        let mut report = ScrubReport::default();
+
+        // the pointer to the current value of each key and the blob holding
+        // it if there is one, or None if it was removed
+        let mut latest = HashMap::new();
+        if self.log.exists() {
+            for record in self.log.iter()? {
+                match record {
+                    Ok((Command::Set { key, .. }, pointer)) => {
+                        latest.insert(key, Some((pointer, None)));
+                    }
+                    Ok((Command::SetDelta { key, .. }, pointer)) => {
+                        latest.insert(key, Some((pointer, None)));
+                    }
+                    Ok((Command::SetBlob { key, blob }, pointer)) => {
+                        latest.insert(key, Some((pointer, Some(blob))));
+                    }
+                    Ok((Command::Remove { key }, _)) => {
+                        latest.insert(key, None);
+                    }
+                    Ok((command, _)) => {
+                        // a ranged tombstone
+                        for (key, current) in latest.iter_mut() {
+                            if command.removes(key, self.collation()) {
+                                *current = None;
+                            }
+                        }
+                    }
+                    Err(err) => {
+                        // records have no framing, so there's no way to
+                        // find where the next one starts
+                        report.problems.push(format!(
+                            "record {} can't be decoded: {}",
+                            report.records + 1,
+                            err
+                        ));
+                        break;
+                    }
+                }
+                report.records += 1;
+            }
+        }
+
+        let index = self.full_index()?;
+        for (key, pointer) in index.iter() {
+            match latest.get(key) {
+                Some(Some((current, Some(blob)))) if current == pointer => {
+                    if !self.blobs.exists(blob) {
+                        report.problems.push(format!(
+                            "the blob holding '{}' is missing",
+                            key
+                        ));
+                    }
+                }
+                Some(Some((current, None))) if current == pointer => {
+                    if let Err(err) = self.get_key(pointer) {
+                        report.problems.push(format!(
+                            "the value of '{}' can't be read: {}",
+                            key, err
+                        ));
+                    }
+                }
+                Some(Some(_)) => report.problems.push(format!(
+                    "the index points at an old value of '{}'",
+                    key
+                )),
+                Some(None) | None => report.problems.push(format!(
+                    "the index has '{}', which isn't set in the log",
+                    key
+                )),
+            }
+        }
+        for (key, pointer) in &latest {
+            if pointer.is_some() && !index.contains_key(key) {
+                report.problems.push(format!(
+                    "the log sets '{}', which is missing from the index",
+                    key
+                ));
+            }
+        }
+
+        report.problems.sort();
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use core::tests::{DefaultTestContext, PersistentTestContext, TestContext};
+    use core::{ErrorKind, IntegrityLevel, KvStore, StoreOptions};
+
+    use crate::LogCommandPointer;
+
+    generate_scrubbable_tests!(LogKvs);
+
+    #[test]
+    fn index_disagreement() -> Result<()> {
+        let context = <DefaultTestContext as TestContext<LogKvs>>::init();
+        let mut store: LogKvs = context.open_store()?;
+
+        store.set("key1".to_owned(), "value1".to_owned())?;
+        store.set("key1".to_owned(), "value2".to_owned())?;
+        store.set("key2".to_owned(), "value3".to_owned())?;
+
+        store.index.remove("key2");
+        store.index.insert(
+            "key1".to_owned(),
+            LogCommandPointer::new(LogKvs::DEFAULT_LOG_ID, 0),
+        );
+
+        let report = store.scrub()?;
+        assert_eq!(report.records, 3);
+        assert_eq!(
+            report.problems,
+            vec![
+                "the index points at an old value of 'key1'".to_owned(),
+                "the log sets 'key2', which is missing from the index"
+                    .to_owned(),
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn paranoid_open() -> Result<()> {
+        let context = <DefaultTestContext as TestContext<LogKvs>>::init();
+        let options = |integrity| StoreOptions {
+            blob_threshold: Some(16),
+            dedup: true,
+            integrity,
+            ..StoreOptions::default()
+        };
+        let mut store: LogKvs =
+            context.open_store_with(options(IntegrityLevel::Paranoid))?;
+        store.set(
+            "key1".to_owned(),
+            "a value long enough for a blob".to_owned(),
+        )?;
+        store.set("key2".to_owned(), "value2".to_owned())?;
+        drop(store);
+        let store: LogKvs =
+            context.open_store_with(options(IntegrityLevel::Paranoid))?;
+        drop(store);
+
+        // a blob changed on disk is only caught by a paranoid open
+        let blobs = PersistentTestContext::<LogKvs>::get_path(&context)
+            .join(LogKvs::BLOB_DIR_NAME);
+        for entry in std::fs::read_dir(&blobs)? {
+            std::fs::write(entry?.path(), "a value changed on disk")?;
+        }
+        let store: LogKvs =
+            context.open_store_with(options(IntegrityLevel::Standard))?;
+        drop(store);
+        let err = TestContext::<LogKvs>::open_store_with(
+            &context,
+            options(IntegrityLevel::Paranoid),
+        )
+        .unwrap_err();
+        match err.kind() {
+            ErrorKind::CorruptDatabase { message: msg, .. } => {
+                assert!(msg.contains("doesn't match its name"), "{}", msg)
+            }
+            kind => panic!("unexpected error {:?}", kind),
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,331 @@
+/*!
+ * Sharing a store between one process that writes to it and any number that
+ * only read it.
+ */
+
+use std::collections::BTreeSet;
+use std::fs::{self, File, OpenOptions};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use core::{Error, Result};
+
+use crate::LogKvs;
+
+/// The stores this process has open for writing, by their canonical path,
+/// so a second writer in the same process is turned away too.
+static WRITERS: Mutex<BTreeSet<PathBuf>> = Mutex::new(BTreeSet::new());
+
+/// Held by the one handle allowed to write to a store. Released when
+/// dropped, or when the process exits, even if it crashes. The lock is
+/// taken with `flock`, which belongs to the open file rather than the
+/// process, so closing some other handle on the lock file doesn't release
+/// it, and stores open in this process are tracked as well.
+#[derive(Debug)]
+pub(crate) struct WriteLock {
+    _file: File,
+    dir: PathBuf,
+}
+
+impl WriteLock {
+    /// The name of the file locked in the store's directory.
+    const FILE_NAME: &'static str = "LOCK";
+
+    /// Take the lock for the store in the given directory, failing if
+    /// another handle, in this process or another, has it.
+    pub fn acquire(dir: &Path) -> Result<WriteLock> {
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(false)
+            .open(dir.join(Self::FILE_NAME))?;
+        let canonical = fs::canonicalize(dir)?;
+        {
+            let mut writers = WRITERS.lock().unwrap_or_else(|e| e.into_inner());
+            if !writers.insert(canonical.clone()) {
+                return Err(Error::locked(format!(
+                    "{} is already open for writing in this process",
+                    dir.display()
+                )));
+            }
+        }
+        let lock = WriteLock {
+            _file: file,
+            dir: canonical,
+        };
+        // dropping the lock on failure takes the store off the list again
+        Self::lock(&lock._file, dir)?;
+        Ok(lock)
+    }
+
+    #[cfg(unix)]
+    fn lock(file: &File, dir: &Path) -> Result<()> {
+        use std::os::unix::io::AsRawFd;
+
+        let locked = unsafe {
+            libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB)
+        };
+        if locked != -1 {
+            return Ok(());
+        }
+        let err = std::io::Error::last_os_error();
+        match err.raw_os_error() {
+            Some(libc::EWOULDBLOCK) => Err(Error::locked(format!(
+                "{} is already open for writing by another process, open it \
+                 read-only instead",
+                dir.display()
+            ))),
+            _ => Err(Error::io(err)),
+        }
+    }
+
+    // other platforms only keep out writers in the same process, relying
+    // on there being one writing process
+    #[cfg(not(unix))]
+    fn lock(_file: &File, _dir: &Path) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for WriteLock {
+    fn drop(&mut self) {
+        let mut writers = WRITERS.lock().unwrap_or_else(|e| e.into_inner());
+        writers.remove(&self.dir);
+    }
+}
+
+impl LogKvs {
+    /// The name of the file holding how many times the log has been
+    /// rewritten, doubled. It's odd while a rewrite is underway.
+    pub(crate) const GENERATION_FILE_NAME: &'static str = "GENERATION";
+    /// A generation that never matches the one on disk, since odd ones
+    /// aren't read, so a handle with it rebuilds its index on refresh.
+    pub(crate) const UNKNOWN_GENERATION: u64 = 1;
+
+    /// Read the store's generation. Returns None while the log is being
+    /// rewritten, since the generation is about to change.
+    pub(crate) fn read_generation(dir: &Path) -> Result<Option<u64>> {
+        let path = dir.join(Self::GENERATION_FILE_NAME);
+        if !path.is_file() {
+            return Ok(Some(0));
+        }
+        match fs::read_to_string(path)?.trim().parse::<u64>() {
+            Ok(generation) if generation % 2 == 0 => Ok(Some(generation)),
+            // either odd, or caught halfway through being written
+            _ => Ok(None),
+        }
+    }
+
+    /// The generation for a writer to carry on from. If the last writer
+    /// stopped partway through a rewrite, it's moved on to the next one.
+    pub(crate) fn writer_generation(dir: &Path) -> Result<u64> {
+        let path = dir.join(Self::GENERATION_FILE_NAME);
+        if !path.is_file() {
+            return Ok(0);
+        }
+        let generation: u64 =
+            fs::read_to_string(&path)?.trim().parse().map_err(|_| {
+                Error::corrupt_database(format!(
+                    "{} doesn't hold a generation",
+                    path.display()
+                ))
+            })?;
+        Ok(generation + generation % 2)
+    }
+
+    /// Record that the log is being rewritten, or has finished being
+    /// rewritten, so readers know their index is out of date.
+    pub(crate) fn bump_generation(&mut self) -> Result<()> {
+        self.generation += 1;
+        fs::write(
+            self.path.join(Self::GENERATION_FILE_NAME),
+            self.generation.to_string(),
+        )?;
+        Ok(())
+    }
+
+    /// Whether the store was opened read-only, see
+    /// [`StoreOptions::read_only`](core::StoreOptions::read_only).
+    pub fn is_read_only(&self) -> bool {
+        self.lock.is_none()
+    }
+
+    /// Catch up a read-only handle with records written since it was opened
+    /// or last refreshed. Cheap when nothing has changed, since only the
+    /// records added are read, unless the log has been compacted, in which
+    /// case the whole index is rebuilt. Does nothing for a handle that can
+    /// write, which is always up to date.
+    ///
+    /// ```rust
+    /// # use tempfile::TempDir;
+    /// # use core::{KvStore, Persistent, StoreOptions};
+    /// # use log_kvs::LogKvs;
+    /// #
+    /// # let temp_dir =
+    /// #     TempDir::new().expect("unable to create temporary working directory");
+    /// let mut writer = LogKvs::open(temp_dir.path()).unwrap();
+    /// let options = StoreOptions {
+    ///     read_only: true,
+    ///     ..StoreOptions::default()
+    /// };
+    /// let mut reader = LogKvs::open_with(temp_dir.path(), options).unwrap();
+    ///
+    /// writer.set("key1".to_owned(), "value1".to_owned()).unwrap();
+    /// assert_eq!(reader.get("key1".to_owned()).unwrap(), None);
+    /// reader.refresh().unwrap();
+    /// assert_eq!(
+    ///     reader.get("key1".to_owned()).unwrap(),
+    ///     Some("value1".to_owned())
+    /// );
+    /// ```
+    pub fn refresh(&mut self) -> Result<()> {
+        if !self.is_read_only() {
+            return Ok(());
+        }
+        let generation = match Self::read_generation(&self.path)? {
+            Some(generation) => generation,
+            // a compaction is underway, catch up once it's finished
+            None => return Ok(()),
+        };
+        if generation == self.generation {
+            self.replay_from(self.indexed_to)?;
+        } else {
+            self.index.clear();
+            self.replay_from(0)?;
+        }
+        // if a compaction started while catching up, the records read may
+        // have come from either log, so throw them away and start over
+        // next time
+        if Self::read_generation(&self.path)? == Some(generation) {
+            self.generation = generation;
+        } else {
+            self.index.clear();
+            self.indexed_to = 0;
+            self.generation = Self::UNKNOWN_GENERATION;
+        }
+        Ok(())
+    }
+
+    /// Replay the records from the given offset to the end of the log into
+    /// the index. Stops early at a record that can't be read, since the
+    /// writer may not have finished writing it.
+    fn replay_from(&mut self, offset: u64) -> Result<()> {
+        self.indexed_to = offset;
+        if !self.log.exists() {
+            return Ok(());
+        }
+        let mut records = self.log.iter_from(offset)?;
+        while let Some(Ok((command, pointer))) = records.next() {
+            self.replay(command, pointer)?;
+            self.indexed_to = records.pos();
+        }
+        Ok(())
+    }
+
+    /// Fail unless the store can be written to.
+    pub(crate) fn check_writable(&self) -> Result<()> {
+        if self.is_read_only() {
+            Err(Error::config(format!(
+                "{} was opened read-only",
+                self.path.display()
+            )))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Fail if the log has been rewritten since a read-only handle's index
+    /// was built, since its pointers, and any value just read with them,
+    /// may be wrong.
+    pub(crate) fn check_generation(&self) -> Result<()> {
+        if !self.is_read_only()
+            || Self::read_generation(&self.path)? == Some(self.generation)
+        {
+            Ok(())
+        } else {
+            Err(Error::config(format!(
+                "{} has been compacted, refresh it before reading",
+                self.path.display()
+            )))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::tests::{DefaultTestContext, PersistentTestContext, TestContext};
+    use core::{
+        Compactable, KvStore, Measurable, Result, Scannable, Scrubbable,
+        StoreOptions,
+    };
+
+    use crate::LogKvs;
+
+    fn read_only() -> StoreOptions {
+        StoreOptions {
+            read_only: true,
+            ..StoreOptions::default()
+        }
+    }
+
+    #[test]
+    fn readers_cant_write() -> Result<()> {
+        let context: DefaultTestContext = TestContext::<LogKvs>::init();
+        let mut writer: LogKvs = context.open_store()?;
+        writer.set("key1".to_owned(), "value1".to_owned())?;
+
+        let mut reader: LogKvs = context.open_store_with(read_only())?;
+        assert!(reader.is_read_only());
+        assert!(reader.set("key2".to_owned(), "value2".to_owned()).is_err());
+        assert!(reader.remove("key1".to_owned()).is_err());
+        assert!(reader.compact().is_err());
+        assert_eq!(reader.get("key1".to_owned())?, Some("value1".to_owned()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn one_writer() -> Result<()> {
+        let context: DefaultTestContext = TestContext::<LogKvs>::init();
+        let path = PersistentTestContext::<LogKvs>::get_path(&context).clone();
+        let writer: LogKvs = context.open_store()?;
+
+        // kept out in the same process too, and repair failing to take the
+        // lock doesn't release it
+        assert!(TestContext::<LogKvs>::open_store(&context).is_err());
+        assert!(LogKvs::repair(&path).is_err());
+        assert!(TestContext::<LogKvs>::open_store(&context).is_err());
+
+        drop(writer);
+        let _writer: LogKvs = context.open_store()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn refresh() -> Result<()> {
+        let context: DefaultTestContext = TestContext::<LogKvs>::init();
+        let mut writer: LogKvs = context.open_store()?;
+        writer.set("key1".to_owned(), "value1".to_owned())?;
+        let mut reader: LogKvs = context.open_store_with(read_only())?;
+        assert_eq!(reader.get("key1".to_owned())?, Some("value1".to_owned()));
+
+        writer.set("key2".to_owned(), "value2".to_owned())?;
+        writer.remove("key1".to_owned())?;
+        reader.refresh()?;
+        assert_eq!(reader.get("key1".to_owned())?, None);
+        assert_eq!(reader.get("key2".to_owned())?, Some("value2".to_owned()));
+
+        writer.compact()?;
+        // the old pointers can't be trusted after a compaction
+        assert!(reader.get("key2".to_owned()).is_err());
+        assert!(reader.stats().is_err());
+        assert!(reader.scrub().is_err());
+        assert!(reader.events().is_err());
+        assert!(reader.scan_filtered("", None, &|_, _| true).is_err());
+        reader.refresh()?;
+        assert_eq!(reader.get("key2".to_owned())?, Some("value2".to_owned()));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,125 @@
+use std::collections::HashSet;
+
+use core::{Measurable, Result, StoreStats, TRASH_PREFIX};
+
+use crate::{Command, LogHeader, LogKvs};
+
+impl Measurable for LogKvs {
+    /// Gather statistics about the store. Reads every record in the log to
+    /// count the stale ones, and the space they and their blobs take up.
+    ///
+    /// ```rust
+    /// # use tempfile::TempDir;
+    /// # use core::{KvStore, Measurable, Persistent};
+    /// # use log_kvs::LogKvs;
+    /// #
+    /// # let temp_dir =
+    /// #     TempDir::new().expect("unable to create temporary working directory");
+    /// # let mut store = LogKvs::open(temp_dir.path()).unwrap();
+    /// store.set("key1".to_owned(), "value1".to_owned());
+    /// store.set("key1".to_owned(), "value2".to_owned());
+    /// assert_eq!(store.stats().unwrap().stale_records, 1);
+    /// ```
+    fn stats(&self) -> Result<StoreStats> {
+        if !self.log.exists() {
+            return Ok(StoreStats::default());
+        }
+        // the index is compared with the records it points at
+        self.check_generation()?;
+
+        let index = self.full_index()?;
+        let mut records: u64 = 0;
+        // the header is needed as long as the log is
+        let mut live_bytes = self.log.size()?.min(LogHeader::LEN);
+        // deduplicated blobs can be shared by several keys
+        let mut live_blobs = HashSet::new();
+        // a record's size is only known once the next one is reached
+        let mut live_start = None;
+        for record in self.log.iter()? {
+            let (command, pointer) = record?;
+            if let Some(start) = live_start.take() {
+                live_bytes += pointer.offset() - start;
+            }
+            match command {
+                Command::Set { key, .. } | Command::SetDelta { key, .. } => {
+                    if index.get(&key) == Some(&pointer) {
+                        live_start = Some(pointer.offset());
+                    }
+                }
+                Command::SetBlob { key, blob } => {
+                    if index.get(&key) == Some(&pointer) {
+                        live_start = Some(pointer.offset());
+                        live_blobs.insert(blob);
+                    }
+                }
+                Command::Remove { .. }
+                | Command::RemovePrefix { .. }
+                | Command::RemoveRange { .. } => {}
+            }
+            records += 1;
+        }
+
+        let log_bytes = self.log.size()?;
+        if let Some(start) = live_start {
+            live_bytes += log_bytes - start;
+        }
+        let mut blob_bytes = 0;
+        let mut live_blob_bytes = 0;
+        for blob in self.blobs.names()? {
+            let size = self.blobs.size(&blob)?;
+            blob_bytes += size;
+            if live_blobs.contains(&blob) {
+                live_blob_bytes += size;
+            }
+        }
+
+        // removed values in the trash are live records, but not keys
+        let indexed = index.len() as u64;
+        let trash = index.keys_with_prefix(TRASH_PREFIX).len() as u64;
+        Ok(StoreStats {
+            keys: indexed - trash,
+            // a compaction partway through could leave these out of step
+            stale_records: records.saturating_sub(indexed),
+            stale_bytes: log_bytes.saturating_sub(live_bytes)
+                + blob_bytes.saturating_sub(live_blob_bytes),
+            disk_bytes: log_bytes + blob_bytes,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use core::tests::{DefaultTestContext, TestContext};
+    use core::{Compactable, KvStore};
+
+    generate_measurable_tests!(LogKvs);
+
+    #[test]
+    fn stale_records() -> Result<()> {
+        let context = <DefaultTestContext as TestContext<LogKvs>>::init();
+        let mut store: LogKvs = context.open_store()?;
+
+        store.set("key1".to_owned(), "value1".to_owned())?;
+        store.set("key1".to_owned(), "value2".to_owned())?;
+        store.set("key2".to_owned(), "value3".to_owned())?;
+        store.remove("key2".to_owned())?;
+
+        let stats = store.stats()?;
+        assert_eq!(stats.keys, 1);
+        // the overwritten set, the removed set and the removal itself
+        assert_eq!(stats.stale_records, 3);
+
+        // compaction should reclaim exactly the stale bytes
+        store.compact()?;
+        drop(store);
+        let store: LogKvs = context.open_store()?;
+        let compacted = store.stats()?;
+        assert_eq!(compacted.stale_records, 0);
+        assert_eq!(compacted.stale_bytes, 0);
+        assert_eq!(compacted.disk_bytes, stats.disk_bytes - stats.stale_bytes);
+
+        Ok(())
+    }
+}
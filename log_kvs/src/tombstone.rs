@@ -0,0 +1,210 @@
+/*!
+ * Removing every key in a range or under a prefix with a single ranged
+ * tombstone, instead of one removal per key. The tombstone is applied to
+ * the index as it's replayed, and dropped with the values it removes when
+ * the store is compacted.
+ */
+
+use core::{observe, Result};
+
+use crate::{Command, LogKvs};
+
+impl LogKvs {
+    /// Remove every key starting with the prefix, returning how many were
+    /// removed. Only one record is written, however many keys there are,
+    /// and the values it removes stay on disk until the store is compacted.
+    /// The values aren't kept in the trash, even with
+    /// `StoreOptions::trash_retention`.
+    ///
+    /// ```rust
+    /// # use tempfile::TempDir;
+    /// # use core::{KvStore, Persistent};
+    /// # use log_kvs::LogKvs;
+    /// #
+    /// # let temp_dir =
+    /// #     TempDir::new().expect("unable to create temporary working directory");
+    /// let mut store = LogKvs::open(temp_dir.path()).unwrap();
+    /// store.set("session:1".to_owned(), "value1".to_owned()).unwrap();
+    /// store.set("session:2".to_owned(), "value2".to_owned()).unwrap();
+    /// store.set("user:1".to_owned(), "value3".to_owned()).unwrap();
+    ///
+    /// assert_eq!(store.drop_prefix("session:").unwrap(), 2);
+    /// assert_eq!(store.get("session:1".to_owned()).unwrap(), None);
+    /// ```
+    pub fn drop_prefix(&mut self, prefix: &str) -> Result<u64> {
+        let command = Command::RemovePrefix {
+            prefix: prefix.to_owned(),
+        };
+        let result = self.write_tombstone(command);
+        observe(&self.observer, "drop_prefix", result, |observer, keys| {
+            for key in keys {
+                observer.on_remove(key);
+            }
+        })
+        .map(|keys| keys.len() as u64)
+    }
+
+    /// Remove every key from `start` up to but not including `end`, or up
+    /// to the last key if there's no end, in the store's collation,
+    /// returning how many were removed. Like [`drop_prefix`], only one
+    /// record is written.
+    ///
+    /// [`drop_prefix`]: LogKvs::drop_prefix
+    pub fn remove_range(
+        &mut self,
+        start: &str,
+        end: Option<&str>,
+    ) -> Result<u64> {
+        let command = Command::RemoveRange {
+            start: start.to_owned(),
+            end: end.map(str::to_owned),
+        };
+        let result = self.write_tombstone(command);
+        observe(&self.observer, "remove_range", result, |observer, keys| {
+            for key in keys {
+                observer.on_remove(key);
+            }
+        })
+        .map(|keys| keys.len() as u64)
+    }
+
+    /// Write a ranged tombstone and drop the keys it covers from the index,
+    /// returning them. Nothing is written if it doesn't cover any.
+    fn write_tombstone(&mut self, command: Command) -> Result<Vec<String>> {
+        self.check_writable()?;
+        // keys only in the part of the log that isn't indexed yet have to
+        // be found too
+        self.finish_backfill()?;
+        let keys = self.index.removed_by(&command).unwrap_or_default();
+        if keys.is_empty() {
+            return Ok(keys);
+        }
+        self.log.append(command)?;
+        for key in &keys {
+            self.index.remove(key);
+        }
+        self.count_write();
+        Ok(keys)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use core::tests::{DefaultTestContext, PersistentTestContext, TestContext};
+    use core::{
+        Collation, Compactable, IndexKind, KvStore, Scannable, Scrubbable,
+        StoreOptions,
+    };
+
+    use crate::LogOp;
+
+    #[test]
+    fn dropped_prefix() -> Result<()> {
+        let context = <DefaultTestContext as TestContext<LogKvs>>::init();
+        let mut store: LogKvs = context.open_store()?;
+        for i in 0..10 {
+            store.set(format!("session:{}", i), "value".to_owned())?;
+        }
+        store.set("sessions".to_owned(), "value".to_owned())?;
+        let records = store.log.iter()?.count();
+
+        assert_eq!(store.drop_prefix("session:")?, 10);
+        assert_eq!(store.log.iter()?.count(), records + 1);
+        assert_eq!(store.drop_prefix("session:")?, 0);
+        assert_eq!(store.log.iter()?.count(), records + 1);
+        store.set("session:3".to_owned(), "again".to_owned())?;
+        assert_eq!(store.keys()?.len(), 2);
+        drop(store);
+
+        // replayed when reopened, without bringing back the dropped keys or
+        // dropping the ones set after
+        let mut store: LogKvs = context.open_store()?;
+        assert_eq!(store.get("session:1".to_owned())?, None);
+        assert_eq!(store.get("session:3".to_owned())?, Some("again".into()));
+        assert_eq!(store.get("sessions".to_owned())?, Some("value".into()));
+
+        store.compact()?;
+        assert_eq!(store.log.iter()?.count(), 2);
+        assert_eq!(store.keys()?.len(), 2);
+
+        Ok(())
+    }
+
+    #[test]
+    fn dropped_prefix_backfilled() -> Result<()> {
+        let context = <DefaultTestContext as TestContext<LogKvs>>::init();
+        let options = StoreOptions {
+            index: IndexKind::Ordered,
+            ..StoreOptions::default()
+        };
+        let mut store: LogKvs = context.open_store_with(options)?;
+        store.set("a:1".to_owned(), "value".to_owned())?;
+        store.set("a:2".to_owned(), "value".to_owned())?;
+        store.drop_prefix("a:")?;
+        store.set("a:3".to_owned(), "value".to_owned())?;
+        drop(store);
+
+        let lazy = StoreOptions {
+            index: IndexKind::Ordered,
+            lazy_open: true,
+            ..StoreOptions::default()
+        };
+        let mut store: LogKvs = context.open_store_with(lazy)?;
+        assert!(!store.is_backfilled());
+        assert_eq!(store.get("a:1".to_owned())?, None);
+        assert_eq!(store.scan("a:", None)?, vec!["a:3"]);
+        store.set("a:1".to_owned(), "new".to_owned())?;
+        while !store.backfill(1)? {}
+        assert_eq!(store.scan("a:", None)?, vec!["a:1", "a:3"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn removed_range() -> Result<()> {
+        let context = <DefaultTestContext as TestContext<LogKvs>>::init();
+        let options = StoreOptions {
+            collation: Collation::Numeric,
+            ..StoreOptions::default()
+        };
+        let mut store: LogKvs = context.open_store_with(options)?;
+        for i in 1..=12 {
+            store.set(format!("shard{}", i), "value".to_owned())?;
+        }
+
+        // in the store's collation, even with a hash index
+        assert_eq!(store.remove_range("shard2", Some("shard10"))?, 8);
+        assert_eq!(store.remove_range("shard2", Some("shard10"))?, 0);
+        store.set("shard5".to_owned(), "moved back".to_owned())?;
+        let mut keys = store.keys()?;
+        keys.sort();
+        assert_eq!(
+            keys,
+            vec!["shard1", "shard10", "shard11", "shard12", "shard5"]
+        );
+        let events: Vec<LogOp> = store
+            .events()?
+            .map(|event| event.map(|event| event.op))
+            .collect::<Result<_>>()?;
+        assert!(events.contains(&LogOp::RemoveRange));
+        drop(store);
+
+        let mut store: LogKvs = context.open_store()?;
+        assert_eq!(store.keys()?.len(), 5);
+        // shard5 sorts before shard11 numerically
+        assert_eq!(store.remove_range("shard11", None)?, 2);
+        assert!(store.scrub()?.problems.is_empty());
+        drop(store);
+
+        // repairs and compactions leave the removed keys out
+        let path = PersistentTestContext::<LogKvs>::get_path(&context);
+        assert_eq!(LogKvs::repair(path)?.keys, 3);
+        let mut store: LogKvs = context.open_store()?;
+        store.compact()?;
+        assert_eq!(store.keys()?.len(), 3);
+
+        Ok(())
+    }
+}
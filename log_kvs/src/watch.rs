@@ -0,0 +1,262 @@
+/*!
+ * Keeping read-only handles up to date as another process writes.
+ */
+
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::sync::{Arc, RwLock};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::LogKvs;
+
+/// Refreshes a shared read-only handle on a background thread whenever the
+/// store's files change, so new records are seen within milliseconds of
+/// being written. On Linux the store's directory is watched with inotify;
+/// elsewhere, and as a fallback, the handle is refreshed every `interval`.
+/// Stops when dropped.
+///
+/// ```rust
+/// # use std::sync::{Arc, RwLock};
+/// # use std::time::Duration;
+/// # use tempfile::TempDir;
+/// # use core::{KvStore, Persistent, StoreOptions};
+/// # use log_kvs::{LogKvs, Refresher};
+/// #
+/// # let temp_dir =
+/// #     TempDir::new().expect("unable to create temporary working directory");
+/// let mut writer = LogKvs::open(temp_dir.path()).unwrap();
+/// let options = StoreOptions {
+///     read_only: true,
+///     ..StoreOptions::default()
+/// };
+/// let reader = LogKvs::open_with(temp_dir.path(), options).unwrap();
+/// let reader = Arc::new(RwLock::new(reader));
+/// let _refresher =
+///     Refresher::spawn(reader.clone(), Duration::from_millis(100));
+///
+/// writer.set("key1".to_owned(), "value1".to_owned()).unwrap();
+/// # std::thread::sleep(Duration::from_millis(300));
+/// // shortly after
+/// assert_eq!(
+///     reader.read().unwrap().get("key1".to_owned()).unwrap(),
+///     Some("value1".to_owned())
+/// );
+/// ```
+pub struct Refresher {
+    stop: Option<Sender<()>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Refresher {
+    /// How often the thread checks whether it's been stopped.
+    const STOP_CHECK: Duration = Duration::from_millis(50);
+
+    /// Start refreshing the handle as the store changes, and at least every
+    /// `interval`. Only takes a write lock while refreshing.
+    pub fn spawn(store: Arc<RwLock<LogKvs>>, interval: Duration) -> Refresher {
+        let (stop, stopped) = mpsc::channel();
+        // watching starts before returning, so no change made after is
+        // missed
+        let mut watch = match store.read() {
+            Ok(store) => Watch::new(&store.path),
+            Err(poisoned) => Watch::new(&poisoned.into_inner().path),
+        };
+
+        let handle = thread::spawn(move || {
+            loop {
+                if !wait(&mut watch, interval, &stopped) {
+                    return;
+                }
+                match store.write() {
+                    // errors are left for the next refresh to retry, and
+                    // for reads to report
+                    Ok(mut store) => {
+                        let _ = store.refresh();
+                    }
+                    // a reader panicked, so the handle can't be trusted
+                    Err(_) => return,
+                }
+            }
+        });
+
+        Refresher {
+            stop: Some(stop),
+            handle: Some(handle),
+        }
+    }
+}
+
+/// Wait until the store changes or `interval` passes. Returns false once
+/// stopped.
+fn wait(
+    watch: &mut Option<Watch>,
+    interval: Duration,
+    stopped: &Receiver<()>,
+) -> bool {
+    let start = Instant::now();
+    loop {
+        match stopped.try_recv() {
+            Err(TryRecvError::Empty) => {}
+            _ => return false,
+        }
+        let elapsed = start.elapsed();
+        if elapsed >= interval {
+            return true;
+        }
+        let slice = std::cmp::min(interval - elapsed, Refresher::STOP_CHECK);
+        match watch {
+            Some(inner) => match inner.changed_within(slice) {
+                Ok(true) => return true,
+                Ok(false) => {}
+                // fall back to refreshing every interval
+                Err(_) => *watch = None,
+            },
+            None => thread::sleep(slice),
+        }
+    }
+}
+
+impl Drop for Refresher {
+    fn drop(&mut self) {
+        // dropping the sender tells the thread to stop when it next checks
+        self.stop.take();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// Watches a directory for files being written, created or replaced.
+#[cfg(target_os = "linux")]
+struct Watch {
+    fd: libc::c_int,
+}
+
+#[cfg(target_os = "linux")]
+impl Watch {
+    /// Start watching, or None if inotify isn't available.
+    fn new(dir: &std::path::Path) -> Option<Watch> {
+        use std::ffi::CString;
+        use std::os::unix::ffi::OsStrExt;
+
+        let dir = CString::new(dir.as_os_str().as_bytes()).ok()?;
+        let fd = unsafe {
+            libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC)
+        };
+        if fd == -1 {
+            return None;
+        }
+        let watch = Watch { fd };
+        let mask = libc::IN_MODIFY | libc::IN_CREATE | libc::IN_MOVED_TO;
+        if unsafe { libc::inotify_add_watch(fd, dir.as_ptr(), mask) } == -1 {
+            return None;
+        }
+        Some(watch)
+    }
+
+    /// Wait up to `timeout` for a change, returning whether there was one.
+    fn changed_within(&mut self, timeout: Duration) -> std::io::Result<bool> {
+        let mut poll = libc::pollfd {
+            fd: self.fd,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let ready =
+            unsafe { libc::poll(&mut poll, 1, timeout.as_millis() as i32) };
+        if ready == -1 {
+            return Err(std::io::Error::last_os_error());
+        }
+        if ready == 0 {
+            return Ok(false);
+        }
+        // drain the events, since any change means refreshing
+        let mut buf = [0u8; 4096];
+        while unsafe {
+            libc::read(self.fd, buf.as_mut_ptr() as *mut _, buf.len())
+        } > 0
+        {}
+        Ok(true)
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for Watch {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.fd);
+        }
+    }
+}
+
+/// Elsewhere there's nothing to watch with, so changes are found by
+/// refreshing every interval.
+#[cfg(not(target_os = "linux"))]
+struct Watch;
+
+#[cfg(not(target_os = "linux"))]
+impl Watch {
+    fn new(_dir: &std::path::Path) -> Option<Watch> {
+        None
+    }
+
+    fn changed_within(&mut self, _timeout: Duration) -> std::io::Result<bool> {
+        Ok(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use core::tests::{DefaultTestContext, TestContext};
+    use core::{Compactable, KvStore, Result, StoreOptions};
+
+    /// Read a key until it has the value, or a second has passed.
+    fn eventually(
+        store: &RwLock<LogKvs>,
+        key: &str,
+        value: Option<&str>,
+    ) -> Result<bool> {
+        let start = Instant::now();
+        while start.elapsed() < Duration::from_secs(1) {
+            let found = store.read().unwrap().get(key.to_owned());
+            if let Ok(found) = found {
+                if found.as_deref() == value {
+                    return Ok(true);
+                }
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+        Ok(false)
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn refresh_on_change() -> Result<()> {
+        let context: DefaultTestContext = TestContext::<LogKvs>::init();
+        let mut writer: LogKvs = context.open_store()?;
+        writer.set("key1".to_owned(), "value1".to_owned())?;
+        let options = StoreOptions {
+            read_only: true,
+            ..StoreOptions::default()
+        };
+        let reader = Arc::new(RwLock::new(context.open_store_with(options)?));
+        // long enough that only watching could pick the changes up in time
+        let refresher =
+            Refresher::spawn(reader.clone(), Duration::from_secs(60));
+
+        writer.set("key2".to_owned(), "value2".to_owned())?;
+        assert!(eventually(&reader, "key2", Some("value2"))?);
+        writer.remove("key1".to_owned())?;
+        assert!(eventually(&reader, "key1", None)?);
+        writer.compact()?;
+        assert!(eventually(&reader, "key2", Some("value2"))?);
+
+        drop(refresher);
+        writer.set("key3".to_owned(), "value3".to_owned())?;
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(reader.read().unwrap().get("key3".to_owned())?, None);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,201 @@
+use std::borrow::Cow;
+use std::io::Read;
+use std::path::Path;
+
+use core::{
+    Capability, Collation, KvStore, Measurable, Persistent, RangeEstimate,
+    Result, ScanOptions, Scannable, ScrubReport, Scrubbable, StoreStats,
+};
+
+use crate::Engine;
+
+/// Any of the enabled engines, dispatched with a `match` instead of a
+/// vtable.
+///
+/// Prefer this to `Box<dyn KvStore>` when the store is called in a hot loop;
+/// see `benches/dispatch.rs` for the difference.
+// boxing the log store would add back the indirection this is here to avoid
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug)]
+pub enum AnyKvs {
+    /// A [`HashMapKvs`](crate::HashMapKvs).
+    #[cfg(feature = "hashmap")]
+    HashMap(crate::HashMapKvs),
+    /// A [`LogKvs`](crate::LogKvs).
+    #[cfg(feature = "log")]
+    Log(crate::LogKvs),
+}
+
+/// Forwards a call to whichever engine is wrapped.
+macro_rules! dispatch {
+    ($self:expr, $store:ident => $call:expr) => {
+        match $self {
+            #[cfg(feature = "hashmap")]
+            AnyKvs::HashMap($store) => $call,
+            #[cfg(feature = "log")]
+            AnyKvs::Log($store) => $call,
+        }
+    };
+}
+
+impl AnyKvs {
+    /// The engine being wrapped.
+    pub fn engine(&self) -> Engine {
+        match self {
+            #[cfg(feature = "hashmap")]
+            AnyKvs::HashMap(_) => Engine::HashMap,
+            #[cfg(feature = "log")]
+            AnyKvs::Log(_) => Engine::Log,
+        }
+    }
+
+    /// Whether the wrapped store supports the given operation.
+    pub fn supports(&self, capability: Capability) -> bool {
+        match (self, capability) {
+            #[cfg(feature = "log")]
+            (AnyKvs::Log(store), Capability::OrderedScan) => {
+                store.index_kind() == core::IndexKind::Ordered
+            }
+            _ => self.engine().supports(capability),
+        }
+    }
+
+    /// Write an independent copy of the store to the given path, which
+    /// mustn't exist yet. See [`Persistent::fork_to`].
+    pub fn fork_to<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        dispatch!(self, store => store.fork_to(path))
+    }
+
+    /// Compact the store, or return an `Unsupported` error if the engine
+    /// can't be compacted.
+    pub fn compact(&mut self) -> Result<()> {
+        match self {
+            #[cfg(feature = "log")]
+            AnyKvs::Log(store) => core::Compactable::compact(store),
+            #[allow(unreachable_patterns)]
+            _ => Err(core::Error::unsupported(Capability::Compaction)),
+        }
+    }
+
+    /// Remove every key starting with the prefix, returning how many were
+    /// removed. The log engine writes a single ranged tombstone, see
+    /// [`LogKvs::drop_prefix`](crate::LogKvs::drop_prefix), and other
+    /// engines remove the keys one at a time.
+    pub fn drop_prefix(&mut self, prefix: &str) -> Result<u64> {
+        match self {
+            #[cfg(feature = "log")]
+            AnyKvs::Log(store) => store.drop_prefix(prefix),
+            #[allow(unreachable_patterns)]
+            _ => {
+                let mut removed = 0;
+                for key in self.keys()? {
+                    if key.starts_with(prefix) {
+                        self.remove(key)?;
+                        removed += 1;
+                    }
+                }
+                Ok(removed)
+            }
+        }
+    }
+}
+
+impl KvStore for AnyKvs {
+    fn set(&mut self, key: String, value: String) -> Result<()> {
+        dispatch!(self, store => store.set(key, value))
+    }
+
+    fn set_from_reader(
+        &mut self,
+        key: String,
+        value: &mut dyn Read,
+    ) -> Result<()> {
+        dispatch!(self, store => store.set_from_reader(key, value))
+    }
+
+    fn get(&self, key: String) -> Result<Option<String>> {
+        dispatch!(self, store => store.get(key))
+    }
+
+    fn get_ref(&self, key: &str) -> Result<Option<Cow<'_, str>>> {
+        dispatch!(self, store => store.get_ref(key))
+    }
+
+    fn contains_key(&self, key: &str) -> Result<bool> {
+        dispatch!(self, store => store.contains_key(key))
+    }
+
+    fn remove(&mut self, key: String) -> Result<Option<String>> {
+        dispatch!(self, store => store.remove(key))
+    }
+
+    fn remove_ref(&mut self, key: &str) -> Result<Option<String>> {
+        dispatch!(self, store => store.remove_ref(key))
+    }
+}
+
+impl Measurable for AnyKvs {
+    fn stats(&self) -> Result<StoreStats> {
+        dispatch!(self, store => store.stats())
+    }
+}
+
+impl Scrubbable for AnyKvs {
+    fn scrub(&self) -> Result<ScrubReport> {
+        dispatch!(self, store => store.scrub())
+    }
+}
+
+impl Scannable for AnyKvs {
+    fn keys(&self) -> Result<Vec<String>> {
+        dispatch!(self, store => store.keys())
+    }
+
+    fn scan(&self, start: &str, end: Option<&str>) -> Result<Vec<String>> {
+        dispatch!(self, store => store.scan(start, end))
+    }
+
+    fn scan_with(
+        &self,
+        start: &str,
+        end: Option<&str>,
+        options: ScanOptions,
+    ) -> Result<Vec<String>> {
+        dispatch!(self, store => store.scan_with(start, end, options))
+    }
+
+    fn estimate_range_size(
+        &self,
+        start: &str,
+        end: Option<&str>,
+    ) -> Result<RangeEstimate> {
+        dispatch!(self, store => store.estimate_range_size(start, end))
+    }
+
+    fn scan_filtered(
+        &self,
+        start: &str,
+        end: Option<&str>,
+        predicate: &dyn Fn(&str, &str) -> bool,
+    ) -> Result<Vec<(String, String)>> {
+        dispatch!(self, store => store.scan_filtered(start, end, predicate))
+    }
+
+    fn collation(&self) -> Collation {
+        dispatch!(self, store => store.collation())
+    }
+}
+
+#[cfg(feature = "hashmap")]
+impl From<crate::HashMapKvs> for AnyKvs {
+    fn from(store: crate::HashMapKvs) -> AnyKvs {
+        AnyKvs::HashMap(store)
+    }
+}
+
+#[cfg(feature = "log")]
+impl From<crate::LogKvs> for AnyKvs {
+    fn from(store: crate::LogKvs) -> AnyKvs {
+        AnyKvs::Log(store)
+    }
+}
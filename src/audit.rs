@@ -0,0 +1,138 @@
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use core::{Error, Result};
+
+/// An append-only record of administrative operations on a store, such as
+/// compactions, imports and restores. It's kept next to the store in
+/// `<store>.audit`, so it survives the store being replaced by a restore.
+///
+/// Each operation is a line of tab separated fields: the time, the
+/// principal that started it, the operation and any detail.
+#[derive(Clone, Debug)]
+pub struct AuditLog {
+    path: PathBuf,
+}
+
+/// An operation recorded in an [`AuditLog`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AuditEntry {
+    /// When it happened, in seconds since the Unix epoch.
+    pub time: u64,
+    /// Who started it, such as a local user name.
+    pub principal: String,
+    /// What was done, such as `compact` or `restore`.
+    pub operation: String,
+    /// Anything else worth knowing, such as where a store was restored
+    /// from. Can be empty.
+    pub detail: String,
+}
+
+impl AuditLog {
+    /// The audit log of the store at the given path.
+    pub fn for_store<P: AsRef<Path>>(path: P) -> AuditLog {
+        let mut audit = path.as_ref().as_os_str().to_owned();
+        audit.push(".audit");
+        AuditLog {
+            path: PathBuf::from(audit),
+        }
+    }
+
+    /// The name of the user running this process, as the principal for
+    /// operations started locally. Falls back to `unknown`.
+    pub fn local_principal() -> String {
+        std::env::var("USER")
+            .or_else(|_| std::env::var("USERNAME"))
+            .unwrap_or_else(|_| "unknown".to_owned())
+    }
+
+    /// Append an operation to the log, returning the entry written. Tabs
+    /// and line breaks in the fields are replaced with spaces.
+    pub fn record(
+        &self,
+        principal: &str,
+        operation: &str,
+        detail: &str,
+    ) -> Result<AuditEntry> {
+        let clean = |field: &str| field.replace(&['\t', '\r', '\n'][..], " ");
+        let entry = AuditEntry {
+            time: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |since| since.as_secs()),
+            principal: clean(principal),
+            operation: clean(operation),
+            detail: clean(detail),
+        };
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(
+            file,
+            "{}\t{}\t{}\t{}",
+            entry.time, entry.principal, entry.operation, entry.detail
+        )?;
+        file.sync_all()?;
+        Ok(entry)
+    }
+
+    /// Every operation recorded, oldest first.
+    pub fn entries(&self) -> Result<Vec<AuditEntry>> {
+        if !self.path.is_file() {
+            return Ok(Vec::new());
+        }
+
+        let mut entries = Vec::new();
+        for line in BufReader::new(File::open(&self.path)?).lines() {
+            let line = line?;
+            let fields: Vec<&str> = line.splitn(4, '\t').collect();
+            let entry = match fields.as_slice() {
+                [time, principal, operation, detail] => {
+                    time.parse().ok().map(|time| AuditEntry {
+                        time,
+                        principal: (*principal).to_owned(),
+                        operation: (*operation).to_owned(),
+                        detail: (*detail).to_owned(),
+                    })
+                }
+                _ => None,
+            };
+            entries.push(entry.ok_or_else(|| {
+                Error::serialization(format!(
+                    "invalid audit log line `{}`",
+                    line
+                ))
+            })?);
+        }
+        Ok(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tempfile::TempDir;
+
+    #[test]
+    fn record_and_read() -> Result<()> {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+        let audit = AuditLog::for_store(temp_dir.path().join("kvs"));
+        assert_eq!(audit.entries()?, vec![]);
+        assert!(!temp_dir.path().join("kvs.audit").exists());
+
+        let compact = audit.record("alice", "compact", "")?;
+        let restore = audit.record("bob", "restore", "from\tbackup\n")?;
+        assert_eq!(restore.detail, "from backup ");
+        assert_eq!(audit.entries()?, vec![compact, restore]);
+
+        std::fs::write(temp_dir.path().join("kvs.audit"), "not\tan entry\n")?;
+        assert!(audit.entries().is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,633 @@
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+
+use core::{fail_point, Capability, Error, Result};
+
+use crate::AnyKvs;
+
+/// The size and SHA-256 digest of every file in a backup or export, so it
+/// can be checked before it's used.
+///
+/// Saved as text, one `<sha256> <size> <path>` line per file, with paths
+/// relative to the manifest's directory.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Manifest {
+    /// The files covered, sorted by path.
+    pub entries: Vec<ManifestEntry>,
+}
+
+/// A file covered by a [`Manifest`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ManifestEntry {
+    /// Where the file is, relative to the manifest's directory.
+    pub path: PathBuf,
+    /// The size of the file in bytes.
+    pub size: u64,
+    /// The SHA-256 digest of the file, in lowercase hex.
+    pub sha256: String,
+}
+
+impl Manifest {
+    /// The name of the manifest inside a backup directory.
+    pub const FILE_NAME: &'static str = "MANIFEST";
+
+    /// Where the manifest of an exported file is kept, next to it.
+    pub fn path_for_file<P: AsRef<Path>>(file: P) -> PathBuf {
+        let mut path = file.as_ref().as_os_str().to_owned();
+        path.push(".manifest");
+        PathBuf::from(path)
+    }
+
+    /// Describe a single file, relative to its directory.
+    pub fn of_file<P: AsRef<Path>>(file: P) -> Result<Manifest> {
+        let file = file.as_ref();
+        let name = file.file_name().ok_or_else(|| {
+            Error::config(format!("{} isn't a file", file.display()))
+        })?;
+        Ok(Manifest {
+            entries: vec![ManifestEntry::of(file, PathBuf::from(name))?],
+        })
+    }
+
+    /// Describe every file under a directory, except a manifest at its top.
+    pub fn of_dir<P: AsRef<Path>>(dir: P) -> Result<Manifest> {
+        let dir = dir.as_ref();
+        let mut entries = Vec::new();
+        let mut pending = vec![PathBuf::new()];
+        while let Some(relative) = pending.pop() {
+            for entry in fs::read_dir(dir.join(&relative))? {
+                let entry = entry?;
+                let path = relative.join(entry.file_name());
+                if entry.file_type()?.is_dir() {
+                    pending.push(path);
+                } else if path != Path::new(Self::FILE_NAME) {
+                    entries.push(ManifestEntry::of(&dir.join(&path), path)?);
+                }
+            }
+        }
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(Manifest { entries })
+    }
+
+    /// Read a manifest written by `save`.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Manifest> {
+        let invalid = |line: &str| {
+            Error::serialization(format!("invalid manifest line `{}`", line))
+        };
+
+        let mut entries = Vec::new();
+        for line in BufReader::new(File::open(path)?).lines() {
+            let line = line?;
+            let mut parts = line.splitn(3, ' ');
+            let (sha256, size, path) =
+                match (parts.next(), parts.next(), parts.next()) {
+                    (Some(sha256), Some(size), Some(path)) => {
+                        (sha256, size, path)
+                    }
+                    _ => return Err(invalid(&line)),
+                };
+            entries.push(ManifestEntry {
+                path: PathBuf::from(path),
+                size: size.parse().map_err(|_| invalid(&line))?,
+                sha256: sha256.to_owned(),
+            });
+        }
+        Ok(Manifest { entries })
+    }
+
+    /// Write the manifest to a file.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        for entry in &self.entries {
+            writeln!(
+                writer,
+                "{} {} {}",
+                entry.sha256,
+                entry.size,
+                entry.path.display()
+            )?;
+        }
+        writer.flush()?;
+        writer.get_ref().sync_all()?;
+        Ok(())
+    }
+
+    /// Check each file against the manifest, relative to the given
+    /// directory. Returns a description of each problem found, so an empty
+    /// list means every file is intact.
+    pub fn verify<P: AsRef<Path>>(&self, dir: P) -> Result<Vec<String>> {
+        let dir = dir.as_ref();
+        let mut problems = Vec::new();
+        for expected in &self.entries {
+            let path = dir.join(&expected.path);
+            if !path.is_file() {
+                problems
+                    .push(format!("{} is missing", expected.path.display()));
+                continue;
+            }
+            let found = ManifestEntry::of(&path, expected.path.clone())?;
+            if found.size != expected.size {
+                problems.push(format!(
+                    "{} is {} bytes, expected {}",
+                    expected.path.display(),
+                    found.size,
+                    expected.size
+                ));
+            } else if found.sha256 != expected.sha256 {
+                problems.push(format!(
+                    "{} doesn't match its digest",
+                    expected.path.display()
+                ));
+            }
+        }
+        Ok(problems)
+    }
+}
+
+impl ManifestEntry {
+    fn of(file: &Path, path: PathBuf) -> Result<ManifestEntry> {
+        let mut reader = File::open(file)?;
+        let mut hash = Sha256::new();
+        let mut size = 0;
+        let mut buf = [0; 8 * 1024];
+        loop {
+            let read = reader.read(&mut buf)?;
+            if read == 0 {
+                break;
+            }
+            hash.input(&buf[..read]);
+            size += read as u64;
+        }
+        Ok(ManifestEntry {
+            path,
+            size,
+            sha256: format!("{:x}", hash.result()),
+        })
+    }
+}
+
+/// Where the store is kept inside a backup directory.
+const BACKUP_STORE_NAME: &str = "store";
+
+/// The list of links in an incremental backup, see [`backup_incremental`].
+pub(crate) const CHAIN_FILE_NAME: &str = "CHAIN";
+
+/// A full or incremental backup in a chain, see [`backup_incremental`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BackupLink {
+    /// The directory it's in, relative to the chain's directory. The first
+    /// link is a full backup, and the rest are increments.
+    pub name: String,
+    /// The store's sequence number when it was taken, which the next
+    /// increment starts from.
+    pub sequence: u64,
+    /// The SHA-256 digest of the store's log up to `sequence`, to tell if
+    /// it was compacted since.
+    pub log_sha256: String,
+}
+
+impl BackupLink {
+    fn load_chain(dir: &Path) -> Result<Vec<BackupLink>> {
+        let invalid = |line: &str| {
+            Error::serialization(format!(
+                "invalid backup chain line `{}`",
+                line
+            ))
+        };
+
+        let mut links = Vec::new();
+        let file = File::open(dir.join(CHAIN_FILE_NAME))?;
+        for line in BufReader::new(file).lines() {
+            let line = line?;
+            let parts: Vec<&str> = line.split(' ').collect();
+            match parts.as_slice() {
+                [name, sequence, log_sha256] => links.push(BackupLink {
+                    name: (*name).to_owned(),
+                    sequence: sequence.parse().map_err(|_| invalid(&line))?,
+                    log_sha256: (*log_sha256).to_owned(),
+                }),
+                _ => return Err(invalid(&line)),
+            }
+        }
+        Ok(links)
+    }
+
+    fn append_to_chain(&self, dir: &Path) -> Result<()> {
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(dir.join(CHAIN_FILE_NAME))?;
+        writeln!(file, "{} {} {}", self.name, self.sequence, self.log_sha256)?;
+        file.sync_all()?;
+        Ok(())
+    }
+}
+
+/// Copy the store into a new backup directory, along with a manifest of
+/// the copy. Uses [`AnyKvs::fork_to`], so it's cheap for the log engine.
+pub fn backup<P: AsRef<Path>>(store: &AnyKvs, dir: P) -> Result<Manifest> {
+    let dir = dir.as_ref();
+    fs::create_dir(dir)?;
+    store.fork_to(dir.join(BACKUP_STORE_NAME))?;
+
+    let manifest = Manifest::of_dir(dir)?;
+    manifest.save(dir.join(Manifest::FILE_NAME))?;
+    fail_point("backup::after_manifest")?;
+    Ok(manifest)
+}
+
+/// Add to a chain of backups in the given directory, starting one with a
+/// full backup if there isn't one there yet. Each later link only holds
+/// what was written since the one before it, found using the store's
+/// sequence numbers.
+///
+/// Only the log engine keeps sequence numbers, and compacting it ends the
+/// chain, after which backing up to a new directory starts a new one.
+pub fn backup_incremental<P: AsRef<Path>>(
+    store: &AnyKvs,
+    dir: P,
+) -> Result<BackupLink> {
+    let dir = dir.as_ref();
+    let log = match store {
+        #[cfg(feature = "log")]
+        AnyKvs::Log(log) => log,
+        #[allow(unreachable_patterns)]
+        _ => return Err(Error::unsupported(Capability::IncrementalBackup)),
+    };
+
+    let chain = if dir.join(CHAIN_FILE_NAME).is_file() {
+        BackupLink::load_chain(dir)?
+    } else {
+        fs::create_dir_all(dir)?;
+        Vec::new()
+    };
+    let name = chain.len().to_string();
+    let link_dir = dir.join(&name);
+    if link_dir.exists() {
+        // left by a backup that failed before it was added to the chain
+        fs::remove_dir_all(&link_dir)?;
+    }
+
+    let sequence = match chain.last() {
+        None => {
+            let sequence = log.sequence()?;
+            backup(store, &link_dir)?;
+            sequence
+        }
+        Some(last) => {
+            if log.sequence()? < last.sequence
+                || log.log_digest(last.sequence)? != last.log_sha256
+            {
+                return Err(Error::config(format!(
+                    "the store was compacted after {} was backed up, start a \
+                     new chain in another directory",
+                    dir.display()
+                )));
+            }
+            fs::create_dir(&link_dir)?;
+            let sequence = log.write_increment(
+                last.sequence,
+                link_dir.join(BACKUP_STORE_NAME),
+            )?;
+            Manifest::of_dir(&link_dir)?
+                .save(link_dir.join(Manifest::FILE_NAME))?;
+            sequence
+        }
+    };
+
+    let link = BackupLink {
+        name,
+        sequence,
+        log_sha256: log.log_digest(sequence)?,
+    };
+    link.append_to_chain(dir)?;
+    Ok(link)
+}
+
+/// Check a backup directory against its manifest, returning a description
+/// of each problem found. Checks every link of a chain written by
+/// [`backup_incremental`].
+pub fn verify_backup<P: AsRef<Path>>(dir: P) -> Result<Vec<String>> {
+    let dir = dir.as_ref();
+    if !dir.join(CHAIN_FILE_NAME).is_file() {
+        return Manifest::load(dir.join(Manifest::FILE_NAME))?.verify(dir);
+    }
+
+    let mut problems = Vec::new();
+    for link in BackupLink::load_chain(dir)? {
+        let link_dir = dir.join(&link.name);
+        if !link_dir.join(Manifest::FILE_NAME).is_file() {
+            problems.push(format!("{} is missing", link.name));
+            continue;
+        }
+        for problem in verify_backup(&link_dir)? {
+            problems.push(format!("{}/{}", link.name, problem));
+        }
+    }
+    Ok(problems)
+}
+
+/// What [`restore`] copied, or would copy with a dry run, counted from the
+/// backup's manifests.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct RestoreReport {
+    /// The number of backups restored: one, or every link of a chain.
+    pub backups: u64,
+    /// The number of files copied, counting each increment's.
+    pub files: u64,
+    /// The number of bytes in the files copied.
+    pub bytes: u64,
+}
+
+impl RestoreReport {
+    /// Count the store's files in the backup in `dir`.
+    fn add_backup(&mut self, dir: &Path) -> Result<()> {
+        let manifest = Manifest::load(dir.join(Manifest::FILE_NAME))?;
+        self.backups += 1;
+        for entry in &manifest.entries {
+            if entry.path.starts_with(BACKUP_STORE_NAME) {
+                self.files += 1;
+                self.bytes += entry.size;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Check a backup, then copy its store to `path`, which mustn't exist yet.
+/// Nothing is copied if the check fails. A chain is restored by copying its
+/// full backup and then applying each increment in order.
+///
+/// Returns what was copied. With `dry_run`, the backup and `path` are still
+/// checked, but nothing is copied.
+pub fn restore<P: AsRef<Path>, Q: AsRef<Path>>(
+    dir: P,
+    path: Q,
+    dry_run: bool,
+) -> Result<RestoreReport> {
+    let (dir, path) = (dir.as_ref(), path.as_ref());
+    let problems = verify_backup(dir)?;
+    if !problems.is_empty() {
+        return Err(Error::corrupt_database(format!(
+            "the backup failed verification: {}",
+            problems.join(", ")
+        )));
+    }
+    if path.exists() {
+        return Err(Error::config(format!(
+            "{} already exists, restore to a new location",
+            path.display()
+        )));
+    }
+
+    let links: Vec<PathBuf> = if dir.join(CHAIN_FILE_NAME).is_file() {
+        BackupLink::load_chain(dir)?
+            .iter()
+            .map(|link| dir.join(&link.name))
+            .collect()
+    } else {
+        vec![dir.to_owned()]
+    };
+    let (base, increments) = links.split_first().ok_or_else(|| {
+        Error::corrupt_database(format!("{} is an empty chain", dir.display()))
+    })?;
+    let mut report = RestoreReport::default();
+    for link in &links {
+        report.add_backup(link)?;
+    }
+    if dry_run {
+        return Ok(report);
+    }
+
+    copy_recursively(&base.join(BACKUP_STORE_NAME), path)?;
+    for link in increments {
+        apply_increment(path, &link.join(BACKUP_STORE_NAME))?;
+    }
+    Ok(report)
+}
+
+#[cfg(feature = "log")]
+fn apply_increment(path: &Path, increment: &Path) -> Result<()> {
+    crate::LogKvs::apply_increment(path, increment)
+}
+
+#[cfg(not(feature = "log"))]
+fn apply_increment(_path: &Path, _increment: &Path) -> Result<()> {
+    Err(Error::unsupported(Capability::IncrementalBackup))
+}
+
+fn copy_recursively(from: &Path, to: &Path) -> Result<()> {
+    if from.is_dir() {
+        fs::create_dir(to)?;
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            copy_recursively(&entry.path(), &to.join(entry.file_name()))?;
+        }
+    } else {
+        fs::copy(from, to)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tempfile::TempDir;
+
+    use core::{ErrorKind, KvStore};
+
+    use crate::{Engine, Kvs};
+
+    #[test]
+    fn backup_and_restore() -> Result<()> {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+
+        for name in Engine::VARIANTS {
+            let dir = temp_dir.path().join(name);
+            fs::create_dir(&dir)?;
+            let mut store = Kvs::builder()
+                .engine(name.parse()?)
+                .path(dir.join("store"))
+                .open_any()?;
+            store.set("key1".to_owned(), "value1".to_owned())?;
+
+            let manifest = backup(&store, dir.join("backup"))?;
+            assert!(!manifest.entries.is_empty());
+            assert!(verify_backup(dir.join("backup"))?.is_empty());
+            store.set("key1".to_owned(), "value2".to_owned())?;
+            drop(store);
+
+            let dry_run =
+                restore(dir.join("backup"), dir.join("restored"), true)?;
+            assert!(!dir.join("restored").exists());
+            let report =
+                restore(dir.join("backup"), dir.join("restored"), false)?;
+            assert_eq!(report, dry_run);
+            assert_eq!(report.backups, 1);
+            assert!(report.files > 0);
+            let restored = Kvs::builder()
+                .engine(name.parse()?)
+                .path(dir.join("restored"))
+                .open_any()?;
+            assert_eq!(
+                restored.get("key1".to_owned())?,
+                Some("value1".to_owned())
+            );
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "log")]
+    fn damaged_backup() -> Result<()> {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+        let mut store = Kvs::builder()
+            .engine(Engine::Log)
+            .path(temp_dir.path().join("store"))
+            .open_any()?;
+        store.set("key1".to_owned(), "value1".to_owned())?;
+        let backup_dir = temp_dir.path().join("backup");
+        let manifest = backup(&store, &backup_dir)?;
+
+        let damaged = backup_dir.join(&manifest.entries[0].path);
+        let mut contents = fs::read(&damaged)?;
+        let last = contents.len() - 1;
+        contents[last] ^= 1;
+        fs::write(&damaged, contents)?;
+
+        let problems = verify_backup(&backup_dir)?;
+        assert_eq!(
+            problems,
+            vec![format!(
+                "{} doesn't match its digest",
+                manifest.entries[0].path.display()
+            )]
+        );
+        let restored = temp_dir.path().join("restored");
+        assert!(restore(&backup_dir, &restored, true).is_err());
+        assert!(restore(&backup_dir, &restored, false).is_err());
+        assert!(!restored.exists());
+
+        fs::remove_file(&damaged)?;
+        assert_eq!(verify_backup(&backup_dir)?.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(all(feature = "hashmap", feature = "log"))]
+    fn incremental_backup() -> Result<()> {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+        let chain_dir = temp_dir.path().join("chain");
+        let mut store = Kvs::builder()
+            .engine(Engine::Log)
+            .path(temp_dir.path().join("store"))
+            .open_any()?;
+        store.set("key1".to_owned(), "value1".to_owned())?;
+        let base = backup_incremental(&store, &chain_dir)?;
+        assert_eq!(base.name, "0");
+
+        store.set("key2".to_owned(), "value2".to_owned())?;
+        store.remove("key1".to_owned())?;
+        let increment = backup_incremental(&store, &chain_dir)?;
+        assert_eq!(increment.name, "1");
+        assert!(increment.sequence > base.sequence);
+        store.set("key3".to_owned(), "value3".to_owned())?;
+        backup_incremental(&store, &chain_dir)?;
+        assert!(verify_backup(&chain_dir)?.is_empty());
+
+        let report =
+            restore(&chain_dir, temp_dir.path().join("restored"), false)?;
+        assert_eq!(report.backups, 3);
+        let restored = Kvs::builder()
+            .engine(Engine::Log)
+            .path(temp_dir.path().join("restored"))
+            .open_any()?;
+        assert_eq!(restored.get("key1".to_owned())?, None);
+        assert_eq!(restored.get("key2".to_owned())?, Some("value2".to_owned()));
+        assert_eq!(restored.get("key3".to_owned())?, Some("value3".to_owned()));
+
+        fs::write(chain_dir.join("1").join(BACKUP_STORE_NAME).join("1"), "")?;
+        assert_eq!(verify_backup(&chain_dir)?.len(), 1);
+        assert!(verify_backup(&chain_dir)?[0].starts_with("1/store/1 "));
+
+        // compacting ends the chain
+        store.compact()?;
+        assert_eq!(
+            backup_incremental(&store, &chain_dir).unwrap_err().kind(),
+            &ErrorKind::Config {
+                message: format!(
+                    "the store was compacted after {} was backed up, start a \
+                     new chain in another directory",
+                    chain_dir.display()
+                )
+            }
+        );
+
+        let hashmap = Kvs::builder()
+            .engine(Engine::HashMap)
+            .path(temp_dir.path().join("hashmap"))
+            .open_any()?;
+        assert_eq!(
+            backup_incremental(&hashmap, temp_dir.path().join("other"))
+                .unwrap_err()
+                .kind(),
+            &ErrorKind::Unsupported {
+                capability: Capability::IncrementalBackup
+            }
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "log")]
+    fn failed_backup() -> Result<()> {
+        use core::{clear_fail_point, set_fail_point, FailAction};
+
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+        let chain_dir = temp_dir.path().join("chain");
+        let mut store = Kvs::builder()
+            .engine(Engine::Log)
+            .path(temp_dir.path().join("store"))
+            .open_any()?;
+        store.set("key1".to_owned(), "value1".to_owned())?;
+
+        set_fail_point("backup::after_manifest", FailAction::Error);
+        assert!(backup_incremental(&store, &chain_dir).is_err());
+        clear_fail_point("backup::after_manifest");
+        // the link that wasn't added to the chain is written again
+        assert_eq!(backup_incremental(&store, &chain_dir)?.name, "0");
+        assert!(verify_backup(&chain_dir)?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn file_manifest() -> Result<()> {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+        let file = temp_dir.path().join("export.aof");
+        fs::write(&file, "contents")?;
+
+        let manifest = Manifest::of_file(&file)?;
+        let manifest_path = Manifest::path_for_file(&file);
+        manifest.save(&manifest_path)?;
+        assert_eq!(Manifest::load(&manifest_path)?, manifest);
+        assert!(manifest.verify(temp_dir.path())?.is_empty());
+
+        fs::write(&file, "changed!")?;
+        assert_eq!(manifest.verify(temp_dir.path())?.len(), 1);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,400 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use core::{
+    ByteOrder, Capability, Clock, Collation, Error, IndexKind, IntegrityLevel,
+    KvStore, Persistent, Result, StoreObserver, StoreOptions, SyncPolicy,
+};
+
+use crate::AnyKvs;
+
+/// Entry point for opening any of the enabled engines.
+///
+/// ```rust
+/// # use tempfile::TempDir;
+/// use kvs::{Engine, Kvs, SyncPolicy};
+///
+/// # let temp_dir =
+/// #     TempDir::new().expect("unable to create temporary working directory");
+/// let mut store = Kvs::builder()
+///     .engine(Engine::Log)
+///     .path(temp_dir.path())
+///     .sync(SyncPolicy::Always)
+///     .open()
+///     .unwrap();
+/// store.set("key1".to_owned(), "value1".to_owned()).unwrap();
+/// ```
+pub struct Kvs;
+
+impl Kvs {
+    /// Start building a store to open.
+    pub fn builder() -> KvsBuilder {
+        KvsBuilder::default()
+    }
+}
+
+/// The engines that can be opened by a [`KvsBuilder`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Engine {
+    /// A [`HashMapKvs`](crate::HashMapKvs), stored in a single file.
+    #[cfg(feature = "hashmap")]
+    HashMap,
+    /// A [`LogKvs`](crate::LogKvs), stored in a directory.
+    #[cfg(feature = "log")]
+    Log,
+}
+
+impl Engine {
+    /// The names of the enabled engines, as parsed by `from_str`.
+    pub const VARIANTS: &'static [&'static str] = &[
+        #[cfg(feature = "hashmap")]
+        "hashmap",
+        #[cfg(feature = "log")]
+        "log",
+    ];
+
+    /// The optional operations this engine supports. The log engine can
+    /// only scan in order when opened with an ordered index, so check
+    /// [`AnyKvs::supports`] for an open store.
+    pub fn capabilities(self) -> &'static [Capability] {
+        match self {
+            #[cfg(feature = "hashmap")]
+            Engine::HashMap => &[Capability::OrderedScan],
+            #[cfg(feature = "log")]
+            Engine::Log => &[
+                Capability::Compaction,
+                Capability::IncrementalBackup,
+                #[cfg(any(target_os = "linux", target_os = "macos"))]
+                Capability::DirectIo,
+            ],
+        }
+    }
+
+    /// Whether this engine supports the given operation.
+    pub fn supports(self, capability: Capability) -> bool {
+        self.capabilities().contains(&capability)
+    }
+}
+
+impl fmt::Display for Engine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            #[cfg(feature = "hashmap")]
+            Engine::HashMap => write!(f, "hashmap"),
+            #[cfg(feature = "log")]
+            Engine::Log => write!(f, "log"),
+        }
+    }
+}
+
+impl FromStr for Engine {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<Engine> {
+        match name {
+            #[cfg(feature = "hashmap")]
+            "hashmap" => Ok(Engine::HashMap),
+            #[cfg(feature = "log")]
+            "log" => Ok(Engine::Log),
+            _ => Err(Error::config(format!("unknown engine '{}'", name))),
+        }
+    }
+}
+
+/// Collects the settings for a store, then opens it.
+#[derive(Clone, Debug, Default)]
+pub struct KvsBuilder {
+    engine: Option<Engine>,
+    path: Option<PathBuf>,
+    options: StoreOptions,
+}
+
+impl KvsBuilder {
+    /// Set which engine to open. Required.
+    pub fn engine(mut self, engine: Engine) -> Self {
+        self.engine = Some(engine);
+        self
+    }
+
+    /// Set where the store is kept. Required.
+    pub fn path<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.path = Some(PathBuf::from(path.as_ref()));
+        self
+    }
+
+    /// Set when writes are synced to disk. Defaults to
+    /// [`SyncPolicy::Never`].
+    pub fn sync(mut self, sync: SyncPolicy) -> Self {
+        self.options.sync = sync;
+        self
+    }
+
+    /// Keep values longer than this many bytes in their own files. Only
+    /// supported by the log engine, others ignore it.
+    pub fn blob_threshold(mut self, bytes: u64) -> Self {
+        self.options.blob_threshold = Some(bytes);
+        self
+    }
+
+    /// Store values kept in blob files once, however many keys hold them.
+    /// Only supported by the log engine, others ignore it.
+    pub fn dedup(mut self, dedup: bool) -> Self {
+        self.options.dedup = dedup;
+        self
+    }
+
+    /// Write overwrites as a change to the previous value, chaining at most
+    /// `depth` changes. Only supported by the log engine, others ignore it.
+    pub fn delta_depth(mut self, depth: u32) -> Self {
+        self.options.delta_depth = Some(depth);
+        self
+    }
+
+    /// Set how keys are indexed in memory. Defaults to
+    /// [`IndexKind::Hash`]. Only the log engine has a choice, others ignore
+    /// it.
+    pub fn index(mut self, index: IndexKind) -> Self {
+        self.options.index = index;
+        self
+    }
+
+    /// Soft delete: keep removed values for at least `retention`, so they
+    /// can be put back with [`Trash::restore_key`](core::Trash::restore_key).
+    /// See [`StoreOptions::trash_retention`].
+    pub fn trash_retention(mut self, retention: Duration) -> Self {
+        self.options.trash_retention = Some(retention);
+        self
+    }
+
+    /// Call the observer's hooks as the store is used, see
+    /// [`StoreObserver`].
+    pub fn observer(mut self, observer: Arc<dyn StoreObserver>) -> Self {
+        self.options.observer = Some(observer);
+        self
+    }
+
+    /// Take the time from the given clock instead of the system's, see
+    /// [`StoreOptions::clock`].
+    pub fn clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.options.clock = Some(clock);
+        self
+    }
+
+    /// Set how thoroughly the store is checked when opened. Defaults to
+    /// [`IntegrityLevel::Standard`]. Only the log engine has a choice,
+    /// others ignore it.
+    pub fn integrity(mut self, integrity: IntegrityLevel) -> Self {
+        self.options.integrity = integrity;
+        self
+    }
+
+    /// Read the store's files with direct I/O, bypassing the page cache.
+    /// See [`StoreOptions::direct_io`].
+    pub fn direct_io(mut self) -> Self {
+        self.options.direct_io = true;
+        self
+    }
+
+    /// Reserve disk for the store this many bytes at a time. See
+    /// [`StoreOptions::preallocate`].
+    pub fn preallocate(mut self, bytes: u64) -> Self {
+        self.options.preallocate = Some(bytes);
+        self
+    }
+
+    /// Set the byte order records are written in, if the store is created.
+    /// See [`StoreOptions::byte_order`].
+    pub fn byte_order(mut self, byte_order: ByteOrder) -> Self {
+        self.options.byte_order = byte_order;
+        self
+    }
+
+    /// Keep the log as a history of every write for this long. See
+    /// [`StoreOptions::history_retention`].
+    pub fn history_retention(mut self, retention: Duration) -> Self {
+        self.options.history_retention = Some(retention);
+        self
+    }
+
+    /// Save the index every `writes` writes. See
+    /// [`StoreOptions::checkpoint_every`].
+    pub fn checkpoint_every(mut self, writes: u64) -> Self {
+        self.options.checkpoint_every = Some(writes);
+        self
+    }
+
+    /// Save the index once `interval` has passed since it was last saved.
+    /// See [`StoreOptions::checkpoint_interval`].
+    pub fn checkpoint_interval(mut self, interval: Duration) -> Self {
+        self.options.checkpoint_interval = Some(interval);
+        self
+    }
+
+    /// Scan keys in the given order. See [`StoreOptions::collation`].
+    pub fn collation(mut self, collation: Collation) -> Self {
+        self.options.collation = collation;
+        self
+    }
+
+    /// Open the store without reading its log first. See
+    /// [`StoreOptions::lazy_open`].
+    pub fn lazy_open(mut self) -> Self {
+        self.options.lazy_open = true;
+        self
+    }
+
+    /// Open the store for reading only, alongside another handle writing to
+    /// it. See [`StoreOptions::read_only`].
+    pub fn read_only(mut self) -> Self {
+        self.options.read_only = true;
+        self
+    }
+
+    /// Open the store, creating it if it doesn't exist.
+    pub fn open(self) -> Result<Box<dyn KvStore>> {
+        Ok(Box::new(self.open_any()?))
+    }
+
+    /// Open the store without boxing it, creating it if it doesn't exist.
+    pub fn open_any(self) -> Result<AnyKvs> {
+        let engine = self
+            .engine
+            .ok_or_else(|| Error::config("no engine given".to_owned()))?;
+        let path = self
+            .path
+            .ok_or_else(|| Error::config("no path given".to_owned()))?;
+
+        Ok(match engine {
+            #[cfg(feature = "hashmap")]
+            Engine::HashMap => {
+                crate::HashMapKvs::open_with(path, self.options)?.into()
+            }
+            #[cfg(feature = "log")]
+            Engine::Log => crate::LogKvs::open_with(path, self.options)?.into(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tempfile::TempDir;
+
+    use core::{ErrorKind, Scannable};
+
+    #[test]
+    fn open_each_engine() -> Result<()> {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+
+        for name in Engine::VARIANTS {
+            let builder = Kvs::builder()
+                .engine(name.parse()?)
+                .path(temp_dir.path().join(name));
+
+            let mut store = builder.clone().open()?;
+            store.set("key1".to_owned(), name.to_string())?;
+            drop(store);
+
+            let store = builder.open_any()?;
+            assert_eq!(store.get("key1".to_owned())?, Some(name.to_string()));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn missing_settings() {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+
+        let err = Kvs::builder().path(temp_dir.path()).open().err().unwrap();
+        assert_eq!(
+            err.kind(),
+            &ErrorKind::Config {
+                message: "no engine given".to_owned()
+            }
+        );
+
+        let err = Kvs::builder().engine(Engine::Log).open().err().unwrap();
+        assert_eq!(
+            err.kind(),
+            &ErrorKind::Config {
+                message: "no path given".to_owned()
+            }
+        );
+    }
+
+    #[test]
+    fn capabilities() -> Result<()> {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+
+        for name in Engine::VARIANTS {
+            let engine: Engine = name.parse()?;
+            let mut store = Kvs::builder()
+                .engine(engine)
+                .path(temp_dir.path().join(name))
+                .open_any()?;
+            store.set("key1".to_owned(), "value1".to_owned())?;
+
+            assert_eq!(store.engine(), engine);
+            let compacted = store.compact();
+            if engine.supports(Capability::Compaction) {
+                compacted?;
+            } else {
+                assert_eq!(
+                    compacted.err().unwrap().kind(),
+                    &ErrorKind::Unsupported {
+                        capability: Capability::Compaction
+                    }
+                );
+            }
+
+            let scanned = store.scan("", None);
+            if store.supports(Capability::OrderedScan) {
+                assert_eq!(scanned?, vec!["key1".to_owned()]);
+            } else {
+                assert_eq!(
+                    scanned.err().unwrap().kind(),
+                    &ErrorKind::Unsupported {
+                        capability: Capability::OrderedScan
+                    }
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    #[cfg(feature = "log")]
+    #[test]
+    fn ordered_index() -> Result<()> {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+
+        let mut store = Kvs::builder()
+            .engine(Engine::Log)
+            .path(temp_dir.path())
+            .index(IndexKind::Ordered)
+            .open_any()?;
+        assert!(store.supports(Capability::OrderedScan));
+        store.set("key2".to_owned(), "value2".to_owned())?;
+        store.set("key1".to_owned(), "value1".to_owned())?;
+        assert_eq!(
+            store.scan("", None)?,
+            vec!["key1".to_owned(), "key2".to_owned()]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn unknown_engine() {
+        assert!("btree".parse::<Engine>().is_err());
+    }
+}
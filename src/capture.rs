@@ -0,0 +1,329 @@
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use core::{
+    Collation, Error, KvStore, RangeEstimate, Result, ScanOptions, Scannable,
+};
+
+/// What a [`CapturedOp`] did.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum CaptureOp {
+    /// A value was set.
+    Set {
+        /// The key set.
+        key: String,
+        /// The value it was set to.
+        value: String,
+    },
+    /// A key was read.
+    Get {
+        /// The key read.
+        key: String,
+    },
+    /// A key was removed.
+    Remove {
+        /// The key removed.
+        key: String,
+    },
+}
+
+/// An operation recorded in a capture file by a [`CaptureKvs`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CapturedOp {
+    /// When it was made, in milliseconds since the Unix epoch.
+    pub time: u64,
+    /// What was done.
+    pub op: CaptureOp,
+}
+
+/// Records every operation made on a store, with the time it was made, to a
+/// capture file, so the workload can be run again later with [`replay`] to
+/// reproduce a bug or benchmark a store against real traffic. Operations
+/// are recorded once the store has handled them, and ones that fail aren't
+/// recorded. Unlike a [`ChangeFeed`](crate::ChangeFeed), reads are recorded
+/// too, and whole values are kept.
+///
+/// Each operation is a line of tab separated fields: the time, `set`, `get`
+/// or `rm`, the key as a JSON string, and for `set` the value as a JSON
+/// string.
+///
+/// ```rust
+/// # use tempfile::TempDir;
+/// use kvs::{read_capture, CaptureKvs, KvStore, MemKvs};
+///
+/// # let temp_dir =
+/// #     TempDir::new().expect("unable to create temporary working directory");
+/// let path = temp_dir.path().join("capture");
+/// let mut store = CaptureKvs::create(MemKvs::new(), &path).unwrap();
+/// store.set("key1".to_owned(), "value1".to_owned()).unwrap();
+/// store.get("key1".to_owned()).unwrap();
+///
+/// assert_eq!(read_capture(&path).unwrap().len(), 2);
+/// ```
+#[derive(Debug)]
+pub struct CaptureKvs<S> {
+    store: S,
+    file: File,
+}
+
+impl<S: KvStore> CaptureKvs<S> {
+    /// Wrap the store, capturing to a new file at the path, replacing any
+    /// file already there.
+    pub fn create<P: AsRef<Path>>(store: S, path: P) -> Result<CaptureKvs<S>> {
+        let file = File::create(path)?;
+        Ok(CaptureKvs { store, file })
+    }
+
+    /// Wrap the store, adding to the capture file at the path, which is
+    /// created if it doesn't exist.
+    pub fn append<P: AsRef<Path>>(store: S, path: P) -> Result<CaptureKvs<S>> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(CaptureKvs { store, file })
+    }
+
+    /// The wrapped store.
+    pub fn into_inner(self) -> S {
+        self.store
+    }
+
+    fn record(&self, op: CaptureOp) -> Result<()> {
+        let captured = CapturedOp {
+            time: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |since| since.as_millis() as u64),
+            op,
+        };
+        // each line is written in one go, so a capture that's cut short
+        // only loses whole operations
+        (&self.file).write_all(encode(&captured)?.as_bytes())?;
+        Ok(())
+    }
+}
+
+impl<S: KvStore> KvStore for CaptureKvs<S> {
+    fn set(&mut self, key: String, value: String) -> Result<()> {
+        self.store.set(key.clone(), value.clone())?;
+        self.record(CaptureOp::Set { key, value })
+    }
+
+    fn get(&self, key: String) -> Result<Option<String>> {
+        let value = self.store.get_ref(&key)?.map(|value| value.into_owned());
+        self.record(CaptureOp::Get { key })?;
+        Ok(value)
+    }
+
+    fn remove(&mut self, key: String) -> Result<Option<String>> {
+        let value = self.store.remove_ref(&key)?;
+        self.record(CaptureOp::Remove { key })?;
+        Ok(value)
+    }
+}
+
+impl<S: KvStore + Scannable> Scannable for CaptureKvs<S> {
+    fn keys(&self) -> Result<Vec<String>> {
+        self.store.keys()
+    }
+
+    fn scan(&self, start: &str, end: Option<&str>) -> Result<Vec<String>> {
+        self.store.scan(start, end)
+    }
+
+    fn scan_with(
+        &self,
+        start: &str,
+        end: Option<&str>,
+        options: ScanOptions,
+    ) -> Result<Vec<String>> {
+        self.store.scan_with(start, end, options)
+    }
+
+    fn estimate_range_size(
+        &self,
+        start: &str,
+        end: Option<&str>,
+    ) -> Result<RangeEstimate> {
+        self.store.estimate_range_size(start, end)
+    }
+
+    fn scan_filtered(
+        &self,
+        start: &str,
+        end: Option<&str>,
+        predicate: &dyn Fn(&str, &str) -> bool,
+    ) -> Result<Vec<(String, String)>> {
+        self.store.scan_filtered(start, end, predicate)
+    }
+
+    fn collation(&self) -> Collation {
+        self.store.collation()
+    }
+}
+
+/// The operations in a capture file written by a [`CaptureKvs`], oldest
+/// first.
+pub fn read_capture<P: AsRef<Path>>(path: P) -> Result<Vec<CapturedOp>> {
+    BufReader::new(File::open(path)?)
+        .lines()
+        .map(|line| {
+            let line = line?;
+            decode(&line).ok_or_else(|| {
+                Error::serialization(format!("invalid capture line `{}`", line))
+            })
+        })
+        .collect()
+}
+
+/// What a [`replay`] did.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ReplayReport {
+    /// How many operations were run.
+    pub operations: u64,
+    /// How long running them took.
+    pub elapsed: Duration,
+}
+
+/// Run the operations in a capture file against the store, in order. With a
+/// speed, they're paced to the gaps between them when they were captured,
+/// divided by the speed, so 1.0 is the original speed and 10.0 ten times
+/// faster. Without one they're run as fast as the store allows.
+///
+/// Reads are made, but what they return isn't compared with anything, so
+/// a replay reproduces the load on a store rather than checking it.
+pub fn replay<P: AsRef<Path>, S: KvStore + ?Sized>(
+    path: P,
+    store: &mut S,
+    speed: Option<f64>,
+) -> Result<ReplayReport> {
+    if let Some(speed) = speed {
+        if speed <= 0.0 || !speed.is_finite() {
+            return Err(Error::config(format!(
+                "replay speed must be a positive number, not {}",
+                speed
+            )));
+        }
+    }
+    let ops = read_capture(path)?;
+    let start = Instant::now();
+    let first_time = ops.first().map_or(0, |captured| captured.time);
+    for captured in &ops {
+        if let Some(speed) = speed {
+            let offset = captured.time.saturating_sub(first_time) as f64;
+            let due = Duration::from_micros((offset * 1000.0 / speed) as u64);
+            if let Some(wait) = due.checked_sub(start.elapsed()) {
+                thread::sleep(wait);
+            }
+        }
+        match &captured.op {
+            CaptureOp::Set { key, value } => {
+                store.set(key.clone(), value.clone())?
+            }
+            CaptureOp::Get { key } => {
+                store.get_ref(key)?;
+            }
+            CaptureOp::Remove { key } => {
+                store.remove_ref(key)?;
+            }
+        }
+    }
+    Ok(ReplayReport {
+        operations: ops.len() as u64,
+        elapsed: start.elapsed(),
+    })
+}
+
+fn encode(captured: &CapturedOp) -> Result<String> {
+    // JSON keeps keys and values with tabs and line breaks on one line
+    let json =
+        |s: &String| serde_json::to_string(s).map_err(Error::serialization);
+    Ok(match &captured.op {
+        CaptureOp::Set { key, value } => format!(
+            "{}\tset\t{}\t{}\n",
+            captured.time,
+            json(key)?,
+            json(value)?
+        ),
+        CaptureOp::Get { key } => {
+            format!("{}\tget\t{}\n", captured.time, json(key)?)
+        }
+        CaptureOp::Remove { key } => {
+            format!("{}\trm\t{}\n", captured.time, json(key)?)
+        }
+    })
+}
+
+fn decode(line: &str) -> Option<CapturedOp> {
+    let fields: Vec<&str> = line.split('\t').collect();
+    let op = match fields.as_slice() {
+        [_, "set", key, value] => CaptureOp::Set {
+            key: serde_json::from_str(key).ok()?,
+            value: serde_json::from_str(value).ok()?,
+        },
+        [_, "get", key] => CaptureOp::Get {
+            key: serde_json::from_str(key).ok()?,
+        },
+        [_, "rm", key] => CaptureOp::Remove {
+            key: serde_json::from_str(key).ok()?,
+        },
+        _ => return None,
+    };
+    Some(CapturedOp {
+        time: fields[0].parse().ok()?,
+        op,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tempfile::TempDir;
+
+    use crate::MemKvs;
+
+    #[test]
+    fn capture_replay() -> Result<()> {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+        let path = temp_dir.path().join("capture");
+        let mut store = CaptureKvs::create(MemKvs::new(), &path)?;
+        store.set("key\t1".to_owned(), "value\n1".to_owned())?;
+        store.set("key2".to_owned(), "value2".to_owned())?;
+        assert_eq!(store.get("key2".to_owned())?, Some("value2".to_owned()));
+        store.remove("key2".to_owned())?;
+        let captured = store.into_inner();
+
+        let ops = read_capture(&path)?;
+        assert_eq!(ops.len(), 4);
+        assert_eq!(
+            ops[0].op,
+            CaptureOp::Set {
+                key: "key\t1".to_owned(),
+                value: "value\n1".to_owned(),
+            }
+        );
+        assert_eq!(
+            ops[2].op,
+            CaptureOp::Get {
+                key: "key2".to_owned()
+            }
+        );
+
+        let mut fresh = MemKvs::new();
+        let report = replay(&path, &mut fresh, None)?;
+        assert_eq!(report.operations, 4);
+        assert_eq!(fresh, captured);
+        let mut paced = MemKvs::new();
+        assert_eq!(replay(&path, &mut paced, Some(100.0))?.operations, 4);
+        assert!(replay(&path, &mut paced, Some(0.0)).is_err());
+
+        // appending carries on from what's there
+        let store = CaptureKvs::append(MemKvs::new(), &path)?;
+        store.get("key2".to_owned())?;
+        assert_eq!(read_capture(&path)?.len(), 5);
+
+        Ok(())
+    }
+}
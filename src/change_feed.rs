@@ -0,0 +1,378 @@
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use sha2::{Digest, Sha256};
+
+use core::{
+    Collation, Error, KvStore, RangeEstimate, Result, ScanOptions, Scannable,
+};
+
+/// What a [`Change`] did to its key.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ChangeOp {
+    /// The key was given a value.
+    Set,
+    /// The key's value was removed.
+    Remove,
+}
+
+/// A write recorded in a [`ChangeFeed`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Change {
+    /// Counts up from 1 with each change, and is never reused.
+    pub sequence: u64,
+    /// What was done.
+    pub op: ChangeOp,
+    /// When it was done, in milliseconds since the Unix epoch.
+    pub time: u64,
+    /// The SHA-256 digest of the value set, in lowercase hex. None for
+    /// removals.
+    pub value_sha256: Option<String>,
+    /// The key changed.
+    pub key: String,
+}
+
+/// A bounded record of the writes to a store, so other systems can pick up
+/// what changed since they last looked with
+/// [`changes_since`](ChangeFeed::changes_since) instead of scanning the
+/// whole store. It's kept next to the store in `<store>.changes`, and holds
+/// at least the last `capacity` changes. Values aren't kept, only their
+/// digest, so readers fetch the current value from the store.
+///
+/// Each change is a line of tab separated fields: the sequence, `set` or
+/// `rm`, the time, the value digest or `-`, and the key as a JSON string.
+#[derive(Debug)]
+pub struct ChangeFeed {
+    path: PathBuf,
+    capacity: usize,
+    changes: VecDeque<Change>,
+    last_sequence: u64,
+    lines_on_disk: usize,
+}
+
+impl ChangeFeed {
+    /// The feed of the store at the given path, keeping at least the last
+    /// `capacity` changes. Carries on from any changes already recorded.
+    pub fn for_store<P: AsRef<Path>>(
+        path: P,
+        capacity: usize,
+    ) -> Result<ChangeFeed> {
+        let mut feed = path.as_ref().as_os_str().to_owned();
+        feed.push(".changes");
+        let mut feed = ChangeFeed {
+            path: PathBuf::from(feed),
+            capacity: capacity.max(1),
+            changes: VecDeque::new(),
+            last_sequence: 0,
+            lines_on_disk: 0,
+        };
+        feed.load()?;
+        Ok(feed)
+    }
+
+    /// The sequence of the latest change, or 0 if there hasn't been one.
+    pub fn last_sequence(&self) -> u64 {
+        self.last_sequence
+    }
+
+    /// The changes after the given sequence, oldest first. Pass 0 for every
+    /// change still held. Returns None if some of the changes after it have
+    /// already been dropped, since the caller would miss them and has to
+    /// read the whole store instead.
+    pub fn changes_since(&self, sequence: u64) -> Option<Vec<Change>> {
+        match self.changes.front() {
+            Some(oldest) if sequence + 1 < oldest.sequence => None,
+            _ => Some(
+                self.changes
+                    .iter()
+                    .filter(|change| change.sequence > sequence)
+                    .cloned()
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Record a change to the store, returning it.
+    pub fn record(
+        &mut self,
+        op: ChangeOp,
+        key: &str,
+        value: Option<&str>,
+    ) -> Result<Change> {
+        let change = Change {
+            sequence: self.last_sequence + 1,
+            op,
+            time: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |since| since.as_millis() as u64),
+            value_sha256: value.map(|value| {
+                format!("{:x}", Sha256::new().chain(value).result())
+            }),
+            key: key.to_owned(),
+        };
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{}", encode(&change)?)?;
+        self.lines_on_disk += 1;
+        self.last_sequence = change.sequence;
+        self.changes.push_back(change.clone());
+        if self.changes.len() > self.capacity {
+            self.changes.pop_front();
+        }
+        // dropped changes are only cleared from the file once there are as
+        // many again, so it isn't rewritten on every change
+        if self.lines_on_disk >= self.capacity.saturating_mul(2) {
+            self.rewrite()?;
+        }
+        Ok(change)
+    }
+
+    fn load(&mut self) -> Result<()> {
+        if !self.path.is_file() {
+            return Ok(());
+        }
+        for line in BufReader::new(File::open(&self.path)?).lines() {
+            let line = line?;
+            let change = decode(&line).ok_or_else(|| {
+                Error::serialization(format!(
+                    "invalid change feed line `{}`",
+                    line
+                ))
+            })?;
+            self.last_sequence = change.sequence;
+            self.changes.push_back(change);
+            if self.changes.len() > self.capacity {
+                self.changes.pop_front();
+            }
+            self.lines_on_disk += 1;
+        }
+        Ok(())
+    }
+
+    /// Replace the file with only the changes still held.
+    fn rewrite(&mut self) -> Result<()> {
+        let mut tmp = self.path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        let tmp = PathBuf::from(tmp);
+        let mut writer = BufWriter::new(File::create(&tmp)?);
+        for change in &self.changes {
+            writeln!(writer, "{}", encode(change)?)?;
+        }
+        writer.flush()?;
+        fs::rename(&tmp, &self.path)?;
+        self.lines_on_disk = self.changes.len();
+        Ok(())
+    }
+}
+
+fn encode(change: &Change) -> Result<String> {
+    let op = match change.op {
+        ChangeOp::Set => "set",
+        ChangeOp::Remove => "rm",
+    };
+    // JSON keeps keys with tabs and line breaks on one line, unchanged
+    let key =
+        serde_json::to_string(&change.key).map_err(Error::serialization)?;
+    Ok(format!(
+        "{}\t{}\t{}\t{}\t{}",
+        change.sequence,
+        op,
+        change.time,
+        change
+            .value_sha256
+            .as_ref()
+            .map_or("-", |digest| &digest[..]),
+        key
+    ))
+}
+
+fn decode(line: &str) -> Option<Change> {
+    let fields: Vec<&str> = line.splitn(5, '\t').collect();
+    match fields.as_slice() {
+        [sequence, op, time, digest, key] => Some(Change {
+            sequence: sequence.parse().ok()?,
+            op: match *op {
+                "set" => ChangeOp::Set,
+                "rm" => ChangeOp::Remove,
+                _ => return None,
+            },
+            time: time.parse().ok()?,
+            value_sha256: match *digest {
+                "-" => None,
+                digest => Some(digest.to_owned()),
+            },
+            key: serde_json::from_str(key).ok()?,
+        }),
+        _ => None,
+    }
+}
+
+/// Records every write to a store in its [`ChangeFeed`]. Changes are
+/// recorded once the store has made them, so one can be missed if the
+/// process stops in between, but never recorded without being made.
+///
+/// ```rust
+/// # use tempfile::TempDir;
+/// use kvs::{ChangeFeed, FeedKvs, KvStore, LogKvs, Persistent};
+///
+/// # let temp_dir =
+/// #     TempDir::new().expect("unable to create temporary working directory");
+/// let path = temp_dir.path().join("kvs");
+/// let feed = ChangeFeed::for_store(&path, 1000).unwrap();
+/// let mut store = FeedKvs::new(LogKvs::open(&path).unwrap(), feed);
+/// store.set("key1".to_owned(), "value1".to_owned()).unwrap();
+/// store.remove("key1".to_owned()).unwrap();
+///
+/// let changes = store.feed().changes_since(1).unwrap();
+/// assert_eq!(changes.len(), 1);
+/// assert_eq!(changes[0].key, "key1");
+/// ```
+#[derive(Debug)]
+pub struct FeedKvs<S> {
+    store: S,
+    feed: ChangeFeed,
+}
+
+impl<S: KvStore> FeedKvs<S> {
+    /// Record the store's writes in the feed.
+    pub fn new(store: S, feed: ChangeFeed) -> FeedKvs<S> {
+        FeedKvs { store, feed }
+    }
+
+    /// The feed the writes are recorded in.
+    pub fn feed(&self) -> &ChangeFeed {
+        &self.feed
+    }
+
+    /// The wrapped store.
+    pub fn into_inner(self) -> S {
+        self.store
+    }
+}
+
+impl<S: KvStore> KvStore for FeedKvs<S> {
+    fn set(&mut self, key: String, value: String) -> Result<()> {
+        self.store.set(key.clone(), value.clone())?;
+        self.feed.record(ChangeOp::Set, &key, Some(&value))?;
+        Ok(())
+    }
+
+    fn get(&self, key: String) -> Result<Option<String>> {
+        self.store.get(key)
+    }
+
+    fn remove(&mut self, key: String) -> Result<Option<String>> {
+        let old = self.store.remove_ref(&key)?;
+        if old.is_some() {
+            self.feed.record(ChangeOp::Remove, &key, None)?;
+        }
+        Ok(old)
+    }
+}
+
+impl<S: Scannable> Scannable for FeedKvs<S> {
+    fn keys(&self) -> Result<Vec<String>> {
+        self.store.keys()
+    }
+
+    fn scan(&self, start: &str, end: Option<&str>) -> Result<Vec<String>> {
+        self.store.scan(start, end)
+    }
+
+    fn scan_with(
+        &self,
+        start: &str,
+        end: Option<&str>,
+        options: ScanOptions,
+    ) -> Result<Vec<String>> {
+        self.store.scan_with(start, end, options)
+    }
+
+    fn estimate_range_size(
+        &self,
+        start: &str,
+        end: Option<&str>,
+    ) -> Result<RangeEstimate> {
+        self.store.estimate_range_size(start, end)
+    }
+
+    fn scan_filtered(
+        &self,
+        start: &str,
+        end: Option<&str>,
+        predicate: &dyn Fn(&str, &str) -> bool,
+    ) -> Result<Vec<(String, String)>> {
+        self.store.scan_filtered(start, end, predicate)
+    }
+
+    fn collation(&self) -> Collation {
+        self.store.collation()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tempfile::TempDir;
+
+    use crate::{HashMapKvs, Persistent};
+
+    #[test]
+    fn changes_since() -> Result<()> {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+        let path = temp_dir.path().join("kvs");
+        let feed = ChangeFeed::for_store(&path, 3)?;
+        assert_eq!(feed.changes_since(0), Some(vec![]));
+
+        let mut store = FeedKvs::new(HashMapKvs::open(&path)?, feed);
+        store.set("key\t1".to_owned(), "value1".to_owned())?;
+        store.remove("key2".to_owned())?;
+        store.remove("key\t1".to_owned())?;
+        let changes = store.feed().changes_since(0).unwrap();
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes[0].op, ChangeOp::Set);
+        assert_eq!(changes[0].key, "key\t1");
+        assert_eq!(
+            changes[0].value_sha256,
+            Some(format!("{:x}", Sha256::digest(b"value1")))
+        );
+        assert_eq!((changes[1].sequence, changes[1].op), (2, ChangeOp::Remove));
+        assert_eq!(changes[1].value_sha256, None);
+        drop(store);
+
+        // carries on after reopening, and drops the oldest changes
+        let feed = ChangeFeed::for_store(&path, 3)?;
+        assert_eq!(feed.changes_since(0).unwrap(), changes);
+        let mut store = FeedKvs::new(HashMapKvs::open(&path)?, feed);
+        for i in 3..=8 {
+            store.set(format!("key{}", i), "value".to_owned())?;
+        }
+        let feed = store.feed();
+        assert_eq!(feed.last_sequence(), 8);
+        assert_eq!(feed.changes_since(4), None);
+        let recent = feed.changes_since(5).unwrap();
+        assert_eq!(
+            recent
+                .iter()
+                .map(|change| change.sequence)
+                .collect::<Vec<_>>(),
+            vec![6, 7, 8]
+        );
+        assert_eq!(feed.changes_since(8), Some(vec![]));
+        drop(store);
+        assert_eq!(
+            ChangeFeed::for_store(&path, 3)?.changes_since(5),
+            Some(recent)
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,247 @@
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+use serde_json::json;
+
+use core::{Error, Result};
+
+use crate::{Change, ChangeFeed, ChangeOp};
+
+/// Somewhere a [`FeedConnector`] delivers changes to.
+pub trait ChangeSink {
+    /// Deliver a change. It doesn't have to be durable until `flush`.
+    fn send(&mut self, change: &Change) -> Result<()>;
+
+    /// Make every change sent so far durable. The connector only moves its
+    /// offset past changes once they've been flushed.
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Writes each change as a line of JSON, to standard output, a file or any
+/// other writer.
+///
+/// ```text
+/// {"key":"key1","op":"set","sequence":1,"time":1570000000000,"value_sha256":"3c9..."}
+/// ```
+#[derive(Debug)]
+pub struct JsonSink<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> JsonSink<W> {
+    /// Write changes to the given writer.
+    pub fn new(writer: W) -> JsonSink<W> {
+        JsonSink { writer }
+    }
+
+    /// The writer changes are written to.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write> ChangeSink for JsonSink<W> {
+    fn send(&mut self, change: &Change) -> Result<()> {
+        let op = match change.op {
+            ChangeOp::Set => "set",
+            ChangeOp::Remove => "rm",
+        };
+        let event = json!({
+            "sequence": change.sequence,
+            "op": op,
+            "time": change.time,
+            "key": change.key,
+            "value_sha256": change.value_sha256,
+        });
+        writeln!(self.writer, "{}", event)?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Tails a store's [`ChangeFeed`] and delivers each change to a
+/// [`ChangeSink`], at least once. How far it's got is kept in
+/// `<store>.changes.<name>.offset` and only moved once the sink has flushed,
+/// so after a crash it starts again from the last flush, and the sink may
+/// see some changes twice. Connectors with different names track their
+/// offsets separately.
+///
+/// ```rust
+/// # use tempfile::TempDir;
+/// use kvs::{
+///     ChangeFeed, FeedConnector, FeedKvs, JsonSink, KvStore, LogKvs,
+///     Persistent,
+/// };
+///
+/// # let temp_dir =
+/// #     TempDir::new().expect("unable to create temporary working directory");
+/// let path = temp_dir.path().join("kvs");
+/// let feed = ChangeFeed::for_store(&path, 1000).unwrap();
+/// let mut store = FeedKvs::new(LogKvs::open(&path).unwrap(), feed);
+/// store.set("key1".to_owned(), "value1".to_owned()).unwrap();
+///
+/// let connector = FeedConnector::new(&path, "etl");
+/// let mut sink = JsonSink::new(Vec::new());
+/// assert_eq!(connector.poll(&mut sink).unwrap(), 1);
+/// // already delivered
+/// assert_eq!(connector.poll(&mut sink).unwrap(), 0);
+/// ```
+#[derive(Clone, Debug)]
+pub struct FeedConnector {
+    store: PathBuf,
+    offset_path: PathBuf,
+    name: String,
+}
+
+impl FeedConnector {
+    /// A connector for the feed of the store at the given path.
+    pub fn new<P: AsRef<Path>>(store: P, name: &str) -> FeedConnector {
+        let mut offset_path = store.as_ref().as_os_str().to_owned();
+        offset_path.push(format!(".changes.{}.offset", name));
+        FeedConnector {
+            store: store.as_ref().to_owned(),
+            offset_path: PathBuf::from(offset_path),
+            name: name.to_owned(),
+        }
+    }
+
+    /// The sequence of the last change delivered and flushed, or 0 if
+    /// there hasn't been one.
+    pub fn offset(&self) -> Result<u64> {
+        if !self.offset_path.is_file() {
+            return Ok(0);
+        }
+        fs::read_to_string(&self.offset_path)?
+            .trim()
+            .parse()
+            .map_err(|_| {
+                Error::corrupt_database(format!(
+                    "{} doesn't hold an offset",
+                    self.offset_path.display()
+                ))
+            })
+    }
+
+    /// Deliver every change recorded since the last poll, returning how
+    /// many were delivered. Fails if the feed has already dropped changes
+    /// the connector hasn't delivered.
+    pub fn poll(&self, sink: &mut dyn ChangeSink) -> Result<u64> {
+        let offset = self.offset()?;
+        // read everything still in the file, whatever the writer's capacity
+        let feed = ChangeFeed::for_store(&self.store, !0)?;
+        let changes = feed.changes_since(offset).ok_or_else(|| {
+            Error::config(format!(
+                "the {} connector fell behind, the changes after {} have been \
+                 dropped from the feed",
+                self.name, offset
+            ))
+        })?;
+        let last = match changes.last() {
+            Some(last) => last.sequence,
+            None => return Ok(0),
+        };
+
+        for change in &changes {
+            sink.send(change)?;
+        }
+        sink.flush()?;
+        self.save_offset(last)?;
+        Ok(changes.len() as u64)
+    }
+
+    /// Poll every `interval` for as long as `keep_going` returns true. It's
+    /// given how many changes the last poll delivered.
+    pub fn follow<F>(
+        &self,
+        sink: &mut dyn ChangeSink,
+        interval: Duration,
+        mut keep_going: F,
+    ) -> Result<()>
+    where
+        F: FnMut(u64) -> bool,
+    {
+        while keep_going(self.poll(sink)?) {
+            thread::sleep(interval);
+        }
+        Ok(())
+    }
+
+    fn save_offset(&self, offset: u64) -> Result<()> {
+        let mut tmp = self.offset_path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        fs::write(&tmp, offset.to_string())?;
+        fs::rename(&tmp, &self.offset_path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tempfile::TempDir;
+
+    use crate::{FeedKvs, HashMapKvs, KvStore, Persistent};
+
+    /// Fails to flush, like a sink whose destination is down.
+    struct DownSink;
+
+    impl ChangeSink for DownSink {
+        fn send(&mut self, _change: &Change) -> Result<()> {
+            Ok(())
+        }
+
+        fn flush(&mut self) -> Result<()> {
+            Err(Error::config("down".to_owned()))
+        }
+    }
+
+    #[test]
+    fn deliver_at_least_once() -> Result<()> {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+        let path = temp_dir.path().join("kvs");
+        let feed = ChangeFeed::for_store(&path, 3)?;
+        let mut store = FeedKvs::new(HashMapKvs::open(&path)?, feed);
+        store.set("key1".to_owned(), "value1".to_owned())?;
+        store.remove("key1".to_owned())?;
+
+        let connector = FeedConnector::new(&path, "etl");
+        assert!(connector.poll(&mut DownSink).is_err());
+        assert_eq!(connector.offset()?, 0);
+
+        let mut sink = JsonSink::new(Vec::new());
+        assert_eq!(connector.poll(&mut sink)?, 2);
+        let lines = String::from_utf8(sink.into_inner()).unwrap();
+        let events: Vec<serde_json::Value> = lines
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(events[0]["key"], "key1");
+        assert_eq!(events[0]["op"], "set");
+        assert_eq!(events[1]["op"], "rm");
+        assert_eq!(events[1]["value_sha256"], serde_json::Value::Null);
+        assert_eq!(connector.offset()?, 2);
+
+        // a connector with another name starts from the beginning
+        let other = FeedConnector::new(&path, "audit");
+        assert_eq!(other.poll(&mut JsonSink::new(Vec::new()))?, 2);
+
+        // falls behind once the feed drops undelivered changes
+        for i in 0..6 {
+            store.set(format!("key{}", i), "value".to_owned())?;
+        }
+        assert!(connector.poll(&mut JsonSink::new(Vec::new())).is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,321 @@
+use std::cmp::Ordering;
+use std::sync::Arc;
+use std::time::UNIX_EPOCH;
+
+use core::{Clock, KvStore, Result, Scannable, SystemClock};
+
+use crate::{StampedKvs, StampedRecord, Stamper};
+
+/// A hybrid logical clock timestamp: the wall clock time in milliseconds,
+/// a counter for events within the same millisecond, and the node that
+/// made it, to break ties. Timestamps from different nodes are ordered
+/// even if their clocks disagree, and a node never goes backwards after
+/// seeing a later one.
+#[derive(Clone, Copy, Debug, Default, Eq, Ord, PartialEq, PartialOrd)]
+pub struct Hlc {
+    /// Milliseconds since the Unix epoch.
+    pub wall: u64,
+    /// Orders timestamps with the same wall time.
+    pub logical: u32,
+    /// The node the timestamp was made on.
+    pub node: u32,
+}
+
+impl Hlc {
+    /// How many characters an encoded timestamp takes up.
+    const ENCODED_LEN: usize = 32;
+
+    /// Fixed width hex, so encoded timestamps sort like the timestamps.
+    fn encode(self) -> String {
+        format!("{:016x}{:08x}{:08x}", self.wall, self.logical, self.node)
+    }
+
+    fn decode(encoded: &str) -> Option<Hlc> {
+        if encoded.len() != Self::ENCODED_LEN || !encoded.is_char_boundary(16) {
+            return None;
+        }
+        Some(Hlc {
+            wall: u64::from_str_radix(&encoded[..16], 16).ok()?,
+            logical: u32::from_str_radix(&encoded[16..24], 16).ok()?,
+            node: u32::from_str_radix(&encoded[24..], 16).ok()?,
+        })
+    }
+}
+
+/// A value, or the removal of one, along with when it was written.
+pub type LwwRecord = StampedRecord<Hlc>;
+
+/// Stamps the writes to an [`LwwKvs`] with a hybrid logical clock.
+#[derive(Debug)]
+pub struct HlcStamper {
+    node: u32,
+    clock: Arc<dyn Clock>,
+    last: Hlc,
+}
+
+impl HlcStamper {
+    /// Move the clock past both the wall time and a timestamp from
+    /// elsewhere, returning the new time.
+    fn observe(&mut self, seen: Hlc) -> Hlc {
+        let now = self
+            .clock
+            .now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_millis() as u64);
+        let wall = now.max(self.last.wall).max(seen.wall);
+        let logical = if wall == self.last.wall && wall == seen.wall {
+            self.last.logical.max(seen.logical) + 1
+        } else if wall == self.last.wall {
+            self.last.logical + 1
+        } else if wall == seen.wall {
+            seen.logical + 1
+        } else {
+            0
+        };
+        self.last = Hlc {
+            wall,
+            logical,
+            node: self.node,
+        };
+        self.last
+    }
+}
+
+impl Stamper for HlcStamper {
+    type Stamp = Hlc;
+
+    const RECORD_NAME: &'static str = "a last-writer-wins record";
+
+    fn encode(stamp: &Hlc) -> String {
+        stamp.encode()
+    }
+
+    fn decode(encoded: &str) -> Option<(Hlc, &str)> {
+        if !encoded.is_char_boundary(Hlc::ENCODED_LEN) {
+            return None;
+        }
+        let (stamp, rest) = encoded.split_at(Hlc::ENCODED_LEN);
+        Some((Hlc::decode(stamp)?, rest))
+    }
+
+    /// The current time, since the last write always wins.
+    fn next(&mut self, _: &dyn Fn() -> Result<Option<Hlc>>) -> Result<Hlc> {
+        Ok(self.observe(Hlc::default()))
+    }
+}
+
+/// What [`LwwKvs::sync_with`] copied between the stores.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct SyncReport {
+    /// Records copied from this store to the other one.
+    pub sent: u64,
+    /// Records copied from the other store to this one.
+    pub received: u64,
+}
+
+/// Keeps each key as a last-writer-wins register, so copies of a store
+/// that were changed independently, such as on devices that were offline,
+/// can be merged with [`sync_with`](LwwKvs::sync_with) without conflicts.
+/// Every write is stamped with a hybrid logical clock, and the later write
+/// to a key wins, wherever it was made. See [`StampedKvs`] for how the
+/// records are kept.
+///
+/// ```rust
+/// # use tempfile::TempDir;
+/// use kvs::{HashMapKvs, KvStore, LwwKvs, Persistent};
+///
+/// # let temp_dir =
+/// #     TempDir::new().expect("unable to create temporary working directory");
+/// let laptop = HashMapKvs::open(temp_dir.path().join("laptop")).unwrap();
+/// let phone = HashMapKvs::open(temp_dir.path().join("phone")).unwrap();
+/// let mut laptop = LwwKvs::new(laptop, 1);
+/// let mut phone = LwwKvs::new(phone, 2);
+///
+/// laptop.set("key1".to_owned(), "value1".to_owned()).unwrap();
+/// phone.set("key2".to_owned(), "value2".to_owned()).unwrap();
+/// laptop.sync_with(&mut phone).unwrap();
+/// assert_eq!(
+///     phone.get("key1".to_owned()).unwrap(),
+///     Some("value1".to_owned())
+/// );
+/// ```
+pub type LwwKvs<S> = StampedKvs<S, HlcStamper>;
+
+impl<S: KvStore> LwwKvs<S> {
+    /// Keep last-writer-wins registers in the store, stamping writes as the
+    /// given node.
+    pub fn new(store: S, node: u32) -> LwwKvs<S> {
+        LwwKvs::with_clock(store, node, Arc::new(SystemClock))
+    }
+
+    /// Like `new`, reading the wall time from the given clock.
+    pub fn with_clock(store: S, node: u32, clock: Arc<dyn Clock>) -> LwwKvs<S> {
+        StampedKvs {
+            store,
+            stamper: HlcStamper {
+                node,
+                clock,
+                last: Hlc::default(),
+            },
+        }
+    }
+}
+
+impl<S: Scannable> LwwKvs<S> {
+    /// Merge the two stores in both directions, so they end up with the
+    /// same records. For each key, whichever store wrote it last wins,
+    /// including removals. Both clocks are moved past every timestamp
+    /// seen, so later writes on either side win over what was synced.
+    pub fn sync_with<T: Scannable>(
+        &mut self,
+        other: &mut LwwKvs<T>,
+    ) -> Result<SyncReport> {
+        let mut report = SyncReport::default();
+        let mut latest = Hlc::default();
+        for key in self.keys_to_sync(other)? {
+            let ours = self.record(&key)?;
+            let theirs = other.record(&key)?;
+            // a missing record sorts before any other
+            let order = ours
+                .as_ref()
+                .map(|ours| ours.stamp)
+                .cmp(&theirs.as_ref().map(|theirs| theirs.stamp));
+            match (order, ours, theirs) {
+                (Ordering::Greater, Some(ours), _) => {
+                    latest = latest.max(ours.stamp);
+                    other.put(key, &ours)?;
+                    report.sent += 1;
+                }
+                (Ordering::Less, _, Some(theirs)) => {
+                    latest = latest.max(theirs.stamp);
+                    self.put(key, &theirs)?;
+                    report.received += 1;
+                }
+                _ => {}
+            }
+        }
+        self.stamper.observe(latest);
+        other.stamper.observe(latest);
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Mutex;
+    use std::time::{Duration, SystemTime};
+
+    use tempfile::TempDir;
+
+    use crate::{HashMapKvs, LogKvs, Persistent};
+
+    /// A clock that only moves when told to.
+    #[derive(Debug)]
+    struct TestClock(Mutex<SystemTime>);
+
+    impl TestClock {
+        fn at_secs(secs: u64) -> Arc<TestClock> {
+            Arc::new(TestClock(Mutex::new(
+                UNIX_EPOCH + Duration::from_secs(secs),
+            )))
+        }
+
+        fn advance(&self, secs: u64) {
+            *self.0.lock().unwrap() += Duration::from_secs(secs);
+        }
+    }
+
+    impl Clock for TestClock {
+        fn now(&self) -> SystemTime {
+            *self.0.lock().unwrap()
+        }
+    }
+
+    #[test]
+    fn last_writer_wins() -> Result<()> {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+        let clock = TestClock::at_secs(1000);
+        let mut a = LwwKvs::with_clock(
+            HashMapKvs::open(temp_dir.path().join("a"))?,
+            1,
+            clock.clone(),
+        );
+        let mut b = LwwKvs::with_clock(
+            LogKvs::open(temp_dir.path().join("b"))?,
+            2,
+            clock.clone(),
+        );
+
+        a.set("key1".to_owned(), "a1".to_owned())?;
+        a.set("key2".to_owned(), "a2".to_owned())?;
+        b.set("key3".to_owned(), "b3".to_owned())?;
+        clock.advance(1);
+        b.set("key1".to_owned(), "b1".to_owned())?;
+        b.remove("key3".to_owned())?;
+        a.remove("key2".to_owned())?;
+
+        let report = a.sync_with(&mut b)?;
+        assert_eq!(
+            report,
+            SyncReport {
+                sent: 1,
+                received: 2
+            }
+        );
+        for store in &[&a as &dyn Scannable, &b] {
+            assert_eq!(store.get("key1".to_owned())?, Some("b1".to_owned()));
+            assert_eq!(store.get("key2".to_owned())?, None);
+            assert_eq!(store.get("key3".to_owned())?, None);
+            assert_eq!(store.keys()?, vec!["key1".to_owned()]);
+        }
+        assert_eq!(a.sync_with(&mut b)?, SyncReport::default());
+
+        Ok(())
+    }
+
+    #[test]
+    fn clock_skew() -> Result<()> {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+        let fast = TestClock::at_secs(2000);
+        let slow = TestClock::at_secs(1000);
+        let mut a = LwwKvs::with_clock(
+            HashMapKvs::open(temp_dir.path().join("a"))?,
+            1,
+            fast,
+        );
+        let mut b = LwwKvs::with_clock(
+            HashMapKvs::open(temp_dir.path().join("b"))?,
+            2,
+            slow,
+        );
+
+        a.set("key1".to_owned(), "a1".to_owned())?;
+        a.sync_with(&mut b)?;
+        // written after the sync, so it wins despite the slow clock
+        b.set("key1".to_owned(), "b1".to_owned())?;
+        assert!(
+            b.record("key1")?.unwrap().stamp > a.record("key1")?.unwrap().stamp
+        );
+        b.sync_with(&mut a)?;
+        assert_eq!(a.get("key1".to_owned())?, Some("b1".to_owned()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn plain_values() -> Result<()> {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+        let mut store = HashMapKvs::open(temp_dir.path().join("a"))?;
+        store.set("key1".to_owned(), "plain".to_owned())?;
+
+        let store = LwwKvs::new(store, 1);
+        assert!(store.get("key1".to_owned()).is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,115 @@
+use std::cmp::Ordering;
+
+use core::{Result, Scannable};
+
+/// How the contents of two stores differ.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct StoreDiff {
+    /// Keys only the first store has a value for, sorted.
+    pub only_in_a: Vec<String>,
+    /// Keys only the second store has a value for, sorted.
+    pub only_in_b: Vec<String>,
+    /// Keys both stores have, with different values, sorted.
+    pub changed: Vec<String>,
+}
+
+impl StoreDiff {
+    /// Whether the stores hold the same keys and values.
+    pub fn is_empty(&self) -> bool {
+        self.only_in_a.is_empty()
+            && self.only_in_b.is_empty()
+            && self.changed.is_empty()
+    }
+}
+
+/// Compare two stores, which can be different engines. Only the keys are
+/// held in memory; values are read one pair at a time.
+///
+/// ```rust
+/// # use tempfile::TempDir;
+/// use kvs::{diff, HashMapKvs, KvStore, LogKvs, Persistent};
+///
+/// # let temp_dir =
+/// #     TempDir::new().expect("unable to create temporary working directory");
+/// let mut a = HashMapKvs::open(temp_dir.path().join("a")).unwrap();
+/// let mut b = LogKvs::open(temp_dir.path().join("b")).unwrap();
+/// a.set("key1".to_owned(), "value1".to_owned()).unwrap();
+/// b.set("key1".to_owned(), "value2".to_owned()).unwrap();
+/// assert_eq!(diff(&a, &b).unwrap().changed, vec!["key1"]);
+/// ```
+pub fn diff<A, B>(a: &A, b: &B) -> Result<StoreDiff>
+where
+    A: Scannable + ?Sized,
+    B: Scannable + ?Sized,
+{
+    let mut a_keys = a.keys()?;
+    let mut b_keys = b.keys()?;
+    a_keys.sort();
+    b_keys.sort();
+
+    let mut diff = StoreDiff::default();
+    let mut a_keys = a_keys.into_iter().peekable();
+    let mut b_keys = b_keys.into_iter().peekable();
+    loop {
+        let order = match (a_keys.peek(), b_keys.peek()) {
+            (Some(a_key), Some(b_key)) => a_key.cmp(b_key),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => break,
+        };
+        match order {
+            Ordering::Less => diff.only_in_a.extend(a_keys.next()),
+            Ordering::Greater => diff.only_in_b.extend(b_keys.next()),
+            Ordering::Equal => {
+                let key = a_keys.next().expect("peeked a key");
+                b_keys.next();
+                if a.get_ref(&key)? != b.get_ref(&key)? {
+                    diff.changed.push(key);
+                }
+            }
+        }
+    }
+
+    Ok(diff)
+}
+
+#[cfg(all(test, feature = "hashmap", feature = "log"))]
+mod tests {
+    use super::*;
+
+    use tempfile::TempDir;
+
+    use core::{KvStore, Persistent};
+
+    use crate::{HashMapKvs, LogKvs};
+
+    #[test]
+    fn across_engines() -> Result<()> {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+        let mut a = HashMapKvs::open(temp_dir.path().join("a"))?;
+        let mut b = LogKvs::open(temp_dir.path().join("b"))?;
+        assert!(diff(&a, &b)?.is_empty());
+
+        for store in &mut [&mut a as &mut dyn KvStore, &mut b] {
+            store.set("same".to_owned(), "value1".to_owned())?;
+            store.set("changed".to_owned(), "value2".to_owned())?;
+        }
+        a.set("changed".to_owned(), "value3".to_owned())?;
+        a.set("a1".to_owned(), "value4".to_owned())?;
+        a.set("a2".to_owned(), "value5".to_owned())?;
+        b.set("b1".to_owned(), "value6".to_owned())?;
+
+        assert_eq!(
+            diff(&a, &b)?,
+            StoreDiff {
+                only_in_a: vec!["a1".to_owned(), "a2".to_owned()],
+                only_in_b: vec!["b1".to_owned()],
+                changed: vec!["changed".to_owned()],
+            }
+        );
+        assert_eq!(diff(&b, &a)?.only_in_a, vec!["b1".to_owned()]);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,142 @@
+use sha2::{Digest, Sha256};
+
+use core::{in_range, Result, Scannable};
+
+/// A digest of the keys and values in a range of a store, see [`digest`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RangeDigest {
+    /// How many keys were in the range.
+    pub keys: u64,
+    /// The root of the Merkle tree over the range, in lowercase hex.
+    pub root: String,
+}
+
+/// Hash the keys and values from `start` up to but not including `end`, or
+/// up to the last key if there's no end, into a Merkle tree. Stores with
+/// the same keys and values in the range have the same root, whatever
+/// engine they use, so it can check a migration or a backup without
+/// comparing the stores directly. Keys are sorted first, so the store
+/// doesn't need to keep them in order.
+///
+/// Each key and value is a SHA-256 leaf, and each pair of nodes is hashed
+/// into its parent, carrying an odd node at the end of a level up as is.
+///
+/// ```rust
+/// # use tempfile::TempDir;
+/// use kvs::{digest, HashMapKvs, KvStore, LogKvs, Persistent};
+///
+/// # let temp_dir =
+/// #     TempDir::new().expect("unable to create temporary working directory");
+/// let mut a = HashMapKvs::open(temp_dir.path().join("a")).unwrap();
+/// let mut b = LogKvs::open(temp_dir.path().join("b")).unwrap();
+/// a.set("key1".to_owned(), "value1".to_owned()).unwrap();
+/// b.set("key1".to_owned(), "value1".to_owned()).unwrap();
+/// assert_eq!(digest(&a, "", None).unwrap(), digest(&b, "", None).unwrap());
+/// ```
+pub fn digest<S>(
+    store: &S,
+    start: &str,
+    end: Option<&str>,
+) -> Result<RangeDigest>
+where
+    S: Scannable + ?Sized,
+{
+    let mut keys: Vec<String> = store
+        .keys()?
+        .into_iter()
+        .filter(|key| in_range(key, start, end))
+        .collect();
+    keys.sort();
+
+    let mut level = Vec::with_capacity(keys.len());
+    for key in &keys {
+        // a key can be removed between listing and reading it
+        if let Some(value) = store.get_ref(key)? {
+            level.push(leaf(key, &value));
+        }
+    }
+    let count = level.len() as u64;
+
+    while level.len() > 1 {
+        level = level
+            .chunks(2)
+            .map(|pair| match pair {
+                [left, right] => node(left, right),
+                [odd] => odd.clone(),
+                _ => unreachable!("chunks are one or two nodes"),
+            })
+            .collect();
+    }
+    let root = match level.pop() {
+        Some(root) => root,
+        None => Sha256::new().result().to_vec(),
+    };
+
+    Ok(RangeDigest {
+        keys: count,
+        root: root.iter().map(|byte| format!("{:02x}", byte)).collect(),
+    })
+}
+
+/// Leaves and nodes are prefixed differently, so a leaf can't be passed
+/// off as a node. Lengths are included so keys and values can't run into
+/// each other.
+fn leaf(key: &str, value: &str) -> Vec<u8> {
+    let mut hash = Sha256::new();
+    hash.input([0u8]);
+    hash.input((key.len() as u64).to_be_bytes());
+    hash.input(key);
+    hash.input((value.len() as u64).to_be_bytes());
+    hash.input(value);
+    hash.result().to_vec()
+}
+
+fn node(left: &[u8], right: &[u8]) -> Vec<u8> {
+    let mut hash = Sha256::new();
+    hash.input([1u8]);
+    hash.input(left);
+    hash.input(right);
+    hash.result().to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tempfile::TempDir;
+
+    use crate::{HashMapKvs, KvStore, LogKvs, Persistent};
+
+    #[test]
+    fn digests() -> Result<()> {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+        let mut a = HashMapKvs::open(temp_dir.path().join("a"))?;
+        let mut b = LogKvs::open(temp_dir.path().join("b"))?;
+        let empty = digest(&a, "", None)?;
+        assert_eq!(empty.keys, 0);
+        assert_eq!(empty, digest(&b, "", None)?);
+
+        for (key, value) in &[("a", "1"), ("b", "2"), ("c", "3")] {
+            a.set(key.to_string(), value.to_string())?;
+            b.set(key.to_string(), value.to_string())?;
+        }
+        let all = digest(&a, "", None)?;
+        assert_eq!(all.keys, 3);
+        assert_eq!(all, digest(&b, "", None)?);
+        assert_ne!(all.root, empty.root);
+
+        b.set("c".to_owned(), "4".to_owned())?;
+        assert_ne!(all, digest(&b, "", None)?);
+        // only the changed key's range differs
+        assert_eq!(digest(&a, "a", Some("c"))?, digest(&b, "a", Some("c"))?);
+        assert_eq!(digest(&a, "a", Some("c"))?.keys, 2);
+
+        // moving a value to another key changes the digest
+        a.remove("c".to_owned())?;
+        a.set("d".to_owned(), "3".to_owned())?;
+        assert_ne!(digest(&a, "", None)?.root, all.root);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,445 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde_json::{json, Map, Value};
+
+use core::{Error, IndexKind, Persistent, Result, StoreOptions, SyncPolicy};
+
+use crate::{
+    CompactionDecision, CompactionPolicy, CompactionScheduler, LogKvs,
+};
+
+/// How a column family is tuned, see [`ColumnFamilies`].
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct FamilyOptions {
+    /// When the family is compacted by
+    /// [`compact_due`](ColumnFamilies::compact_due).
+    pub compaction: CompactionPolicy,
+    /// How long removed values are kept in the family's trash, see
+    /// [`StoreOptions::trash_retention`].
+    pub trash_retention: Option<Duration>,
+    /// When the family's writes are synced to disk.
+    pub sync: SyncPolicy,
+    /// How the family's keys are indexed in memory. Families that are
+    /// scanned need `IndexKind::Ordered`.
+    pub index: IndexKind,
+}
+
+impl FamilyOptions {
+    /// Check the options make sense together, returning a `Config` error
+    /// describing the first problem if they don't.
+    pub fn validate(&self) -> Result<()> {
+        let policy = &self.compaction;
+        if policy.busy_stale_percent > 100 || policy.idle_stale_percent > 100 {
+            return Err(Error::config(
+                "compaction percentages can't be over 100".to_owned(),
+            ));
+        }
+        if policy.idle_stale_percent > policy.busy_stale_percent {
+            return Err(Error::config(format!(
+                "compacting when idle at {}% stale and when busy at {}% means \
+                 never waiting for the store to be idle",
+                policy.idle_stale_percent, policy.busy_stale_percent
+            )));
+        }
+        Ok(())
+    }
+
+    fn to_json(&self) -> Value {
+        let policy = &self.compaction;
+        json!({
+            "compaction": {
+                "min_stale_bytes": policy.min_stale_bytes,
+                "idle_stale_percent": policy.idle_stale_percent,
+                "busy_stale_percent": policy.busy_stale_percent,
+                "idle_after_ms": policy.idle_after.as_millis() as u64,
+                "max_writes_per_sec": policy.max_writes_per_sec,
+            },
+            "trash_retention_ms": self
+                .trash_retention
+                .map(|retention| retention.as_millis() as u64),
+            "sync": match self.sync {
+                SyncPolicy::Always => "always",
+                SyncPolicy::Never => "never",
+            },
+            "index": match self.index {
+                IndexKind::Hash => "hash",
+                IndexKind::Ordered => "ordered",
+            },
+        })
+    }
+
+    fn from_json(json: &Value) -> Option<FamilyOptions> {
+        let policy = &json["compaction"];
+        let trash_retention = match &json["trash_retention_ms"] {
+            Value::Null => None,
+            ms => Some(Duration::from_millis(ms.as_u64()?)),
+        };
+        // families created before these were kept have the defaults
+        let sync = match json["sync"].as_str() {
+            None => SyncPolicy::default(),
+            Some("always") => SyncPolicy::Always,
+            Some("never") => SyncPolicy::Never,
+            Some(_) => return None,
+        };
+        let index = match json["index"].as_str() {
+            None => IndexKind::default(),
+            Some("hash") => IndexKind::Hash,
+            Some("ordered") => IndexKind::Ordered,
+            Some(_) => return None,
+        };
+        Some(FamilyOptions {
+            compaction: CompactionPolicy {
+                min_stale_bytes: policy["min_stale_bytes"].as_u64()?,
+                idle_stale_percent: policy["idle_stale_percent"].as_u64()?,
+                busy_stale_percent: policy["busy_stale_percent"].as_u64()?,
+                idle_after: Duration::from_millis(
+                    policy["idle_after_ms"].as_u64()?,
+                ),
+                max_writes_per_sec: policy["max_writes_per_sec"].as_u64()?,
+            },
+            trash_retention,
+            sync,
+            index,
+        })
+    }
+}
+
+#[derive(Debug)]
+struct Family {
+    store: LogKvs,
+    options: FamilyOptions,
+    scheduler: Arc<CompactionScheduler>,
+}
+
+impl Family {
+    fn open(path: &Path, options: FamilyOptions) -> Result<Family> {
+        let scheduler =
+            Arc::new(CompactionScheduler::new(options.compaction.clone()));
+        let store = LogKvs::open_with(
+            path,
+            StoreOptions {
+                trash_retention: options.trash_retention,
+                sync: options.sync,
+                index: options.index,
+                observer: Some(scheduler.clone()),
+                ..StoreOptions::default()
+            },
+        )?;
+        Ok(Family {
+            store,
+            options,
+            scheduler,
+        })
+    }
+}
+
+/// Independent keyspaces in one directory, each a [`LogKvs`] with its own
+/// log and [`FamilyOptions`], so small, hot metadata and bulk data can be
+/// tuned separately without opening unrelated stores.
+///
+/// The families and their options are listed in a `FAMILIES` manifest, and
+/// the options are checked again each time it's opened. Each family is kept
+/// in `cf/<name>`. There's always a `default` family, which can't be dropped,
+/// and whose write lock keeps other processes from opening the directory.
+///
+/// ```rust
+/// # use tempfile::TempDir;
+/// use kvs::{ColumnFamilies, FamilyOptions, KvStore};
+///
+/// # let temp_dir =
+/// #     TempDir::new().expect("unable to create temporary working directory");
+/// let mut families = ColumnFamilies::open(temp_dir.path()).unwrap();
+/// families
+///     .create_family("blobs", FamilyOptions::default())
+///     .unwrap();
+///
+/// let blobs = families.family_mut("blobs").unwrap();
+/// blobs.set("key1".to_owned(), "value1".to_owned()).unwrap();
+/// let default = families.family("default").unwrap();
+/// assert_eq!(default.get("key1".to_owned()).unwrap(), None);
+/// ```
+#[derive(Debug)]
+pub struct ColumnFamilies {
+    dir: PathBuf,
+    families: BTreeMap<String, Family>,
+}
+
+impl ColumnFamilies {
+    /// The family every directory has.
+    pub const DEFAULT: &'static str = "default";
+    /// The name of the manifest listing the families.
+    const MANIFEST_FILE_NAME: &'static str = "FAMILIES";
+    /// The directory the families are kept in.
+    const FAMILY_DIR_NAME: &'static str = "cf";
+
+    /// Open the families in the given directory, creating it with only the
+    /// default family if it doesn't exist.
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<ColumnFamilies> {
+        let dir = dir.as_ref().to_owned();
+        let manifest = dir.join(Self::MANIFEST_FILE_NAME);
+        let mut families = ColumnFamilies {
+            dir,
+            families: BTreeMap::new(),
+        };
+
+        let mut listed = if manifest.is_file() {
+            Self::read_manifest(&manifest)?
+        } else {
+            BTreeMap::new()
+        };
+        // opened first, so its lock is taken before anything else is read
+        let default = listed
+            .remove(Self::DEFAULT)
+            .unwrap_or_else(FamilyOptions::default);
+        families.open_family(Self::DEFAULT, default)?;
+        for (name, options) in listed {
+            families.open_family(&name, options)?;
+        }
+        families.write_manifest()?;
+        Ok(families)
+    }
+
+    /// Add a family with the given options, which are kept in the manifest
+    /// and used each time it's opened. Names are letters, digits, `-` and
+    /// `_`.
+    pub fn create_family(
+        &mut self,
+        name: &str,
+        options: FamilyOptions,
+    ) -> Result<()> {
+        let valid = !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid {
+            return Err(Error::config(format!(
+                "`{}` isn't a valid column family name",
+                name
+            )));
+        }
+        options.validate()?;
+        if self.families.contains_key(name) {
+            return Err(Error::config(format!(
+                "the column family {} already exists",
+                name
+            )));
+        }
+        // left behind if the family was dropped by a process that stopped
+        // before removing it
+        let path = self.family_path(name);
+        if path.exists() {
+            fs::remove_dir_all(&path)?;
+        }
+        self.open_family(name, options)?;
+        self.write_manifest()
+    }
+
+    /// Remove a family and everything in it, returning whether there was
+    /// one with the name. The default family can't be dropped.
+    pub fn drop_family(&mut self, name: &str) -> Result<bool> {
+        if name == Self::DEFAULT {
+            return Err(Error::config(
+                "the default column family can't be dropped".to_owned(),
+            ));
+        }
+        let family = match self.families.remove(name) {
+            Some(family) => family,
+            None => return Ok(false),
+        };
+        // the family is forgotten before its files go, so a crash leaves
+        // files that are ignored rather than a family that's missing them
+        self.write_manifest()?;
+        drop(family);
+        fs::remove_dir_all(self.family_path(name))?;
+        Ok(true)
+    }
+
+    /// The names of the families, in order.
+    pub fn names(&self) -> Vec<&str> {
+        self.families.keys().map(|name| &name[..]).collect()
+    }
+
+    /// A family's store.
+    pub fn family(&self, name: &str) -> Option<&LogKvs> {
+        self.families.get(name).map(|family| &family.store)
+    }
+
+    /// A family's store, to write to.
+    pub fn family_mut(&mut self, name: &str) -> Option<&mut LogKvs> {
+        self.families.get_mut(name).map(|family| &mut family.store)
+    }
+
+    /// The options a family was created with.
+    pub fn options(&self, name: &str) -> Option<&FamilyOptions> {
+        self.families.get(name).map(|family| &family.options)
+    }
+
+    /// Compact each family its own policy says is due, returning the
+    /// decision made for each.
+    pub fn compact_due(
+        &mut self,
+    ) -> Result<BTreeMap<String, CompactionDecision>> {
+        let mut decisions = BTreeMap::new();
+        for (name, family) in &mut self.families {
+            let decision = family.scheduler.run(&mut family.store)?;
+            decisions.insert(name.clone(), decision);
+        }
+        Ok(decisions)
+    }
+
+    fn family_path(&self, name: &str) -> PathBuf {
+        self.dir.join(Self::FAMILY_DIR_NAME).join(name)
+    }
+
+    fn open_family(
+        &mut self,
+        name: &str,
+        options: FamilyOptions,
+    ) -> Result<()> {
+        let path = self.family_path(name);
+        fs::create_dir_all(&path)?;
+        let family = Family::open(&path, options)?;
+        self.families.insert(name.to_owned(), family);
+        Ok(())
+    }
+
+    fn read_manifest(path: &Path) -> Result<BTreeMap<String, FamilyOptions>> {
+        let invalid = || {
+            Error::corrupt_database(format!(
+                "{} isn't a valid column family manifest",
+                path.display()
+            ))
+        };
+        let manifest: Value = serde_json::from_str(&fs::read_to_string(path)?)
+            .map_err(|_| invalid())?;
+        let mut families = BTreeMap::new();
+        for (name, options) in
+            manifest["families"].as_object().ok_or_else(invalid)?
+        {
+            let options =
+                FamilyOptions::from_json(options).ok_or_else(invalid)?;
+            options.validate().map_err(|err| {
+                Error::corrupt_database(format!(
+                    "the column family {} in {} has invalid options: {}",
+                    name,
+                    path.display(),
+                    err
+                ))
+            })?;
+            families.insert(name.clone(), options);
+        }
+        Ok(families)
+    }
+
+    fn write_manifest(&self) -> Result<()> {
+        let families: Map<String, Value> = self
+            .families
+            .iter()
+            .map(|(name, family)| (name.clone(), family.options.to_json()))
+            .collect();
+        let path = self.dir.join(Self::MANIFEST_FILE_NAME);
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        fs::write(&tmp, json!({ "families": families }).to_string())?;
+        fs::rename(&tmp, &path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tempfile::TempDir;
+
+    use core::KvStore;
+
+    #[test]
+    fn families() -> Result<()> {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+        let mut families = ColumnFamilies::open(temp_dir.path())?;
+        assert_eq!(families.names(), vec!["default"]);
+
+        let eager = FamilyOptions {
+            compaction: CompactionPolicy {
+                min_stale_bytes: 0,
+                idle_stale_percent: 0,
+                busy_stale_percent: 0,
+                ..CompactionPolicy::default()
+            },
+            trash_retention: Some(Duration::from_secs(60)),
+            sync: SyncPolicy::Always,
+            index: IndexKind::Ordered,
+        };
+        families.create_family("meta", eager.clone())?;
+        assert!(families.create_family("meta", eager.clone()).is_err());
+        assert!(families.create_family("../up", eager.clone()).is_err());
+        let never_idle = FamilyOptions {
+            compaction: CompactionPolicy {
+                idle_stale_percent: 90,
+                busy_stale_percent: 50,
+                ..CompactionPolicy::default()
+            },
+            ..FamilyOptions::default()
+        };
+        assert!(families.create_family("odd", never_idle).is_err());
+        families.create_family("bulk", FamilyOptions::default())?;
+
+        for name in &["meta", "bulk"] {
+            let store = families.family_mut(name).unwrap();
+            store.set("key1".to_owned(), name.to_string())?;
+            store.set("key1".to_owned(), name.to_string())?;
+        }
+        assert_eq!(
+            families.family("default").unwrap().get("key1".to_owned())?,
+            None
+        );
+
+        // each family is compacted by its own policy
+        let decisions = families.compact_due()?;
+        assert!(decisions["meta"].compact);
+        assert!(!decisions["bulk"].compact);
+
+        drop(families);
+        let mut families = ColumnFamilies::open(temp_dir.path())?;
+        assert_eq!(families.names(), vec!["bulk", "default", "meta"]);
+        assert_eq!(families.options("meta"), Some(&eager));
+        assert_eq!(
+            families.family("bulk").unwrap().get("key1".to_owned())?,
+            Some("bulk".to_owned())
+        );
+
+        assert!(families.drop_family("bulk")?);
+        assert!(!families.drop_family("bulk")?);
+        assert!(families.drop_family("default").is_err());
+        drop(families);
+        let families = ColumnFamilies::open(temp_dir.path())?;
+        assert_eq!(families.names(), vec!["default", "meta"]);
+        assert_eq!(
+            families.family("meta").unwrap().index_kind(),
+            IndexKind::Ordered
+        );
+        drop(families);
+
+        // options are checked again when opened
+        let manifest = temp_dir.path().join("FAMILIES");
+        let listed = fs::read_to_string(&manifest)?;
+        fs::write(&manifest, listed.replace("\"ordered\"", "\"btree\""))?;
+        assert!(ColumnFamilies::open(temp_dir.path()).is_err());
+        fs::write(
+            &manifest,
+            listed.replace(
+                "\"idle_stale_percent\":0",
+                "\"idle_stale_percent\":101",
+            ),
+        )?;
+        assert!(ColumnFamilies::open(temp_dir.path()).is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,286 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use core::{
+    Compactable, FlowWarning, KvStore, Measurable, Result, StoreObserver,
+};
+
+/// When a [`FlowKvs`] warns that a store is falling behind.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FlowThresholds {
+    /// A write taking longer than this is a stall.
+    pub stall_after: Duration,
+    /// Warn once more stale bytes than this are waiting for compaction.
+    pub max_stale_bytes: u64,
+    /// Warn once the store takes up more bytes than this on disk.
+    pub max_disk_bytes: u64,
+}
+
+impl Default for FlowThresholds {
+    fn default() -> FlowThresholds {
+        FlowThresholds {
+            stall_after: Duration::from_millis(100),
+            max_stale_bytes: 64 * 1024 * 1024,
+            max_disk_bytes: 1024 * 1024 * 1024,
+        }
+    }
+}
+
+/// What a [`FlowKvs`] has measured. The write counters only go up, while
+/// the gauges are as of the last [`check`](FlowKvs::check).
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct FlowMetrics {
+    /// How many writes there have been.
+    pub writes: u64,
+    /// How many of them stalled.
+    pub write_stalls: u64,
+    /// How long the stalled writes took altogether.
+    pub stalled_for: Duration,
+    /// The longest any write took.
+    pub slowest_write: Duration,
+    /// How many stale bytes compaction would reclaim.
+    pub compaction_debt: u64,
+    /// How many bytes the store takes up on disk.
+    pub disk_bytes: u64,
+}
+
+/// Measures how well a store keeps up with its writes, and passes a
+/// [`FlowWarning`] to the observer as each threshold is crossed, so slow
+/// disks and overdue compactions are noticed before writes time out.
+///
+/// Every write is timed. Compaction debt and disk usage come from the
+/// store's statistics, which can mean reading the whole store, so they're
+/// only gathered by [`check`](FlowKvs::check), to be called periodically.
+/// They're warned about once each time they go over their threshold, not
+/// at every check while they stay over it.
+///
+/// ```rust
+/// # use std::sync::Arc;
+/// # use tempfile::TempDir;
+/// use kvs::{FlowKvs, FlowThresholds, KvStore, LogKvs, Persistent};
+///
+/// # let temp_dir =
+/// #     TempDir::new().expect("unable to create temporary working directory");
+/// let store = LogKvs::open(temp_dir.path()).unwrap();
+/// let mut store = FlowKvs::new(store, FlowThresholds::default(), None);
+/// store.set("key1".to_owned(), "value1".to_owned()).unwrap();
+/// store.set("key1".to_owned(), "value2".to_owned()).unwrap();
+///
+/// let metrics = store.check().unwrap();
+/// assert_eq!(metrics.writes, 2);
+/// assert!(metrics.compaction_debt > 0);
+/// ```
+#[derive(Debug)]
+pub struct FlowKvs<S> {
+    store: S,
+    thresholds: FlowThresholds,
+    observer: Option<Arc<dyn StoreObserver>>,
+    metrics: FlowMetrics,
+}
+
+impl<S: Measurable> FlowKvs<S> {
+    /// Measure the store, warning the observer, if there is one, when the
+    /// thresholds are crossed.
+    pub fn new(
+        store: S,
+        thresholds: FlowThresholds,
+        observer: Option<Arc<dyn StoreObserver>>,
+    ) -> FlowKvs<S> {
+        FlowKvs {
+            store,
+            thresholds,
+            observer,
+            metrics: FlowMetrics::default(),
+        }
+    }
+
+    /// The metrics as of now, without gathering the store's statistics.
+    pub fn metrics(&self) -> &FlowMetrics {
+        &self.metrics
+    }
+
+    /// Gather the store's statistics to update the compaction debt and disk
+    /// usage, warning about any that have gone over their threshold since
+    /// the last check.
+    pub fn check(&mut self) -> Result<&FlowMetrics> {
+        let stats = self.store.stats()?;
+        let thresholds = &self.thresholds;
+        let previous = &self.metrics;
+        let mut warnings = Vec::new();
+        if stats.stale_bytes > thresholds.max_stale_bytes
+            && previous.compaction_debt <= thresholds.max_stale_bytes
+        {
+            warnings.push(FlowWarning::CompactionDebt {
+                stale_bytes: stats.stale_bytes,
+            });
+        }
+        if stats.disk_bytes > thresholds.max_disk_bytes
+            && previous.disk_bytes <= thresholds.max_disk_bytes
+        {
+            warnings.push(FlowWarning::DiskUsage {
+                disk_bytes: stats.disk_bytes,
+            });
+        }
+
+        self.metrics.compaction_debt = stats.stale_bytes;
+        self.metrics.disk_bytes = stats.disk_bytes;
+        for warning in &warnings {
+            self.warn(warning);
+        }
+        Ok(&self.metrics)
+    }
+
+    /// Compact the wrapped store. The compaction debt it clears is seen at
+    /// the next check.
+    pub fn compact(&mut self) -> Result<()>
+    where
+        S: Compactable,
+    {
+        self.store.compact()
+    }
+
+    /// The wrapped store.
+    pub fn into_inner(self) -> S {
+        self.store
+    }
+
+    /// Count a write that took the given time, warning if it stalled.
+    fn timed(&mut self, operation: &str, started: Instant) {
+        let took = started.elapsed();
+        let metrics = &mut self.metrics;
+        metrics.writes += 1;
+        metrics.slowest_write = metrics.slowest_write.max(took);
+        if took > self.thresholds.stall_after {
+            metrics.write_stalls += 1;
+            metrics.stalled_for += took;
+            self.warn(&FlowWarning::WriteStall {
+                operation: operation.to_owned(),
+                took,
+            });
+        }
+    }
+
+    fn warn(&self, warning: &FlowWarning) {
+        if let Some(observer) = &self.observer {
+            observer.on_warning(warning);
+        }
+    }
+}
+
+impl<S: Measurable> KvStore for FlowKvs<S> {
+    fn set(&mut self, key: String, value: String) -> Result<()> {
+        let started = Instant::now();
+        self.store.set(key, value)?;
+        self.timed("set", started);
+        Ok(())
+    }
+
+    fn get(&self, key: String) -> Result<Option<String>> {
+        self.store.get(key)
+    }
+
+    fn remove(&mut self, key: String) -> Result<Option<String>> {
+        let started = Instant::now();
+        let old = self.store.remove(key)?;
+        self.timed("remove", started);
+        Ok(old)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Mutex;
+
+    use tempfile::TempDir;
+
+    use crate::{LogKvs, Persistent};
+
+    #[derive(Debug, Default)]
+    struct Warnings(Mutex<Vec<FlowWarning>>);
+
+    impl StoreObserver for Warnings {
+        fn on_warning(&self, warning: &FlowWarning) {
+            self.0.lock().unwrap().push(warning.clone());
+        }
+    }
+
+    #[test]
+    fn warnings() -> Result<()> {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+        let warnings = Arc::new(Warnings::default());
+        let thresholds = FlowThresholds {
+            stall_after: Duration::from_secs(60),
+            max_stale_bytes: 0,
+            max_disk_bytes: 1024,
+        };
+        let mut store = FlowKvs::new(
+            LogKvs::open(temp_dir.path())?,
+            thresholds,
+            Some(warnings.clone()),
+        );
+
+        store.set("key1".to_owned(), "value1".to_owned())?;
+        store.set("key1".to_owned(), "value2".to_owned())?;
+        store.remove("key2".to_owned())?;
+        let metrics = store.check()?.clone();
+        assert_eq!((metrics.writes, metrics.write_stalls), (3, 0));
+        assert!(metrics.compaction_debt > 0);
+        assert!(metrics.disk_bytes > 0);
+        assert_eq!(
+            *warnings.0.lock().unwrap(),
+            vec![FlowWarning::CompactionDebt {
+                stale_bytes: metrics.compaction_debt
+            }]
+        );
+
+        // only warned about again after dropping back under the threshold
+        store.set("key1".to_owned(), "value3".to_owned())?;
+        store.check()?;
+        assert_eq!(warnings.0.lock().unwrap().len(), 1);
+        store.compact()?;
+        assert_eq!(store.check()?.compaction_debt, 0);
+        store.set("key1".to_owned(), "x".repeat(2048))?;
+        store.set("key1".to_owned(), "value4".to_owned())?;
+        store.check()?;
+        let warned = warnings.0.lock().unwrap();
+        assert_eq!(warned.len(), 3);
+        match &warned[2] {
+            FlowWarning::DiskUsage { disk_bytes } => {
+                assert!(*disk_bytes > 1024)
+            }
+            warning => panic!("unexpected warning {:?}", warning),
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn stalls() -> Result<()> {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+        let warnings = Arc::new(Warnings::default());
+        let thresholds = FlowThresholds {
+            stall_after: Duration::from_secs(0),
+            ..FlowThresholds::default()
+        };
+        let mut store = FlowKvs::new(
+            LogKvs::open(temp_dir.path())?,
+            thresholds,
+            Some(warnings.clone()),
+        );
+
+        // every write takes some time, so each one counts as a stall
+        for i in 0..3 {
+            store.set(format!("key{}", i), "value".to_owned())?;
+        }
+        let metrics = store.metrics();
+        assert_eq!(metrics.write_stalls, 3);
+        assert!(metrics.stalled_for >= metrics.slowest_write);
+        assert_eq!(warnings.0.lock().unwrap().len(), 3);
+
+        Ok(())
+    }
+}
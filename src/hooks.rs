@@ -0,0 +1,276 @@
+use std::fmt;
+
+use core::{
+    Collation, Error, KvStore, RangeEstimate, Result, ScanOptions, Scannable,
+};
+
+type Validator =
+    Box<dyn Fn(&str, &str) -> std::result::Result<(), String> + Send + Sync>;
+type Transformer = Box<dyn Fn(&str, String) -> String + Send + Sync>;
+
+/// Runs hooks registered for the keys under a prefix as they're read and
+/// written: transformers that change values on their way in or out, and
+/// validators that refuse writes of values they don't accept with a
+/// `Rejected` error. Hooks for a prefix apply to every key starting with
+/// it, so an empty prefix covers the whole store.
+///
+/// On a write, the write transformers run first, in the order they were
+/// registered, then the validators check what they made. On a read,
+/// including the value returned by a removal, the read transformers run
+/// in order. Values already in the store aren't checked when a hook is
+/// registered.
+///
+/// ```rust
+/// # use tempfile::TempDir;
+/// use kvs::{require_json, HookKvs, KvStore, LogKvs, Persistent};
+///
+/// # let temp_dir =
+/// #     TempDir::new().expect("unable to create temporary working directory");
+/// let mut store = HookKvs::new(LogKvs::open(temp_dir.path()).unwrap());
+/// store.validate("user/", require_json);
+/// store.transform_writes("name/", |_, value| value.trim().to_owned());
+///
+/// assert!(store.set("user/1".to_owned(), "{".to_owned()).is_err());
+/// store.set("user/1".to_owned(), r#"{"id": 1}"#.to_owned()).unwrap();
+/// store.set("name/1".to_owned(), " Ada \n".to_owned()).unwrap();
+/// assert_eq!(
+///     store.get("name/1".to_owned()).unwrap(),
+///     Some("Ada".to_owned())
+/// );
+/// ```
+pub struct HookKvs<S> {
+    store: S,
+    validators: Vec<(String, Validator)>,
+    write_transformers: Vec<(String, Transformer)>,
+    read_transformers: Vec<(String, Transformer)>,
+}
+
+impl<S: KvStore> HookKvs<S> {
+    /// Wrap the store, with no hooks yet.
+    pub fn new(store: S) -> HookKvs<S> {
+        HookKvs {
+            store,
+            validators: Vec::new(),
+            write_transformers: Vec::new(),
+            read_transformers: Vec::new(),
+        }
+    }
+
+    /// Check the values written to keys under the prefix, given the key and
+    /// value. A write the validator returns an error for is refused, with
+    /// the error as the reason.
+    pub fn validate<F>(&mut self, prefix: &str, validator: F)
+    where
+        F: Fn(&str, &str) -> std::result::Result<(), String>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.validators
+            .push((prefix.to_owned(), Box::new(validator)));
+    }
+
+    /// Change the values written to keys under the prefix, given the key
+    /// and value, before they're validated and stored.
+    pub fn transform_writes<F>(&mut self, prefix: &str, transformer: F)
+    where
+        F: Fn(&str, String) -> String + Send + Sync + 'static,
+    {
+        self.write_transformers
+            .push((prefix.to_owned(), Box::new(transformer)));
+    }
+
+    /// Change the values read from keys under the prefix, given the key and
+    /// value, leaving them as they are in the store.
+    pub fn transform_reads<F>(&mut self, prefix: &str, transformer: F)
+    where
+        F: Fn(&str, String) -> String + Send + Sync + 'static,
+    {
+        self.read_transformers
+            .push((prefix.to_owned(), Box::new(transformer)));
+    }
+
+    /// The wrapped store.
+    pub fn into_inner(self) -> S {
+        self.store
+    }
+
+    fn on_write(&self, key: &str, mut value: String) -> Result<String> {
+        for (prefix, transformer) in &self.write_transformers {
+            if key.starts_with(prefix.as_str()) {
+                value = transformer(key, value);
+            }
+        }
+        for (prefix, validator) in &self.validators {
+            if key.starts_with(prefix.as_str()) {
+                validator(key, &value).map_err(|reason| {
+                    Error::rejected_key(key, format!("{}: {}", key, reason))
+                })?;
+            }
+        }
+        Ok(value)
+    }
+
+    fn on_read(&self, key: &str, mut value: String) -> String {
+        for (prefix, transformer) in &self.read_transformers {
+            if key.starts_with(prefix.as_str()) {
+                value = transformer(key, value);
+            }
+        }
+        value
+    }
+}
+
+/// A validator for [`HookKvs::validate`] that only accepts values that are
+/// JSON. Checking them against a schema is left to a validator of its own.
+pub fn require_json(
+    _key: &str,
+    value: &str,
+) -> std::result::Result<(), String> {
+    serde_json::from_str::<serde_json::Value>(value)
+        .map(|_| ())
+        .map_err(|err| format!("not JSON, {}", err))
+}
+
+impl<S: fmt::Debug> fmt::Debug for HookKvs<S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fn prefixes<T>(hooks: &[(String, T)]) -> Vec<&str> {
+            hooks.iter().map(|(prefix, _)| prefix.as_str()).collect()
+        }
+        f.debug_struct("HookKvs")
+            .field("store", &self.store)
+            .field("validators", &prefixes(&self.validators))
+            .field("write_transformers", &prefixes(&self.write_transformers))
+            .field("read_transformers", &prefixes(&self.read_transformers))
+            .finish()
+    }
+}
+
+impl<S: KvStore> KvStore for HookKvs<S> {
+    fn set(&mut self, key: String, value: String) -> Result<()> {
+        let value = self.on_write(&key, value)?;
+        self.store.set(key, value)
+    }
+
+    fn get(&self, key: String) -> Result<Option<String>> {
+        Ok(self
+            .store
+            .get_ref(&key)?
+            .map(|value| self.on_read(&key, value.into_owned())))
+    }
+
+    fn remove(&mut self, key: String) -> Result<Option<String>> {
+        Ok(self
+            .store
+            .remove_ref(&key)?
+            .map(|value| self.on_read(&key, value)))
+    }
+}
+
+impl<S: KvStore + Scannable> Scannable for HookKvs<S> {
+    fn keys(&self) -> Result<Vec<String>> {
+        self.store.keys()
+    }
+
+    fn scan(&self, start: &str, end: Option<&str>) -> Result<Vec<String>> {
+        self.store.scan(start, end)
+    }
+
+    fn scan_with(
+        &self,
+        start: &str,
+        end: Option<&str>,
+        options: ScanOptions,
+    ) -> Result<Vec<String>> {
+        self.store.scan_with(start, end, options)
+    }
+
+    fn estimate_range_size(
+        &self,
+        start: &str,
+        end: Option<&str>,
+    ) -> Result<RangeEstimate> {
+        self.store.estimate_range_size(start, end)
+    }
+
+    /// The predicate sees values as they'd be read, after the read
+    /// transformers.
+    fn scan_filtered(
+        &self,
+        start: &str,
+        end: Option<&str>,
+        predicate: &dyn Fn(&str, &str) -> bool,
+    ) -> Result<Vec<(String, String)>> {
+        let pairs = self.store.scan_filtered(start, end, &|key, value| {
+            predicate(key, &self.on_read(key, value.to_owned()))
+        })?;
+        Ok(pairs
+            .into_iter()
+            .map(|(key, value)| {
+                let value = self.on_read(&key, value);
+                (key, value)
+            })
+            .collect())
+    }
+
+    fn collation(&self) -> Collation {
+        self.store.collation()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tempfile::TempDir;
+
+    use core::ErrorKind;
+
+    use crate::{HashMapKvs, Persistent};
+
+    fn hooked(path: &std::path::Path) -> Result<HookKvs<HashMapKvs>> {
+        let mut store = HookKvs::new(HashMapKvs::open(path)?);
+        store.transform_writes("", |_, value| value.trim().to_owned());
+        store.validate("num/", |_, value| {
+            value
+                .parse::<i64>()
+                .map(|_| ())
+                .map_err(|err| err.to_string())
+        });
+        store.transform_reads("upper/", |_, value| value.to_uppercase());
+        Ok(store)
+    }
+
+    #[test]
+    fn hooks() -> Result<()> {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+        let mut store = hooked(&temp_dir.path().join("kvs"))?;
+        store.set("num/1".to_owned(), " 42\n".to_owned())?;
+        assert_eq!(store.get("num/1".to_owned())?, Some("42".to_owned()));
+        let err = store.set("num/2".to_owned(), "x".to_owned()).unwrap_err();
+        match err.kind() {
+            ErrorKind::Rejected { key, message } => {
+                assert_eq!(key.as_deref(), Some("num/2"));
+                assert!(message.starts_with("num/2"));
+            }
+            kind => panic!("unexpected error {:?}", kind),
+        }
+        assert_eq!(store.get("num/2".to_owned())?, None);
+
+        // stored as written, read transformed
+        store.set("upper/1".to_owned(), "abc".to_owned())?;
+        assert_eq!(store.get("upper/1".to_owned())?, Some("ABC".to_owned()));
+        let pairs =
+            store.scan_filtered("upper/", None, &|_, value| value == "ABC")?;
+        assert_eq!(pairs, vec![("upper/1".to_owned(), "ABC".to_owned())]);
+        assert_eq!(store.remove("upper/1".to_owned())?, Some("ABC".to_owned()));
+        let store = store.into_inner();
+        assert_eq!(store.get("num/1".to_owned())?, Some("42".to_owned()));
+
+        assert!(require_json("", "[1, 2]").is_ok());
+        assert!(require_json("", "[1, 2").is_err());
+
+        Ok(())
+    }
+}
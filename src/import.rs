@@ -0,0 +1,195 @@
+use std::io::{BufRead, BufReader, Read};
+
+use serde_json::Value;
+
+use core::{Error, KvStore, Result};
+
+/// Shortcut for an error describing a record that can't be imported.
+fn invalid(msg: String) -> Error {
+    Error::serialization(msg)
+}
+
+/// Set a key for each row of a CSV file, taking the key and value from the
+/// given columns, counting from 0. Rows are read and written one at a
+/// time. Returns the number of keys set.
+///
+/// ```rust
+/// # use tempfile::TempDir;
+/// use kvs::{import_csv, HashMapKvs, KvStore, Persistent};
+///
+/// # let temp_dir =
+/// #     TempDir::new().expect("unable to create temporary working directory");
+/// let mut store = HashMapKvs::open(temp_dir.path().join("kvs")).unwrap();
+/// let csv = "id,name\n1,alice\n2,bob\n";
+/// import_csv(&mut csv.as_bytes(), &mut store, 0, 1, true).unwrap();
+/// assert_eq!(store.get("2".to_owned()).unwrap(), Some("bob".to_owned()));
+/// ```
+pub fn import_csv<S>(
+    reader: &mut dyn Read,
+    store: &mut S,
+    key_col: usize,
+    value_col: usize,
+    has_headers: bool,
+) -> Result<u64>
+where
+    S: KvStore + ?Sized,
+{
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(has_headers)
+        .flexible(true)
+        .from_reader(reader);
+
+    let mut imported = 0;
+    for record in reader.records() {
+        let record = record.map_err(|err| invalid(err.to_string()))?;
+        let line = record.position().map_or(0, |pos| pos.line());
+        let field = |col: usize| {
+            record.get(col).map(str::to_owned).ok_or_else(|| {
+                invalid(format!("line {} has no column {}", line, col))
+            })
+        };
+        store.set(field(key_col)?, field(value_col)?)?;
+        imported += 1;
+    }
+    Ok(imported)
+}
+
+/// Set a key for each line of a JSON lines file, where each line is an
+/// object holding the key and value in the given fields. String values are
+/// stored as is, anything else as JSON. Blank lines are skipped. Returns
+/// the number of keys set.
+///
+/// ```rust
+/// # use tempfile::TempDir;
+/// use kvs::{import_jsonl, HashMapKvs, KvStore, Persistent};
+///
+/// # let temp_dir =
+/// #     TempDir::new().expect("unable to create temporary working directory");
+/// let mut store = HashMapKvs::open(temp_dir.path().join("kvs")).unwrap();
+/// let jsonl = r#"{"id": 1, "tags": ["a", "b"]}"#;
+/// import_jsonl(&mut jsonl.as_bytes(), &mut store, "id", "tags").unwrap();
+/// assert_eq!(
+///     store.get("1".to_owned()).unwrap(),
+///     Some(r#"["a","b"]"#.to_owned())
+/// );
+/// ```
+pub fn import_jsonl<S>(
+    reader: &mut dyn Read,
+    store: &mut S,
+    key_field: &str,
+    value_field: &str,
+) -> Result<u64>
+where
+    S: KvStore + ?Sized,
+{
+    let mut imported = 0;
+    for (i, line) in BufReader::new(reader).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let mut record: Value = serde_json::from_str(&line)
+            .map_err(|err| invalid(format!("line {}: {}", i + 1, err)))?;
+        let mut field = |name: &str| {
+            record.get_mut(name).map(Value::take).ok_or_else(|| {
+                invalid(format!("line {} has no field `{}`", i + 1, name))
+            })
+        };
+
+        let key = match field(key_field)? {
+            Value::String(key) => key,
+            key @ Value::Number(_) | key @ Value::Bool(_) => key.to_string(),
+            _ => {
+                return Err(invalid(format!(
+                    "line {} has a key that isn't a string or number",
+                    i + 1
+                )))
+            }
+        };
+        let value = match field(value_field)? {
+            Value::String(value) => value,
+            value => value.to_string(),
+        };
+        store.set(key, value)?;
+        imported += 1;
+    }
+    Ok(imported)
+}
+
+#[cfg(all(test, feature = "hashmap"))]
+mod tests {
+    use super::*;
+
+    use tempfile::TempDir;
+
+    use core::{ErrorKind, Persistent};
+
+    use crate::HashMapKvs;
+
+    #[test]
+    fn csv() -> Result<()> {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+        let mut store = HashMapKvs::open(temp_dir.path().join("kvs"))?;
+
+        let csv = "name,id,note\nalice,1,\"quoted, with comma\"\nbob,2,x\n";
+        assert_eq!(import_csv(&mut csv.as_bytes(), &mut store, 1, 2, true)?, 2);
+        assert_eq!(
+            store.get("1".to_owned())?,
+            Some("quoted, with comma".to_owned())
+        );
+        assert_eq!(store.get("id".to_owned())?, None);
+
+        let short = "a,b,c\nd,e\n";
+        let err = import_csv(&mut short.as_bytes(), &mut store, 0, 2, false)
+            .unwrap_err();
+        assert_eq!(
+            err.kind(),
+            &ErrorKind::Serde {
+                message: "line 2 has no column 2".to_owned()
+            }
+        );
+        // rows before the bad one were still set
+        assert_eq!(store.get("a".to_owned())?, Some("c".to_owned()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn jsonl() -> Result<()> {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+        let mut store = HashMapKvs::open(temp_dir.path().join("kvs"))?;
+
+        let jsonl = concat!(
+            r#"{"k": "a", "v": "text", "other": 1}"#,
+            "\n\n",
+            r#"{"k": 2, "v": {"nested": true}}"#,
+            "\n",
+        );
+        assert_eq!(
+            import_jsonl(&mut jsonl.as_bytes(), &mut store, "k", "v")?,
+            2
+        );
+        assert_eq!(store.get("a".to_owned())?, Some("text".to_owned()));
+        assert_eq!(
+            store.get("2".to_owned())?,
+            Some(r#"{"nested":true}"#.to_owned())
+        );
+
+        let missing = r#"{"k": "b"}"#;
+        let err = import_jsonl(&mut missing.as_bytes(), &mut store, "k", "v")
+            .unwrap_err();
+        assert_eq!(
+            err.kind(),
+            &ErrorKind::Serde {
+                message: "line 1 has no field `v`".to_owned()
+            }
+        );
+        assert!(
+            import_jsonl(&mut &b"not json"[..], &mut store, "k", "v").is_err()
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,196 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use core::{Error, Result, StoreObserver};
+
+/// Counts the reads and writes of each key, to find hot spots. Register it
+/// with [`KvsBuilder::observer`](crate::KvsBuilder::observer) when opening
+/// the store.
+///
+/// ```rust
+/// # use std::sync::Arc;
+/// # use tempfile::TempDir;
+/// use kvs::{Engine, KeyStats, KvStore, Kvs};
+///
+/// # let temp_dir =
+/// #     TempDir::new().expect("unable to create temporary working directory");
+/// let stats = Arc::new(KeyStats::new());
+/// let mut store = Kvs::builder()
+///     .engine(Engine::Log)
+///     .path(temp_dir.path())
+///     .observer(stats.clone())
+///     .open_any()
+///     .unwrap();
+/// store.set("key1".to_owned(), "value1".to_owned()).unwrap();
+/// store.get("key1".to_owned()).unwrap();
+/// assert_eq!(stats.top_keys(1)[0].reads, 1);
+/// ```
+#[derive(Debug, Default)]
+pub struct KeyStats {
+    keys: Mutex<HashMap<String, KeyAccess>>,
+}
+
+/// How often a key has been used, see [`KeyStats`].
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct KeyAccess {
+    /// The key.
+    pub key: String,
+    /// How many times it was read, including while it had no value.
+    pub reads: u64,
+    /// How many times it was set or removed.
+    pub writes: u64,
+    /// When it was last read or written, in seconds since the Unix epoch.
+    pub last_access: u64,
+}
+
+impl KeyStats {
+    /// Start with no keys counted.
+    pub fn new() -> KeyStats {
+        KeyStats::default()
+    }
+
+    /// Carry on from counts written by `save`, or start with none if the
+    /// file doesn't exist.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<KeyStats> {
+        let path = path.as_ref();
+        let mut keys = HashMap::new();
+        if !path.is_file() {
+            return Ok(KeyStats::default());
+        }
+
+        for line in BufReader::new(File::open(path)?).lines() {
+            let line = line?;
+            let fields: Vec<&str> = line.splitn(4, ' ').collect();
+            let access = match fields.as_slice() {
+                [reads, writes, last_access, key] => {
+                    match (reads.parse(), writes.parse(), last_access.parse()) {
+                        (Ok(reads), Ok(writes), Ok(last_access)) => {
+                            Some(KeyAccess {
+                                key: (*key).to_owned(),
+                                reads,
+                                writes,
+                                last_access,
+                            })
+                        }
+                        _ => None,
+                    }
+                }
+                _ => None,
+            };
+            let access = access.ok_or_else(|| {
+                Error::serialization(format!(
+                    "invalid key stats line `{}`",
+                    line
+                ))
+            })?;
+            keys.insert(access.key.clone(), access);
+        }
+        Ok(KeyStats {
+            keys: Mutex::new(keys),
+        })
+    }
+
+    /// Write the counts to a file, one `<reads> <writes> <last access>
+    /// <key>` line per key.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        for access in self.keys.lock().unwrap().values() {
+            writeln!(
+                writer,
+                "{} {} {} {}",
+                access.reads, access.writes, access.last_access, access.key
+            )?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// The `n` keys used most, by reads and writes together. Ties go to the
+    /// key used most recently.
+    pub fn top_keys(&self, n: usize) -> Vec<KeyAccess> {
+        let mut keys: Vec<KeyAccess> =
+            self.keys.lock().unwrap().values().cloned().collect();
+        keys.sort_by(|a, b| {
+            (b.reads + b.writes, b.last_access, &a.key).cmp(&(
+                a.reads + a.writes,
+                a.last_access,
+                &b.key,
+            ))
+        });
+        keys.truncate(n);
+        keys
+    }
+
+    fn count(&self, key: &str, read: bool) {
+        let mut keys = self.keys.lock().unwrap();
+        let access = keys.entry(key.to_owned()).or_insert_with(|| KeyAccess {
+            key: key.to_owned(),
+            ..KeyAccess::default()
+        });
+        if read {
+            access.reads += 1;
+        } else {
+            access.writes += 1;
+        }
+        access.last_access = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_secs());
+    }
+}
+
+impl StoreObserver for KeyStats {
+    fn on_get(&self, key: &str) {
+        self.count(key, true);
+    }
+
+    fn on_set(&self, key: &str, _value_len: u64) {
+        self.count(key, false);
+    }
+
+    fn on_remove(&self, key: &str) {
+        self.count(key, false);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tempfile::TempDir;
+
+    #[test]
+    fn top_keys() -> Result<()> {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+        let stats = KeyStats::new();
+        for _ in 0..3 {
+            stats.on_get("hot");
+        }
+        stats.on_set("hot", 1);
+        stats.on_set("warm key", 1);
+        stats.on_remove("warm key");
+        stats.on_get("cold");
+
+        let top = stats.top_keys(2);
+        assert_eq!(
+            top.iter().map(|access| &access.key[..]).collect::<Vec<_>>(),
+            vec!["hot", "warm key"]
+        );
+        assert_eq!((top[0].reads, top[0].writes), (3, 1));
+        assert_eq!((top[1].reads, top[1].writes), (0, 2));
+
+        let path = temp_dir.path().join("kvs.keystats");
+        stats.save(&path)?;
+        let loaded = KeyStats::load(&path)?;
+        assert_eq!(loaded.top_keys(3), stats.top_keys(3));
+        assert!(KeyStats::load(temp_dir.path().join("missing"))?
+            .top_keys(1)
+            .is_empty());
+
+        Ok(())
+    }
+}
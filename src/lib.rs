@@ -0,0 +1,110 @@
+#![deny(missing_docs)]
+
+/*!
+ * A single crate to depend on for key-value stores. Re-exports the common
+ * traits and errors from `core`, along with each engine enabled by a
+ * feature flag:
+ *
+ * - `hashmap`: [`HashMapKvs`], an in-memory hashmap saved to a file.
+ * - `log`: [`LogKvs`], an append-only log in a directory.
+ *
+ * Both engines are enabled by default. [`Kvs::builder`] opens whichever
+ * engine is picked at runtime, either boxed or as an [`AnyKvs`].
+ *
+ * [`ColumnFamilies`] keeps several separately tuned log stores in one
+ * directory, and needs the `log` engine.
+ *
+ * [`MemKvs`] and [`MockKvStore`] stand in for a store when unit testing
+ * code that takes a `KvStore`, without touching disk.
+ *
+ * The `key-stats` feature adds `KeyStats`, which counts how often each key
+ * is read and written, and the `version-vectors` feature adds
+ * `VersionedKvs`, which catches conflicting writes when syncing stores.
+ *
+ * ```rust
+ * # use tempfile::TempDir;
+ * use kvs::{KvStore, LogKvs, Persistent};
+ *
+ * # let temp_dir =
+ * #     TempDir::new().expect("unable to create temporary working directory");
+ * let mut store = LogKvs::open(temp_dir.path()).unwrap();
+ * store.set("key1".to_owned(), "value1".to_owned()).unwrap();
+ * assert_eq!(
+ *     store.get("key1".to_owned()).unwrap(),
+ *     Some("value1".to_owned())
+ * );
+ * ```
+ */
+
+pub use core::*;
+
+#[cfg(feature = "hashmap")]
+pub use hashmap_kvs::HashMapKvs;
+
+#[cfg(feature = "log")]
+pub use log_kvs::{
+    Backfiller, LogEvent, LogEvents, LogKvs, LogOp, Refresher, RepairReport,
+};
+
+mod any;
+pub use any::*;
+mod audit;
+pub use audit::*;
+mod backup;
+pub use backup::*;
+mod builder;
+pub use builder::*;
+mod capture;
+pub use capture::*;
+mod change_feed;
+pub use change_feed::*;
+mod connector;
+pub use connector::*;
+mod crdt;
+pub use crdt::*;
+mod diff;
+pub use diff::*;
+mod digest;
+pub use digest::*;
+mod flow;
+pub use flow::*;
+#[cfg(feature = "log")]
+mod families;
+#[cfg(feature = "log")]
+pub use families::*;
+mod mem;
+pub use mem::*;
+mod merge;
+pub use merge::*;
+mod mock;
+pub use mock::*;
+mod hooks;
+pub use hooks::*;
+mod import;
+pub use import::*;
+#[cfg(feature = "key-stats")]
+mod key_stats;
+#[cfg(feature = "key-stats")]
+pub use key_stats::*;
+mod query;
+pub use query::*;
+mod redis;
+pub use redis::*;
+#[cfg(feature = "log")]
+mod reshard;
+#[cfg(feature = "log")]
+pub use reshard::*;
+mod retention;
+pub use retention::*;
+mod scheduler;
+pub use scheduler::*;
+mod stamped;
+pub use stamped::*;
+mod tenant;
+pub use tenant::*;
+mod view;
+pub use view::*;
+#[cfg(feature = "version-vectors")]
+mod version_vector;
+#[cfg(feature = "version-vectors")]
+pub use version_vector::*;
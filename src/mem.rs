@@ -0,0 +1,174 @@
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::ops::Bound;
+
+use core::{in_range, KvStore, RangeEstimate, Result, ScanOptions, Scannable};
+
+/// A store kept entirely in memory, in key order, and lost when dropped.
+/// Meant for unit testing code that takes a `KvStore` without touching
+/// disk, or as scratch space.
+///
+/// ```rust
+/// use kvs::{KvStore, MemKvs, Scannable};
+///
+/// let mut store = MemKvs::new();
+/// store.set("b".to_owned(), "value1".to_owned()).unwrap();
+/// store.set("a".to_owned(), "value2".to_owned()).unwrap();
+/// assert_eq!(store.scan("a", None).unwrap(), vec!["a", "b"]);
+/// ```
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct MemKvs {
+    map: BTreeMap<String, String>,
+}
+
+impl MemKvs {
+    /// An empty store.
+    pub fn new() -> MemKvs {
+        MemKvs::default()
+    }
+
+    /// The entries from `start` up to but not including `end`, in order.
+    fn range<'a>(
+        &'a self,
+        start: &str,
+        end: Option<&'a str>,
+    ) -> impl Iterator<Item = (&'a String, &'a String)> + 'a {
+        self.map
+            .range(start.to_owned()..)
+            .take_while(move |(key, _)| in_range(key, "", end))
+    }
+}
+
+impl KvStore for MemKvs {
+    fn set(&mut self, key: String, value: String) -> Result<()> {
+        self.map.insert(key, value);
+        Ok(())
+    }
+
+    fn get(&self, key: String) -> Result<Option<String>> {
+        Ok(self.map.get(&key).cloned())
+    }
+
+    /// Lends out the value held in memory.
+    fn get_ref(&self, key: &str) -> Result<Option<Cow<'_, str>>> {
+        Ok(self.map.get(key).map(|value| Cow::Borrowed(value.as_str())))
+    }
+
+    fn contains_key(&self, key: &str) -> Result<bool> {
+        Ok(self.map.contains_key(key))
+    }
+
+    fn remove(&mut self, key: String) -> Result<Option<String>> {
+        Ok(self.map.remove(&key))
+    }
+
+    fn remove_ref(&mut self, key: &str) -> Result<Option<String>> {
+        Ok(self.map.remove(key))
+    }
+}
+
+impl Scannable for MemKvs {
+    fn keys(&self) -> Result<Vec<String>> {
+        Ok(self.map.keys().cloned().collect())
+    }
+
+    fn scan(&self, start: &str, end: Option<&str>) -> Result<Vec<String>> {
+        Ok(self.range(start, end).map(|(key, _)| key.clone()).collect())
+    }
+
+    /// Walks the map from whichever end the options start at, so only the
+    /// keys up to the end of the page are visited.
+    fn scan_with(
+        &self,
+        start: &str,
+        end: Option<&str>,
+        options: ScanOptions,
+    ) -> Result<Vec<String>> {
+        let keys = match end {
+            Some(end) if end <= start => return Ok(Vec::new()),
+            Some(end) => self.map.range::<str, _>((
+                Bound::Included(start),
+                Bound::Excluded(end),
+            )),
+            None => self
+                .map
+                .range::<str, _>((Bound::Included(start), Bound::Unbounded)),
+        };
+        let keys = keys.map(|(key, _)| key.clone());
+        Ok(if options.reverse {
+            options.page(keys.rev())
+        } else {
+            options.page(keys)
+        })
+    }
+
+    /// The exact number of keys in the range, and the length of each key
+    /// and value in it, since they're all in memory.
+    fn estimate_range_size(
+        &self,
+        start: &str,
+        end: Option<&str>,
+    ) -> Result<RangeEstimate> {
+        Ok(self.range(start, end).fold(
+            RangeEstimate::default(),
+            |estimate, (key, value)| RangeEstimate {
+                keys: estimate.keys + 1,
+                bytes: estimate.bytes + (key.len() + value.len()) as u64,
+            },
+        ))
+    }
+
+    fn scan_filtered(
+        &self,
+        start: &str,
+        end: Option<&str>,
+        predicate: &dyn Fn(&str, &str) -> bool,
+    ) -> Result<Vec<(String, String)>> {
+        Ok(self
+            .range(start, end)
+            .filter(|(key, value)| predicate(key, value))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mem_kvs() -> Result<()> {
+        let mut store = MemKvs::new();
+        for key in &["c", "a", "b", "d"] {
+            store.set((*key).to_owned(), format!("value-{}", key))?;
+        }
+        assert_eq!(store.get_ref("a")?, Some(Cow::Borrowed("value-a")));
+        assert_eq!(store.remove("d".to_owned())?, Some("value-d".to_owned()));
+        assert_eq!(store.remove("d".to_owned())?, None);
+
+        assert_eq!(store.scan("b", None)?, vec!["b", "c"]);
+        assert_eq!(store.scan("a", Some("c"))?, vec!["a", "b"]);
+        assert!(store.scan("c", Some("a"))?.is_empty());
+        assert_eq!(store.scan_rev("a", Some("c"))?, vec!["b", "a"]);
+        let last = ScanOptions {
+            reverse: true,
+            limit: Some(1),
+            ..ScanOptions::default()
+        };
+        assert_eq!(store.scan_with("", None, last)?, vec!["c"]);
+        assert_eq!(
+            store.estimate_range_size("", None)?,
+            RangeEstimate { keys: 3, bytes: 24 }
+        );
+        let pairs = store.scan_filtered("", None, &|key, _| key != "b")?;
+        assert_eq!(
+            pairs,
+            vec![
+                ("a".to_owned(), "value-a".to_owned()),
+                ("c".to_owned(), "value-c".to_owned()),
+            ]
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,187 @@
+use std::collections::BTreeMap;
+
+use core::{KvStore, Result, Scannable};
+
+/// What to do when a key has different values in the stores being merged.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ConflictPolicy {
+    /// Take the value from the last source that has the key, over the
+    /// earlier sources and the destination.
+    PreferSource,
+    /// Write nothing if any key has different values.
+    Fail,
+}
+
+/// What a merge did.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct MergeReport {
+    /// The number of values written to the destination.
+    pub written: u64,
+    /// Keys with different values in different stores, sorted. With
+    /// `ConflictPolicy::Fail`, nothing is written if there are any.
+    pub conflicts: Vec<String>,
+}
+
+/// Copy every key from the sources into the destination, in order.
+/// Values already in the destination are left alone unless a source has a
+/// different one. Only keys are held in memory; values are read one at a
+/// time.
+///
+/// ```rust
+/// # use tempfile::TempDir;
+/// use kvs::{merge, ConflictPolicy, HashMapKvs, KvStore, LogKvs, Persistent};
+///
+/// # let temp_dir =
+/// #     TempDir::new().expect("unable to create temporary working directory");
+/// let mut source = HashMapKvs::open(temp_dir.path().join("a")).unwrap();
+/// let mut dest = LogKvs::open(temp_dir.path().join("b")).unwrap();
+/// source.set("key1".to_owned(), "value1".to_owned()).unwrap();
+/// let report =
+///     merge(&[&source], &mut dest, ConflictPolicy::PreferSource).unwrap();
+/// assert_eq!(report.written, 1);
+/// ```
+pub fn merge<D>(
+    sources: &[&dyn Scannable],
+    dest: &mut D,
+    policy: ConflictPolicy,
+) -> Result<MergeReport>
+where
+    D: Scannable + ?Sized,
+{
+    let mut report = MergeReport::default();
+
+    // which sources hold each key, in the order they're merged
+    let mut holders: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+    for (i, source) in sources.iter().enumerate() {
+        for key in source.keys()? {
+            holders.entry(key).or_default().push(i);
+        }
+    }
+
+    if policy == ConflictPolicy::Fail {
+        for (key, holders) in &holders {
+            if conflicts(key, holders, sources, &*dest)? {
+                report.conflicts.push(key.clone());
+            }
+        }
+        if !report.conflicts.is_empty() {
+            return Ok(report);
+        }
+    }
+
+    for (key, holders) in holders {
+        if policy == ConflictPolicy::PreferSource
+            && conflicts(&key, &holders, sources, &*dest)?
+        {
+            report.conflicts.push(key.clone());
+        }
+        let last = sources[*holders.last().expect("a key has a holder")];
+        let value = match last.get(key.clone())? {
+            Some(value) => value,
+            // removed since its keys were listed
+            None => continue,
+        };
+        if dest.get(key.clone())?.as_ref() != Some(&value) {
+            dest.set(key, value)?;
+            report.written += 1;
+        }
+    }
+
+    Ok(report)
+}
+
+/// Whether the stores holding a key, and the destination if it has it,
+/// disagree on its value.
+fn conflicts<D>(
+    key: &str,
+    holders: &[usize],
+    sources: &[&dyn Scannable],
+    dest: &D,
+) -> Result<bool>
+where
+    D: KvStore + ?Sized,
+{
+    let mut first = dest.get(key.to_owned())?;
+    for &i in holders {
+        let value = sources[i].get(key.to_owned())?;
+        match &first {
+            Some(first) if value.as_ref() != Some(first) => return Ok(true),
+            Some(_) => {}
+            None => first = value,
+        }
+    }
+    Ok(false)
+}
+
+#[cfg(all(test, feature = "hashmap", feature = "log"))]
+mod tests {
+    use super::*;
+
+    use tempfile::TempDir;
+
+    use core::Persistent;
+
+    use crate::{HashMapKvs, LogKvs};
+
+    fn stores(temp_dir: &TempDir) -> Result<(HashMapKvs, LogKvs, LogKvs)> {
+        let mut a = HashMapKvs::open(temp_dir.path().join("a"))?;
+        let mut b = LogKvs::open(temp_dir.path().join("b"))?;
+        let mut dest = LogKvs::open(temp_dir.path().join("dest"))?;
+        a.set("a1".to_owned(), "value1".to_owned())?;
+        a.set("same".to_owned(), "value2".to_owned())?;
+        a.set("conflict".to_owned(), "value3".to_owned())?;
+        b.set("b1".to_owned(), "value4".to_owned())?;
+        b.set("same".to_owned(), "value2".to_owned())?;
+        b.set("conflict".to_owned(), "value5".to_owned())?;
+        dest.set("dest1".to_owned(), "value6".to_owned())?;
+        dest.set("same".to_owned(), "value2".to_owned())?;
+        Ok((a, b, dest))
+    }
+
+    #[test]
+    fn prefer_source() -> Result<()> {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+        let (a, b, mut dest) = stores(&temp_dir)?;
+
+        let report = merge(&[&a, &b], &mut dest, ConflictPolicy::PreferSource)?;
+        assert_eq!(
+            report,
+            MergeReport {
+                written: 3,
+                conflicts: vec!["conflict".to_owned()],
+            }
+        );
+        assert_eq!(dest.get("conflict".to_owned())?, Some("value5".to_owned()));
+        assert_eq!(dest.get("a1".to_owned())?, Some("value1".to_owned()));
+        assert_eq!(dest.get("dest1".to_owned())?, Some("value6".to_owned()));
+        assert_eq!(dest.keys()?.len(), 5);
+
+        Ok(())
+    }
+
+    #[test]
+    fn fail_on_conflict() -> Result<()> {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+        let (a, mut b, mut dest) = stores(&temp_dir)?;
+
+        let report = merge(&[&a, &b], &mut dest, ConflictPolicy::Fail)?;
+        assert_eq!(report.written, 0);
+        assert_eq!(report.conflicts, vec!["conflict".to_owned()]);
+        assert_eq!(dest.keys()?.len(), 2);
+
+        // a source disagreeing with the destination is also a conflict
+        b.remove("conflict".to_owned())?;
+        dest.set("a1".to_owned(), "value7".to_owned())?;
+        let report = merge(&[&a, &b], &mut dest, ConflictPolicy::Fail)?;
+        assert_eq!(report.conflicts, vec!["a1".to_owned()]);
+
+        dest.remove("a1".to_owned())?;
+        let report = merge(&[&a, &b], &mut dest, ConflictPolicy::Fail)?;
+        assert_eq!(report.written, 3);
+        assert!(report.conflicts.is_empty());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,214 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use core::{Error, ErrorKind, KvStore, Result};
+
+use crate::MemKvs;
+
+/// One of the calls a [`MockKvStore`] can script a response for.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum MockOp {
+    /// `set`, including `set_from_reader`.
+    Set,
+    /// `get`, including the other reads built on it.
+    Get,
+    /// `remove`, including `remove_ref`.
+    Remove,
+}
+
+/// A call made to a [`MockKvStore`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum MockCall {
+    /// A value was set.
+    Set {
+        /// The key set.
+        key: String,
+        /// The value it was set to.
+        value: String,
+    },
+    /// A key was read.
+    Get {
+        /// The key read.
+        key: String,
+    },
+    /// A key was removed.
+    Remove {
+        /// The key removed.
+        key: String,
+    },
+}
+
+/// What a scripted call to a [`MockKvStore`] does instead of going to its
+/// store.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum MockResponse {
+    /// Succeed without changing anything, returning the value for a `get`
+    /// or `remove`.
+    Value(Option<String>),
+    /// Fail with an error of this kind.
+    Error(ErrorKind),
+}
+
+/// A store for unit testing code that takes a `KvStore`: it records every
+/// call made to it, and responds to each from an in-memory store unless a
+/// response has been scripted with [`respond`](MockKvStore::respond).
+///
+/// ```rust
+/// use kvs::{
+///     ErrorKind, KvStore, MockCall, MockKvStore, MockOp, MockResponse,
+/// };
+///
+/// let mut store = MockKvStore::new();
+/// store.respond(
+///     MockOp::Set,
+///     MockResponse::Error(ErrorKind::Io {
+///         path: None,
+///         message: "full".to_owned(),
+///     }),
+/// );
+/// assert!(store.set("key1".to_owned(), "value1".to_owned()).is_err());
+/// store.set("key1".to_owned(), "value1".to_owned()).unwrap();
+/// assert_eq!(
+///     store.get("key1".to_owned()).unwrap(),
+///     Some("value1".to_owned())
+/// );
+/// assert_eq!(
+///     store.calls()[2],
+///     MockCall::Get {
+///         key: "key1".to_owned()
+///     }
+/// );
+/// ```
+#[derive(Debug, Default)]
+pub struct MockKvStore {
+    store: MemKvs,
+    calls: Mutex<Vec<MockCall>>,
+    responses: Mutex<VecDeque<(MockOp, MockResponse)>>,
+}
+
+impl MockKvStore {
+    /// An empty store, with no responses scripted.
+    pub fn new() -> MockKvStore {
+        MockKvStore::default()
+    }
+
+    /// Start with the entries in the given store.
+    pub fn with_store(store: MemKvs) -> MockKvStore {
+        MockKvStore {
+            store,
+            ..MockKvStore::default()
+        }
+    }
+
+    /// Respond to the next call of `op` that doesn't already have a response
+    /// scripted. Responses for the same call are used in the order they were
+    /// scripted.
+    pub fn respond(&self, op: MockOp, response: MockResponse) {
+        self.responses.lock().unwrap().push_back((op, response));
+    }
+
+    /// Every call made so far, oldest first.
+    pub fn calls(&self) -> Vec<MockCall> {
+        self.calls.lock().unwrap().clone()
+    }
+
+    /// Forget the calls made so far.
+    pub fn clear_calls(&self) {
+        self.calls.lock().unwrap().clear();
+    }
+
+    /// The store calls go to when they're not scripted.
+    pub fn store(&self) -> &MemKvs {
+        &self.store
+    }
+
+    /// Record the call, and take the response scripted for it, if any.
+    fn call(&self, op: MockOp, call: MockCall) -> Option<MockResponse> {
+        self.calls.lock().unwrap().push(call);
+        let mut responses = self.responses.lock().unwrap();
+        let i = responses.iter().position(|(scripted, _)| *scripted == op)?;
+        responses.remove(i).map(|(_, response)| response)
+    }
+}
+
+impl KvStore for MockKvStore {
+    fn set(&mut self, key: String, value: String) -> Result<()> {
+        let call = MockCall::Set {
+            key: key.clone(),
+            value: value.clone(),
+        };
+        match self.call(MockOp::Set, call) {
+            Some(MockResponse::Value(_)) => Ok(()),
+            Some(MockResponse::Error(kind)) => Err(Error::from(kind)),
+            None => self.store.set(key, value),
+        }
+    }
+
+    fn get(&self, key: String) -> Result<Option<String>> {
+        let call = MockCall::Get { key: key.clone() };
+        match self.call(MockOp::Get, call) {
+            Some(MockResponse::Value(value)) => Ok(value),
+            Some(MockResponse::Error(kind)) => Err(Error::from(kind)),
+            None => self.store.get(key),
+        }
+    }
+
+    fn remove(&mut self, key: String) -> Result<Option<String>> {
+        let call = MockCall::Remove { key: key.clone() };
+        match self.call(MockOp::Remove, call) {
+            Some(MockResponse::Value(value)) => Ok(value),
+            Some(MockResponse::Error(kind)) => Err(Error::from(kind)),
+            None => self.store.remove(key),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scripted() -> Result<()> {
+        let mut store = MockKvStore::new();
+        store.respond(MockOp::Get, MockResponse::Value(Some("a".to_owned())));
+        store.respond(
+            MockOp::Remove,
+            MockResponse::Error(ErrorKind::Io {
+                path: None,
+                message: "gone".to_owned(),
+            }),
+        );
+        store.respond(MockOp::Get, MockResponse::Value(None));
+
+        store.set("key1".to_owned(), "value1".to_owned())?;
+        assert_eq!(store.get("key1".to_owned())?, Some("a".to_owned()));
+        let err = store.remove("key1".to_owned()).unwrap_err();
+        assert_eq!(
+            err.kind(),
+            &ErrorKind::Io {
+                path: None,
+                message: "gone".to_owned(),
+            }
+        );
+        assert_eq!(store.get("key1".to_owned())?, None);
+        // back to the store once the script runs out
+        assert_eq!(store.get("key1".to_owned())?, Some("value1".to_owned()));
+        assert_eq!(store.calls().len(), 5);
+        assert_eq!(
+            store.calls()[0],
+            MockCall::Set {
+                key: "key1".to_owned(),
+                value: "value1".to_owned(),
+            }
+        );
+
+        store.clear_calls();
+        assert!(store.calls().is_empty());
+        assert_eq!(
+            store.store().get("key1".to_owned())?,
+            Some("value1".to_owned())
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,498 @@
+use std::cmp::Ordering;
+use std::iter::Peekable;
+use std::str::Chars;
+
+use serde_json::Value;
+
+use core::{Error, Result, Scannable};
+
+/// Part of a key's value a query selects or compares.
+#[derive(Clone, Debug, PartialEq)]
+enum Field {
+    /// The key itself.
+    Key,
+    /// The whole value, decoded as JSON if it can be.
+    Value,
+    /// A field inside the value, such as `value.address.city`.
+    Path(Vec<String>),
+}
+
+impl Field {
+    fn get(&self, key: &str, value: &Value) -> Value {
+        match self {
+            Field::Key => Value::String(key.to_owned()),
+            Field::Value => value.clone(),
+            Field::Path(path) => path
+                .iter()
+                .try_fold(value, |value, name| value.get(name))
+                .cloned()
+                .unwrap_or(Value::Null),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Condition {
+    field: Field,
+    op: Op,
+    literal: Value,
+}
+
+impl Condition {
+    fn matches(&self, key: &str, value: &Value) -> bool {
+        let ordering = compare(&self.field.get(key, value), &self.literal);
+        match self.op {
+            Op::Eq => ordering == Some(Ordering::Equal),
+            Op::Ne => ordering != Some(Ordering::Equal),
+            Op::Lt => ordering == Some(Ordering::Less),
+            Op::Le => {
+                ordering == Some(Ordering::Less)
+                    || ordering == Some(Ordering::Equal)
+            }
+            Op::Gt => ordering == Some(Ordering::Greater),
+            Op::Ge => {
+                ordering == Some(Ordering::Greater)
+                    || ordering == Some(Ordering::Equal)
+            }
+        }
+    }
+}
+
+/// Values that aren't JSON are treated as strings.
+fn decode(value: &str) -> Value {
+    serde_json::from_str(value)
+        .unwrap_or_else(|_| Value::String(value.to_owned()))
+}
+
+/// Values of different types don't compare, so only `!=` matches them.
+fn compare(a: &Value, b: &Value) -> Option<Ordering> {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => {
+            a.as_f64()?.partial_cmp(&b.as_f64()?)
+        }
+        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+        (Value::Bool(a), Value::Bool(b)) => Some(a.cmp(b)),
+        (Value::Null, Value::Null) => Some(Ordering::Equal),
+        (a, b) if a == b => Some(Ordering::Equal),
+        _ => None,
+    }
+}
+
+/// A query over a store's keys and their values decoded as JSON, for
+/// poking at structured data while debugging:
+///
+/// ```text
+/// SELECT <field>, ... [WHERE <field> <op> <literal> [AND ...]] [LIMIT <n>]
+/// ```
+///
+/// A field is `key`, `value`, or a path into the value such as
+/// `value.address.city`, and `*` selects the key and the value. Comparisons
+/// are `=`, `!=`, `<`, `<=`, `>` and `>=` against a number, a quoted
+/// string, `true`, `false` or `null`. Values that aren't JSON are treated
+/// as strings, and a path that isn't in the value is `null`. Keywords
+/// aren't case sensitive.
+///
+/// There are no secondary indexes to use, so every key is checked, inside
+/// the store with [`Scannable::scan_filtered`]. Only the values of keys in
+/// the range allowed by conditions on `key` are read, though.
+///
+/// ```rust
+/// # use tempfile::TempDir;
+/// use kvs::{HashMapKvs, KvStore, Persistent, Query};
+/// use serde_json::json;
+///
+/// # let temp_dir =
+/// #     TempDir::new().expect("unable to create temporary working directory");
+/// let mut store = HashMapKvs::open(temp_dir.path().join("kvs")).unwrap();
+/// store.set("ann".to_owned(), r#"{"age": 34}"#.to_owned()).unwrap();
+/// store.set("bob".to_owned(), r#"{"age": 27}"#.to_owned()).unwrap();
+///
+/// let query = Query::parse("SELECT key WHERE value.age > 30").unwrap();
+/// assert_eq!(query.run(&store).unwrap(), vec![vec![json!("ann")]]);
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct Query {
+    fields: Vec<Field>,
+    conditions: Vec<Condition>,
+    limit: Option<usize>,
+}
+
+impl Query {
+    /// Parse a query, returning a `Config` error describing what's wrong
+    /// with it if it's invalid.
+    pub fn parse(query: &str) -> Result<Query> {
+        let mut tokens = tokenize(query)?.into_iter().peekable();
+        expect_keyword(&mut tokens, "SELECT")?;
+
+        let mut fields = Vec::new();
+        loop {
+            match tokens.next() {
+                Some(Token::Symbol(ref symbol)) if symbol == "*" => {
+                    fields.push(Field::Key);
+                    fields.push(Field::Value);
+                }
+                Some(Token::Word(word)) => fields.push(parse_field(&word)?),
+                token => return Err(unexpected(token, "a field")),
+            }
+            match tokens.peek() {
+                Some(Token::Symbol(symbol)) if symbol == "," => {
+                    tokens.next();
+                }
+                _ => break,
+            }
+        }
+
+        let mut conditions = Vec::new();
+        if next_is_keyword(&mut tokens, "WHERE") {
+            loop {
+                let field = match tokens.next() {
+                    Some(Token::Word(word)) => parse_field(&word)?,
+                    token => return Err(unexpected(token, "a field")),
+                };
+                let op = match tokens.next() {
+                    Some(Token::Symbol(symbol)) => match symbol.as_str() {
+                        "=" => Op::Eq,
+                        "!=" | "<>" => Op::Ne,
+                        "<" => Op::Lt,
+                        "<=" => Op::Le,
+                        ">" => Op::Gt,
+                        ">=" => Op::Ge,
+                        _ => {
+                            return Err(unexpected(
+                                Some(Token::Symbol(symbol)),
+                                "a comparison",
+                            ))
+                        }
+                    },
+                    token => return Err(unexpected(token, "a comparison")),
+                };
+                let literal = match tokens.next() {
+                    Some(Token::Str(string)) => Value::String(string),
+                    Some(Token::Word(word)) => parse_literal(&word)?,
+                    token => return Err(unexpected(token, "a literal")),
+                };
+                conditions.push(Condition { field, op, literal });
+                if !next_is_keyword(&mut tokens, "AND") {
+                    break;
+                }
+            }
+        }
+
+        let limit = if next_is_keyword(&mut tokens, "LIMIT") {
+            match tokens.next() {
+                Some(Token::Word(word)) => {
+                    Some(word.parse().map_err(|_| {
+                        Error::config(format!("`{}` isn't a valid limit", word))
+                    })?)
+                }
+                token => return Err(unexpected(token, "a limit")),
+            }
+        } else {
+            None
+        };
+
+        match tokens.next() {
+            None => Ok(Query {
+                fields,
+                conditions,
+                limit,
+            }),
+            token => Err(unexpected(token, "the end of the query")),
+        }
+    }
+
+    /// The selected fields of each matching key, in key order. The
+    /// conditions are checked inside the store with
+    /// [`scan_filtered`](Scannable::scan_filtered).
+    pub fn run<S>(&self, store: &S) -> Result<Vec<Vec<Value>>>
+    where
+        S: Scannable + ?Sized,
+    {
+        let (start, end) = self.key_range();
+        let mut entries = store.scan_filtered(
+            &start,
+            end.as_ref().map(|end| &end[..]),
+            &|key, value| self.matches(key, value),
+        )?;
+        if let Some(limit) = self.limit {
+            entries.truncate(limit);
+        }
+
+        Ok(entries
+            .into_iter()
+            .map(|(key, value)| {
+                let value = decode(&value);
+                self.fields
+                    .iter()
+                    .map(|field| field.get(&key, &value))
+                    .collect()
+            })
+            .collect())
+    }
+
+    /// Whether a key and its value meet the query's conditions, to filter
+    /// entries with elsewhere.
+    pub fn matches(&self, key: &str, value: &str) -> bool {
+        let value = decode(value);
+        self.conditions
+            .iter()
+            .all(|condition| condition.matches(key, &value))
+    }
+
+    /// The narrowest range of keys the conditions on `key` allow.
+    fn key_range(&self) -> (String, Option<String>) {
+        let mut start = String::new();
+        let mut end: Option<String> = None;
+        for condition in &self.conditions {
+            let literal = match (&condition.field, &condition.literal) {
+                (Field::Key, Value::String(literal)) => literal,
+                _ => continue,
+            };
+            let (from, to) = match condition.op {
+                Op::Eq => {
+                    (Some(literal.clone()), Some(format!("{}\0", literal)))
+                }
+                Op::Ge => (Some(literal.clone()), None),
+                Op::Gt => (Some(format!("{}\0", literal)), None),
+                Op::Lt => (None, Some(literal.clone())),
+                Op::Le => (None, Some(format!("{}\0", literal))),
+                Op::Ne => (None, None),
+            };
+            if let Some(from) = from {
+                if from > start {
+                    start = from;
+                }
+            }
+            if let Some(to) = to {
+                end = match end {
+                    Some(end) if end <= to => Some(end),
+                    _ => Some(to),
+                };
+            }
+        }
+        (start, end)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    /// A keyword, field, number or other bare word.
+    Word(String),
+    /// A quoted string, without its quotes.
+    Str(String),
+    /// Punctuation, such as a comparison or a comma.
+    Symbol(String),
+}
+
+fn tokenize(query: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = query.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '\'' || c == '"' {
+            chars.next();
+            tokens.push(Token::Str(quoted(&mut chars, c)?));
+        } else if "=!<>,*".contains(c) {
+            chars.next();
+            let mut symbol = c.to_string();
+            if let Some(&next) = chars.peek() {
+                if (next == '=' && "!<>".contains(c))
+                    || (c == '<' && next == '>')
+                {
+                    symbol.push(next);
+                    chars.next();
+                }
+            }
+            tokens.push(Token::Symbol(symbol));
+        } else {
+            let mut word = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() || "'\"=!<>,*".contains(c) {
+                    break;
+                }
+                word.push(c);
+                chars.next();
+            }
+            tokens.push(Token::Word(word));
+        }
+    }
+    Ok(tokens)
+}
+
+/// Read a string up to its closing quote, where a doubled quote stands for
+/// the quote itself.
+fn quoted(chars: &mut Peekable<Chars>, quote: char) -> Result<String> {
+    let mut string = String::new();
+    loop {
+        match chars.next() {
+            Some(c) if c == quote => {
+                if chars.peek() == Some(&quote) {
+                    string.push(quote);
+                    chars.next();
+                } else {
+                    return Ok(string);
+                }
+            }
+            Some(c) => string.push(c),
+            None => {
+                return Err(Error::config(format!(
+                    "the string `{}` isn't closed",
+                    string
+                )))
+            }
+        }
+    }
+}
+
+fn parse_field(word: &str) -> Result<Field> {
+    let mut parts = word.split('.');
+    let field = match parts.next() {
+        Some(part) if part.eq_ignore_ascii_case("key") => Field::Key,
+        Some(part) if part.eq_ignore_ascii_case("value") => Field::Value,
+        _ => {
+            return Err(Error::config(format!(
+                "`{}` isn't a field, expected `key`, `value` or `value.<path>`",
+                word
+            )))
+        }
+    };
+    let path: Vec<String> = parts.map(str::to_owned).collect();
+    match field {
+        _ if path.is_empty() => Ok(field),
+        Field::Value if path.iter().all(|part| !part.is_empty()) => {
+            Ok(Field::Path(path))
+        }
+        _ => Err(Error::config(format!("`{}` isn't a valid field", word))),
+    }
+}
+
+fn parse_literal(word: &str) -> Result<Value> {
+    match word {
+        "true" => Ok(Value::Bool(true)),
+        "false" => Ok(Value::Bool(false)),
+        "null" => Ok(Value::Null),
+        _ => match serde_json::from_str(word) {
+            Ok(Value::Number(number)) => Ok(Value::Number(number)),
+            _ => Err(Error::config(format!(
+                "`{}` isn't a literal, strings need quotes",
+                word
+            ))),
+        },
+    }
+}
+
+fn expect_keyword<I>(tokens: &mut Peekable<I>, keyword: &str) -> Result<()>
+where
+    I: Iterator<Item = Token>,
+{
+    if next_is_keyword(tokens, keyword) {
+        Ok(())
+    } else {
+        Err(unexpected(tokens.next(), keyword))
+    }
+}
+
+/// Take the next token if it's the given keyword.
+fn next_is_keyword<I>(tokens: &mut Peekable<I>, keyword: &str) -> bool
+where
+    I: Iterator<Item = Token>,
+{
+    match tokens.peek() {
+        Some(Token::Word(word)) if word.eq_ignore_ascii_case(keyword) => {
+            tokens.next();
+            true
+        }
+        _ => false,
+    }
+}
+
+fn unexpected(token: Option<Token>, expected: &str) -> Error {
+    let found = match token {
+        Some(Token::Word(word)) | Some(Token::Symbol(word)) => {
+            format!("`{}`", word)
+        }
+        Some(Token::Str(string)) => format!("'{}'", string),
+        None => "the end of the query".to_owned(),
+    };
+    Error::config(format!("expected {}, found {}", expected, found))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::json;
+    use tempfile::TempDir;
+
+    use crate::{HashMapKvs, KvStore, Persistent};
+
+    #[test]
+    fn queries() -> Result<()> {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+        let mut store = HashMapKvs::open(temp_dir.path().join("kvs"))?;
+        for (key, value) in &[
+            ("user/ann", r#"{"age": 34, "address": {"city": "Oslo"}}"#),
+            ("user/bob", r#"{"age": 27, "admin": true}"#),
+            ("user/cy", r#"{"age": "unknown"}"#),
+            ("note", "not json"),
+        ] {
+            store.set(key.to_string(), value.to_string())?;
+        }
+        let run = |query: &str| Query::parse(query)?.run(&store);
+
+        assert_eq!(
+            run("SELECT key WHERE value.age > 30")?,
+            vec![vec![json!("user/ann")]]
+        );
+        assert_eq!(
+            run("select key, value.address.city where value.age >= 27 and \
+                 value.age < 34")?,
+            vec![vec![json!("user/bob"), json!(null)]]
+        );
+        assert_eq!(
+            run("SELECT key WHERE value.address.city = 'Oslo'")?,
+            vec![vec![json!("user/ann")]]
+        );
+        assert_eq!(run("SELECT key WHERE value.admin != true")?.len(), 3);
+        assert_eq!(
+            run("SELECT * WHERE value = \"not json\"")?,
+            vec![vec![json!("note"), json!("not json")]]
+        );
+        // conditions on the key only read the values in their range
+        assert_eq!(
+            run("SELECT key WHERE key >= 'user/' AND key < 'user0' LIMIT 2")?,
+            vec![vec![json!("user/ann")], vec![json!("user/bob")]]
+        );
+        assert_eq!(
+            run("SELECT value.age WHERE key = 'user/cy'")?,
+            vec![vec![json!("unknown")]]
+        );
+
+        for invalid in &[
+            "",
+            "SELECT",
+            "SELECT size",
+            "SELECT key WHERE value.age >",
+            "SELECT key WHERE value.name = ann",
+            "SELECT key WHERE value.name = 'ann",
+            "SELECT key LIMIT ten",
+            "SELECT key extra",
+        ] {
+            assert!(Query::parse(invalid).is_err(), "{}", invalid);
+        }
+
+        Ok(())
+    }
+}
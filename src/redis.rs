@@ -0,0 +1,421 @@
+use std::io::{BufRead, BufReader, Read, Write};
+
+use core::{Error, KvStore, Result, Scannable};
+
+/// Shortcut for an error describing a dump that can't be imported.
+fn invalid(msg: String) -> Error {
+    Error::serialization(msg)
+}
+
+/// Write every key in the store as a Redis append-only file, one `SET`
+/// command per key in key order. Returns the number of keys written.
+///
+/// ```rust
+/// # use tempfile::TempDir;
+/// use kvs::{export_aof, HashMapKvs, KvStore, Persistent};
+///
+/// # let temp_dir =
+/// #     TempDir::new().expect("unable to create temporary working directory");
+/// let mut store = HashMapKvs::open(temp_dir.path().join("kvs")).unwrap();
+/// store.set("key1".to_owned(), "value1".to_owned()).unwrap();
+///
+/// let mut aof = Vec::new();
+/// export_aof(&store, &mut aof).unwrap();
+/// assert_eq!(aof, b"*3\r\n$3\r\nSET\r\n$4\r\nkey1\r\n$6\r\nvalue1\r\n");
+/// ```
+pub fn export_aof<S>(store: &S, writer: &mut dyn Write) -> Result<u64>
+where
+    S: Scannable + ?Sized,
+{
+    let mut keys = store.keys()?;
+    keys.sort();
+
+    let mut written = 0;
+    for key in keys {
+        let value = match store.get(key.clone())? {
+            Some(value) => value,
+            None => continue,
+        };
+        write!(writer, "*3\r\n$3\r\nSET\r\n")?;
+        for arg in &[&key, &value] {
+            write!(writer, "${}\r\n", arg.len())?;
+            writer.write_all(arg.as_bytes())?;
+            writer.write_all(b"\r\n")?;
+        }
+        written += 1;
+    }
+    writer.flush()?;
+    Ok(written)
+}
+
+/// Apply the commands in a Redis append-only file to the store. Supports
+/// `SET` without options, `MSET` and `DEL`, and skips the commands that
+/// don't change data (`SELECT`, `MULTI`, `EXEC`, `PING`). Returns the
+/// number of commands applied.
+pub fn import_aof<S>(reader: &mut dyn Read, store: &mut S) -> Result<u64>
+where
+    S: KvStore + ?Sized,
+{
+    let mut reader = BufReader::new(reader);
+    let mut applied = 0;
+    while let Some(args) = read_resp_command(&mut reader)? {
+        let mut args = args.into_iter();
+        let name = args.next().unwrap_or_default().to_ascii_uppercase();
+        let args: Vec<String> = args.collect();
+        match name.as_str() {
+            "SET" if args.len() == 2 => {
+                let mut args = args.into_iter();
+                store.set(args.next().unwrap(), args.next().unwrap())?;
+            }
+            "SET" => {
+                return Err(invalid(
+                    "SET with options such as expiry times isn't supported"
+                        .to_owned(),
+                ))
+            }
+            "MSET" if !args.is_empty() => {
+                let mut args = args.into_iter();
+                while let Some(key) = args.next() {
+                    let value = args.next().ok_or_else(|| {
+                        invalid(format!("MSET has no value for `{}`", key))
+                    })?;
+                    store.set(key, value)?;
+                }
+            }
+            "DEL" if !args.is_empty() => {
+                for key in args {
+                    store.remove(key)?;
+                }
+            }
+            "SELECT" | "MULTI" | "EXEC" | "PING" => continue,
+            _ => {
+                return Err(invalid(format!(
+                    "unsupported command `{} ...` with {} arguments",
+                    name,
+                    args.len()
+                )))
+            }
+        }
+        applied += 1;
+    }
+    Ok(applied)
+}
+
+/// Read one RESP array of bulk strings, or None at the end of the input.
+fn read_resp_command<R: BufRead>(
+    reader: &mut R,
+) -> Result<Option<Vec<String>>> {
+    let count = match read_resp_line(reader)? {
+        Some(line) => parse_resp_len(&line, '*')?,
+        None => return Ok(None),
+    };
+
+    let mut args = Vec::with_capacity(count);
+    for _ in 0..count {
+        let line = read_resp_line(reader)?
+            .ok_or_else(|| invalid("the file ends mid-command".to_owned()))?;
+        let len = parse_resp_len(&line, '$')?;
+
+        let mut arg = vec![0; len + 2];
+        reader.read_exact(&mut arg)?;
+        if !arg.ends_with(b"\r\n") {
+            return Err(invalid(
+                "a bulk string isn't followed by CRLF".to_owned(),
+            ));
+        }
+        arg.truncate(len);
+        args.push(utf8(arg)?);
+    }
+    Ok(Some(args))
+}
+
+/// Read a line ending in CRLF, without the ending.
+fn read_resp_line<R: BufRead>(reader: &mut R) -> Result<Option<String>> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    if !line.ends_with("\r\n") {
+        return Err(invalid(format!("`{}` isn't followed by CRLF", line)));
+    }
+    line.truncate(line.len() - 2);
+    Ok(Some(line))
+}
+
+fn parse_resp_len(line: &str, prefix: char) -> Result<usize> {
+    if !line.starts_with(prefix) {
+        return Err(invalid(format!(
+            "expected `{}`, found `{}`",
+            prefix, line
+        )));
+    }
+    line[1..]
+        .parse()
+        .map_err(|_| invalid(format!("`{}` isn't a valid length", line)))
+}
+
+fn utf8(bytes: Vec<u8>) -> Result<String> {
+    String::from_utf8(bytes).map_err(|_| {
+        invalid("only UTF-8 keys and values can be imported".to_owned())
+    })
+}
+
+/// Set every key in a Redis RDB dump in the store. Only string values
+/// without expiry times are supported, though they may be stored as
+/// integers or LZF compressed. The checksum at the end isn't verified.
+/// Returns the number of keys set.
+pub fn import_rdb<S>(reader: &mut dyn Read, store: &mut S) -> Result<u64>
+where
+    S: KvStore + ?Sized,
+{
+    let mut reader = BufReader::new(reader);
+    let mut magic = [0; 9];
+    reader.read_exact(&mut magic)?;
+    if &magic[..5] != b"REDIS" || !magic[5..].iter().all(u8::is_ascii_digit) {
+        return Err(invalid("not an RDB file".to_owned()));
+    }
+
+    let mut imported = 0;
+    loop {
+        match read_u8(&mut reader)? {
+            // end of file, followed by a checksum
+            0xFF => return Ok(imported),
+            // select database
+            0xFE => {
+                read_rdb_len(&mut reader)?;
+            }
+            // hash table size hints
+            0xFB => {
+                read_rdb_len(&mut reader)?;
+                read_rdb_len(&mut reader)?;
+            }
+            // auxiliary field
+            0xFA => {
+                read_rdb_string(&mut reader)?;
+                read_rdb_string(&mut reader)?;
+            }
+            0xFC | 0xFD => {
+                return Err(invalid(
+                    "keys with expiry times aren't supported".to_owned(),
+                ))
+            }
+            // string value
+            0 => {
+                let key = utf8(read_rdb_string(&mut reader)?)?;
+                let value = utf8(read_rdb_string(&mut reader)?)?;
+                store.set(key, value)?;
+                imported += 1;
+            }
+            kind => {
+                return Err(invalid(format!(
+                    "only string values are supported, found type {}",
+                    kind
+                )))
+            }
+        }
+    }
+}
+
+fn read_u8<R: Read>(reader: &mut R) -> Result<u8> {
+    let mut byte = [0];
+    reader.read_exact(&mut byte)?;
+    Ok(byte[0])
+}
+
+/// A length, or one of the special string encodings.
+enum RdbLen {
+    Len(usize),
+    Encoded(u8),
+}
+
+fn read_rdb_len_or_encoding<R: Read>(reader: &mut R) -> Result<RdbLen> {
+    let first = read_u8(reader)?;
+    Ok(match first >> 6 {
+        0 => RdbLen::Len(usize::from(first & 0x3F)),
+        1 => {
+            let next = read_u8(reader)?;
+            RdbLen::Len((usize::from(first & 0x3F) << 8) | usize::from(next))
+        }
+        2 if first == 0x80 => {
+            let mut bytes = [0; 4];
+            reader.read_exact(&mut bytes)?;
+            RdbLen::Len(u32::from_be_bytes(bytes) as usize)
+        }
+        2 if first == 0x81 => {
+            let mut bytes = [0; 8];
+            reader.read_exact(&mut bytes)?;
+            RdbLen::Len(u64::from_be_bytes(bytes) as usize)
+        }
+        2 => return Err(invalid(format!("invalid length byte {:#x}", first))),
+        _ => RdbLen::Encoded(first & 0x3F),
+    })
+}
+
+fn read_rdb_len<R: Read>(reader: &mut R) -> Result<usize> {
+    match read_rdb_len_or_encoding(reader)? {
+        RdbLen::Len(len) => Ok(len),
+        RdbLen::Encoded(_) => {
+            Err(invalid("expected a length, found a string".to_owned()))
+        }
+    }
+}
+
+fn read_rdb_string<R: Read>(reader: &mut R) -> Result<Vec<u8>> {
+    Ok(match read_rdb_len_or_encoding(reader)? {
+        RdbLen::Len(len) => {
+            let mut bytes = vec![0; len];
+            reader.read_exact(&mut bytes)?;
+            bytes
+        }
+        RdbLen::Encoded(0) => (read_u8(reader)? as i8).to_string().into_bytes(),
+        RdbLen::Encoded(1) => {
+            let mut bytes = [0; 2];
+            reader.read_exact(&mut bytes)?;
+            i16::from_le_bytes(bytes).to_string().into_bytes()
+        }
+        RdbLen::Encoded(2) => {
+            let mut bytes = [0; 4];
+            reader.read_exact(&mut bytes)?;
+            i32::from_le_bytes(bytes).to_string().into_bytes()
+        }
+        RdbLen::Encoded(3) => {
+            let compressed_len = read_rdb_len(reader)?;
+            let len = read_rdb_len(reader)?;
+            let mut compressed = vec![0; compressed_len];
+            reader.read_exact(&mut compressed)?;
+            lzf_decompress(&compressed, len)?
+        }
+        RdbLen::Encoded(encoding) => {
+            return Err(invalid(format!(
+                "unknown string encoding {}",
+                encoding
+            )))
+        }
+    })
+}
+
+/// Decompress LZF data, which is either a run of literal bytes or a copy of
+/// earlier output, picked by a control byte.
+fn lzf_decompress(input: &[u8], len: usize) -> Result<Vec<u8>> {
+    let corrupt = || invalid("invalid LZF compressed string".to_owned());
+    let mut output = Vec::with_capacity(len);
+    let mut input = input.iter().copied();
+
+    while let Some(control) = input.next() {
+        let control = usize::from(control);
+        if control < 32 {
+            for _ in 0..=control {
+                output.push(input.next().ok_or_else(corrupt)?);
+            }
+        } else {
+            let mut run = control >> 5;
+            if run == 7 {
+                run += usize::from(input.next().ok_or_else(corrupt)?);
+            }
+            run += 2;
+            let back = ((control & 0x1F) << 8)
+                + usize::from(input.next().ok_or_else(corrupt)?)
+                + 1;
+            if back > output.len() {
+                return Err(corrupt());
+            }
+            // the copy can overlap what it's writing, so go byte by byte
+            let start = output.len() - back;
+            for i in start..start + run {
+                output.push(output[i]);
+            }
+        }
+    }
+
+    if output.len() != len {
+        return Err(corrupt());
+    }
+    Ok(output)
+}
+
+#[cfg(all(test, feature = "hashmap"))]
+mod tests {
+    use super::*;
+
+    use tempfile::TempDir;
+
+    use core::Persistent;
+
+    use crate::HashMapKvs;
+
+    fn open(temp_dir: &TempDir, name: &str) -> Result<HashMapKvs> {
+        HashMapKvs::open(temp_dir.path().join(name))
+    }
+
+    #[test]
+    fn aof_round_trip() -> Result<()> {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+        let mut store = open(&temp_dir, "a")?;
+        store.set("key1".to_owned(), "value1".to_owned())?;
+        store.set("multi\r\nline".to_owned(), "ünïcode\r\n".to_owned())?;
+
+        let mut aof = Vec::new();
+        assert_eq!(export_aof(&store, &mut aof)?, 2);
+
+        let mut copy = open(&temp_dir, "b")?;
+        assert_eq!(import_aof(&mut aof.as_slice(), &mut copy)?, 2);
+        assert!(crate::diff(&store, &copy)?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn aof_commands() -> Result<()> {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+        let mut store = open(&temp_dir, "kvs")?;
+        let aof = concat!(
+            "*2\r\n$6\r\nSELECT\r\n$1\r\n0\r\n",
+            "*5\r\n$4\r\nmset\r\n",
+            "$1\r\na\r\n$1\r\n1\r\n$1\r\nb\r\n$1\r\n2\r\n",
+            "*3\r\n$3\r\nDEL\r\n$1\r\na\r\n$1\r\nc\r\n",
+        );
+
+        assert_eq!(import_aof(&mut aof.as_bytes(), &mut store)?, 2);
+        assert_eq!(store.get("a".to_owned())?, None);
+        assert_eq!(store.get("b".to_owned())?, Some("2".to_owned()));
+
+        let expiring =
+            "*5\r\n$3\r\nSET\r\n$1\r\na\r\n$1\r\n1\r\n$2\r\nEX\r\n$1\r\n9\r\n";
+        assert!(import_aof(&mut expiring.as_bytes(), &mut store).is_err());
+        let truncated = "*3\r\n$3\r\nSET\r\n$1\r\na\r\n";
+        assert!(import_aof(&mut truncated.as_bytes(), &mut store).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn rdb() -> Result<()> {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+        let mut store = open(&temp_dir, "kvs")?;
+
+        let mut rdb = b"REDIS0009".to_vec();
+        rdb.extend_from_slice(b"\xFA\x09redis-ver\x055.0.0");
+        rdb.extend_from_slice(b"\xFE\x00\xFB\x03\x00");
+        rdb.extend_from_slice(b"\x00\x04key1\x06value1");
+        // an integer, and ten 'a's compressed as a literal then a copy
+        rdb.extend_from_slice(b"\x00\x03int\xC1\x39\x30");
+        rdb.extend_from_slice(b"\x00\x03lzf\xC3\x05\x0A\x00a\xE0\x00\x00");
+        rdb.extend_from_slice(b"\xFF\x00\x00\x00\x00\x00\x00\x00\x00");
+
+        assert_eq!(import_rdb(&mut rdb.as_slice(), &mut store)?, 3);
+        assert_eq!(store.get("key1".to_owned())?, Some("value1".to_owned()));
+        assert_eq!(store.get("int".to_owned())?, Some("12345".to_owned()));
+        assert_eq!(store.get("lzf".to_owned())?, Some("a".repeat(10)));
+
+        // lists aren't supported
+        let mut list = b"REDIS0009".to_vec();
+        list.extend_from_slice(b"\x01\x04key1\x01\x01a\xFF");
+        assert!(import_rdb(&mut list.as_slice(), &mut store).is_err());
+        assert!(import_rdb(&mut &b"not a dump"[..], &mut store).is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,551 @@
+use std::fs;
+use std::path::Path;
+
+use core::{in_range, Collation, Error, KvStore, Result, Scannable};
+
+use crate::{digest, LogKvs, LogOp, RangeDigest};
+
+/// How far a [`move_keys`] got before it stopped, as recorded in its
+/// marker.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MovePhase {
+    /// Keys are being copied to the destination. Resuming copies them
+    /// again, and rolling back removes them from the destination.
+    Copying,
+    /// The keys have been copied and checked, and are being removed from
+    /// the source. The move can only be resumed.
+    Removing,
+}
+
+/// What a [`move_keys`] did.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MoveReport {
+    /// How many keys were copied to the destination, including writes to
+    /// the source caught up on after the first copy.
+    pub copied: u64,
+    /// How many keys were removed from the source. 0 if a resumed move
+    /// had already removed them before it was interrupted.
+    pub removed: u64,
+    /// Whether it carried on from a move that was interrupted.
+    pub resumed: bool,
+    /// The digest the source and destination agreed on before the keys
+    /// were removed from the source.
+    pub digest: RangeDigest,
+}
+
+/// The state of a move, kept in its marker file so an interrupted move can
+/// be resumed or rolled back. Saved as one tab separated line: the phase,
+/// the source's sequence and generation the destination has caught up to,
+/// and the range's start and end as JSON.
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct MoveMarker {
+    phase: MovePhase,
+    sequence: u64,
+    generation: u64,
+    start: String,
+    end: Option<String>,
+}
+
+impl MoveMarker {
+    fn read(path: &Path) -> Result<Option<MoveMarker>> {
+        if !path.is_file() {
+            return Ok(None);
+        }
+        let line = fs::read_to_string(path)?;
+        MoveMarker::decode(line.trim_end_matches('\n'))
+            .map(Some)
+            .ok_or_else(|| {
+                Error::corrupt_database(format!(
+                    "{} isn't a move marker",
+                    path.display()
+                ))
+            })
+    }
+
+    fn decode(line: &str) -> Option<MoveMarker> {
+        let fields: Vec<&str> = line.split('\t').collect();
+        let (phase, sequence, generation, start, end) = match fields.as_slice()
+        {
+            [phase, sequence, generation, start, end] => {
+                (*phase, *sequence, *generation, *start, *end)
+            }
+            _ => return None,
+        };
+        Some(MoveMarker {
+            phase: match phase {
+                "copying" => MovePhase::Copying,
+                "removing" => MovePhase::Removing,
+                _ => return None,
+            },
+            sequence: sequence.parse().ok()?,
+            generation: generation.parse().ok()?,
+            start: serde_json::from_str(start).ok()?,
+            end: serde_json::from_str(end).ok()?,
+        })
+    }
+
+    fn write(&self, path: &Path) -> Result<()> {
+        let phase = match self.phase {
+            MovePhase::Copying => "copying",
+            MovePhase::Removing => "removing",
+        };
+        let line = format!(
+            "{}\t{}\t{}\t{}\t{}\n",
+            phase,
+            self.sequence,
+            self.generation,
+            serde_json::to_string(&self.start).map_err(Error::serialization)?,
+            serde_json::to_string(&self.end).map_err(Error::serialization)?,
+        );
+        // written whole and renamed over the old one, so a crash leaves
+        // either the old marker or the new one
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, line)?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    fn end(&self) -> Option<&str> {
+        self.end.as_ref().map(|end| &end[..])
+    }
+
+    fn covers(&self, key: &str) -> bool {
+        in_range(key, &self.start, self.end())
+    }
+}
+
+/// Move the keys from `start` up to but not including `end`, or up to the
+/// last key if there's no end, out of the source and into the destination,
+/// such as when resharding. The keys are copied, along with any writes made
+/// to the source since the copy started, then the range's digest is checked
+/// to be the same in both stores, and only then are the keys removed from
+/// the source, with a single ranged tombstone.
+///
+/// Each step is recorded in a marker file at the given path, which is
+/// removed once the move is done. If the move is interrupted, calling this
+/// again with the same marker and range carries on from where it stopped,
+/// or [`roll_back_move`] undoes it if the keys haven't started being
+/// removed from the source. Resuming always catches up on the writes made
+/// to the source since, and checks the digests again, before removing
+/// anything, and refuses to carry on if the source has been compacted in
+/// the meantime, since those writes can't be found.
+///
+/// The destination mustn't hold any keys in the range when a move starts,
+/// and the source has to be in bytewise collation, so the range it removes
+/// is the one that was copied.
+///
+/// ```rust
+/// # use tempfile::TempDir;
+/// use kvs::{move_keys, KvStore, LogKvs, MemKvs, Persistent};
+///
+/// # let temp_dir =
+/// #     TempDir::new().expect("unable to create temporary working directory");
+/// let mut source = LogKvs::open(temp_dir.path().join("source")).unwrap();
+/// source.set("a:1".to_owned(), "value1".to_owned()).unwrap();
+/// source.set("b:1".to_owned(), "value2".to_owned()).unwrap();
+/// let mut dest = MemKvs::new();
+///
+/// let marker = temp_dir.path().join("move");
+/// let report =
+///     move_keys(&mut source, &mut dest, "b:", Some("b;"), &marker).unwrap();
+/// assert_eq!(report.removed, 1);
+/// assert_eq!(source.get("b:1".to_owned()).unwrap(), None);
+/// assert_eq!(dest.get("b:1".to_owned()).unwrap(), Some("value2".to_owned()));
+/// ```
+pub fn move_keys<D, P>(
+    source: &mut LogKvs,
+    dest: &mut D,
+    start: &str,
+    end: Option<&str>,
+    marker_path: P,
+) -> Result<MoveReport>
+where
+    D: Scannable + ?Sized,
+    P: AsRef<Path>,
+{
+    let marker_path = marker_path.as_ref();
+    let (mut marker, resumed) = match MoveMarker::read(marker_path)? {
+        Some(marker) => {
+            if marker.start != start || marker.end() != end {
+                return Err(Error::config(format!(
+                    "{} is for a move of a different range",
+                    marker_path.display()
+                )));
+            }
+            (marker, true)
+        }
+        None => {
+            if source.collation() != Collation::Bytewise {
+                return Err(Error::config(
+                    "keys can only be moved out of a store in bytewise \
+                     collation"
+                        .to_owned(),
+                ));
+            }
+            if dest.keys()?.iter().any(|key| in_range(key, start, end)) {
+                return Err(Error::config(
+                    "the destination already holds keys in the range"
+                        .to_owned(),
+                ));
+            }
+            let marker = MoveMarker {
+                phase: MovePhase::Copying,
+                sequence: source.sequence()?,
+                generation: source.generation(),
+                start: start.to_owned(),
+                end: end.map(str::to_owned),
+            };
+            marker.write(marker_path)?;
+            (marker, false)
+        }
+    };
+
+    let mut copied = 0;
+    if marker.phase == MovePhase::Copying {
+        // the whole range is copied again when resuming, since there's no
+        // telling how far the copy got
+        for key in source.keys()? {
+            if !marker.covers(&key) {
+                continue;
+            }
+            if let Some(value) = source.get_ref(&key)? {
+                dest.set(key, value.into_owned())?;
+                copied += 1;
+            }
+        }
+    } else if removed_already(source, &marker)? {
+        // stopped after the tombstone was written but before the marker
+        // was removed, so there's nothing left to do
+        fs::remove_file(marker_path)?;
+        return Ok(MoveReport {
+            copied,
+            removed: 0,
+            resumed,
+            digest: digest(&*dest, start, end)?,
+        });
+    }
+    // writes to the source since the marker, including any made while a
+    // move was stopped partway through removing, have to reach the
+    // destination before the range is removed from the source
+    copied += catch_up(source, dest, &mut marker, marker_path)?;
+
+    let source_digest = digest(&*source, start, end)?;
+    let dest_digest = digest(&*dest, start, end)?;
+    if source_digest != dest_digest {
+        return Err(Error::corrupt_database(format!(
+            "the destination's copy of the range doesn't match the source: {} \
+             keys with digest {}, expected {} with {}",
+            dest_digest.keys,
+            dest_digest.root,
+            source_digest.keys,
+            source_digest.root
+        )));
+    }
+    if marker.phase == MovePhase::Copying {
+        marker.phase = MovePhase::Removing;
+        marker.write(marker_path)?;
+    }
+
+    let removed = source.remove_range(start, end)?;
+    fs::remove_file(marker_path)?;
+    Ok(MoveReport {
+        copied,
+        removed,
+        resumed,
+        digest: source_digest,
+    })
+}
+
+/// Undo an interrupted [`move_keys`], removing the keys it copied from the
+/// destination and then its marker, and returning how many were removed.
+/// Fails if the keys have started being removed from the source, since
+/// the destination may hold the only copy; resume the move instead.
+pub fn roll_back_move<D, P>(dest: &mut D, marker_path: P) -> Result<u64>
+where
+    D: Scannable + ?Sized,
+    P: AsRef<Path>,
+{
+    let marker_path = marker_path.as_ref();
+    let marker = match MoveMarker::read(marker_path)? {
+        Some(marker) => marker,
+        None => {
+            return Err(Error::config(format!(
+                "there's no move to roll back at {}",
+                marker_path.display()
+            )))
+        }
+    };
+    if marker.phase == MovePhase::Removing {
+        return Err(Error::config(
+            "the moved keys are being removed from the source, so the move \
+             can only be resumed"
+                .to_owned(),
+        ));
+    }
+
+    // the destination held no keys in the range when the move started
+    let mut removed = 0;
+    for key in dest.keys()? {
+        if marker.covers(&key) && dest.remove_ref(&key)?.is_some() {
+            removed += 1;
+        }
+    }
+    fs::remove_file(marker_path)?;
+    Ok(removed)
+}
+
+/// The phase of the move recorded in the marker, or None if there's no move
+/// underway.
+pub fn move_phase<P: AsRef<Path>>(marker_path: P) -> Result<Option<MovePhase>> {
+    Ok(MoveMarker::read(marker_path.as_ref())?.map(|marker| marker.phase))
+}
+
+/// Whether the first write to the source since the marker is the move's own
+/// tombstone, so a move stopped while removing had already removed the
+/// range.
+fn removed_already(source: &LogKvs, marker: &MoveMarker) -> Result<bool> {
+    if source.generation() != marker.generation
+        || source.sequence()? == marker.sequence
+    {
+        return Ok(false);
+    }
+    match source.events_since(marker.sequence)?.next() {
+        Some(event) => {
+            let event = event?;
+            Ok(event.op == LogOp::RemoveRange
+                && event.key == marker.start
+                && event.value == marker.end)
+        }
+        None => Ok(false),
+    }
+}
+
+/// Apply the writes made to the source in the range since the marker's
+/// sequence to the destination, until there are none left, moving the
+/// marker on. Returns how many keys were written.
+fn catch_up<D: Scannable + ?Sized>(
+    source: &LogKvs,
+    dest: &mut D,
+    marker: &mut MoveMarker,
+    marker_path: &Path,
+) -> Result<u64> {
+    let mut written = 0;
+    loop {
+        // sequences from before a compaction don't point at the same
+        // records, even if the log's the same length
+        if source.generation() != marker.generation {
+            return Err(Error::config(
+                "the source was compacted during the move, so the writes made \
+                 since it started can't be caught up on; roll it back and \
+                 start again"
+                    .to_owned(),
+            ));
+        }
+        let sequence = source.sequence()?;
+        if sequence == marker.sequence {
+            return Ok(written);
+        }
+        for event in source.events_since(marker.sequence)? {
+            let event = event?;
+            match event.op {
+                LogOp::Set if marker.covers(&event.key) => {
+                    let value = event.value.unwrap_or_default();
+                    dest.set(event.key, value)?;
+                    written += 1;
+                }
+                LogOp::Remove if marker.covers(&event.key) => {
+                    dest.remove_ref(&event.key)?;
+                }
+                LogOp::RemovePrefix | LogOp::RemoveRange => {
+                    // whatever the tombstone covered is gone from the source
+                    for key in dest.keys()? {
+                        if marker.covers(&key) && !source.contains_key(&key)? {
+                            dest.remove_ref(&key)?;
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        marker.sequence = sequence;
+        marker.write(marker_path)?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tempfile::TempDir;
+
+    use core::{Compactable, Persistent};
+
+    use crate::MemKvs;
+
+    #[test]
+    fn interrupted_move() -> Result<()> {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+        let mut source = LogKvs::open(temp_dir.path().join("source"))?;
+        for i in 0..10 {
+            source.set(format!("a:{}", i), format!("value{}", i))?;
+            source.set(format!("b:{}", i), format!("value{}", i))?;
+        }
+        let marker_path = temp_dir.path().join("move");
+
+        // stopped partway through copying, with writes to the source since
+        let mut dest = MemKvs::new();
+        let marker = MoveMarker {
+            phase: MovePhase::Copying,
+            sequence: source.sequence()?,
+            generation: source.generation(),
+            start: "b:".to_owned(),
+            end: Some("b;".to_owned()),
+        };
+        marker.write(&marker_path)?;
+        dest.set("b:0".to_owned(), "value0".to_owned())?;
+        dest.set("b:9".to_owned(), "value9".to_owned())?;
+        source.remove("b:9".to_owned())?;
+        source.set("b:1".to_owned(), "changed".to_owned())?;
+        assert_eq!(move_phase(&marker_path)?, Some(MovePhase::Copying));
+        assert!(move_keys(&mut source, &mut dest, "b:", None, &marker_path)
+            .is_err());
+
+        let report =
+            move_keys(&mut source, &mut dest, "b:", Some("b;"), &marker_path)?;
+        assert!(report.resumed);
+        assert_eq!(report.removed, 9);
+        assert_eq!(report.digest.keys, 9);
+        assert_eq!(move_phase(&marker_path)?, None);
+        assert_eq!(dest.get("b:1".to_owned())?, Some("changed".to_owned()));
+        assert_eq!(dest.get("b:9".to_owned())?, None);
+        assert_eq!(source.scan_filtered("b:", None, &|_, _| true)?.len(), 0);
+        assert_eq!(source.keys()?.len(), 10);
+
+        // a destination already holding keys in the range is refused
+        assert!(move_keys(&mut source, &mut dest, "b:", None, &marker_path)
+            .is_err());
+        assert_eq!(move_phase(&marker_path)?, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn resumed_removal() -> Result<()> {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+        let mut source = LogKvs::open(temp_dir.path().join("source"))?;
+        for i in 0..5 {
+            source.set(format!("b:{}", i), format!("value{}", i))?;
+        }
+        let marker_path = temp_dir.path().join("move");
+        let mut dest = MemKvs::new();
+        for i in 0..5 {
+            dest.set(format!("b:{}", i), format!("value{}", i))?;
+        }
+        let marker = MoveMarker {
+            phase: MovePhase::Removing,
+            sequence: source.sequence()?,
+            generation: source.generation(),
+            start: "b:".to_owned(),
+            end: Some("b;".to_owned()),
+        };
+
+        // stopped before the tombstone, with writes to the source since
+        marker.write(&marker_path)?;
+        source.set("b:1".to_owned(), "changed".to_owned())?;
+        source.set("b:5".to_owned(), "value5".to_owned())?;
+        source.remove("b:4".to_owned())?;
+        let report =
+            move_keys(&mut source, &mut dest, "b:", Some("b;"), &marker_path)?;
+        assert_eq!((report.copied, report.removed), (2, 5));
+        assert_eq!(dest.get("b:1".to_owned())?, Some("changed".to_owned()));
+        assert_eq!(dest.get("b:5".to_owned())?, Some("value5".to_owned()));
+        assert_eq!(dest.get("b:4".to_owned())?, None);
+        assert!(source.keys()?.is_empty());
+
+        // stopped after the tombstone, before the marker was removed
+        source.set("b:6".to_owned(), "value6".to_owned())?;
+        dest.set("b:6".to_owned(), "value6".to_owned())?;
+        let marker = MoveMarker {
+            sequence: source.sequence()?,
+            ..marker
+        };
+        marker.write(&marker_path)?;
+        source.remove_range("b:", Some("b;"))?;
+        let report =
+            move_keys(&mut source, &mut dest, "b:", Some("b;"), &marker_path)?;
+        assert_eq!(report.removed, 0);
+        assert_eq!(dest.keys()?.len(), 6);
+        assert_eq!(move_phase(&marker_path)?, None);
+
+        // and refuses to remove anything once the source is compacted
+        let marker = MoveMarker {
+            sequence: source.sequence()?,
+            generation: source.generation(),
+            ..marker
+        };
+        marker.write(&marker_path)?;
+        source.set("b:7".to_owned(), "value7".to_owned())?;
+        source.compact()?;
+        assert!(move_keys(
+            &mut source,
+            &mut dest,
+            "b:",
+            Some("b;"),
+            &marker_path
+        )
+        .is_err());
+        assert_eq!(source.get("b:7".to_owned())?, Some("value7".to_owned()));
+        assert_eq!(move_phase(&marker_path)?, Some(MovePhase::Removing));
+
+        Ok(())
+    }
+
+    #[test]
+    fn rolled_back_move() -> Result<()> {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+        let mut source = LogKvs::open(temp_dir.path().join("source"))?;
+        source.set("a:1".to_owned(), "value1".to_owned())?;
+        source.set("a:2".to_owned(), "value2".to_owned())?;
+        let marker_path = temp_dir.path().join("move");
+        let mut dest = MemKvs::new();
+        dest.set("b:1".to_owned(), "kept".to_owned())?;
+
+        let marker = MoveMarker {
+            phase: MovePhase::Copying,
+            sequence: source.sequence()?,
+            generation: source.generation(),
+            start: "a:".to_owned(),
+            end: Some("a;".to_owned()),
+        };
+        marker.write(&marker_path)?;
+        dest.set("a:1".to_owned(), "value1".to_owned())?;
+        source.compact()?;
+
+        // the writes since can't be found once the source is compacted
+        assert!(move_keys(
+            &mut source,
+            &mut dest,
+            "a:",
+            Some("a;"),
+            &marker_path
+        )
+        .is_err());
+        assert_eq!(roll_back_move(&mut dest, &marker_path)?, 2);
+        assert!(roll_back_move(&mut dest, &marker_path).is_err());
+        assert_eq!(dest.keys()?, vec!["b:1"]);
+        assert_eq!(source.keys()?.len(), 2);
+
+        // once keys are being removed from the source, it can't be undone
+        MoveMarker {
+            phase: MovePhase::Removing,
+            ..marker
+        }
+        .write(&marker_path)?;
+        assert!(roll_back_move(&mut dest, &marker_path).is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,133 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use core::Result;
+
+use crate::backup::CHAIN_FILE_NAME;
+use crate::Manifest;
+
+/// Which backups to keep when pruning, see [`prune_backups`]. A backup is
+/// pruned if it breaks either limit, but the newest is always kept.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct RetentionPolicy {
+    /// Keep at most this many backups.
+    pub keep: Option<usize>,
+    /// Prune backups last written longer ago than this.
+    pub max_age: Option<Duration>,
+}
+
+/// Remove old backups from a directory holding one backup per
+/// subdirectory, as written by [`backup`](crate::backup) or
+/// [`backup_incremental`](crate::backup_incremental). A chain of incremental
+/// backups is kept or removed as a whole, since its links depend on each
+/// other, and its age is that of its newest link. Anything else in the
+/// directory is left alone.
+///
+/// Returns the backups pruned, oldest first. With `dry_run`, nothing is
+/// removed.
+pub fn prune_backups<P: AsRef<Path>>(
+    dir: P,
+    policy: &RetentionPolicy,
+    dry_run: bool,
+) -> Result<Vec<PathBuf>> {
+    let mut backups = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let marker = if path.join(CHAIN_FILE_NAME).is_file() {
+            path.join(CHAIN_FILE_NAME)
+        } else if path.join(Manifest::FILE_NAME).is_file() {
+            path.join(Manifest::FILE_NAME)
+        } else {
+            continue;
+        };
+        backups.push((fs::metadata(marker)?.modified()?, path));
+    }
+    // newest first
+    backups.sort_by(|a, b| b.cmp(a));
+
+    let now = SystemTime::now();
+    let mut pruned = Vec::new();
+    for (i, (modified, path)) in backups.into_iter().enumerate() {
+        let too_many = match policy.keep {
+            Some(keep) => i >= keep,
+            None => false,
+        };
+        let too_old = match policy.max_age {
+            Some(max_age) => {
+                now.duration_since(modified).unwrap_or_default() > max_age
+            }
+            None => false,
+        };
+        if i > 0 && (too_many || too_old) {
+            pruned.push(path);
+        }
+    }
+    pruned.reverse();
+
+    if !dry_run {
+        for path in &pruned {
+            fs::remove_dir_all(path)?;
+        }
+    }
+    Ok(pruned)
+}
+
+#[cfg(all(test, feature = "log"))]
+mod tests {
+    use super::*;
+
+    use tempfile::TempDir;
+
+    use core::KvStore;
+
+    use crate::{backup, backup_incremental, Engine, Kvs};
+
+    #[test]
+    fn prune() -> Result<()> {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+        let backups = temp_dir.path().join("backups");
+        fs::create_dir(&backups)?;
+        let mut store = Kvs::builder()
+            .engine(Engine::Log)
+            .path(temp_dir.path().join("store"))
+            .open_any()?;
+        store.set("key1".to_owned(), "value1".to_owned())?;
+
+        for name in &["a", "b", "c"] {
+            backup(&store, backups.join(name))?;
+            // make sure each backup is newer than the last
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        backup_incremental(&store, backups.join("chain"))?;
+        fs::create_dir(backups.join("not a backup"))?;
+
+        let keep_two = RetentionPolicy {
+            keep: Some(2),
+            ..RetentionPolicy::default()
+        };
+        let pruned = prune_backups(&backups, &keep_two, true)?;
+        assert_eq!(pruned, vec![backups.join("a"), backups.join("b")]);
+        assert!(backups.join("a").exists());
+
+        assert_eq!(prune_backups(&backups, &keep_two, false)?, pruned);
+        assert!(!backups.join("a").exists());
+        assert!(backups.join("c").exists());
+
+        // the newest backup is kept even if it's too old
+        let keep_none = RetentionPolicy {
+            max_age: Some(Duration::from_secs(0)),
+            ..RetentionPolicy::default()
+        };
+        std::thread::sleep(Duration::from_millis(20));
+        assert_eq!(
+            prune_backups(&backups, &keep_none, false)?,
+            vec![backups.join("c")]
+        );
+        assert!(backups.join("chain").exists());
+        assert!(backups.join("not a backup").exists());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,279 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use core::{Compactable, Measurable, Result, StoreObserver, StoreStats};
+
+/// When a [`CompactionScheduler`] compacts a store.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CompactionPolicy {
+    /// Never compact while there are fewer stale bytes than this.
+    pub min_stale_bytes: u64,
+    /// Compact once this percentage of the store is stale, if it's idle.
+    pub idle_stale_percent: u64,
+    /// Compact once this percentage of the store is stale, even if it's
+    /// busy.
+    pub busy_stale_percent: u64,
+    /// How long the store has to go without reads or writes to be idle.
+    pub idle_after: Duration,
+    /// Compact at the idle percentage even while busy, if the store is
+    /// written to faster than this many times a second, since the stale
+    /// bytes will only keep growing.
+    pub max_writes_per_sec: u64,
+}
+
+impl Default for CompactionPolicy {
+    fn default() -> CompactionPolicy {
+        CompactionPolicy {
+            min_stale_bytes: 1024 * 1024,
+            idle_stale_percent: 25,
+            busy_stale_percent: 60,
+            idle_after: Duration::from_secs(5),
+            max_writes_per_sec: 1000,
+        }
+    }
+}
+
+/// What a [`CompactionScheduler`] decided to do and why, along with the
+/// measurements it was based on, for tuning its policy.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CompactionDecision {
+    /// Whether to compact now.
+    pub compact: bool,
+    /// Why, in a few words.
+    pub reason: String,
+    /// The percentage of the store's bytes that are stale.
+    pub stale_percent: u64,
+    /// How many more stale bytes there are than at the previous decision.
+    pub stale_growth: u64,
+    /// Writes a second since the previous decision.
+    pub writes_per_sec: u64,
+    /// How long since the store was last read or written.
+    pub idle_for: Duration,
+}
+
+/// Decides when to compact a store from how it's being used, instead of at
+/// a fixed size. Register it as the store's observer so it sees reads and
+/// writes, then call [`run`](CompactionScheduler::run) periodically.
+/// Compaction waits for the store to be idle, unless the stale bytes pile
+/// up too far.
+///
+/// ```rust
+/// # use std::sync::Arc;
+/// # use std::time::Duration;
+/// # use tempfile::TempDir;
+/// use kvs::{
+///     CompactionPolicy, CompactionScheduler, KvStore, LogKvs, Persistent,
+///     StoreOptions,
+/// };
+///
+/// # let temp_dir =
+/// #     TempDir::new().expect("unable to create temporary working directory");
+/// let scheduler = Arc::new(CompactionScheduler::new(CompactionPolicy {
+///     min_stale_bytes: 0,
+///     idle_after: Duration::from_secs(0),
+///     ..CompactionPolicy::default()
+/// }));
+/// let options = StoreOptions {
+///     observer: Some(scheduler.clone()),
+///     ..StoreOptions::default()
+/// };
+/// let mut store = LogKvs::open_with(temp_dir.path(), options).unwrap();
+/// store.set("key1".to_owned(), "value1".to_owned()).unwrap();
+/// store.set("key1".to_owned(), "value2".to_owned()).unwrap();
+///
+/// assert!(scheduler.run(&mut store).unwrap().compact);
+/// // one of the two records, next to the log's header
+/// assert_eq!(scheduler.last_decision().unwrap().stale_percent, 35);
+/// ```
+#[derive(Debug)]
+pub struct CompactionScheduler {
+    policy: CompactionPolicy,
+    activity: Mutex<Activity>,
+}
+
+/// What the scheduler has seen since its previous decision.
+#[derive(Debug)]
+struct Activity {
+    last_access: Option<Instant>,
+    writes: u64,
+    since: Instant,
+    stale_bytes: u64,
+    compacting: bool,
+    last_decision: Option<CompactionDecision>,
+}
+
+impl CompactionScheduler {
+    /// Schedule compactions following the given policy.
+    pub fn new(policy: CompactionPolicy) -> CompactionScheduler {
+        CompactionScheduler {
+            policy,
+            activity: Mutex::new(Activity {
+                last_access: None,
+                writes: 0,
+                since: Instant::now(),
+                stale_bytes: 0,
+                compacting: false,
+                last_decision: None,
+            }),
+        }
+    }
+
+    /// Decide whether to compact a store with the given statistics, based
+    /// on the reads and writes seen since the previous decision.
+    pub fn decide(&self, stats: &StoreStats) -> CompactionDecision {
+        let now = Instant::now();
+        let mut activity = self.activity.lock().unwrap();
+        let elapsed = now.duration_since(activity.since);
+        let writes_per_sec = if elapsed.as_millis() == 0 {
+            0
+        } else {
+            (activity.writes as u128 * 1000 / elapsed.as_millis()) as u64
+        };
+        let idle_for = match activity.last_access {
+            Some(last_access) => now.duration_since(last_access),
+            None => elapsed,
+        };
+        let stale_percent = (stats.stale_bytes * 100)
+            .checked_div(stats.disk_bytes)
+            .unwrap_or(0);
+        let policy = &self.policy;
+        let idle = idle_for >= policy.idle_after;
+
+        let (compact, reason) = if activity.compacting {
+            (false, "already compacting".to_owned())
+        } else if stats.stale_bytes == 0
+            || stats.stale_bytes < policy.min_stale_bytes
+        {
+            (false, format!("only {} stale bytes", stats.stale_bytes))
+        } else if stale_percent >= policy.busy_stale_percent {
+            (true, format!("{}% stale", stale_percent))
+        } else if stale_percent < policy.idle_stale_percent {
+            (false, format!("only {}% stale", stale_percent))
+        } else if idle {
+            (true, format!("idle with {}% stale", stale_percent))
+        } else if writes_per_sec > policy.max_writes_per_sec {
+            (
+                true,
+                format!(
+                    "{} writes a second with {}% stale",
+                    writes_per_sec, stale_percent
+                ),
+            )
+        } else {
+            (false, "waiting for the store to be idle".to_owned())
+        };
+
+        let decision = CompactionDecision {
+            compact,
+            reason,
+            stale_percent,
+            stale_growth: stats
+                .stale_bytes
+                .saturating_sub(activity.stale_bytes),
+            writes_per_sec,
+            idle_for,
+        };
+        activity.writes = 0;
+        activity.since = now;
+        activity.stale_bytes = stats.stale_bytes;
+        activity.last_decision = Some(decision.clone());
+        decision
+    }
+
+    /// Gather the store's statistics, then compact it if
+    /// [`decide`](CompactionScheduler::decide) says to. Returns the
+    /// decision made.
+    pub fn run<S>(&self, store: &mut S) -> Result<CompactionDecision>
+    where
+        S: Compactable + Measurable,
+    {
+        let decision = self.decide(&store.stats()?);
+        if decision.compact {
+            store.compact()?;
+            // compaction reclaims the stale bytes counted before it
+            self.activity.lock().unwrap().stale_bytes = 0;
+        }
+        Ok(decision)
+    }
+
+    /// The most recent decision, if one has been made.
+    pub fn last_decision(&self) -> Option<CompactionDecision> {
+        self.activity.lock().unwrap().last_decision.clone()
+    }
+
+    fn touch(&self, write: bool) {
+        let mut activity = self.activity.lock().unwrap();
+        activity.last_access = Some(Instant::now());
+        if write {
+            activity.writes += 1;
+        }
+    }
+}
+
+impl StoreObserver for CompactionScheduler {
+    fn on_get(&self, _key: &str) {
+        self.touch(false);
+    }
+
+    fn on_set(&self, _key: &str, _value_len: u64) {
+        self.touch(true);
+    }
+
+    fn on_remove(&self, _key: &str) {
+        self.touch(true);
+    }
+
+    fn on_compaction_start(&self) {
+        self.activity.lock().unwrap().compacting = true;
+    }
+
+    fn on_compaction_end(&self) {
+        self.activity.lock().unwrap().compacting = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats(stale_bytes: u64, disk_bytes: u64) -> StoreStats {
+        StoreStats {
+            stale_bytes,
+            disk_bytes,
+            ..StoreStats::default()
+        }
+    }
+
+    #[test]
+    fn decide() {
+        let scheduler = CompactionScheduler::new(CompactionPolicy {
+            min_stale_bytes: 10,
+            idle_after: Duration::from_secs(60),
+            ..CompactionPolicy::default()
+        });
+
+        assert!(!scheduler.decide(&stats(5, 10)).compact);
+        // busy, but not enough is stale to compact anyway
+        scheduler.on_set("key1", 1);
+        let decision = scheduler.decide(&stats(30, 100));
+        assert_eq!(decision.reason, "waiting for the store to be idle");
+        assert_eq!(decision.stale_growth, 25);
+        let decision = scheduler.decide(&stats(60, 100));
+        assert!(decision.compact);
+        assert_eq!(decision.reason, "60% stale");
+        assert_eq!(scheduler.last_decision(), Some(decision));
+
+        scheduler.on_compaction_start();
+        assert!(!scheduler.decide(&stats(90, 100)).compact);
+        scheduler.on_compaction_end();
+
+        let idle = CompactionScheduler::new(CompactionPolicy {
+            min_stale_bytes: 0,
+            idle_after: Duration::from_secs(0),
+            ..CompactionPolicy::default()
+        });
+        idle.on_get("key1");
+        assert_eq!(idle.decide(&stats(30, 100)).reason, "idle with 30% stale");
+        assert!(!idle.decide(&stats(10, 100)).compact);
+    }
+}
@@ -0,0 +1,186 @@
+use std::fmt::Debug;
+
+use core::{Collation, Error, KvStore, RangeEstimate, Result, Scannable};
+
+/// How a [`StampedKvs`] stamps its writes, so the stores it wraps can be
+/// synced. See [`LwwKvs`](crate::LwwKvs) and `VersionedKvs`.
+pub trait Stamper {
+    /// What each record is stamped with.
+    type Stamp: Clone + Debug + Eq;
+
+    /// What the records are called in errors, such as "a last-writer-wins
+    /// record".
+    const RECORD_NAME: &'static str;
+
+    /// The stamp as it's stored, ahead of the value. It mustn't contain
+    /// `=` or `-`, unless it has a fixed length.
+    fn encode(stamp: &Self::Stamp) -> String;
+
+    /// Split the stamp off the front of a stored record, returning it and
+    /// the rest of the record.
+    fn decode(encoded: &str) -> Option<(Self::Stamp, &str)>;
+
+    /// The stamp for a write made to this copy of the store. `current`
+    /// reads the stamp the key has now, for stampers that need it.
+    fn next(
+        &mut self,
+        current: &dyn Fn() -> Result<Option<Self::Stamp>>,
+    ) -> Result<Self::Stamp>;
+}
+
+/// A value, or the removal of one, along with its stamp.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct StampedRecord<T> {
+    /// The stamp the record was written with.
+    pub stamp: T,
+    /// The value, or None if the key was removed.
+    pub value: Option<String>,
+}
+
+/// Keeps a stamp with each key, so copies of a store changed independently
+/// can be synced.
+///
+/// Wraps any store, but everything in it must have been written through
+/// the same kind of `StampedKvs`, since values are stored with their stamp.
+/// Removals are kept as tombstones so they can be synced too, and are never
+/// cleaned up. Each copy of the store needs its own node id.
+#[derive(Debug)]
+pub struct StampedKvs<S, K> {
+    pub(crate) store: S,
+    pub(crate) stamper: K,
+}
+
+impl<S, K: Stamper> StampedKvs<S, K> {
+    /// The wrapped store.
+    pub fn into_inner(self) -> S {
+        self.store
+    }
+
+    /// The stamp, then `=` and the value, or `-` for a removal.
+    fn encode(record: &StampedRecord<K::Stamp>) -> String {
+        let stamp = K::encode(&record.stamp);
+        match &record.value {
+            Some(value) => format!("{}={}", stamp, value),
+            None => format!("{}-", stamp),
+        }
+    }
+
+    fn decode(key: &str, encoded: &str) -> Result<StampedRecord<K::Stamp>> {
+        let invalid = || {
+            Error::serialization(format!(
+                "the value of {} isn't {}",
+                key,
+                K::RECORD_NAME
+            ))
+        };
+        let (stamp, rest) = K::decode(encoded).ok_or_else(invalid)?;
+        let value = match rest.chars().next() {
+            Some('=') => Some(rest[1..].to_owned()),
+            Some('-') if rest.len() == 1 => None,
+            _ => return Err(invalid()),
+        };
+        Ok(StampedRecord { stamp, value })
+    }
+}
+
+impl<S: KvStore, K: Stamper> StampedKvs<S, K> {
+    /// The record for a key, including the tombstone of a removed one.
+    pub fn record(&self, key: &str) -> Result<Option<StampedRecord<K::Stamp>>> {
+        Self::read(&self.store, key)
+    }
+
+    fn read(store: &S, key: &str) -> Result<Option<StampedRecord<K::Stamp>>> {
+        match store.get_ref(key)? {
+            Some(encoded) => Ok(Some(Self::decode(key, &encoded)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Write a record as is, such as one copied from another store.
+    pub(crate) fn put(
+        &mut self,
+        key: String,
+        record: &StampedRecord<K::Stamp>,
+    ) -> Result<()> {
+        self.store.set(key, Self::encode(record))
+    }
+
+    fn write(&mut self, key: String, value: Option<String>) -> Result<()> {
+        let store = &self.store;
+        let current =
+            || Ok(Self::read(store, &key)?.map(|record| record.stamp));
+        let stamp = self.stamper.next(&current)?;
+        self.put(key, &StampedRecord { stamp, value })
+    }
+}
+
+impl<S: Scannable, K: Stamper> StampedKvs<S, K> {
+    /// Every key in either store, including tombstones, in order, for
+    /// `sync_with` to go through.
+    pub(crate) fn keys_to_sync<T: Scannable, J>(
+        &self,
+        other: &StampedKvs<T, J>,
+    ) -> Result<Vec<String>> {
+        let mut keys = self.store.keys()?;
+        keys.extend(other.store.keys()?);
+        keys.sort();
+        keys.dedup();
+        Ok(keys)
+    }
+}
+
+impl<S: KvStore, K: Stamper> KvStore for StampedKvs<S, K> {
+    fn set(&mut self, key: String, value: String) -> Result<()> {
+        self.write(key, Some(value))
+    }
+
+    fn get(&self, key: String) -> Result<Option<String>> {
+        Ok(self.record(&key)?.and_then(|record| record.value))
+    }
+
+    /// Leaves a tombstone, so the removal can be synced.
+    fn remove(&mut self, key: String) -> Result<Option<String>> {
+        let old = self.get(key.clone())?;
+        if old.is_some() {
+            self.write(key, None)?;
+        }
+        Ok(old)
+    }
+}
+
+impl<S: Scannable, K: Stamper> Scannable for StampedKvs<S, K> {
+    /// Every key with a value, leaving out tombstones, so each record is
+    /// read.
+    fn keys(&self) -> Result<Vec<String>> {
+        let mut keys = Vec::new();
+        for key in self.store.keys()? {
+            if self.get_ref(&key)?.is_some() {
+                keys.push(key);
+            }
+        }
+        Ok(keys)
+    }
+
+    fn scan(&self, start: &str, end: Option<&str>) -> Result<Vec<String>> {
+        let mut keys = Vec::new();
+        for key in self.store.scan(start, end)? {
+            if self.get_ref(&key)?.is_some() {
+                keys.push(key);
+            }
+        }
+        Ok(keys)
+    }
+
+    /// The wrapped store's estimate, which counts tombstones too.
+    fn estimate_range_size(
+        &self,
+        start: &str,
+        end: Option<&str>,
+    ) -> Result<RangeEstimate> {
+        self.store.estimate_range_size(start, end)
+    }
+
+    fn collation(&self) -> Collation {
+        self.store.collation()
+    }
+}
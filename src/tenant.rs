@@ -0,0 +1,425 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use core::{
+    in_range, Collation, Error, KvStore, RangeEstimate, Result, ScanOptions,
+    Scannable,
+};
+
+/// How much a tenant of a [`TenantKvs`] can store. A write that would take
+/// it over either limit is refused with a `Rejected` error, while writes
+/// that shrink what it stores are always allowed, even if it's over.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct TenantQuota {
+    /// The most keys it can have.
+    pub max_keys: Option<u64>,
+    /// The most bytes its keys and values can take up altogether.
+    pub max_bytes: Option<u64>,
+}
+
+/// What a tenant of a [`TenantKvs`] stores and has done, see
+/// [`TenantKvs::stats`].
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct TenantStats {
+    /// How many keys it has.
+    pub keys: u64,
+    /// How many bytes its keys and values take up, not counting the prefix
+    /// its keys are stored under.
+    pub bytes: u64,
+    /// How many times it's read a key, including ones without a value.
+    pub reads: u64,
+    /// How many times it's set or removed a key.
+    pub writes: u64,
+    /// How many of its writes were refused for going over its quota.
+    pub rejected: u64,
+}
+
+#[derive(Debug)]
+struct Tenant {
+    quota: TenantQuota,
+    keys: u64,
+    bytes: u64,
+    reads: AtomicU64,
+    writes: u64,
+    rejected: u64,
+}
+
+/// Hosts many tenants in one store, each seeing only its own keys, with a
+/// quota on how much it can store and statistics on what it's done.
+///
+/// Each tenant's keys are stored under `<tenant>/`, and tenant names can't
+/// contain `/`, so no tenant's keys can reach into another's. Tenants are
+/// used through the [`TenantStore`] returned by
+/// [`tenant`](TenantKvs::tenant), which strips the prefix from the keys it
+/// returns. What each tenant stores is counted when it's added, by reading
+/// its keys, and kept up to date as it writes, so the store shouldn't be
+/// written to other than through its tenants in the meantime. The store has
+/// to be in bytewise collation, since in others a tenant's range of keys
+/// can take in another's, like `A/` in `a/`'s when case-insensitive.
+///
+/// ```rust
+/// # use tempfile::TempDir;
+/// use kvs::{KvStore, LogKvs, Persistent, TenantKvs, TenantQuota};
+///
+/// # let temp_dir =
+/// #     TempDir::new().expect("unable to create temporary working directory");
+/// let mut store = TenantKvs::new(LogKvs::open(temp_dir.path()).unwrap());
+/// let quota = TenantQuota {
+///     max_keys: Some(1),
+///     max_bytes: None,
+/// };
+/// store.add_tenant("acme", quota).unwrap();
+/// store.add_tenant("globex", TenantQuota::default()).unwrap();
+///
+/// let mut acme = store.tenant("acme").unwrap();
+/// acme.set("key1".to_owned(), "value1".to_owned()).unwrap();
+/// assert!(acme.set("key2".to_owned(), "value2".to_owned()).is_err());
+///
+/// let globex = store.tenant("globex").unwrap();
+/// assert_eq!(globex.get("key1".to_owned()).unwrap(), None);
+/// assert_eq!(store.stats("acme").unwrap().keys, 1);
+/// ```
+#[derive(Debug)]
+pub struct TenantKvs<S> {
+    store: S,
+    tenants: HashMap<String, Tenant>,
+}
+
+/// What separates a tenant's name from its keys.
+const SEPARATOR: char = '/';
+
+impl<S: Scannable> TenantKvs<S> {
+    /// Host tenants in the store, starting with none.
+    pub fn new(store: S) -> TenantKvs<S> {
+        TenantKvs {
+            store,
+            tenants: HashMap::new(),
+        }
+    }
+
+    /// Add a tenant, or change the quota of one already added. Counts what
+    /// it already stores, which means reading all of its values.
+    pub fn add_tenant(&mut self, name: &str, quota: TenantQuota) -> Result<()> {
+        if self.store.collation() != Collation::Bytewise {
+            return Err(Error::config(
+                "tenants can only be hosted in a store in bytewise collation"
+                    .to_owned(),
+            ));
+        }
+        if name.is_empty() || name.contains(SEPARATOR) {
+            return Err(Error::config(format!(
+                "tenant names can't be empty or contain '{}', like '{}'",
+                SEPARATOR, name
+            )));
+        }
+        if let Some(tenant) = self.tenants.get_mut(name) {
+            tenant.quota = quota;
+            return Ok(());
+        }
+
+        let (start, end) = bounds(name);
+        let mut keys = 0;
+        let mut bytes = 0;
+        for (key, value) in
+            self.store.scan_filtered(&start, Some(&end), &|_, _| true)?
+        {
+            keys += 1;
+            bytes += (key.len() - start.len() + value.len()) as u64;
+        }
+        self.tenants.insert(
+            name.to_owned(),
+            Tenant {
+                quota,
+                keys,
+                bytes,
+                reads: AtomicU64::new(0),
+                writes: 0,
+                rejected: 0,
+            },
+        );
+        Ok(())
+    }
+
+    /// The store as the tenant sees it.
+    pub fn tenant(&mut self, name: &str) -> Result<TenantStore<'_, S>> {
+        if !self.tenants.contains_key(name) {
+            return Err(Error::config(format!("no tenant named '{}'", name)));
+        }
+        let (prefix, end) = bounds(name);
+        Ok(TenantStore {
+            kvs: self,
+            name: name.to_owned(),
+            prefix,
+            end,
+        })
+    }
+
+    /// What the tenant stores and has done, or None if there's no tenant by
+    /// that name.
+    pub fn stats(&self, name: &str) -> Option<TenantStats> {
+        self.tenants.get(name).map(|tenant| TenantStats {
+            keys: tenant.keys,
+            bytes: tenant.bytes,
+            reads: tenant.reads.load(Ordering::Relaxed),
+            writes: tenant.writes,
+            rejected: tenant.rejected,
+        })
+    }
+
+    /// The wrapped store.
+    pub fn into_inner(self) -> S {
+        self.store
+    }
+}
+
+/// The prefix of the tenant's keys, and the first key after them.
+fn bounds(name: &str) -> (String, String) {
+    let end = char::from(SEPARATOR as u8 + 1);
+    (format!("{}{}", name, SEPARATOR), format!("{}{}", name, end))
+}
+
+/// One tenant's view of a [`TenantKvs`], holding only its keys.
+#[derive(Debug)]
+pub struct TenantStore<'a, S> {
+    kvs: &'a mut TenantKvs<S>,
+    name: String,
+    prefix: String,
+    end: String,
+}
+
+impl<'a, S: Scannable> TenantStore<'a, S> {
+    fn tenant(&self) -> &Tenant {
+        &self.kvs.tenants[&self.name]
+    }
+
+    fn tenant_mut(&mut self) -> &mut Tenant {
+        self.kvs.tenants.get_mut(&self.name).unwrap()
+    }
+
+    fn full_key(&self, key: &str) -> String {
+        format!("{}{}", self.prefix, key)
+    }
+
+    /// Where a range of the tenant's keys is in the store.
+    fn range(&self, start: &str, end: Option<&str>) -> (String, String) {
+        (
+            self.full_key(start),
+            end.map_or_else(|| self.end.clone(), |end| self.full_key(end)),
+        )
+    }
+}
+
+impl<'a, S: Scannable> KvStore for TenantStore<'a, S> {
+    fn set(&mut self, key: String, value: String) -> Result<()> {
+        let full_key = self.full_key(&key);
+        let old = self.kvs.store.get_ref(&full_key)?.map(|old| old.len());
+        let tenant = self.tenant();
+        let keys = tenant.keys + if old.is_some() { 0 } else { 1 };
+        let bytes = tenant
+            .bytes
+            .saturating_sub(old.map_or(0, |old| (key.len() + old) as u64))
+            + (key.len() + value.len()) as u64;
+        let over = |limit: Option<u64>, now: u64, after: u64| match limit {
+            Some(limit) => after > limit && after > now,
+            None => false,
+        };
+        let quota = tenant.quota;
+        if over(quota.max_keys, tenant.keys, keys)
+            || over(quota.max_bytes, tenant.bytes, bytes)
+        {
+            self.tenant_mut().rejected += 1;
+            return Err(Error::rejected(format!(
+                "setting '{}' would take tenant '{}' over its quota",
+                key, self.name
+            )));
+        }
+
+        self.kvs.store.set(full_key, value)?;
+        let tenant = self.tenant_mut();
+        tenant.keys = keys;
+        tenant.bytes = bytes;
+        tenant.writes += 1;
+        Ok(())
+    }
+
+    fn get(&self, key: String) -> Result<Option<String>> {
+        self.tenant().reads.fetch_add(1, Ordering::Relaxed);
+        self.kvs.store.get(self.full_key(&key))
+    }
+
+    fn remove(&mut self, key: String) -> Result<Option<String>> {
+        let old = self.kvs.store.remove(self.full_key(&key))?;
+        let tenant = self.tenant_mut();
+        tenant.writes += 1;
+        if let Some(old) = &old {
+            tenant.keys -= 1;
+            tenant.bytes =
+                tenant.bytes.saturating_sub((key.len() + old.len()) as u64);
+        }
+        Ok(old)
+    }
+}
+
+impl<'a, S: Scannable> Scannable for TenantStore<'a, S> {
+    fn keys(&self) -> Result<Vec<String>> {
+        Ok(self
+            .kvs
+            .store
+            .keys()?
+            .into_iter()
+            .filter(|key| in_range(key, &self.prefix, Some(&self.end)))
+            .map(|key| key[self.prefix.len()..].to_owned())
+            .collect())
+    }
+
+    fn scan(&self, start: &str, end: Option<&str>) -> Result<Vec<String>> {
+        let (start, end) = self.range(start, end);
+        Ok(self
+            .kvs
+            .store
+            .scan(&start, Some(&end))?
+            .into_iter()
+            .map(|key| key[self.prefix.len()..].to_owned())
+            .collect())
+    }
+
+    fn scan_with(
+        &self,
+        start: &str,
+        end: Option<&str>,
+        options: ScanOptions,
+    ) -> Result<Vec<String>> {
+        let (start, end) = self.range(start, end);
+        Ok(self
+            .kvs
+            .store
+            .scan_with(&start, Some(&end), options)?
+            .into_iter()
+            .map(|key| key[self.prefix.len()..].to_owned())
+            .collect())
+    }
+
+    fn estimate_range_size(
+        &self,
+        start: &str,
+        end: Option<&str>,
+    ) -> Result<RangeEstimate> {
+        let (start, end) = self.range(start, end);
+        self.kvs.store.estimate_range_size(&start, Some(&end))
+    }
+
+    fn scan_filtered(
+        &self,
+        start: &str,
+        end: Option<&str>,
+        predicate: &dyn Fn(&str, &str) -> bool,
+    ) -> Result<Vec<(String, String)>> {
+        let (start, end) = self.range(start, end);
+        let prefix_len = self.prefix.len();
+        Ok(self
+            .kvs
+            .store
+            .scan_filtered(&start, Some(&end), &|key, value| {
+                predicate(&key[prefix_len..], value)
+            })?
+            .into_iter()
+            .map(|(key, value)| (key[prefix_len..].to_owned(), value))
+            .collect())
+    }
+
+    fn collation(&self) -> Collation {
+        self.kvs.store.collation()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tempfile::TempDir;
+
+    use core::ErrorKind;
+
+    use crate::{Engine, HashMapKvs, Kvs, Persistent};
+
+    #[test]
+    fn tenants() -> Result<()> {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+        let path = temp_dir.path().join("kvs");
+        let mut store = TenantKvs::new(HashMapKvs::open(&path)?);
+        let quota = TenantQuota {
+            max_keys: None,
+            max_bytes: Some(20),
+        };
+        store.add_tenant("a", quota)?;
+        store.add_tenant("ab", TenantQuota::default())?;
+        assert!(store.add_tenant("a/b", quota).is_err());
+        assert!(store.tenant("c").is_err());
+
+        let mut a = store.tenant("a")?;
+        a.set("key1".to_owned(), "value1".to_owned())?;
+        a.set("key1".to_owned(), "value1b".to_owned())?;
+        let err = a.set("key2".to_owned(), "value2".to_owned()).unwrap_err();
+        match err.kind() {
+            ErrorKind::Rejected { .. } => {}
+            kind => panic!("unexpected error {:?}", kind),
+        }
+        // shrinking is fine
+        a.set("key1".to_owned(), "v".to_owned())?;
+        a.set("key2".to_owned(), "value2".to_owned())?;
+        assert_eq!(a.get("key1".to_owned())?, Some("v".to_owned()));
+        assert_eq!(a.keys()?.len(), 2);
+
+        // tenants whose names share a prefix don't see each other
+        let mut ab = store.tenant("ab")?;
+        assert!(ab.keys()?.is_empty());
+        ab.set("key1".to_owned(), "other".to_owned())?;
+        let pairs = ab.scan_filtered("", None, &|_, _| true)?;
+        assert_eq!(pairs, vec![("key1".to_owned(), "other".to_owned())]);
+        assert_eq!(
+            store.stats("a"),
+            Some(TenantStats {
+                keys: 2,
+                bytes: 15,
+                reads: 1,
+                writes: 4,
+                rejected: 1,
+            })
+        );
+
+        let mut a = store.tenant("a")?;
+        assert_eq!(a.remove("key2".to_owned())?, Some("value2".to_owned()));
+        drop(store);
+
+        // what's stored is counted again when reopened
+        let mut store = TenantKvs::new(HashMapKvs::open(&path)?);
+        store.add_tenant("a", quota)?;
+        assert_eq!(store.stats("a").unwrap().keys, 1);
+        assert_eq!(store.stats("a").unwrap().bytes, 5);
+
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(feature = "log")]
+    fn bytewise_only() -> Result<()> {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+        let store = Kvs::builder()
+            .engine(Engine::Log)
+            .path(temp_dir.path().join("kvs"))
+            .collation(Collation::CaseInsensitive)
+            .open_any()?;
+        let mut store = TenantKvs::new(store);
+        let err = store.add_tenant("a", TenantQuota::default()).unwrap_err();
+        match err.kind() {
+            ErrorKind::Config { message } => {
+                assert!(message.contains("bytewise collation"), "{}", message)
+            }
+            kind => panic!("unexpected error {:?}", kind),
+        }
+
+        Ok(())
+    }
+}
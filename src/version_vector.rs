@@ -0,0 +1,380 @@
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+
+use core::{KvStore, Result, Scannable};
+
+use crate::{StampedKvs, StampedRecord, Stamper};
+
+/// How many writes each node has made to a key, so two versions of the key
+/// can be told apart as one following on from the other, or as made
+/// concurrently without either seeing the other.
+///
+/// Versions are partially ordered: one is less than another if every node's
+/// count in it is at most the other's, and they're incomparable if each has
+/// a write the other hasn't seen.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct VersionVector {
+    /// Each node's count, leaving out the ones that haven't written.
+    counts: BTreeMap<u32, u64>,
+}
+
+impl VersionVector {
+    /// A version no node has written to.
+    pub fn new() -> VersionVector {
+        VersionVector::default()
+    }
+
+    /// How many writes the node has made.
+    pub fn get(&self, node: u32) -> u64 {
+        self.counts.get(&node).copied().unwrap_or(0)
+    }
+
+    /// Count a write by the node.
+    pub fn increment(&mut self, node: u32) {
+        *self.counts.entry(node).or_insert(0) += 1;
+    }
+
+    /// Take the highest count for each node from the other version too, so
+    /// it follows on from both.
+    pub fn merge(&mut self, other: &VersionVector) {
+        for (&node, &count) in &other.counts {
+            let ours = self.counts.entry(node).or_insert(0);
+            *ours = (*ours).max(count);
+        }
+    }
+
+    /// Whether each version has writes the other hasn't seen.
+    pub fn is_concurrent_with(&self, other: &VersionVector) -> bool {
+        self.partial_cmp(other).is_none()
+    }
+
+    /// `<node>:<count>` for each node, separated by commas.
+    fn encode(&self) -> String {
+        let mut encoded = String::new();
+        for (node, count) in &self.counts {
+            if !encoded.is_empty() {
+                encoded.push(',');
+            }
+            let _ = write!(encoded, "{}:{}", node, count);
+        }
+        encoded
+    }
+
+    fn decode(encoded: &str) -> Option<VersionVector> {
+        let mut counts = BTreeMap::new();
+        for pair in encoded.split(',').filter(|pair| !pair.is_empty()) {
+            let mut parts = pair.splitn(2, ':');
+            let node = parts.next()?.parse().ok()?;
+            let count = parts.next()?.parse().ok()?;
+            counts.insert(node, count);
+        }
+        Some(VersionVector { counts })
+    }
+}
+
+impl PartialOrd for VersionVector {
+    fn partial_cmp(&self, other: &VersionVector) -> Option<Ordering> {
+        let nodes = self.counts.keys().chain(other.counts.keys());
+        let (mut less, mut greater) = (false, false);
+        for &node in nodes {
+            match self.get(node).cmp(&other.get(node)) {
+                Ordering::Less => less = true,
+                Ordering::Greater => greater = true,
+                Ordering::Equal => {}
+            }
+        }
+        match (less, greater) {
+            (false, false) => Some(Ordering::Equal),
+            (true, false) => Some(Ordering::Less),
+            (false, true) => Some(Ordering::Greater),
+            (true, true) => None,
+        }
+    }
+}
+
+/// A value, or the removal of one, along with its version.
+pub type VersionedRecord = StampedRecord<VersionVector>;
+
+/// Stamps the writes to a [`VersionedKvs`] with a version vector.
+#[derive(Debug)]
+pub struct VersionStamper {
+    node: u32,
+}
+
+impl Stamper for VersionStamper {
+    type Stamp = VersionVector;
+
+    const RECORD_NAME: &'static str = "a versioned record";
+
+    fn encode(stamp: &VersionVector) -> String {
+        stamp.encode()
+    }
+
+    fn decode(encoded: &str) -> Option<(VersionVector, &str)> {
+        // versions are only digits, `:` and `,`
+        let split = encoded.find(&['=', '-'][..])?;
+        let (version, rest) = encoded.split_at(split);
+        Some((VersionVector::decode(version)?, rest))
+    }
+
+    /// The key's current version with one more write by this node, so it
+    /// follows on from what was there.
+    fn next(
+        &mut self,
+        current: &dyn Fn() -> Result<Option<VersionVector>>,
+    ) -> Result<VersionVector> {
+        let mut version = current()?.unwrap_or_default();
+        version.increment(self.node);
+        Ok(version)
+    }
+}
+
+/// Two versions of a key written concurrently in the stores being synced,
+/// passed to the resolver given to [`VersionedKvs::sync_with`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Conflict {
+    /// The key written.
+    pub key: String,
+    /// Its value in the store being synced from, or None if it was removed.
+    pub ours: Option<String>,
+    /// Its value in the other store, or None if it was removed.
+    pub theirs: Option<String>,
+}
+
+/// What to do about a [`Conflict`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Resolution {
+    /// Give the key this value in both stores.
+    Set(String),
+    /// Remove the key from both stores.
+    Remove,
+    /// Leave both stores as they are, so the conflict comes up again on
+    /// the next sync.
+    Defer,
+}
+
+/// What [`VersionedKvs::sync_with`] did.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct VersionSyncReport {
+    /// Records copied from this store to the other one.
+    pub sent: u64,
+    /// Records copied from the other store to this one.
+    pub received: u64,
+    /// Conflicts the resolver settled, which were written to both stores.
+    pub resolved: u64,
+    /// Keys with conflicts the resolver deferred, sorted.
+    pub deferred: Vec<String>,
+}
+
+/// Keeps a version vector with each key, so copies of a store changed
+/// independently can be synced in both directions with
+/// [`sync_with`](VersionedKvs::sync_with), and writes made to the same key
+/// on both sides without either seeing the other are caught as conflicts
+/// for a resolver to settle, instead of one silently winning as with an
+/// [`LwwKvs`](crate::LwwKvs). See [`StampedKvs`] for how the records are
+/// kept.
+///
+/// ```rust
+/// use kvs::{KvStore, MemKvs, Resolution, VersionedKvs};
+///
+/// let mut laptop = VersionedKvs::new(MemKvs::new(), 1);
+/// let mut phone = VersionedKvs::new(MemKvs::new(), 2);
+/// laptop.set("key1".to_owned(), "value1".to_owned()).unwrap();
+/// laptop
+///     .sync_with(&mut phone, &|_| Resolution::Defer)
+///     .unwrap();
+///
+/// // both change the key before syncing again
+/// laptop.set("key1".to_owned(), "laptop".to_owned()).unwrap();
+/// phone.set("key1".to_owned(), "phone".to_owned()).unwrap();
+/// let report = laptop
+///     .sync_with(&mut phone, &|conflict| {
+///         let mut values: Vec<String> = conflict
+///             .ours
+///             .iter()
+///             .chain(&conflict.theirs)
+///             .cloned()
+///             .collect();
+///         values.sort();
+///         Resolution::Set(values.join("+"))
+///     })
+///     .unwrap();
+/// assert_eq!(report.resolved, 1);
+/// assert_eq!(
+///     phone.get("key1".to_owned()).unwrap(),
+///     Some("laptop+phone".to_owned())
+/// );
+/// ```
+pub type VersionedKvs<S> = StampedKvs<S, VersionStamper>;
+
+impl<S: KvStore> VersionedKvs<S> {
+    /// Keep versioned records in the store, counting writes as the given
+    /// node.
+    pub fn new(store: S, node: u32) -> VersionedKvs<S> {
+        StampedKvs {
+            store,
+            stamper: VersionStamper { node },
+        }
+    }
+}
+
+impl<S: Scannable> VersionedKvs<S> {
+    /// Sync the two stores in both directions. For each key, a record that
+    /// follows on from the other store's is copied over it. Records written
+    /// concurrently are a conflict, which `resolve` is asked to settle
+    /// unless both sides ended up with the same value. A settled conflict
+    /// is written to both stores with a version following on from both, so
+    /// it isn't raised again.
+    pub fn sync_with<T: Scannable>(
+        &mut self,
+        other: &mut VersionedKvs<T>,
+        resolve: &dyn Fn(&Conflict) -> Resolution,
+    ) -> Result<VersionSyncReport> {
+        let mut report = VersionSyncReport::default();
+        for key in self.keys_to_sync(other)? {
+            let (ours, theirs) = match (self.record(&key)?, other.record(&key)?)
+            {
+                (Some(ours), None) => {
+                    other.put(key, &ours)?;
+                    report.sent += 1;
+                    continue;
+                }
+                (None, Some(theirs)) => {
+                    self.put(key, &theirs)?;
+                    report.received += 1;
+                    continue;
+                }
+                (Some(ours), Some(theirs)) => (ours, theirs),
+                (None, None) => continue,
+            };
+            let value = match ours.stamp.partial_cmp(&theirs.stamp) {
+                Some(Ordering::Greater) => {
+                    other.put(key, &ours)?;
+                    report.sent += 1;
+                    continue;
+                }
+                Some(Ordering::Less) => {
+                    self.put(key, &theirs)?;
+                    report.received += 1;
+                    continue;
+                }
+                Some(Ordering::Equal) => continue,
+                None if ours.value == theirs.value => ours.value,
+                None => {
+                    let conflict = Conflict {
+                        key: key.clone(),
+                        ours: ours.value,
+                        theirs: theirs.value,
+                    };
+                    match resolve(&conflict) {
+                        Resolution::Set(value) => Some(value),
+                        Resolution::Remove => None,
+                        Resolution::Defer => {
+                            report.deferred.push(key);
+                            continue;
+                        }
+                    }
+                }
+            };
+
+            let mut stamp = ours.stamp;
+            stamp.merge(&theirs.stamp);
+            stamp.increment(self.stamper.node);
+            let settled = VersionedRecord { stamp, value };
+            self.put(key.clone(), &settled)?;
+            other.put(key, &settled)?;
+            report.resolved += 1;
+        }
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::cell::RefCell;
+
+    use crate::MemKvs;
+
+    #[test]
+    fn version_order() {
+        let mut a = VersionVector::new();
+        a.increment(1);
+        let mut b = a.clone();
+        b.increment(2);
+        assert!(a < b);
+        assert_eq!(a.partial_cmp(&a), Some(Ordering::Equal));
+
+        a.increment(1);
+        assert!(a.is_concurrent_with(&b));
+        a.merge(&b);
+        assert!(a > b);
+        assert_eq!((a.get(1), a.get(2), a.get(3)), (2, 1, 0));
+        assert_eq!(VersionVector::decode(&a.encode()), Some(a));
+    }
+
+    #[test]
+    fn conflicting_sync() -> Result<()> {
+        let mut a = VersionedKvs::new(MemKvs::new(), 1);
+        let mut b = VersionedKvs::new(MemKvs::new(), 2);
+        for key in &["key1", "key2", "key3", "key4"] {
+            a.set(key.to_string(), "value".to_owned())?;
+        }
+        let never = |_: &Conflict| -> Resolution { panic!("no conflicts yet") };
+        assert_eq!(a.sync_with(&mut b, &never)?.sent, 4);
+
+        // one side changes key1, both change key2, key3 and key4
+        a.set("key1".to_owned(), "a".to_owned())?;
+        a.set("key2".to_owned(), "a".to_owned())?;
+        b.remove("key2".to_owned())?;
+        a.set("key3".to_owned(), "same".to_owned())?;
+        b.set("key3".to_owned(), "same".to_owned())?;
+        a.set("key4".to_owned(), "a".to_owned())?;
+        b.set("key4".to_owned(), "b".to_owned())?;
+
+        let seen = RefCell::new(Vec::new());
+        let report = a.sync_with(&mut b, &|conflict| {
+            seen.borrow_mut().push(conflict.clone());
+            match &conflict.key[..] {
+                "key2" => Resolution::Remove,
+                _ => Resolution::Defer,
+            }
+        })?;
+        assert_eq!(report.sent, 1);
+        assert_eq!(report.resolved, 2);
+        assert_eq!(report.deferred, vec!["key4"]);
+        assert_eq!(
+            seen.into_inner(),
+            vec![
+                Conflict {
+                    key: "key2".to_owned(),
+                    ours: Some("a".to_owned()),
+                    theirs: None,
+                },
+                Conflict {
+                    key: "key4".to_owned(),
+                    ours: Some("a".to_owned()),
+                    theirs: Some("b".to_owned()),
+                },
+            ]
+        );
+        assert_eq!(b.get("key1".to_owned())?, Some("a".to_owned()));
+        assert_eq!(a.get("key2".to_owned())?, None);
+        assert_eq!(b.get("key3".to_owned())?, Some("same".to_owned()));
+        assert_eq!(b.get("key4".to_owned())?, Some("b".to_owned()));
+
+        // settled conflicts don't come up again, and deferred ones do
+        let report = b.sync_with(&mut a, &|conflict| {
+            Resolution::Set(conflict.theirs.clone().unwrap_or_default())
+        })?;
+        assert_eq!(report.resolved, 1);
+        assert_eq!(b.get("key4".to_owned())?, Some("a".to_owned()));
+        let report = a.sync_with(&mut b, &never)?;
+        assert_eq!(report, VersionSyncReport::default());
+        assert_eq!(a.keys()?.len(), 3);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,379 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde_json::{json, Map, Value};
+
+use core::{
+    Collation, Error, KvStore, RangeEstimate, Result, ScanOptions, Scannable,
+};
+
+use crate::FeedKvs;
+
+/// What a view works out over the keys under its prefix.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Aggregate {
+    /// How many keys there are.
+    Count,
+    /// The sum of their values. Values that aren't numbers are left out.
+    Sum,
+}
+
+#[derive(Debug)]
+struct View {
+    prefix: String,
+    aggregate: Aggregate,
+    total: f64,
+    /// What each key adds to the total, so it can be taken off again when
+    /// the key changes.
+    keys: HashMap<String, f64>,
+}
+
+impl View {
+    fn new(prefix: &str, aggregate: Aggregate) -> View {
+        View {
+            prefix: prefix.to_owned(),
+            aggregate,
+            total: 0.0,
+            keys: HashMap::new(),
+        }
+    }
+
+    fn update(&mut self, key: &str, value: Option<&str>) {
+        if !key.starts_with(&self.prefix) {
+            return;
+        }
+        if let Some(old) = self.keys.remove(key) {
+            self.total -= old;
+        }
+        let new = match (self.aggregate, value) {
+            (_, None) => None,
+            (Aggregate::Count, Some(_)) => Some(1.0),
+            (Aggregate::Sum, Some(value)) => value.trim().parse().ok(),
+        };
+        if let Some(new) = new {
+            self.total += new;
+            self.keys.insert(key.to_owned(), new);
+        }
+    }
+}
+
+/// Keeps aggregates over the keys under a prefix up to date as the store
+/// is written, so they can be read without scanning. Views are registered
+/// with [`register`](ViewKvs::register), then updated from the store's
+/// [`ChangeFeed`](crate::ChangeFeed) after each write.
+///
+/// [`save`](ViewKvs::save) writes the views to `<store>.views` along with
+/// the last change applied. Opening carries on from there, applying any
+/// changes made since, or rebuilds the views from the store if the feed has
+/// already dropped some of them.
+///
+/// ```rust
+/// # use tempfile::TempDir;
+/// use kvs::{
+///     Aggregate, ChangeFeed, FeedKvs, KvStore, LogKvs, Persistent, ViewKvs,
+/// };
+///
+/// # let temp_dir =
+/// #     TempDir::new().expect("unable to create temporary working directory");
+/// let path = temp_dir.path().join("kvs");
+/// let feed = ChangeFeed::for_store(&path, 1000).unwrap();
+/// let store = FeedKvs::new(LogKvs::open(&path).unwrap(), feed);
+/// let mut store = ViewKvs::open(&path, store).unwrap();
+/// store.register("orders", "order/", Aggregate::Count).unwrap();
+///
+/// store.set("order/1".to_owned(), "10".to_owned()).unwrap();
+/// store.set("order/2".to_owned(), "15".to_owned()).unwrap();
+/// assert_eq!(store.view("orders"), Some(2.0));
+/// ```
+#[derive(Debug)]
+pub struct ViewKvs<S> {
+    store: FeedKvs<S>,
+    path: PathBuf,
+    applied: u64,
+    views: BTreeMap<String, View>,
+}
+
+impl<S: Scannable> ViewKvs<S> {
+    /// Keep views over the store at the given path, loading any saved
+    /// there.
+    pub fn open<P: AsRef<Path>>(
+        path: P,
+        store: FeedKvs<S>,
+    ) -> Result<ViewKvs<S>> {
+        let mut views = path.as_ref().as_os_str().to_owned();
+        views.push(".views");
+        let mut views = ViewKvs {
+            store,
+            path: PathBuf::from(views),
+            applied: 0,
+            views: BTreeMap::new(),
+        };
+        views.load()?;
+        views.catch_up()?;
+        Ok(views)
+    }
+
+    /// Add a view, or replace the one with the same name, building it from
+    /// the keys already in the store.
+    pub fn register(
+        &mut self,
+        name: &str,
+        prefix: &str,
+        aggregate: Aggregate,
+    ) -> Result<()> {
+        let mut view = View::new(prefix, aggregate);
+        build(&self.store, &mut view)?;
+        self.views.insert(name.to_owned(), view);
+        Ok(())
+    }
+
+    /// Remove a view, returning whether there was one with the name.
+    pub fn unregister(&mut self, name: &str) -> bool {
+        self.views.remove(name).is_some()
+    }
+
+    /// The current value of a view, or None if there's no view with the
+    /// name.
+    pub fn view(&self, name: &str) -> Option<f64> {
+        self.views.get(name).map(|view| view.total)
+    }
+
+    /// Write the views to `<store>.views`.
+    pub fn save(&self) -> Result<()> {
+        let views: Map<String, Value> = self
+            .views
+            .iter()
+            .map(|(name, view)| {
+                let aggregate = match view.aggregate {
+                    Aggregate::Count => "count",
+                    Aggregate::Sum => "sum",
+                };
+                let view = json!({
+                    "prefix": view.prefix,
+                    "aggregate": aggregate,
+                    "keys": view.keys,
+                });
+                (name.clone(), view)
+            })
+            .collect();
+        let saved = json!({ "applied": self.applied, "views": views });
+
+        let mut tmp = self.path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        fs::write(&tmp, saved.to_string())?;
+        fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+
+    /// The wrapped store.
+    pub fn into_inner(self) -> FeedKvs<S> {
+        self.store
+    }
+
+    fn load(&mut self) -> Result<()> {
+        if !self.path.is_file() {
+            return Ok(());
+        }
+        let invalid = || {
+            Error::serialization(format!(
+                "{} doesn't hold saved views",
+                self.path.display()
+            ))
+        };
+        let saved: Value =
+            serde_json::from_str(&fs::read_to_string(&self.path)?)
+                .map_err(Error::serialization)?;
+
+        let applied = saved["applied"].as_u64().ok_or_else(invalid)?;
+        let mut views = BTreeMap::new();
+        for (name, saved) in saved["views"].as_object().ok_or_else(invalid)? {
+            let aggregate = match saved["aggregate"].as_str() {
+                Some("count") => Aggregate::Count,
+                Some("sum") => Aggregate::Sum,
+                _ => return Err(invalid()),
+            };
+            let prefix = saved["prefix"].as_str().ok_or_else(invalid)?;
+            let mut view = View::new(prefix, aggregate);
+            for (key, adds) in saved["keys"].as_object().ok_or_else(invalid)? {
+                let adds = adds.as_f64().ok_or_else(invalid)?;
+                view.total += adds;
+                view.keys.insert(key.clone(), adds);
+            }
+            views.insert(name.clone(), view);
+        }
+        self.applied = applied;
+        self.views = views;
+        Ok(())
+    }
+
+    /// Apply the changes made since the views were last updated, or build
+    /// them again if the feed no longer has all of them.
+    fn catch_up(&mut self) -> Result<()> {
+        let feed = self.store.feed();
+        let last = feed.last_sequence();
+        let changes = if self.applied > last {
+            // the feed was started again since the views were saved
+            None
+        } else {
+            feed.changes_since(self.applied)
+        };
+
+        match changes {
+            Some(changes) => {
+                for change in changes {
+                    // the feed only has a digest of the value, so read the
+                    // current one, which later changes will correct
+                    let value = self.store.get_ref(&change.key)?;
+                    for view in self.views.values_mut() {
+                        view.update(
+                            &change.key,
+                            value.as_ref().map(|value| value.as_ref()),
+                        );
+                    }
+                }
+            }
+            None => {
+                for view in self.views.values_mut() {
+                    *view = View::new(&view.prefix, view.aggregate);
+                    build(&self.store, view)?;
+                }
+            }
+        }
+        self.applied = last;
+        Ok(())
+    }
+}
+
+fn build<S: Scannable + ?Sized>(store: &S, view: &mut View) -> Result<()> {
+    for key in store.keys()? {
+        if key.starts_with(&view.prefix) {
+            let value = store.get_ref(&key)?;
+            view.update(&key, value.as_ref().map(|value| value.as_ref()));
+        }
+    }
+    Ok(())
+}
+
+impl<S: Scannable> KvStore for ViewKvs<S> {
+    fn set(&mut self, key: String, value: String) -> Result<()> {
+        self.store.set(key, value)?;
+        self.catch_up()
+    }
+
+    fn get(&self, key: String) -> Result<Option<String>> {
+        self.store.get(key)
+    }
+
+    fn remove(&mut self, key: String) -> Result<Option<String>> {
+        let old = self.store.remove(key)?;
+        self.catch_up()?;
+        Ok(old)
+    }
+}
+
+impl<S: Scannable> Scannable for ViewKvs<S> {
+    fn keys(&self) -> Result<Vec<String>> {
+        self.store.keys()
+    }
+
+    fn scan(&self, start: &str, end: Option<&str>) -> Result<Vec<String>> {
+        self.store.scan(start, end)
+    }
+
+    fn scan_with(
+        &self,
+        start: &str,
+        end: Option<&str>,
+        options: ScanOptions,
+    ) -> Result<Vec<String>> {
+        self.store.scan_with(start, end, options)
+    }
+
+    fn estimate_range_size(
+        &self,
+        start: &str,
+        end: Option<&str>,
+    ) -> Result<RangeEstimate> {
+        self.store.estimate_range_size(start, end)
+    }
+
+    fn scan_filtered(
+        &self,
+        start: &str,
+        end: Option<&str>,
+        predicate: &dyn Fn(&str, &str) -> bool,
+    ) -> Result<Vec<(String, String)>> {
+        self.store.scan_filtered(start, end, predicate)
+    }
+
+    fn collation(&self) -> Collation {
+        self.store.collation()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tempfile::TempDir;
+
+    use crate::{ChangeFeed, HashMapKvs, Persistent};
+
+    fn open(path: &Path, capacity: usize) -> Result<ViewKvs<HashMapKvs>> {
+        let feed = ChangeFeed::for_store(path, capacity)?;
+        ViewKvs::open(path, FeedKvs::new(HashMapKvs::open(path)?, feed))
+    }
+
+    #[test]
+    fn views() -> Result<()> {
+        let temp_dir = TempDir::new()
+            .expect("unable to create temporary working directory");
+        let path = temp_dir.path().join("kvs");
+        let mut store = open(&path, 3)?;
+        store.set("order/1".to_owned(), "10".to_owned())?;
+        store.set("user/1".to_owned(), "5".to_owned())?;
+
+        // built from what's already there
+        store.register("orders", "order/", Aggregate::Count)?;
+        store.register("total", "order/", Aggregate::Sum)?;
+        assert_eq!(store.view("orders"), Some(1.0));
+        assert_eq!(store.view("total"), Some(10.0));
+        assert_eq!(store.view("users"), None);
+
+        store.set("order/2".to_owned(), "2.5".to_owned())?;
+        store.set("order/1".to_owned(), "20".to_owned())?;
+        store.set("order/3".to_owned(), "not a number".to_owned())?;
+        assert_eq!(store.view("orders"), Some(3.0));
+        assert_eq!(store.view("total"), Some(22.5));
+        store.remove("order/1".to_owned())?;
+        assert_eq!(store.view("orders"), Some(2.0));
+        assert_eq!(store.view("total"), Some(2.5));
+        store.save()?;
+
+        // carries on from the changes made since saving
+        store.set("order/4".to_owned(), "1".to_owned())?;
+        drop(store);
+        let store = open(&path, 3)?;
+        assert_eq!(store.view("orders"), Some(3.0));
+        assert_eq!(store.view("total"), Some(3.5));
+        store.save()?;
+
+        // rebuilds once the feed has dropped unapplied changes
+        let feed = ChangeFeed::for_store(&path, 3)?;
+        let mut inner = FeedKvs::new(HashMapKvs::open(&path)?, feed);
+        for i in 5..10 {
+            inner.set(format!("order/{}", i), "1".to_owned())?;
+        }
+        drop(inner);
+        let mut store = open(&path, 3)?;
+        assert_eq!(store.view("orders"), Some(8.0));
+        assert_eq!(store.view("total"), Some(8.5));
+
+        assert!(store.unregister("orders"));
+        assert!(!store.unregister("orders"));
+        assert_eq!(store.view("orders"), None);
+
+        Ok(())
+    }
+}